@@ -0,0 +1,156 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use vector_calc::helper::{CalculatorState, Matrix, Value, Vector};
+use vector_calc::ast::CompiledExpr;
+use vector_calc::{kernels, parser};
+
+const DIMS: usize = 50_000;
+const LARGE_DIMS: usize = 1_000_000;
+
+fn big_vector(offset: f64) -> Value {
+    (0..DIMS).map(|i| i as f64 + offset).collect::<Vector>().into()
+}
+
+fn state_with_vectors() -> CalculatorState {
+    let mut state = CalculatorState::new();
+    state.set_var("a".to_owned(), big_vector(0.0));
+    state.set_var("b".to_owned(), big_vector(0.5));
+    state.set_var("c".to_owned(), big_vector(1.0));
+    state
+}
+
+/// Variables are borrowed, so this only allocates for `a + b` and `a * 2`, the rest reuse those buffers
+fn four_operators(c: &mut Criterion) {
+    let state = state_with_vectors();
+    c.bench_function("a + b - c + a * 2", |bench| {
+        bench.iter(|| parser::eval_expression(black_box("a + b - c + a * 2"), &state).unwrap())
+    });
+}
+
+fn single_variable(c: &mut Criterion) {
+    let state = state_with_vectors();
+    c.bench_function("a", |bench| {
+        bench.iter(|| parser::eval_expression(black_box("a"), &state).unwrap())
+    });
+}
+
+/// Neither of these copies the vector: assignment shares its components and `dot` borrows them.
+/// Both still take longer for the bigger vector, since assignment checks every component is finite
+fn shared_vectors(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shared vectors");
+    for dims in [1_000, 1_000_000] {
+        let mut state = CalculatorState::new();
+        state.set_var("big".to_owned(), (0..dims).map(|i| i as f64).collect::<Vector>().into());
+        group.bench_with_input(BenchmarkId::new("x = big", dims), &dims, |bench, _| {
+            bench.iter(|| parser::eval(black_box("x = big;"), &mut state).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("big dot big", dims), &dims, |bench, _| {
+            bench.iter(|| parser::eval_expression(black_box("big dot big"), &state).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Passing a variable to a function hands over a clone, which shares the numbers for vectors and
+/// matrices alike, so these should take as long for either size
+fn referenced_variables(c: &mut Criterion) {
+    const EXPR: &str = "isvec(big) + isvec(big) + isvec(big) + isvec(big) + isvec(big)";
+    let mut group = c.benchmark_group("referenced variables");
+    for side in [32, 1_000] {
+        let dims = side * side;
+        let mut state = CalculatorState::new();
+        state.set_var("big".to_owned(), (0..dims).map(|i| i as f64).collect::<Vector>().into());
+        group.bench_with_input(BenchmarkId::new("vector", dims), &dims, |bench, _| {
+            bench.iter(|| parser::eval_expression(black_box(EXPR), &state).unwrap())
+        });
+        let rows = (0..side).map(|row| (0..side).map(|col| (row * side + col) as f64).collect()).collect();
+        state.set_var("big".to_owned(), Value::Matrix(Matrix::from_rows(rows).unwrap()));
+        group.bench_with_input(BenchmarkId::new("matrix", dims), &dims, |bench, _| {
+            bench.iter(|| parser::eval_expression(black_box(EXPR), &state).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn parse_only(c: &mut Criterion) {
+    c.bench_function("parse only", |bench| {
+        bench.iter(|| parser::check(black_box("x = <1, 2, 3> + a * 2 - b dot c / 4;")).unwrap())
+    });
+}
+
+fn evaluate_small(c: &mut Criterion) {
+    let state = CalculatorState::new();
+    c.bench_function("evaluate small", |bench| {
+        bench.iter(|| parser::eval_expression(black_box("<1, 2, 3> * 2 + <4, 5, 6> cross <1, 0, 0>"), &state).unwrap())
+    });
+}
+
+/// The one to watch for regressions: each of these should only allocate once, for its first operator
+fn evaluate_large(c: &mut Criterion) {
+    let mut state = CalculatorState::new();
+    state.set_var("v".to_owned(), (0..LARGE_DIMS).map(|i| i as f64).collect::<Vector>().into());
+    state.set_var("w".to_owned(), (0..LARGE_DIMS).map(|i| i as f64 * 0.5).collect::<Vector>().into());
+    let mut group = c.benchmark_group("evaluate large");
+    for expr in ["v * 2 + w", "w + v * 2", "2 * v - w"] {
+        group.bench_function(expr, |bench| {
+            bench.iter(|| parser::eval_expression(black_box(expr), &state).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// The same expression each time with different variables, parsed every time against compiled once
+fn compiled(c: &mut Criterion) {
+    const EXPR: &str = "x * 2 + y * 3 - x * y / 4 + 2 * π";
+    let mut state = CalculatorState::new();
+    let compiled = CompiledExpr::compile(EXPR).unwrap();
+    let mut group = c.benchmark_group("compiled");
+    group.bench_function("parse and eval", |bench| {
+        let mut i = 0.0;
+        bench.iter(|| {
+            i += 1.0;
+            state.set_var("x".to_owned(), Value::Number(i));
+            state.set_var("y".to_owned(), Value::Number(i * 0.5));
+            parser::eval_expression(black_box(EXPR), &state).unwrap()
+        })
+    });
+    group.bench_function("compiled eval", |bench| {
+        let mut i = 0.0;
+        bench.iter(|| {
+            i += 1.0;
+            state.set_var("x".to_owned(), Value::Number(i));
+            state.set_var("y".to_owned(), Value::Number(i * 0.5));
+            black_box(&compiled).eval(&state).unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// The kernels the vector operators use against the plain loops they fall back to, which only
+/// differ when built with `--features simd,parallel`
+fn kernels(c: &mut Criterion) {
+    let a: Vec<f64> = (0..LARGE_DIMS).map(|i| i as f64).collect();
+    let b: Vec<f64> = (0..LARGE_DIMS).map(|i| 1.0 / (i as f64 + 1.0)).collect();
+    let mut group = c.benchmark_group("kernels");
+    group.bench_function("dot", |bench| bench.iter(|| kernels::dot(black_box(&a), black_box(&b))));
+    group.bench_function("scalar dot", |bench| bench.iter(|| kernels::scalar::dot(black_box(&a), black_box(&b))));
+    group.bench_function("add", |bench| bench.iter(|| kernels::zip(black_box(&a), black_box(&b), |x, y| x + y)));
+    group.bench_function("scalar add", |bench| {
+        bench.iter(|| kernels::scalar::zip(black_box(&a), black_box(&b), |x, y| x + y))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    four_operators,
+    single_variable,
+    shared_vectors,
+    referenced_variables,
+    parse_only,
+    evaluate_small,
+    evaluate_large,
+    compiled,
+    kernels
+);
+criterion_main!(benches);
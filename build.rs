@@ -0,0 +1,14 @@
+use std::process::Command;
+
+/// Passes the commit being built to the crate as `VECALC_GIT_HASH`, for `--version`. Builds from
+/// outside a git checkout, like a published crate, just don't have it.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output();
+    if let Some(output) = output.ok().filter(|output| output.status.success()) {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=VECALC_GIT_HASH={}", hash.trim());
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Drives the calculator through its C API. From the repository root:
+ *
+ *     cargo build --release --features ffi
+ *     cc examples/ffi/main.c -Iexamples/ffi target/release/libvector_calc.a -lm -lpthread -ldl -o vecalc_ffi
+ *     ./vecalc_ffi
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "vector_calc.h"
+
+static void run(VecalcState *state, const char *line) {
+    int code = vecalc_eval(state, line);
+    printf(">> %s\n", line);
+    if (code == VECALC_OK) {
+        const char *result = vecalc_last_result_string(state);
+        if (result != NULL) {
+            printf("%s\n", result);
+        }
+    } else if (code == VECALC_ERROR) {
+        printf("%s\n", vecalc_last_error_string(state));
+    } else {
+        printf("vecalc_eval failed with code %d\n", code);
+    }
+}
+
+int main(void) {
+    VecalcState *state = vecalc_state_new();
+    if (state == NULL) {
+        return 1;
+    }
+
+    run(state, "a = <1, 2, 3>");
+    run(state, "b = a * 2 + <0, 0, 1>");
+    run(state, "a dot b");
+    run(state, "a cross c");
+
+    /* Ask for the length first, then copy the components out */
+    intptr_t dims = vecalc_get_vector(state, "b", NULL, 0);
+    if (dims < 0) {
+        vecalc_state_free(state);
+        return 1;
+    }
+    double *components = malloc((size_t)dims * sizeof(double));
+    vecalc_get_vector(state, "b", components, (size_t)dims);
+    printf("b has %ld components:", (long)dims);
+    for (intptr_t i = 0; i < dims; i++) {
+        printf(" %g", components[i]);
+    }
+    printf("\n");
+
+    free(components);
+    vecalc_state_free(state);
+    return 0;
+}
@@ -0,0 +1,84 @@
+#ifndef VECTOR_CALC_H
+#define VECTOR_CALC_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The line evaluated, `vecalc_last_result_string` has its result if it had one
+#define VECALC_OK 0
+
+// The line didn't parse or evaluate, see `vecalc_last_error_string`
+#define VECALC_ERROR 1
+
+// A pointer was null, or the input wasn't UTF-8
+#define VECALC_INVALID_ARGUMENT 2
+
+// Something went wrong inside the calculator. The state can still be freed, but its variables
+// may not be what they were
+#define VECALC_PANIC 3
+
+// A calculator session, only used through pointers from `vecalc_state_new`
+typedef struct VecalcState VecalcState;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A new session with no variables, to be freed with `vecalc_state_free`. Null if creating it failed.
+struct VecalcState *vecalc_state_new(void);
+
+// Frees a session from `vecalc_state_new`, and the strings it handed out. Null is ignored.
+//
+// # Safety
+//
+// `state` must be null or from `vecalc_state_new`, and not already freed.
+void vecalc_state_free(struct VecalcState *state);
+
+// Evaluates one line, like typing it into the REPL, and gives one of the `VECALC_` codes.
+//
+// Clears the last result and error first, so after `VECALC_OK` the result string is null for
+// lines without a result, like assignments.
+//
+// # Safety
+//
+// `state` must be from `vecalc_state_new`, and `input` a NUL terminated string.
+int vecalc_eval(struct VecalcState *state, const char *input);
+
+// The result of the last `vecalc_eval`, formatted like the REPL shows it, or null if it didn't have one
+//
+// # Safety
+//
+// `state` must be from `vecalc_state_new`.
+const char *vecalc_last_result_string(const struct VecalcState *state);
+
+// The error from the last `vecalc_eval`, or null if it didn't fail
+//
+// # Safety
+//
+// `state` must be from `vecalc_state_new`.
+const char *vecalc_last_error_string(const struct VecalcState *state);
+
+// Copies up to `out_len` components of the vector variable `name` into `out_ptr`, and gives
+// how many components it has. Call it with a null `out_ptr` to just get the length.
+//
+// Gives -1 if there's no vector with that name, or an argument is invalid.
+//
+// # Safety
+//
+// `state` must be from `vecalc_state_new`, `name` a NUL terminated string, and `out_ptr`
+// null or valid for writing `out_len` doubles.
+ptrdiff_t vecalc_get_vector(const struct VecalcState *state,
+                            const char *name,
+                            double *out_ptr,
+                            size_t out_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VECTOR_CALC_H */
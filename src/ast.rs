@@ -0,0 +1,1106 @@
+//! Expressions as a tree, between parsing and evaluating them.
+//!
+//! [`lower`] turns the pairs from the grammar into an [`Expr`] and [`eval`] walks it against the
+//! variables in a [`CalculatorState`]. Keeping the two apart means an expression can be inspected,
+//! timed or echoed before anything is evaluated.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::num::ParseFloatError;
+
+use once_cell::sync::Lazy;
+use pest::iterators::{Pair, Pairs};
+use pest::prec_climber::{Assoc, Operator, PrecClimber};
+
+use crate::error::{missing_rule, next_pair, pair_span, unexpected_rule, EvalError, ParseError, Span};
+use crate::helper::{
+    central_difference, constant, find_root, simpson, CalculatorState, Complex, Matrix, Quantity, RootError, Unit, Value,
+    Vector, LAST_RESULT_NAME,
+};
+use crate::output::print_debug;
+use crate::parser::{self, call_function, Rule};
+
+/// Operator precedence, loosest first. `dot` and `cross` bind tighter than `*` and `/`, the way
+/// `a·b c` reads in a textbook, so `2 * a dot b` is `2 * (a dot b)` and `a dot b * 2` is
+/// `(a dot b) * 2`. Only `^` binds tighter, so `a dot b ^ 2` is `a dot (b ^ 2)`.
+///
+/// `%` is the remainder, at the same level as `*` and `/`, so `7 % 4 * 2` is `(7 % 4) * 2`.
+///
+/// The comparisons `==`, `!=` and `~=` are loosest of all, so `a + b == c` is `(a + b) == c`.
+///
+/// Everything is left associative apart from `^`, so `a cross b cross c` is `(a cross b) cross c`
+/// and `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+///
+/// A `-` in front of a value binds tighter than all of these but `^`, which the grammar groups
+/// with the value, see [`lower_operand`]. So `^` only gets here in a line like `^ 2` that goes on
+/// from the last result.
+static PREC_CLIMBER: Lazy<PrecClimber<Rule>> = Lazy::new(|| {
+    use Assoc::*;
+    use Rule::*;
+
+    PrecClimber::new(vec![
+        Operator::new(equal, Left) | Operator::new(not_equal, Left) | Operator::new(approx_equal, Left),
+        Operator::new(add, Left) | Operator::new(subtract, Left),
+        Operator::new(multiply, Left) | Operator::new(divide, Left) | Operator::new(modulo, Left),
+        Operator::new(dot, Left) | Operator::new(cross, Left),
+        Operator::new(power, Right)
+    ])
+});
+
+/// A parsed expression. Every node keeps the span of the input it came from, so errors found
+/// while evaluating can still point at it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number { value: f64, span: Span },
+    /// The imaginary part of a literal like `4i`
+    Imaginary { value: f64, span: Span },
+    /// A literal with a unit, like `5 N`
+    Quantity { value: f64, unit: Unit, span: Span },
+    VectorLiteral { components: Vec<Expr>, span: Span },
+    /// The rows, which are all vector literals of the same length
+    MatrixLiteral { rows: Vec<Expr>, span: Span },
+    Ident { name: String, span: Span },
+    /// `name@back`, the value the variable had `back` assignments ago
+    History { name: String, back: usize, span: Span },
+    BinaryOp {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        /// Just the operator, which is where errors from applying it point
+        op_span: Span,
+        span: Span,
+    },
+    UnaryOp { op: UnaryOp, operand: Box<Expr>, span: Span },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        name_span: Span,
+        span: Span,
+    },
+    /// `<body for var in start..end if filter>`, see `eval_comprehension`
+    Comprehension {
+        body: Box<Expr>,
+        var: String,
+        start: Box<Expr>,
+        end: Box<Expr>,
+        filter: Option<Box<Expr>>,
+        span: Span,
+    },
+    /// `let name = value in body`, see `eval_let`
+    Let {
+        name: String,
+        value: Box<Expr>,
+        body: Box<Expr>,
+        span: Span,
+    },
+    /// A value put in place of a variable by `substitute`, for values that don't have a literal
+    /// node of their own. The parser never makes one
+    Bound { value: Value, span: Span },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Dot,
+    Cross,
+    Equal,
+    NotEqual,
+    ApproxEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Number { span, .. }
+            | Self::Imaginary { span, .. }
+            | Self::Quantity { span, .. }
+            | Self::VectorLiteral { span, .. }
+            | Self::MatrixLiteral { span, .. }
+            | Self::Ident { span, .. }
+            | Self::History { span, .. }
+            | Self::BinaryOp { span, .. }
+            | Self::UnaryOp { span, .. }
+            | Self::Call { span, .. }
+            | Self::Comprehension { span, .. }
+            | Self::Let { span, .. }
+            | Self::Bound { span, .. } => *span,
+        }
+    }
+
+    /// How tightly the outermost part binds, like `BinaryOp::precedence`. Negative literals are
+    /// written with a `-`, so they bind like one, and a `let` takes the rest of the expression
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::BinaryOp { op, .. } => op.precedence(),
+            Self::UnaryOp { .. } => NEGATE_PRECEDENCE,
+            Self::Number { value, .. } | Self::Imaginary { value, .. } | Self::Quantity { value, .. }
+                if value.is_sign_negative() =>
+            {
+                NEGATE_PRECEDENCE
+            }
+            Self::Bound { value: Value::Number(value), .. } if value.is_sign_negative() => NEGATE_PRECEDENCE,
+            Self::Let { .. } => 0,
+            _ => u8::MAX,
+        }
+    }
+
+    /// A copy with every use of the variable `name` replaced by `value`. A nested comprehension
+    /// or `let` with its own `name` keeps it in its body, which refers to its own
+    fn substitute(&self, name: &str, value: &Value) -> Expr {
+        let boxed = |expr: &Expr| Box::new(expr.substitute(name, value));
+        let all = |exprs: &[Expr]| exprs.iter().map(|expr| expr.substitute(name, value)).collect();
+        match self {
+            Self::Ident { name: ident, span } if ident == name => match value {
+                Value::Number(value) => Self::Number { value: *value, span: *span },
+                value => Self::Bound { value: value.clone(), span: *span },
+            },
+            Self::VectorLiteral { components, span } => Self::VectorLiteral { components: all(components), span: *span },
+            Self::MatrixLiteral { rows, span } => Self::MatrixLiteral { rows: all(rows), span: *span },
+            Self::BinaryOp { op, lhs, rhs, op_span, span } => Self::BinaryOp {
+                op: *op,
+                lhs: boxed(lhs),
+                rhs: boxed(rhs),
+                op_span: *op_span,
+                span: *span,
+            },
+            Self::UnaryOp { op, operand, span } => Self::UnaryOp { op: *op, operand: boxed(operand), span: *span },
+            Self::Call { name: function, args, name_span, span } => Self::Call {
+                name: function.clone(),
+                // Like a comprehension, a call that binds `name` keeps it in the expression it binds it in
+                args: match bound_variable(function, args) {
+                    Some(bound) if bound == name => {
+                        args[..2].iter().cloned().chain(args[2..].iter().map(|arg| arg.substitute(name, value))).collect()
+                    }
+                    _ => all(args),
+                },
+                name_span: *name_span,
+                span: *span,
+            },
+            Self::Comprehension { body, var, start, end, filter, span } => {
+                let shadowed = var == name;
+                let inner = |expr: &Expr| Box::new(if shadowed { expr.clone() } else { expr.substitute(name, value) });
+                Self::Comprehension {
+                    body: inner(body),
+                    var: var.clone(),
+                    start: boxed(start),
+                    end: boxed(end),
+                    filter: filter.as_deref().map(inner),
+                    span: *span,
+                }
+            }
+            Self::Let { name: var, value: bound, body, span } => Self::Let {
+                name: var.clone(),
+                value: boxed(bound),
+                body: if var == name { body.clone() } else { boxed(body) },
+                span: *span,
+            },
+            leaf => leaf.clone(),
+        }
+    }
+}
+
+impl BinaryOp {
+    /// How it's written, which is also how errors name it
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Modulo => "%",
+            Self::Power => "^",
+            Self::Dot => "dot",
+            Self::Cross => "cross",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::ApproxEqual => "~=",
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(self, Self::Equal | Self::NotEqual | Self::ApproxEqual)
+    }
+
+    /// How tightly it binds, higher first, following `PREC_CLIMBER`
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Equal | Self::NotEqual | Self::ApproxEqual => 1,
+            Self::Add | Self::Subtract => 2,
+            Self::Multiply | Self::Divide | Self::Modulo => 3,
+            Self::Dot | Self::Cross => 4,
+            Self::Power => 6,
+        }
+    }
+}
+
+/// Where `-` binds, between `^` and everything else
+const NEGATE_PRECEDENCE: u8 = 5;
+
+/// `expr` as it's written, in parentheses if `parenthesize` is true
+fn grouped(expr: &Expr, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+/// Writes the expression back out in the calculator's syntax, with one space around each operator.
+///
+/// Parentheses only go where the precedence rules need them, so `(a + b) * c` keeps them but
+/// `a + (b * c)` doesn't.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number { value, .. } => write!(f, "{}", value),
+            Self::Imaginary { value, .. } => write!(f, "{}i", value),
+            Self::Quantity { value, unit, .. } => write!(f, "{} {}", value, unit),
+            Self::VectorLiteral { components, .. } => write!(f, "<{}>", comma_separated(components)),
+            Self::MatrixLiteral { rows, .. } => write!(f, "[{}]", comma_separated(rows)),
+            Self::Ident { name, .. } => write!(f, "{}", name),
+            Self::History { name, back, .. } => write!(f, "{}@{}", name, back),
+            Self::BinaryOp { op, lhs, rhs, .. } => {
+                // An operand at the same level needs them on the side the operator doesn't group
+                let (level, right_assoc) = (op.precedence(), *op == BinaryOp::Power);
+                let lhs = grouped(lhs, lhs.precedence() < level || (right_assoc && lhs.precedence() == level));
+                let rhs = grouped(rhs, rhs.precedence() < level || (!right_assoc && rhs.precedence() == level));
+                write!(f, "{} {} {}", lhs, op.symbol(), rhs)
+            }
+            Self::UnaryOp { op: UnaryOp::Negate, operand, .. } => {
+                write!(f, "-{}", grouped(operand, operand.precedence() < NEGATE_PRECEDENCE))
+            }
+            Self::Call { name, args, .. } => write!(f, "{}({})", name, comma_separated(args)),
+            Self::Comprehension { body, var, start, end, filter, .. } => {
+                write!(f, "<{} for {} in {}..{}", body, var, start, end)?;
+                if let Some(filter) = filter {
+                    write!(f, " if {}", filter)?;
+                }
+                write!(f, ">")
+            }
+            Self::Let { name, value, body, .. } => write!(f, "let {} = {} in {}", name, value, body),
+            Self::Bound { value, .. } => match value.to_literal() {
+                Some(literal) => write!(f, "{}", literal),
+                None => write!(f, "{}", value),
+            },
+        }
+    }
+}
+
+fn comma_separated(exprs: &[Expr]) -> String {
+    exprs.iter().map(Expr::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl Expr {
+    /// Like `Display`, but with every operator and its operands in parentheses, like
+    /// `(a + (b * 2))`, to show how the expression was grouped
+    pub fn parenthesized(&self) -> String {
+        match self {
+            Self::BinaryOp { op, lhs, rhs, .. } => {
+                format!("({} {} {})", lhs.parenthesized(), op.symbol(), rhs.parenthesized())
+            }
+            Self::UnaryOp { op: UnaryOp::Negate, operand, .. } => format!("(-{})", operand.parenthesized()),
+            Self::Call { name, args, .. } => {
+                let args: Vec<String> = args.iter().map(Expr::parenthesized).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Self::Comprehension { body, var, start, end, filter, .. } => {
+                let filter = filter.as_ref().map_or(String::new(), |filter| format!(" if {}", filter.parenthesized()));
+                format!(
+                    "<{} for {} in {}..{}{}>",
+                    body.parenthesized(),
+                    var,
+                    start.parenthesized(),
+                    end.parenthesized(),
+                    filter
+                )
+            }
+            Self::Let { name, value, body, .. } => {
+                format!("(let {} = {} in {})", name, value.parenthesized(), body.parenthesized())
+            }
+            leaf => leaf.to_string(),
+        }
+    }
+
+    /// One node per line, with operands and arguments indented under what uses them
+    pub fn tree(&self) -> String {
+        let mut lines = Vec::new();
+        self.push_tree_lines(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn push_tree_lines(&self, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        let children: Vec<&Expr> = match self {
+            Self::BinaryOp { op, lhs, rhs, .. } => {
+                lines.push(format!("{}{}", indent, op.symbol()));
+                vec![lhs, rhs]
+            }
+            Self::UnaryOp { op: UnaryOp::Negate, operand, .. } => {
+                lines.push(format!("{}-", indent));
+                vec![operand]
+            }
+            Self::Call { name, args, .. } => {
+                lines.push(format!("{}{}()", indent, name));
+                args.iter().collect()
+            }
+            // The range, then what's evaluated for each value
+            Self::Comprehension { body, var, start, end, filter, .. } => {
+                lines.push(format!("{}for {} in ..", indent, var));
+                let mut children: Vec<&Expr> = vec![start, end, body];
+                children.extend(filter.as_deref());
+                children
+            }
+            // What's bound, then what it's bound in
+            Self::Let { name, value, body, .. } => {
+                lines.push(format!("{}let {}", indent, name));
+                vec![value, body]
+            }
+            leaf => {
+                lines.push(format!("{}{}", indent, leaf));
+                Vec::new()
+            }
+        };
+        for child in children {
+            child.push_tree_lines(depth + 1, lines);
+        }
+    }
+}
+
+/// Builds the tree for the inside of an `expression` pair
+pub fn lower(pairs: Pairs<Rule>) -> Result<Expr, ParseError> {
+    PREC_CLIMBER.climb(
+        pairs,
+        lower_operand,
+        |lhs: Result<Expr, ParseError>, op: Pair<Rule>, rhs: Result<Expr, ParseError>| {
+            let (lhs, rhs) = (lhs?, rhs?);
+            let op_span = pair_span(&op);
+            let op = match op.as_rule() {
+                Rule::add => BinaryOp::Add,
+                Rule::subtract => BinaryOp::Subtract,
+                Rule::multiply => BinaryOp::Multiply,
+                Rule::divide => BinaryOp::Divide,
+                Rule::modulo => BinaryOp::Modulo,
+                Rule::power => BinaryOp::Power,
+                Rule::dot => BinaryOp::Dot,
+                Rule::cross => BinaryOp::Cross,
+                Rule::equal => BinaryOp::Equal,
+                Rule::not_equal => BinaryOp::NotEqual,
+                Rule::approx_equal => BinaryOp::ApproxEqual,
+                _ => return Err(unexpected_rule(&op, "an operator")),
+            };
+            Ok(Expr::BinaryOp {
+                op,
+                span: (lhs.span().0, rhs.span().1),
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                op_span,
+            })
+        },
+    )
+}
+
+/// Builds the tree for an `operand`, which is the `-`s in front of each value of a `^` chain.
+/// The chain is right associative and inside the `-`s before it, so `-2 ^ -3 ^ 2` is
+/// `-(2 ^ -(3 ^ 2))`
+fn lower_operand(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    if pair.as_rule() != Rule::operand {
+        return lower_value(pair);
+    }
+    let span = pair_span(&pair);
+    // Each value with the `^` before it, if there is one, and where the `-`s in front of it start
+    let mut factors = Vec::new();
+    let (mut power_span, mut signs) = (None, Vec::new());
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::unary_operator => signs.push(pair.as_span().start()),
+            Rule::power => power_span = Some(pair_span(&pair)),
+            _ => factors.push((power_span.take(), std::mem::take(&mut signs), lower_value(pair)?)),
+        }
+    }
+
+    let (mut expr, mut op_span): (Option<Expr>, Option<Span>) = (None, None);
+    for (power_span, signs, base) in factors.into_iter().rev() {
+        let mut factor = match (expr, op_span) {
+            (Some(rhs), Some(op_span)) => Expr::BinaryOp {
+                op: BinaryOp::Power,
+                span: (base.span().0, rhs.span().1),
+                lhs: Box::new(base),
+                rhs: Box::new(rhs),
+                op_span,
+            },
+            _ => base,
+        };
+        for &start in signs.iter().rev() {
+            factor = Expr::UnaryOp {
+                op: UnaryOp::Negate,
+                span: (start, factor.span().1),
+                operand: Box::new(factor),
+            };
+        }
+        expr = Some(factor);
+        op_span = power_span;
+    }
+    expr.ok_or_else(|| missing_rule(span, "a value"))
+}
+
+/// Reads a `bare_number`, without the underscores it can have between digits
+pub(crate) fn parse_decimal(text: &str) -> Result<f64, ParseFloatError> {
+    if text.contains('_') {
+        text.replace('_', "").parse()
+    } else {
+        text.parse()
+    }
+}
+
+/// Reads an `angle`, in radians
+pub(crate) fn parse_angle(text: &str) -> Result<f64, ParseFloatError> {
+    match text.strip_suffix("deg") {
+        Some(degrees) => Ok(parse_decimal(degrees)?.to_radians()),
+        None => parse_decimal(text.trim_end_matches("rad")),
+    }
+}
+
+/// Reads a `percent`, as a fraction of 1
+pub(crate) fn parse_percent(text: &str) -> Result<f64, ParseFloatError> {
+    Ok(parse_decimal(text.trim_end_matches('%'))? / 100.0)
+}
+
+/// Whole numbers above this can't all be stored exactly in an `f64`
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+/// Reads a `radix_number` like `0xff` or `-0b101`.
+///
+/// Values are stored as `f64`, so anything past 2^53 is an error rather than being rounded.
+pub(crate) fn parse_radix_number(pair: &Pair<Rule>) -> Result<f64, ParseError> {
+    let literal = pair.as_str();
+    let invalid = |reason: String| ParseError::InvalidInteger {
+        literal: literal.to_owned(),
+        reason,
+        span: pair_span(pair),
+    };
+    let (negative, unsigned) = match literal.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, literal),
+    };
+    let (radix, name, digits) = match unsigned.split_at(2) {
+        ("0x", digits) => (16, "hex", digits),
+        (_, digits) => (2, "binary", digits),
+    };
+
+    if digits.is_empty() {
+        return Err(invalid(format!("needs {} digits after the {}", name, &unsigned[..2])));
+    }
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err(invalid("can only have single underscores between digits".to_owned()));
+    }
+    let digits = digits.replace('_', "");
+    let digits = digits.as_str();
+    if let Some(bad) = digits.chars().find(|c| !c.is_digit(radix)) {
+        return Err(invalid(format!("has '{}', which isn't a {} digit", bad, name)));
+    }
+    let value = u64::from_str_radix(digits, radix)
+        .ok()
+        .filter(|&value| value <= MAX_EXACT_INTEGER)
+        .ok_or_else(|| invalid("is too big to store exactly, the most is 2^53".to_owned()))?;
+    Ok(if negative { -(value as f64) } else { value as f64 })
+}
+
+/// A `unit` like `kg·m/s^2`, where each `/` divides by just the unit after it
+fn parse_unit(pair: Pair<Rule>) -> Result<Unit, ParseError> {
+    let mut unit = Unit::default();
+    let mut divide = false;
+    for pair in pair.into_inner() {
+        if pair.as_rule() == Rule::unit_divide {
+            divide = true;
+            continue;
+        }
+        let span = pair_span(&pair);
+        let mut factor = pair.into_inner();
+        let name_pair = next_pair(&mut factor, span, "a unit name")?;
+        let power: i32 = match factor.next() {
+            Some(power_pair) => power_pair.as_str().parse().map_err(|_| unexpected_rule(&power_pair, "a unit power"))?,
+            None => 1,
+        };
+        let factor = Unit::named(name_pair.as_str())
+            .and_then(|unit| unit.powf(power as f64))
+            .ok_or_else(|| unexpected_rule(&name_pair, "a known unit"))?;
+        unit = if divide { unit / factor } else { unit * factor };
+        divide = false;
+    }
+    Ok(unit)
+}
+
+fn lower_value(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = pair_span(&pair);
+    match pair.as_rule() {
+        Rule::bare_number => Ok(Expr::Number { value: parse_decimal(pair.as_str())?, span }),
+        Rule::radix_number => Ok(Expr::Number { value: parse_radix_number(&pair)?, span }),
+        Rule::angle => Ok(Expr::Number { value: parse_angle(pair.as_str())?, span }),
+        Rule::percent => Ok(Expr::Number { value: parse_percent(pair.as_str())?, span }),
+        Rule::quantity => {
+            let mut pairs = pair.into_inner();
+            let value = parse_decimal(next_pair(&mut pairs, span, "a number")?.as_str())?;
+            let unit = parse_unit(next_pair(&mut pairs, span, "a unit")?)?;
+            Ok(Expr::Quantity { value, unit, span })
+        }
+        Rule::imaginary => Ok(Expr::Imaginary {
+            value: parse_decimal(pair.as_str().trim_end_matches('i'))?,
+            span,
+        }),
+        Rule::vector => Ok(Expr::VectorLiteral {
+            components: pair.into_inner().map(lower_value).collect::<Result<_, _>>()?,
+            span,
+        }),
+        Rule::matrix => {
+            let row_pairs: Vec<Pair<Rule>> = pair.into_inner().collect();
+            let lengths: Vec<usize> = row_pairs.iter().map(|row_pair| row_pair.clone().into_inner().count()).collect();
+            if let Some(row) = lengths.iter().position(|&len| len != lengths[0]) {
+                return Err(ParseError::RaggedMatrix {
+                    row: row + 1,
+                    expected: lengths[0],
+                    found: lengths[row],
+                    span: pair_span(&row_pairs[row]),
+                });
+            }
+            Ok(Expr::MatrixLiteral {
+                rows: row_pairs.into_iter().map(lower_value).collect::<Result<_, _>>()?,
+                span,
+            })
+        }
+        Rule::ident => Ok(Expr::Ident { name: pair.as_str().to_owned(), span }),
+        Rule::last_result => Ok(Expr::Ident { name: LAST_RESULT_NAME.to_owned(), span }),
+        // The tree is all the grouping needs, `Display` puts back the parentheses it has to
+        Rule::parenthesized => lower(next_pair(&mut pair.into_inner(), span, "an expression")?.into_inner()),
+        Rule::history_value => {
+            let mut pairs = pair.into_inner();
+            let name = next_pair(&mut pairs, span, "a variable name")?.as_str().to_owned();
+            let index_pair = next_pair(&mut pairs, span, "a number")?;
+            let back = index_pair.as_str().replace('_', "").parse().map_err(|_| ParseError::InvalidInteger {
+                literal: index_pair.as_str().to_owned(),
+                reason: "is too far back".to_owned(),
+                span: pair_span(&index_pair),
+            })?;
+            Ok(Expr::History { name, back, span })
+        }
+        Rule::function_call => {
+            let mut pairs = pair.into_inner();
+            let name_pair = next_pair(&mut pairs, span, "a function name")?;
+            Ok(Expr::Call {
+                name: name_pair.as_str().to_owned(),
+                name_span: pair_span(&name_pair),
+                args: pairs.map(|arg| lower(arg.into_inner())).collect::<Result<_, _>>()?,
+                span,
+            })
+        }
+        Rule::comprehension => {
+            // The keywords are only there to be skipped
+            let mut pairs = pair
+                .into_inner()
+                .filter(|pair| !matches!(pair.as_rule(), Rule::keyword_for | Rule::keyword_in | Rule::keyword_if));
+            let mut next = || next_pair(&mut pairs, span, "the parts of a comprehension");
+            let body = Box::new(lower(next()?.into_inner())?);
+            let var = next()?.as_str().to_owned();
+            let start = Box::new(lower(next()?.into_inner())?);
+            let end = Box::new(lower(next()?.into_inner())?);
+            let filter = match pairs.next() {
+                Some(pair) => Some(Box::new(lower(pair.into_inner())?)),
+                None => None,
+            };
+            Ok(Expr::Comprehension { body, var, start, end, filter, span })
+        }
+        Rule::let_expression => {
+            let mut pairs = pair
+                .into_inner()
+                .filter(|pair| !matches!(pair.as_rule(), Rule::keyword_let | Rule::keyword_in));
+            let mut next = || next_pair(&mut pairs, span, "the parts of a let");
+            let name = next()?.as_str().to_owned();
+            let value = Box::new(lower(next()?.into_inner())?);
+            let body = Box::new(lower(next()?.into_inner())?);
+            Ok(Expr::Let { name, value, body, span })
+        }
+        _ => Err(unexpected_rule(&pair, "a value")),
+    }
+}
+
+/// A copy of the tree with every part that only uses literals and built in constants like `π`
+/// evaluated ahead of time, so `2 * π * r` becomes `6.283185307179586 * r`. Useful for an
+/// expression that's evaluated many times.
+///
+/// Function calls and comprehensions are left alone apart from the arguments of the calls, as are
+/// parts that fail to evaluate, so
+/// their errors still come from evaluating the tree. So are parts that give a quaternion or a
+/// complex number, which don't have a literal.
+///
+/// A variable with the same name as a constant hides it, so this shouldn't be used on trees
+/// evaluated with one. See `fold_constants_for` for that.
+pub fn fold_constants(expr: &Expr) -> Expr {
+    fold(expr, &|_| false, &CalculatorState::new())
+}
+
+/// Like `fold_constants`, but leaves alone constants that `state` has a variable hiding
+pub fn fold_constants_for(expr: &Expr, state: &CalculatorState) -> Expr {
+    fold(expr, &|name| state.contains_key(name), &CalculatorState::new())
+}
+
+/// Folds `expr` bottom up. `hidden` says whether a variable hides a constant, and `empty` is a
+/// state without variables to evaluate the constant parts with
+fn fold(expr: &Expr, hidden: &dyn Fn(&str) -> bool, empty: &CalculatorState) -> Expr {
+    let folded = match expr {
+        Expr::BinaryOp { op, lhs, rhs, op_span, span } => Expr::BinaryOp {
+            op: *op,
+            lhs: Box::new(fold(lhs, hidden, empty)),
+            rhs: Box::new(fold(rhs, hidden, empty)),
+            op_span: *op_span,
+            span: *span,
+        },
+        Expr::UnaryOp { op, operand, span } => Expr::UnaryOp {
+            op: *op,
+            operand: Box::new(fold(operand, hidden, empty)),
+            span: *span,
+        },
+        // The call itself stays, but its arguments can still be folded
+        Expr::Call { name, args, name_span, span } => {
+            let args = match bound_variable(name, args) {
+                // The bound variable hides a constant with its name, and has to stay a variable
+                Some(var) => args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| match i {
+                        0 => fold(arg, &|name| name == var || hidden(name), empty),
+                        1 => arg.clone(),
+                        _ => fold(arg, hidden, empty),
+                    })
+                    .collect(),
+                None => args.iter().map(|arg| fold(arg, hidden, empty)).collect(),
+            };
+            return Expr::Call {
+                name: name.clone(),
+                args,
+                name_span: *name_span,
+                span: *span,
+            }
+        }
+        // The name hides a constant in the body, like a variable does
+        Expr::Let { name, value, body, span } => {
+            return Expr::Let {
+                name: name.clone(),
+                value: Box::new(fold(value, hidden, empty)),
+                body: Box::new(fold(body, &|var| var == name || hidden(var), empty)),
+                span: *span,
+            }
+        }
+        Expr::Ident { name, span } => {
+            return match constant(name).filter(|_| !hidden(name)) {
+                Some(value) => Expr::Number { value, span: *span },
+                None => expr.clone(),
+            }
+        }
+        other => return other.clone(),
+    };
+
+    let operands_constant = match &folded {
+        // What it gives depends on the session's epsilon
+        Expr::BinaryOp { op: BinaryOp::ApproxEqual, .. } => false,
+        Expr::BinaryOp { lhs, rhs, .. } => is_literal(lhs) && is_literal(rhs),
+        Expr::UnaryOp { operand, .. } => is_literal(operand),
+        _ => false,
+    };
+    if !operands_constant {
+        return folded;
+    }
+    let span = folded.span();
+    match eval(&folded, empty) {
+        Ok(Value::Number(value)) => Expr::Number { value, span },
+        Ok(Value::Vector(vec)) => vector_literal(&vec, span),
+        Ok(Value::Matrix(matrix)) => Expr::MatrixLiteral {
+            rows: (0..matrix.shape().0)
+                .map(|row| vector_literal(&Vector::from(matrix.row(row).to_vec()), span))
+                .collect(),
+            span,
+        },
+        _ => folded,
+    }
+}
+
+/// An expression parsed once, to be evaluated many times with different variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledExpr {
+    expr: Expr,
+    /// Sorted, without repeats
+    variables: Vec<String>,
+}
+
+impl CompiledExpr {
+    /// Parses a single expression and folds its constant parts with `fold_constants`, so a
+    /// variable with the same name as a constant won't be used where the constant was folded
+    pub fn compile(input: &str) -> Result<Self, ParseError> {
+        let expr = fold_constants(&parser::parse_expression_tree(input)?);
+        let mut variables = BTreeSet::new();
+        free_variables(&expr, &mut Vec::new(), &mut variables);
+        Ok(Self {
+            expr,
+            variables: variables.into_iter().collect(),
+        })
+    }
+
+    /// Evaluates the expression against the variables in `state`, like `parser::eval_expression`
+    pub fn eval(&self, state: &CalculatorState) -> Result<Value, EvalError> {
+        eval(&self.expr, state)
+    }
+
+    /// The variables the expression uses, sorted, for checking they're all set before
+    /// evaluating. Doesn't include constants, `_`, or the variables comprehensions step through
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// The tree, after folding
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+/// Adds the names `expr` uses that aren't in `bound` to `free`
+fn free_variables<'e>(expr: &'e Expr, bound: &mut Vec<&'e str>, free: &mut BTreeSet<String>) {
+    match expr {
+        Expr::Ident { name, .. } | Expr::History { name, .. } => {
+            if name != LAST_RESULT_NAME && constant(name).is_none() && !bound.contains(&name.as_str()) {
+                free.insert(name.clone());
+            }
+        }
+        Expr::Call { name, args, .. } if bound_variable(name, args).is_some() => {
+            bound.extend(bound_variable(name, args));
+            free_variables(&args[0], bound, free);
+            bound.pop();
+            for arg in &args[2..] {
+                free_variables(arg, bound, free);
+            }
+        }
+        Expr::VectorLiteral { components: exprs, .. } | Expr::MatrixLiteral { rows: exprs, .. } | Expr::Call { args: exprs, .. } => {
+            for expr in exprs {
+                free_variables(expr, bound, free);
+            }
+        }
+        Expr::BinaryOp { lhs, rhs, .. } => {
+            free_variables(lhs, bound, free);
+            free_variables(rhs, bound, free);
+        }
+        Expr::UnaryOp { operand, .. } => free_variables(operand, bound, free),
+        // The range is outside the variable's scope, the body and filter are inside it
+        Expr::Comprehension { body, var, start, end, filter, .. } => {
+            free_variables(start, bound, free);
+            free_variables(end, bound, free);
+            bound.push(var);
+            free_variables(body, bound, free);
+            if let Some(filter) = filter {
+                free_variables(filter, bound, free);
+            }
+            bound.pop();
+        }
+        Expr::Let { name, value, body, .. } => {
+            free_variables(value, bound, free);
+            bound.push(name);
+            free_variables(body, bound, free);
+            bound.pop();
+        }
+        Expr::Number { .. } | Expr::Imaginary { .. } | Expr::Quantity { .. } | Expr::Bound { .. } => {}
+    }
+}
+
+/// Whether the expression is a literal that folding can use, rather than one it can't make
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number { .. } | Expr::Imaginary { .. } => true,
+        Expr::VectorLiteral { components, .. } => components.iter().all(|component| matches!(component, Expr::Number { .. })),
+        Expr::MatrixLiteral { rows, .. } => rows.iter().all(is_literal),
+        _ => false,
+    }
+}
+
+fn vector_literal(vec: &Vector, span: Span) -> Expr {
+    Expr::VectorLiteral {
+        components: vec.iter().map(|&value| Expr::Number { value, span }).collect(),
+        span,
+    }
+}
+
+/// Evaluates the tree against the variables in `state`
+pub fn eval(expr: &Expr, state: &CalculatorState) -> Result<Value, EvalError> {
+    eval_borrowed(expr, state).map(Cow::into_owned)
+}
+
+/// Variables come back borrowed from the state, everything else is a new value. That way
+/// something like `a + b` only allocates once for the result, and when only the right hand
+/// side is owned, as in `a + b * 2`, its buffer is reused instead.
+fn eval_borrowed<'s>(expr: &Expr, state: &'s CalculatorState) -> Result<Cow<'s, Value>, EvalError> {
+    print_debug!(state, 3, "(eval) {}", expr);
+    match expr {
+        Expr::Number { value, .. } => Ok(Cow::Owned(Value::Number(*value))),
+        Expr::Imaginary { value, .. } => Ok(Cow::Owned(Value::Complex(Complex::new(0.0, *value)))),
+        Expr::Quantity { value, unit, .. } if state.units => Ok(Cow::Owned(Quantity::value_of(*value, *unit))),
+        Expr::Quantity { value, .. } => Ok(Cow::Owned(Value::Number(*value))),
+        Expr::VectorLiteral { components, .. } => components
+            .iter()
+            .map(|component| match component {
+                // The grammar only allows number literals in vectors, so skip the walk for those
+                Expr::Number { value, .. } => Ok(*value),
+                _ => f64::try_from(&*eval_borrowed(component, state)?).map_err(|err| err.with_span(component.span())),
+            })
+            .collect::<Result<Vector, EvalError>>()
+            .map(|vec| Cow::Owned(Value::Vector(vec))),
+        Expr::MatrixLiteral { rows, .. } => {
+            let vectors = rows
+                .iter()
+                .map(|row| Vector::try_from(eval(row, state)?).map_err(|err| err.with_span(row.span())))
+                .collect::<Result<Vec<Vector>, EvalError>>()?;
+            let expected = vectors.first().map_or(0, Vector::dims);
+            let lengths: Vec<usize> = vectors.iter().map(Vector::dims).collect();
+            Matrix::from_rows(vectors)
+                .map(|matrix| Cow::Owned(Value::Matrix(matrix)))
+                .map_err(|row| EvalError::DimensionMismatch {
+                    op: "matrix row",
+                    left: expected,
+                    right: lengths[row],
+                    span: Some(rows[row].span()),
+                })
+        }
+        Expr::Ident { name, span } if name == LAST_RESULT_NAME => state
+            .get_result(-1)
+            .map(Cow::Borrowed)
+            .ok_or(EvalError::UnknownResult { index: -1, span: Some(*span) }),
+        Expr::Ident { name, span } => match (state.get_var(name), constant(name)) {
+            (Some(value), _) => {
+                state.update_stats(|stats| stats.record_reference(name));
+                Ok(Cow::Borrowed(value))
+            }
+            (None, Some(value)) => Ok(Cow::Owned(Value::Number(value))),
+            (None, None) if !state.strict_vars => {
+                state.print_warning(&format!("'{}' isn't defined, using 0", name));
+                Ok(Cow::Owned(Value::Number(0.0)))
+            }
+            (None, None) => Err(EvalError::UnknownIdentifier {
+                name: name.clone(),
+                suggestions: state.suggest_variables(name),
+                span: Some(*span),
+            }),
+        },
+        Expr::History { name, back, span } => previous_value(name, *back, *span, state).map(Cow::Borrowed),
+        Expr::BinaryOp { op, lhs, rhs, op_span, .. } => {
+            let lhs = eval_borrowed(lhs, state)?;
+            let rhs = eval_borrowed(rhs, state)?;
+            // Errors from the operators don't know where they are, so point them at the operator
+            binary_op(*op, lhs, rhs, state)
+                .map(Cow::Owned)
+                .map_err(|err| err.with_span(*op_span))
+        }
+        Expr::UnaryOp { op: UnaryOp::Negate, operand, .. } => Ok(Cow::Owned(match eval_borrowed(operand, state)? {
+            Cow::Owned(value) => -value,
+            Cow::Borrowed(value) => -value,
+        })),
+        Expr::Call { name, args, name_span, .. } => call_function(name, args, *name_span, state).map(Cow::Owned),
+        Expr::Comprehension { body, var, start, end, filter, span } => {
+            eval_comprehension(body, var, (start, end), filter.as_deref(), *span, state)
+                .map(|vec| Cow::Owned(Value::Vector(vec)))
+        }
+        Expr::Let { name, value, body, .. } => eval_let(name, value, body, state).map(Cow::Owned),
+        Expr::Bound { value, .. } => Ok(Cow::Owned(value.clone())),
+    }
+}
+
+/// The value `name` had `back` assignments ago, for `v@n` and `prev(v, n)`
+pub(crate) fn previous_value<'s>(name: &str, back: usize, span: Span, state: &'s CalculatorState) -> Result<&'s Value, EvalError> {
+    if !state.contains_key(name) {
+        return Err(EvalError::UnknownIdentifier {
+            name: name.to_owned(),
+            suggestions: state.suggest_variables(name),
+            span: Some(span),
+        });
+    }
+    state.previous_var(name, back).ok_or_else(|| EvalError::NotInHistory {
+        name: name.to_owned(),
+        back,
+        recorded: state.workspace().history(name).count(),
+        span: Some(span),
+    })
+}
+
+/// The variable a call binds in its first argument, like `x` in `nderiv(x^2, x, 3)`
+pub(crate) fn bound_variable<'e>(function: &str, args: &'e [Expr]) -> Option<&'e str> {
+    match (function, args) {
+        ("nderiv" | "solve", [_, Expr::Ident { name, .. }, _])
+        | ("nintegrate", [_, Expr::Ident { name, .. }, _, _] | [_, Expr::Ident { name, .. }, _, _, _]) => Some(name),
+        _ => None,
+    }
+}
+
+/// The derivative of `body` with respect to `var` where it's `at`, by `central_difference`
+pub(crate) fn derivative(body: &Expr, var: &str, at: f64, state: &CalculatorState) -> Result<f64, EvalError> {
+    central_difference(|x| sample(body, var, x, state), at, at.abs().max(1.0))
+}
+
+/// A root of `body` in `var` near `guess`, by `find_root` with the state's epsilon as the tolerance
+pub(crate) fn root(body: &Expr, var: &str, guess: f64, state: &CalculatorState) -> Result<f64, EvalError> {
+    let max_iterations = state.limits.max_iterations;
+    find_root(|x| sample(body, var, x, state), guess, state.epsilon, max_iterations).map_err(|err| match err {
+        RootError::Function(err) => err,
+        RootError::NoBracket { last } => EvalError::NoRoot {
+            name: "solve".to_owned(),
+            last,
+            span: None,
+        },
+        RootError::OutOfIterations { iterations, last } => EvalError::DidNotConverge {
+            name: "solve".to_owned(),
+            iterations,
+            last,
+            span: None,
+        },
+    })
+}
+
+/// `body` evaluated with `var` set to `x`, which has to give a number
+fn sample(body: &Expr, var: &str, x: f64, state: &CalculatorState) -> Result<f64, EvalError> {
+    f64::try_from(&eval(&body.substitute(var, &Value::Number(x)), state)?).map_err(|err| err.with_span(body.span()))
+}
+
+/// The integral of `body` over `var` from `a` to `b`, by Simpson's rule with `subdivisions`
+/// intervals, or adaptively without. Stops at the first sample that isn't finite
+pub(crate) fn integral(
+    body: &Expr,
+    var: &str,
+    (a, b): (f64, f64),
+    subdivisions: Option<usize>,
+    state: &CalculatorState,
+) -> Result<f64, EvalError> {
+    let value_at = |x: f64| -> Result<f64, EvalError> {
+        let y = sample(body, var, x, state)?;
+        if !y.is_finite() {
+            return Err(EvalError::NonFiniteSample {
+                name: "nintegrate".to_owned(),
+                expr: body.to_string(),
+                var: var.to_owned(),
+                at: x,
+                span: Some(body.span()),
+            });
+        }
+        Ok(y)
+    };
+    simpson(value_at, a, b, subdivisions)
+}
+
+/// Evaluates `body` with `var` set to each of `start`, `start + 1` and so on up to `end`.
+///
+/// The variable is only substituted into the body and filter, so a variable with the same name
+/// is hidden while they're evaluated but left as it was.
+fn eval_comprehension(
+    body: &Expr,
+    var: &str,
+    (start, end): (&Expr, &Expr),
+    filter: Option<&Expr>,
+    span: Span,
+    state: &CalculatorState,
+) -> Result<Vector, EvalError> {
+    let bound = |expr: &Expr| -> Result<f64, EvalError> {
+        let x = f64::try_from(&*eval_borrowed(expr, state)?).map_err(|err| err.with_span(expr.span()))?;
+        if !x.is_finite() {
+            return Err(EvalError::InvalidRange {
+                msg: format!("'{}' is {}, ranges need finite ends", expr, x),
+                span: Some(expr.span()),
+            });
+        }
+        Ok(x)
+    };
+    let (first, last) = (bound(start)?, bound(end)?);
+    // Converting saturates, so a huge range still fails the check
+    let len = if last < first { 0 } else { ((last - first).floor() + 1.0) as usize };
+    state.limits.check_vector_len(len).map_err(|err| err.with_span(span))?;
+
+    let mut components = Vec::with_capacity(len);
+    for i in 0..len {
+        let x = Value::Number(first + i as f64);
+        if let Some(filter) = filter {
+            let keep = f64::try_from(&eval(&filter.substitute(var, &x), state)?)
+                .map_err(|err| err.with_span(filter.span()))?;
+            if keep == 0.0 || keep.is_nan() {
+                continue;
+            }
+        }
+        let component = f64::try_from(&eval(&body.substitute(var, &x), state)?).map_err(|err| err.with_span(body.span()))?;
+        components.push(component);
+    }
+    Ok(Vector::from(components))
+}
+
+/// Evaluates `body` with `name` set to what `value` gives.
+///
+/// Like a comprehension's variable, the value is substituted into the body rather than stored,
+/// so the state is never changed. A variable with the same name is hidden in the body and is
+/// still there afterwards, even if the body fails.
+fn eval_let(name: &str, value: &Expr, body: &Expr, state: &CalculatorState) -> Result<Value, EvalError> {
+    let value = eval(value, state)?;
+    eval(&body.substitute(name, &value), state)
+}
+
+/// The value with its unit taken off if `.units` is off, since variables can still have units
+/// from before it was turned off
+fn units_if_on<'v>(value: Cow<'v, Value>, state: &CalculatorState) -> Cow<'v, Value> {
+    match value.unit() {
+        Some(_) if !state.units => Cow::Owned(value.without_unit()),
+        _ => value,
+    }
+}
+
+/// 1 for true and 0 for false, like `isvec()` gives
+fn truth(value: bool) -> Value {
+    Value::Number(if value { 1.0 } else { 0.0 })
+}
+
+fn binary_op(op: BinaryOp, lhs: Cow<Value>, rhs: Cow<Value>, state: &CalculatorState) -> Result<Value, EvalError> {
+    match (op, units_if_on(lhs, state), units_if_on(rhs, state)) {
+        (BinaryOp::Add, Cow::Owned(lhs), rhs) => lhs + &*rhs,
+        (BinaryOp::Add, Cow::Borrowed(lhs), Cow::Owned(rhs)) => lhs + rhs,
+        (BinaryOp::Add, Cow::Borrowed(lhs), rhs) => lhs + &*rhs,
+        (BinaryOp::Subtract, Cow::Owned(lhs), rhs) => lhs - &*rhs,
+        (BinaryOp::Subtract, Cow::Borrowed(lhs), Cow::Owned(rhs)) => lhs - rhs,
+        (BinaryOp::Subtract, Cow::Borrowed(lhs), rhs) => lhs - &*rhs,
+        (BinaryOp::Multiply, Cow::Owned(lhs), rhs) => lhs * &*rhs,
+        (BinaryOp::Multiply, Cow::Borrowed(lhs), Cow::Owned(rhs)) => lhs * rhs,
+        (BinaryOp::Multiply, Cow::Borrowed(lhs), rhs) => lhs * &*rhs,
+        (BinaryOp::Divide, lhs, rhs) if !state.strict => lhs.div_ieee(&rhs),
+        (BinaryOp::Divide, Cow::Owned(lhs), rhs) => lhs / &*rhs,
+        (BinaryOp::Divide, Cow::Borrowed(lhs), rhs) => lhs / &*rhs,
+        (BinaryOp::Modulo, lhs, rhs) if !state.strict => lhs.rem_ieee(&rhs),
+        (BinaryOp::Modulo, lhs, rhs) => &*lhs % &*rhs,
+        (BinaryOp::Equal, lhs, rhs) => Ok(truth(parser::values_equal(&lhs, &rhs))),
+        (BinaryOp::NotEqual, lhs, rhs) => Ok(truth(!parser::values_equal(&lhs, &rhs))),
+        (BinaryOp::ApproxEqual, lhs, rhs) => Ok(truth(parser::values_close(&lhs, &rhs, state))),
+        (op, lhs, rhs) => match (op, &*lhs, &*rhs) {
+            (BinaryOp::Dot, Value::Vector(lhs), Value::Vector(rhs)) => lhs.dot(rhs).map(Value::Number),
+            (BinaryOp::Cross, Value::Vector(lhs), Value::Vector(rhs)) => lhs.cross(rhs).map(Value::Vector),
+            (BinaryOp::Power, Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs.powf(*rhs))),
+            (BinaryOp::Power, Value::Quantity(lhs), Value::Number(rhs)) => lhs.powf(*rhs),
+            (BinaryOp::Power, lhs, rhs) if lhs.is_number() && rhs.unit().is_some() => Err(EvalError::UnitMismatch {
+                op: "^",
+                left: "no unit".to_owned(),
+                right: rhs.unit().expect("Checked by the guard").to_string(),
+                span: None,
+            }),
+            (op, lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: op.symbol(),
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        },
+    }
+}
@@ -0,0 +1,377 @@
+//! The functions `call_function` knows, and the help `.builtins` and `.help` show for them.
+//!
+//! A function that isn't in `BUILTINS` can't be called, so each one has to come with its help.
+
+/// What a builtin works on, for grouping them in `.builtins`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Vector,
+    Matrix,
+    Complex,
+    Trigonometry,
+    Calculus,
+    Constructor,
+    Check,
+    Session,
+}
+
+impl Category {
+    /// In the order `.builtins` lists them
+    pub const ALL: [Category; 8] = [
+        Self::Vector,
+        Self::Matrix,
+        Self::Complex,
+        Self::Trigonometry,
+        Self::Calculus,
+        Self::Constructor,
+        Self::Check,
+        Self::Session,
+    ];
+
+    pub fn heading(self) -> &'static str {
+        match self {
+            Self::Vector => "Vectors",
+            Self::Matrix => "Matrices",
+            Self::Complex => "Complex numbers and quaternions",
+            Self::Trigonometry => "Trigonometry",
+            Self::Calculus => "Calculus",
+            Self::Constructor => "Constructors",
+            Self::Check => "Checks",
+            Self::Session => "Session",
+        }
+    }
+}
+
+/// A function and its help
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub category: Category,
+    /// How it's called, with optional arguments in brackets, like `extend(v, n[, fill])`
+    pub signature: &'static str,
+    /// One line, giving the formula if there is one
+    pub description: &'static str,
+    /// Lines to type into a new session, the last one calling the function
+    pub example: &'static [&'static str],
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "mag",
+        category: Category::Vector,
+        signature: "mag(v)",
+        description: "The magnitude, |v|",
+        example: &["mag(<3, 4>)"],
+    },
+    Builtin {
+        name: "normalize",
+        category: Category::Vector,
+        signature: "normalize(v)",
+        description: "The unit vector in the direction of v, v / |v|",
+        example: &["normalize(<3, 4>)"],
+    },
+    Builtin {
+        name: "dot",
+        category: Category::Vector,
+        signature: "dot(a, b)",
+        description: "The dot product, a·b, the same as a dot b",
+        example: &["dot(<1, 2>, <3, 4>)"],
+    },
+    Builtin {
+        name: "cross",
+        category: Category::Vector,
+        signature: "cross(a, b)",
+        description: "The cross product of 3D vectors, a×b, the same as a cross b",
+        example: &["cross(<1, 0, 0>, <0, 1, 0>)"],
+    },
+    Builtin {
+        name: "cossim",
+        category: Category::Vector,
+        signature: "cossim(a, b)",
+        description: "Cosine similarity, a·b / (|a||b|), from -1 to 1",
+        example: &["cossim(<1, 0>, <1, 1>)"],
+    },
+    Builtin {
+        name: "coords",
+        category: Category::Vector,
+        signature: "coords(v, b1, b2[, b3])",
+        description: "The coordinates of a 2D or 3D v in the basis b1, b2 and b3",
+        example: &["coords(<3, 4>, <1, 0>, <1, 1>)"],
+    },
+    Builtin {
+        name: "lerp",
+        category: Category::Vector,
+        signature: "lerp(a, b, t)",
+        description: "Linear interpolation, a + (b - a)t",
+        example: &["lerp(<0, 0>, <10, 20>, 0.25)"],
+    },
+    Builtin {
+        name: "slerp",
+        category: Category::Vector,
+        signature: "slerp(a, b, t)",
+        description: "Interpolation along the arc from a to b, with the magnitude going linearly between theirs",
+        example: &["slerp(<1, 0>, <0, 2>, 0.5)"],
+    },
+    Builtin {
+        name: "work",
+        category: Category::Vector,
+        signature: "work(F, d)",
+        description: "The work done by force F over displacement d, F·d",
+        example: &["work(<3, 4, 0>, <2, 0, 0>)"],
+    },
+    Builtin {
+        name: "torque",
+        category: Category::Vector,
+        signature: "torque(r, F)",
+        description: "The torque from force F at lever arm r, r×F",
+        example: &["torque(<1, 0, 0>, <0, 5, 0>)"],
+    },
+    Builtin {
+        name: "along",
+        category: Category::Vector,
+        signature: "along(v, dir)",
+        description: "The part of v along dir, (v·d̂)d̂ where d̂ is dir normalized",
+        example: &["along(<3, 4>, <2, 0>)"],
+    },
+    Builtin {
+        name: "vmin",
+        category: Category::Vector,
+        signature: "vmin(a, b)",
+        description: "The smaller of each pair of components",
+        example: &["vmin(<1, 5>, <3, 2>)"],
+    },
+    Builtin {
+        name: "vmax",
+        category: Category::Vector,
+        signature: "vmax(a, b)",
+        description: "The larger of each pair of components",
+        example: &["vmax(<1, 5>, <3, 2>)"],
+    },
+    Builtin {
+        name: "vclamp",
+        category: Category::Vector,
+        signature: "vclamp(p, lo, hi)",
+        description: "Each component of p moved between lo's and hi's, into their bounding box",
+        example: &["vclamp(<-1, 5>, <0, 0>, <2, 2>)"],
+    },
+    Builtin {
+        name: "bbox_min",
+        category: Category::Vector,
+        signature: "bbox_min(v, ...)",
+        description: "The smallest of each component across the vectors, a corner of their bounding box",
+        example: &["bbox_min(<1, 5>, <3, 2>, <2, 4>)"],
+    },
+    Builtin {
+        name: "bbox_max",
+        category: Category::Vector,
+        signature: "bbox_max(v, ...)",
+        description: "The largest of each component across the vectors, the other corner of their bounding box",
+        example: &["bbox_max(<1, 5>, <3, 2>, <2, 4>)"],
+    },
+    Builtin {
+        name: "extent",
+        category: Category::Vector,
+        signature: "extent(v, ...)",
+        description: "The size of the vectors' bounding box, bbox_max - bbox_min",
+        example: &["extent(<1, 5>, <3, 2>, <2, 4>)"],
+    },
+    Builtin {
+        name: "dims",
+        category: Category::Vector,
+        signature: "dims(v)",
+        description: "How many components v has, 0 for a number",
+        example: &["dims(<1, 2, 3>)"],
+    },
+    Builtin {
+        name: "extend",
+        category: Category::Vector,
+        signature: "extend(v, n[, fill])",
+        description: "v padded to n components with zeros, or with fill",
+        example: &["extend(<1, 2>, 3)"],
+    },
+    Builtin {
+        name: "truncate",
+        category: Category::Vector,
+        signature: "truncate(v, n)",
+        description: "The first n components of v",
+        example: &["truncate(<1, 2, 3>, 2)"],
+    },
+    Builtin {
+        name: "transpose",
+        category: Category::Matrix,
+        signature: "transpose(m)",
+        description: "m with its rows and columns swapped",
+        example: &["transpose([<1, 2>, <3, 4>])"],
+    },
+    Builtin {
+        name: "identity",
+        category: Category::Matrix,
+        signature: "identity(n)",
+        description: "The n by n identity matrix",
+        example: &["identity(2)"],
+    },
+    Builtin {
+        name: "conj",
+        category: Category::Complex,
+        signature: "conj(z)",
+        description: "The complex conjugate, with the imaginary part negated",
+        example: &["conj(3 + 4i)"],
+    },
+    Builtin {
+        name: "abs",
+        category: Category::Complex,
+        signature: "abs(z)",
+        description: "The magnitude of a complex or real number, |z|",
+        example: &["abs(3 + 4i)"],
+    },
+    Builtin {
+        name: "arg",
+        category: Category::Complex,
+        signature: "arg(z)",
+        description: "The angle of a complex number from the positive real axis, in radians",
+        example: &["arg(2i)"],
+    },
+    Builtin {
+        name: "quat",
+        category: Category::Complex,
+        signature: "quat(axis, angle)",
+        description: "The quaternion rotating by angle radians about axis",
+        example: &["quat(<0, 0, 1>, π / 2)"],
+    },
+    Builtin {
+        name: "quaternion",
+        category: Category::Complex,
+        signature: "quaternion(w, x, y, z)",
+        description: "The quaternion w + xi + yj + zk",
+        example: &["quaternion(1, 0, 0, 0)"],
+    },
+    Builtin {
+        name: "qrotate",
+        category: Category::Complex,
+        signature: "qrotate(q, v)",
+        description: "The 3D vector v rotated by the quaternion q, q v q⁻¹",
+        example: &["qrotate(quat(<0, 0, 1>, π / 2), <1, 0, 0>)"],
+    },
+    Builtin {
+        name: "sin",
+        category: Category::Trigonometry,
+        signature: "sin(x)",
+        description: "The sine of x radians",
+        example: &["sin(pi / 6)"],
+    },
+    Builtin {
+        name: "cos",
+        category: Category::Trigonometry,
+        signature: "cos(x)",
+        description: "The cosine of x radians",
+        example: &["cos(60deg)"],
+    },
+    Builtin {
+        name: "tan",
+        category: Category::Trigonometry,
+        signature: "tan(x)",
+        description: "The tangent of x radians",
+        example: &["tan(pi / 4)"],
+    },
+    Builtin {
+        name: "nderiv",
+        category: Category::Calculus,
+        signature: "nderiv(f, x, at)",
+        description: "The derivative of the expression f in x where x is at, by central differences",
+        example: &["nderiv(x^3, x, 2)"],
+    },
+    Builtin {
+        name: "nintegrate",
+        category: Category::Calculus,
+        signature: "nintegrate(f, x, a, b[, n])",
+        description: "The integral of f in x from a to b by Simpson's rule, adaptive unless n intervals are given",
+        example: &["nintegrate(x^2, x, 0, 3)"],
+    },
+    Builtin {
+        name: "solve",
+        category: Category::Calculus,
+        signature: "solve(f, x, guess)",
+        description: "A value of x near guess where f is 0, by Newton's method",
+        example: &["solve(x^2 - 2, x, 1)"],
+    },
+    Builtin {
+        name: "vec",
+        category: Category::Constructor,
+        signature: "vec(x, ...)",
+        description: "The vector with these components, which unlike <...> can be expressions",
+        example: &["vec(1 + 1, 2 * 3)"],
+    },
+    Builtin {
+        name: "isvec",
+        category: Category::Check,
+        signature: "isvec(x)",
+        description: "1 if x is a vector, 0 otherwise",
+        example: &["isvec(<1, 2>)"],
+    },
+    Builtin {
+        name: "isnum",
+        category: Category::Check,
+        signature: "isnum(x)",
+        description: "1 if x is a real number, 0 otherwise",
+        example: &["isnum(<1, 2>)"],
+    },
+    Builtin {
+        name: "assert",
+        category: Category::Check,
+        signature: "assert(x)",
+        description: "Fails if x is 0, otherwise gives x back. For a comparison the failure shows both sides",
+        example: &["a = <1, 2>", "assert(mag(a) ~= 5 ^ 0.5)"],
+    },
+    Builtin {
+        name: "expect",
+        category: Category::Check,
+        signature: "expect(actual, expected)",
+        description: "Fails unless actual is within the epsilon of expected, otherwise gives actual back",
+        example: &["expect(0.1 + 0.2, 0.3)"],
+    },
+    Builtin {
+        name: "out",
+        category: Category::Session,
+        signature: "out(n)",
+        description: "Result n, or counting back from the latest if n is negative",
+        example: &["<1, 2> * 2", "out(-1)"],
+    },
+    Builtin {
+        name: "prev",
+        category: Category::Session,
+        signature: "prev(v[, n])",
+        description: "The value the variable v had n assignments ago, or 1 if n is left out",
+        example: &["v = <1, 2>", "v = <3, 4>", "prev(v)"],
+    },
+    Builtin {
+        name: "env",
+        category: Category::Session,
+        signature: "env(NAME[, default])",
+        description: "The environment variable NAME read as a number, vector or matrix literal, or default if it isn't set",
+        example: &["env(VECTOR_CALC_EXAMPLE_SCALE, 2)"],
+    },
+    Builtin {
+        name: "plot",
+        category: Category::Session,
+        signature: "plot(v)",
+        description: "Shows a chart of v's components, and gives v back",
+        example: &["plot(<1, 4, 9, 16>)"],
+    },
+    Builtin {
+        name: "draw",
+        category: Category::Session,
+        signature: "draw(v, ...[, size])",
+        description: "Draws up to 9 2D vectors from the origin, and gives how many were drawn",
+        example: &["draw(<1, 2>, <2, -1>)"],
+    },
+];
+
+/// The builtin called `name`, if there is one
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Every builtin's name, in the order they're listed
+pub fn names() -> impl Iterator<Item = &'static str> + Clone {
+    BUILTINS.iter().map(|builtin| builtin.name)
+}
@@ -0,0 +1,33 @@
+//! Copying to the system clipboard for `.copy`, which needs the `clipboard` feature
+
+#[cfg(feature = "clipboard")]
+use std::sync::Mutex;
+
+#[cfg(feature = "clipboard")]
+use once_cell::sync::Lazy;
+
+/// Kept open for the whole session, since on X11 what was copied goes away with it
+#[cfg(feature = "clipboard")]
+static CLIPBOARD: Lazy<Mutex<Option<arboard::Clipboard>>> = Lazy::new(|| Mutex::new(None));
+
+/// Puts `text` on the system clipboard.
+///
+/// Fails with a message for the user if there's no clipboard to use, like over SSH.
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> Result<(), String> {
+    let mut clipboard = CLIPBOARD.lock().unwrap_or_else(|err| err.into_inner());
+    if clipboard.is_none() {
+        let opened = arboard::Clipboard::new().map_err(|err| format!("no clipboard available ({})", err))?;
+        *clipboard = Some(opened);
+    }
+    clipboard
+        .as_mut()
+        .expect("Opened above")
+        .set_text(text)
+        .map_err(|err| format!("couldn't copy to the clipboard ({})", err))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> Result<(), String> {
+    Err("this build doesn't support the clipboard, rebuild with `--features clipboard`".to_owned())
+}
@@ -0,0 +1,111 @@
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use vector_calc::parser;
+
+/// How a command's file argument is completed
+struct PathArgument {
+    /// Which word of the line the path is, counting the command as 0
+    position: usize,
+    /// Files with other extensions aren't offered. Empty allows anything
+    extensions: &'static [&'static str],
+    /// The command adds the extension itself, so it's left off the completion
+    implied_extension: bool,
+}
+
+/// Commands that take a path, and where
+const PATH_ARGUMENTS: &[(&str, PathArgument)] = &[
+    (".save", PathArgument { position: 1, extensions: &["vecalc"], implied_extension: true }),
+    (".load", PathArgument { position: 1, extensions: &["vecalc"], implied_extension: true }),
+    (".import_vec", PathArgument { position: 2, extensions: &["csv", "txt"], implied_extension: false }),
+    (".export_vec", PathArgument { position: 2, extensions: &["csv", "txt"], implied_extension: false }),
+    (".transcript", PathArgument { position: 1, extensions: &[], implied_extension: false }),
+    (".out", PathArgument { position: 1, extensions: &[], implied_extension: false }),
+];
+
+/// Completes file names for the commands that take a path, and command, function and variable
+/// names everywhere else
+pub struct ReplHelper {
+    files: FilenameCompleter,
+    /// The variables to offer, since the completer can't borrow the state. Set before each line
+    pub variables: Vec<String>,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self {
+            files: FilenameCompleter::new(),
+            variables: Vec::new(),
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Highlighter for ReplHelper {}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let Some(argument) = path_argument(&line[..pos]) else {
+            let (start, names) = parser::complete_with_variables(line, pos, self.variables.iter().map(String::as_str));
+            let candidates = names
+                .into_iter()
+                .map(|name| Pair {
+                    display: name.clone(),
+                    replacement: name,
+                })
+                .collect();
+            return Ok((start, candidates));
+        };
+        let (start, candidates) = self.files.complete_path(line, pos)?;
+        let candidates = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                // Directories end with a separator, and are always offered so they can be completed into
+                if candidate.replacement.ends_with(std::path::MAIN_SEPARATOR) || argument.extensions.is_empty() {
+                    return Some(candidate);
+                }
+                let (stem, extension) = candidate.replacement.rsplit_once('.')?;
+                if !argument.extensions.contains(&extension) {
+                    return None;
+                }
+                if !argument.implied_extension {
+                    return Some(candidate);
+                }
+                let display = candidate.display.rsplit_once('.').map_or(stem, |(stem, _)| stem);
+                Some(Pair {
+                    display: display.to_owned(),
+                    replacement: stem.to_owned(),
+                })
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+/// The path argument the end of `before_cursor` is in, if it's in one
+fn path_argument(before_cursor: &str) -> Option<&'static PathArgument> {
+    let mut words = before_cursor.split_whitespace();
+    let command = words.next()?;
+    let (_, argument) = PATH_ARGUMENTS.iter().find(|(name, _)| *name == command)?;
+
+    // A word is only being typed if the cursor is right after it
+    let finished_words = words.count() + 1;
+    let current = if before_cursor.ends_with(char::is_whitespace) {
+        finished_words
+    } else {
+        finished_words - 1
+    };
+    (current == argument.position).then_some(argument)
+}
@@ -0,0 +1,255 @@
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use rustyline::{CompletionType, EditMode};
+use vector_calc::helper::{Limits, NumberLocale};
+
+const HISTORY_FILE_NAME: &str = "history.txt";
+const CONFIG_FILE_NAME: &str = "config";
+const DEFAULT_HISTORY_SIZE: usize = 5000;
+const DEBUG_ENV_VAR: &str = "VECALC_DEBUG";
+
+/// Where (and whether) the REPL history gets persisted
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistorySetting {
+    /// Use `history.txt` in the platform data dir
+    Default,
+    Path(PathBuf),
+    Disabled,
+}
+
+/// Startup options, built from the config file and then the command line,
+/// so flags always win over the config file.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub history: HistorySetting,
+    /// Maximum number of history entries kept
+    pub history_size: usize,
+    /// Start with informational and debug output suppressed
+    pub quiet: bool,
+    /// Start at debug level 3
+    pub verbose: bool,
+    /// Initial debug level from `VECALC_DEBUG`, overridden by `verbose`
+    pub debug_level: Option<u32>,
+    /// File to run instead of reading from stdin
+    pub script: Option<PathBuf>,
+    /// When running a script or piped input, stop at the first line that fails
+    pub fail_fast: bool,
+    /// Report each line as a JSON object on stdout
+    pub json: bool,
+    /// Serve connections on this address or Unix socket path instead of reading lines
+    pub serve: Option<String>,
+    /// Let server connections use the commands that read and write files
+    pub serve_allow_fs: bool,
+    /// Starting limits, which server connections can only lower
+    pub limits: Limits,
+    /// Emacs or vi key bindings for the line editor
+    pub edit_mode: EditMode,
+    /// Whether completions are listed, or cycled through with each tab
+    pub completion_type: CompletionType,
+    /// Let the terminal mark pasted text, so pasting several lines doesn't run each one
+    pub bracketed_paste: bool,
+    /// How `.import_vec` reads numbers to start with
+    pub locale: NumberLocale,
+    /// Variables to set before anything runs, each `NAME=VALUE`, in order
+    pub settings: Vec<String>,
+    /// Print the usage or version instead of starting
+    pub show_help: bool,
+    pub show_version: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            history: HistorySetting::Default,
+            history_size: DEFAULT_HISTORY_SIZE,
+            quiet: false,
+            verbose: false,
+            debug_level: None,
+            script: None,
+            fail_fast: false,
+            json: false,
+            serve: None,
+            serve_allow_fs: false,
+            limits: Limits::default(),
+            edit_mode: EditMode::Emacs,
+            completion_type: CompletionType::Circular,
+            bracketed_paste: true,
+            locale: NumberLocale::Point,
+            settings: Vec::new(),
+            show_help: false,
+            show_version: false,
+        }
+    }
+}
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "vecalc")
+}
+
+impl Options {
+    /// Reads the config file (if there is one) and the environment, and then applies the command
+    /// line arguments on top.
+    pub fn load<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        if let Some(dirs) = project_dirs() {
+            let config_path = dirs.config_dir().join(CONFIG_FILE_NAME);
+            if let Ok(contents) = std::fs::read_to_string(&config_path) {
+                options
+                    .apply_config(&contents)
+                    .map_err(|err| format!("{}: {}", config_path.display(), err))?;
+            }
+        }
+
+        if let Ok(level) = std::env::var(DEBUG_ENV_VAR) {
+            options.debug_level = Some(
+                level
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{} should be a debug level, not '{}'", DEBUG_ENV_VAR, level))?,
+            );
+        }
+
+        options.apply_args(args)?;
+        Ok(options)
+    }
+
+    /// Config file format is one `key = value` per line, with `#` comments.
+    pub fn apply_config(&mut self, contents: &str) -> Result<(), String> {
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", line_num + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "history" => {
+                    self.history = match value {
+                        "off" | "false" => HistorySetting::Disabled,
+                        "default" | "on" | "true" => HistorySetting::Default,
+                        path => HistorySetting::Path(PathBuf::from(path)),
+                    }
+                }
+                "history_size" => {
+                    self.history_size = parse_history_size(value)
+                        .map_err(|err| format!("line {}: {}", line_num + 1, err))?
+                }
+                "edit_mode" => {
+                    self.edit_mode = match value {
+                        "emacs" => EditMode::Emacs,
+                        "vi" => EditMode::Vi,
+                        _ => return Err(format!("line {}: edit_mode should be emacs or vi", line_num + 1)),
+                    }
+                }
+                "completion" => {
+                    self.completion_type = match value {
+                        "circular" => CompletionType::Circular,
+                        "list" => CompletionType::List,
+                        _ => return Err(format!("line {}: completion should be circular or list", line_num + 1)),
+                    }
+                }
+                "bracketed_paste" => {
+                    self.bracketed_paste = match value {
+                        "on" | "true" => true,
+                        "off" | "false" => false,
+                        _ => return Err(format!("line {}: bracketed_paste should be on or off", line_num + 1)),
+                    }
+                }
+                "locale" => {
+                    self.locale = match value {
+                        "point" => NumberLocale::Point,
+                        "comma" => NumberLocale::Comma,
+                        _ => return Err(format!("line {}: locale should be point or comma", line_num + 1)),
+                    }
+                }
+                _ => return Err(format!("line {}: unknown key '{}'", line_num + 1, key)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_args<I: Iterator<Item = String>>(&mut self, mut args: I) -> Result<(), String> {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--history" => {
+                    let path = args.next().ok_or("--history expects a path")?;
+                    self.history = HistorySetting::Path(PathBuf::from(path));
+                }
+                "--no-history" => self.history = HistorySetting::Disabled,
+                "--history-size" => {
+                    let size = args.next().ok_or("--history-size expects a number")?;
+                    self.history_size = parse_history_size(&size)?;
+                }
+                "--quiet" | "-q" => self.quiet = true,
+                "--verbose" | "-v" => self.verbose = true,
+                "--fail-fast" => self.fail_fast = true,
+                "--json" => self.json = true,
+                "--serve" => self.serve = Some(args.next().ok_or("--serve expects an address or socket path")?),
+                "--serve-allow-fs" => self.serve_allow_fs = true,
+                "--limit" => {
+                    let setting = args.next().ok_or("--limit expects NAME=VALUE")?;
+                    self.set_limit(&setting)?;
+                }
+                "--set" => self.settings.push(args.next().ok_or("--set expects NAME=VALUE")?),
+                "--vi" => self.edit_mode = EditMode::Vi,
+                "--emacs" => self.edit_mode = EditMode::Emacs,
+                "--help" | "-h" => self.show_help = true,
+                "--version" | "-V" => self.show_version = true,
+                _ if arg.starts_with('-') => return Err(format!("Unknown argument '{}'", arg)),
+                _ if self.script.is_some() => return Err(format!("Only one script can be run, got '{}' too", arg)),
+                _ => self.script = Some(PathBuf::from(arg)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a `NAME=VALUE` limit setting
+    fn set_limit(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("--limit expects NAME=VALUE, got '{}'", setting))?;
+        let value = value
+            .replace('_', "")
+            .parse()
+            .map_err(|_| format!("invalid value '{}' for limit {}", value, name))?;
+        self.limits.set(name, value).map_err(|err| err.message())
+    }
+
+    /// Resolves the history file, creating its parent directory if needed.
+    ///
+    /// Returns `None` when history persistence is disabled or there's no usable data dir.
+    pub fn history_path(&self) -> Option<PathBuf> {
+        let path = match &self.history {
+            HistorySetting::Disabled => return None,
+            HistorySetting::Path(path) => path.clone(),
+            HistorySetting::Default => project_dirs()?.data_dir().join(HISTORY_FILE_NAME),
+        };
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            create_dir(parent)?;
+        }
+        Some(path)
+    }
+}
+
+fn parse_history_size(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid history size '{}'", value))
+}
+
+fn create_dir(dir: &Path) -> Option<()> {
+    match std::fs::create_dir_all(dir) {
+        Ok(()) => Some(()),
+        Err(err) => {
+            eprintln!("Couldn't create {}: {}", dir.display(), err);
+            None
+        }
+    }
+}
@@ -0,0 +1,840 @@
+use std::num::ParseFloatError;
+
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pair;
+use thiserror::Error;
+
+use crate::helper::TypeConstraint;
+use crate::parser::{Rule, COMMANDS};
+
+/// A byte range `(start, end)` of the input line
+pub type Span = (usize, usize);
+
+/// The input is malformed, so it was never evaluated
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("Tokenization error: {}", pest_message(.0))]
+    PestError(Box<pest::error::Error<Rule>>),
+    #[error("Float parsing error: {0}")]
+    ValueParseError(#[from] ParseFloatError),
+    /// A matrix literal with a row that's a different length from the first
+    #[error("Row {row} of the matrix has {found} components, but the first row has {expected}")]
+    RaggedMatrix {
+        row: usize,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// A hex or binary literal that has bad digits, or is too big to store exactly
+    #[error("Invalid number '{literal}': {reason}")]
+    InvalidInteger {
+        literal: String,
+        reason: String,
+        span: Span,
+    },
+    /// A `<` that's never closed, which pest would otherwise report as wherever it gave up
+    #[error("Unterminated vector literal starting at column {column}")]
+    UnterminatedVector { column: usize, span: Span },
+    /// The grammar matched a rule where the code handling it didn't expect one, which means
+    /// `calc.pest` and the code have drifted apart
+    #[error("Unexpected {rule} in the parse tree")]
+    UnexpectedRule {
+        rule: String,
+        expected: &'static str,
+        span: Span,
+    },
+    /// Something before an `=` that isn't a variable or one of its components, like `<1, 2> = 3`
+    #[error("Can't assign to '{target}'")]
+    InvalidAssignmentTarget { target: String, span: Span },
+    /// A `.` followed by something that isn't a parser command
+    #[error("Unknown command '.{name}'")]
+    UnknownCommand {
+        name: String,
+        suggestions: Vec<String>,
+        span: Span,
+    },
+}
+
+impl From<pest::error::Error<Rule>> for ParseError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        Self::PestError(Box::new(err))
+    }
+}
+
+impl ParseError {
+    /// The variant's name, which unlike `kind` is different for every variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PestError(_) => "PestError",
+            Self::ValueParseError(_) => "ValueParseError",
+            Self::RaggedMatrix { .. } => "RaggedMatrix",
+            Self::InvalidInteger { .. } => "InvalidInteger",
+            Self::UnknownCommand { .. } => "UnknownCommand",
+            Self::UnterminatedVector { .. } => "UnterminatedVector",
+            Self::InvalidAssignmentTarget { .. } => "InvalidAssignmentTarget",
+            Self::UnexpectedRule { .. } => "UnexpectedRule",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PestError(_) => "Syntax error",
+            Self::ValueParseError(_) => "Number error",
+            Self::RaggedMatrix { .. } => "Matrix error",
+            Self::InvalidInteger { .. } => "Number error",
+            Self::UnknownCommand { .. } => "Unknown command",
+            Self::UnterminatedVector { .. } | Self::InvalidAssignmentTarget { .. } => "Syntax error",
+            Self::UnexpectedRule { .. } => "Internal error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::PestError(err) => pest_message(err),
+            Self::ValueParseError(err) => err.to_string(),
+            Self::RaggedMatrix { row, expected, found, .. } => format!(
+                "row {} has {} components, but the first row has {}",
+                row, found, expected
+            ),
+            Self::InvalidInteger { literal, reason, .. } => format!("'{}' {}", literal, reason),
+            Self::UnterminatedVector { column, .. } => {
+                format!("unterminated vector literal starting at column {}, it needs a '>'", column)
+            }
+            Self::UnexpectedRule { rule, expected, .. } => format!(
+                "the parser gave {} where {} was expected. This is a bug, please report it with the line that caused it",
+                rule, expected
+            ),
+            Self::InvalidAssignmentTarget { target, .. } => format!(
+                "left-hand side of assignment must be a variable or component, like `v`, `v[0]` or `v.x`, not '{}'",
+                target
+            ),
+            Self::UnknownCommand { name, suggestions, .. } => {
+                let mut commands: Vec<String> = COMMANDS.iter().map(|command| format!(".{}", command)).collect();
+                commands.sort();
+                let msg = with_suggestions(format!("'.{}' is not a command", name), suggestions, ".");
+                let separator = if suggestions.is_empty() { "." } else { "" };
+                format!("{}{} The commands are {}", msg, separator, commands.join(", "))
+            }
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::PestError(err) => Some(match err.location {
+                InputLocation::Pos(pos) => (pos, pos),
+                InputLocation::Span(span) => span,
+            }),
+            Self::ValueParseError(_) => None,
+            Self::RaggedMatrix { span, .. }
+            | Self::InvalidInteger { span, .. }
+            | Self::UnknownCommand { span, .. }
+            | Self::UnterminatedVector { span, .. }
+            | Self::InvalidAssignmentTarget { span, .. }
+            | Self::UnexpectedRule { span, .. } => Some(*span),
+        }
+    }
+}
+
+/// The input is well formed, but evaluating it failed.
+///
+/// Every variant has a `span` of the input it refers to. Errors from the `Value` and `Vector`
+/// operators don't know where they came from, so theirs is `None` until a caller that does
+/// fills it in with [`EvalError::with_span`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// An operator was used on a combination of types it doesn't support, like `vector * vector`
+    #[error("Can't use {op} on a {left} and a {right}")]
+    TypeMismatch {
+        op: &'static str,
+        left: &'static str,
+        right: &'static str,
+        span: Option<Span>,
+    },
+    #[error("Expected a {expected}, found a {found}")]
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+        span: Option<Span>,
+    },
+    /// A function argument of the wrong type, where the function says what the argument is for,
+    /// like the force in `work()`
+    #[error("{role} must be a {expected}, got a {found}")]
+    WrongArgumentType {
+        name: String,
+        role: &'static str,
+        expected: &'static str,
+        found: &'static str,
+        span: Option<Span>,
+    },
+    /// Vectors that need as many components as each other, like the two sides of `+`
+    #[error("{op}: left operand has {left} components, right has {right}")]
+    DimensionMismatch {
+        op: &'static str,
+        left: usize,
+        right: usize,
+        span: Option<Span>,
+    },
+    /// An argument to a function taking any number of vectors, counting from 1, that doesn't
+    /// have as many components as the first
+    #[error("Argument {argument} of {name}() has {found} components, but the first has {expected}")]
+    ArgumentDimensionMismatch {
+        name: String,
+        argument: usize,
+        expected: usize,
+        found: usize,
+        span: Option<Span>,
+    },
+    /// Matrix shapes that don't work together, as `(rows, columns)` with vectors as columns
+    #[error("Can't use {op} on a {}x{} and a {}x{}", left.0, left.1, right.0, right.1)]
+    ShapeMismatch {
+        op: &'static str,
+        left: (usize, usize),
+        right: (usize, usize),
+        span: Option<Span>,
+    },
+    /// An operation that only works in a fixed number of dimensions, like the cross product
+    #[error("{op} needs {expected} dimensional vectors, found {found}")]
+    UnsupportedDimensions {
+        op: &'static str,
+        expected: usize,
+        found: usize,
+        span: Option<Span>,
+    },
+    /// Numbers with units that don't go together, like `5 N + 2 m`. Each side is written like
+    /// `N·m`, or `no unit` for a plain number
+    #[error("Can't use {op} on {left} and {right}")]
+    UnitMismatch {
+        op: &'static str,
+        left: String,
+        right: String,
+        span: Option<Span>,
+    },
+    /// A unit raised to a power that would leave it with a fractional exponent, like `m^0.5`
+    #[error("{unit} can't be raised to the power {exponent}")]
+    FractionalUnitPower {
+        unit: String,
+        exponent: f64,
+        span: Option<Span>,
+    },
+    #[error("Division by zero")]
+    DivisionByZero { span: Option<Span> },
+    #[error("Unknown identifier '{name}'")]
+    UnknownIdentifier {
+        name: String,
+        /// Similar names that are defined, see [`closest_matches`](crate::helper::closest_matches)
+        suggestions: Vec<String>,
+        span: Option<Span>,
+    },
+    #[error("Index {index} is out of range for a vector with {len} components")]
+    IndexOutOfRange {
+        index: usize,
+        len: usize,
+        span: Option<Span>,
+    },
+    #[error("Unknown function '{name}'")]
+    UnknownFunction {
+        name: String,
+        suggestions: Vec<String>,
+        span: Option<Span>,
+    },
+    #[error("Invalid arguments to {name}(): {msg}")]
+    InvalidArguments {
+        name: String,
+        msg: &'static str,
+        span: Option<Span>,
+    },
+    /// Basis vectors that are linearly dependent, or close enough that solving with them is unreliable
+    #[error("The basis is singular")]
+    SingularBasis { span: Option<Span> },
+    #[error("No stored result number {index}")]
+    UnknownResult { index: i64, span: Option<Span> },
+    /// `env()` couldn't give the environment variable's value, and `msg` says why
+    #[error("Environment variable '{name}' {msg}")]
+    Environment {
+        name: String,
+        msg: String,
+        span: Option<Span>,
+    },
+    /// An expression produced infinity or NaN, and `variable` is where it would have been stored
+    #[error("'{expr}' isn't finite")]
+    NonFinite {
+        expr: String,
+        variable: Option<String>,
+        span: Option<Span>,
+    },
+    /// `nintegrate()` sampled its expression somewhere it isn't finite
+    #[error("'{expr}' isn't finite where {var} is {at}")]
+    NonFiniteSample {
+        name: String,
+        expr: String,
+        var: String,
+        at: f64,
+        span: Option<Span>,
+    },
+    /// `solve()` couldn't get any closer and there was no sign change to narrow down, so more
+    /// iterations wouldn't help. `last` is where it got to
+    #[error("{name}() found no root near the guess")]
+    NoRoot { name: String, last: f64, span: Option<Span> },
+    /// `solve()` ran out of iterations, and `last` is where it got to
+    #[error("{name}() didn't converge after {iterations} iterations")]
+    DidNotConverge {
+        name: String,
+        iterations: usize,
+        last: f64,
+        span: Option<Span>,
+    },
+    /// From `assert()` or `expect()`, with what was checked and what was wrong
+    #[error("Assertion failed: {msg}")]
+    AssertionFailed { msg: String, span: Option<Span> },
+    /// A comprehension's range has an end that isn't finite
+    #[error("Invalid range: {msg}")]
+    InvalidRange { msg: String, span: Option<Span> },
+    /// `v@n` or `prev(v, n)` went back further than the variable's history
+    #[error("'{name}' doesn't have a value from {back} assignments ago")]
+    NotInHistory {
+        name: String,
+        back: usize,
+        /// How many earlier values there are
+        recorded: usize,
+        span: Option<Span>,
+    },
+    /// Something needed more than one of the state's `Limits` allows
+    #[error("Limit exceeded: {limit} is {max}, but this needs {attempted}")]
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+        attempted: usize,
+        span: Option<Span>,
+    },
+}
+
+impl EvalError {
+    /// The variant's name, which unlike `kind` is different for every variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TypeMismatch { .. } => "TypeMismatch",
+            Self::WrongType { .. } => "WrongType",
+            Self::WrongArgumentType { .. } => "WrongArgumentType",
+            Self::DimensionMismatch { .. } => "DimensionMismatch",
+            Self::ArgumentDimensionMismatch { .. } => "ArgumentDimensionMismatch",
+            Self::ShapeMismatch { .. } => "ShapeMismatch",
+            Self::UnitMismatch { .. } => "UnitMismatch",
+            Self::FractionalUnitPower { .. } => "FractionalUnitPower",
+            Self::UnsupportedDimensions { .. } => "UnsupportedDimensions",
+            Self::DivisionByZero { .. } => "DivisionByZero",
+            Self::UnknownIdentifier { .. } => "UnknownIdentifier",
+            Self::IndexOutOfRange { .. } => "IndexOutOfRange",
+            Self::UnknownFunction { .. } => "UnknownFunction",
+            Self::InvalidArguments { .. } => "InvalidArguments",
+            Self::SingularBasis { .. } => "SingularBasis",
+            Self::UnknownResult { .. } => "UnknownResult",
+            Self::Environment { .. } => "Environment",
+            Self::NonFinite { .. } => "NonFinite",
+            Self::NonFiniteSample { .. } => "NonFiniteSample",
+            Self::NoRoot { .. } => "NoRoot",
+            Self::DidNotConverge { .. } => "DidNotConverge",
+            Self::AssertionFailed { .. } => "AssertionFailed",
+            Self::InvalidRange { .. } => "InvalidRange",
+            Self::LimitExceeded { .. } => "LimitExceeded",
+            Self::NotInHistory { .. } => "NotInHistory",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::TypeMismatch { .. } | Self::WrongType { .. } | Self::WrongArgumentType { .. } => "Type error",
+            Self::DimensionMismatch { .. }
+            | Self::ArgumentDimensionMismatch { .. }
+            | Self::UnsupportedDimensions { .. } => "Dimension mismatch",
+            Self::ShapeMismatch { .. } => "Shape mismatch",
+            Self::UnitMismatch { .. } | Self::FractionalUnitPower { .. } => "Unit mismatch",
+            Self::DivisionByZero { .. } => "Division by zero",
+            Self::UnknownIdentifier { .. } => "Unknown identifier",
+            Self::IndexOutOfRange { .. } => "Index out of range",
+            Self::UnknownFunction { .. } => "Unknown function",
+            Self::InvalidArguments { .. } => "Invalid arguments",
+            Self::SingularBasis { .. } => "Singular basis",
+            Self::UnknownResult { .. } => "Unknown result",
+            Self::Environment { .. } => "Environment error",
+            Self::NonFinite { .. } | Self::NonFiniteSample { .. } => "Non-finite value",
+            Self::NoRoot { .. } => "No root found",
+            Self::DidNotConverge { .. } => "Did not converge",
+            Self::AssertionFailed { .. } => "Assertion failed",
+            Self::InvalidRange { .. } => "Invalid range",
+            Self::LimitExceeded { .. } => "Limit exceeded",
+            Self::NotInHistory { .. } => "Not in history",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::TypeMismatch { op, left, right, .. } => format!("can't use {} on a {} and a {}", op, left, right),
+            Self::WrongType { expected, found, .. } => format!("expected a {}, found a {}", expected, found),
+            Self::WrongArgumentType { name, role, expected, found, .. } => {
+                format!("{}(): the {} must be a {}, got a {}", name, role, expected, found)
+            }
+            Self::DimensionMismatch { op, left, right, .. } => {
+                format!("{}: left operand has {} components, right has {}", op, left, right)
+            }
+            Self::ArgumentDimensionMismatch { name, argument, expected, found, .. } => format!(
+                "argument {} of {}() has {} components, but the first has {}",
+                argument, name, found, expected
+            ),
+            Self::ShapeMismatch { op, left, right, .. } => format!(
+                "can't use {} on a {}x{} and a {}x{}",
+                op, left.0, left.1, right.0, right.1
+            ),
+            Self::UnitMismatch { op, left, right, .. } => format!("can't use {} on {} and {}", op, left, right),
+            Self::FractionalUnitPower { unit, exponent, .. } => {
+                format!("{} to the power {} would have a fractional unit", unit, exponent)
+            }
+            Self::UnsupportedDimensions { op, expected, found, .. } => {
+                format!("{} needs {} dimensional vectors, found {}", op, expected, found)
+            }
+            Self::DivisionByZero { .. } => "can't divide by zero, use `.strict off` to get inf or NaN".to_owned(),
+            Self::UnknownIdentifier { name, suggestions, .. } => {
+                with_suggestions(format!("'{}' is not defined", name), suggestions, "")
+            }
+            Self::IndexOutOfRange { index, len, .. } => {
+                format!("index {} is past the end of a vector with {} components", index, len)
+            }
+            Self::UnknownFunction { name, suggestions, .. } => {
+                with_suggestions(format!("'{}' is not a function", name), suggestions, "")
+            }
+            Self::InvalidArguments { name, msg, .. } => format!("{}(): {}", name, msg),
+            Self::SingularBasis { .. } => {
+                "the basis vectors are linearly dependent, or too close to it to solve with".to_owned()
+            }
+            Self::UnknownResult { index: -1, .. } => "there's no result yet".to_owned(),
+            Self::UnknownResult { index, .. } => format!("result {} isn't stored", index),
+            Self::Environment { name, msg, .. } => format!("environment variable '{}' {}", name, msg),
+            Self::NonFinite { expr, variable: Some(variable), .. } => format!(
+                "'{}' isn't finite, so it wasn't assigned to '{}'. Use `.strict off` to allow it",
+                expr, variable
+            ),
+            Self::NonFinite { expr, variable: None, .. } => {
+                format!("'{}' isn't finite. Use `.strict off` to allow it", expr)
+            }
+            Self::NonFiniteSample { name, expr, var, at, .. } => {
+                format!("'{}' isn't finite where {} is {}, so {}() can't use it", expr, var, at, name)
+            }
+            Self::NoRoot { name, last, .. } => format!(
+                "{}() couldn't get closer than {} and found no sign change nearby. Try another guess",
+                name, last
+            ),
+            Self::DidNotConverge { name, iterations, last, .. } => format!(
+                "{}() didn't converge after {} iterations, the last estimate was {}. Try another guess, or `.limits max_iterations N`",
+                name, iterations, last
+            ),
+            Self::AssertionFailed { msg, .. } | Self::InvalidRange { msg, .. } => msg.clone(),
+            Self::LimitExceeded { limit, max, attempted, .. } => format!(
+                "{} is {}, but this needs {}. Use `.limits {} N` to change it",
+                limit, max, attempted, limit
+            ),
+            Self::NotInHistory { name, recorded: 0, .. } => {
+                format!("'{}' hasn't been reassigned, so it has no earlier values", name)
+            }
+            Self::NotInHistory { name, back, recorded: 1, .. } => {
+                format!("'{}' only has 1 earlier value, so {}@{} goes back too far", name, name, back)
+            }
+            Self::NotInHistory { name, back, recorded, .. } => format!(
+                "'{}' only has {} earlier values, so {}@{} goes back too far",
+                name, recorded, name, back
+            ),
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::TypeMismatch { span, .. }
+            | Self::WrongType { span, .. }
+            | Self::WrongArgumentType { span, .. }
+            | Self::DimensionMismatch { span, .. }
+            | Self::ArgumentDimensionMismatch { span, .. }
+            | Self::ShapeMismatch { span, .. }
+            | Self::UnitMismatch { span, .. }
+            | Self::FractionalUnitPower { span, .. }
+            | Self::UnsupportedDimensions { span, .. }
+            | Self::DivisionByZero { span }
+            | Self::UnknownIdentifier { span, .. }
+            | Self::IndexOutOfRange { span, .. }
+            | Self::UnknownFunction { span, .. }
+            | Self::InvalidArguments { span, .. }
+            | Self::SingularBasis { span }
+            | Self::UnknownResult { span, .. }
+            | Self::Environment { span, .. }
+            | Self::NonFinite { span, .. }
+            | Self::NonFiniteSample { span, .. }
+            | Self::NoRoot { span, .. }
+            | Self::DidNotConverge { span, .. }
+            | Self::AssertionFailed { span, .. }
+            | Self::InvalidRange { span, .. }
+            | Self::LimitExceeded { span, .. }
+            | Self::NotInHistory { span, .. } => *span,
+        }
+    }
+
+    /// Sets the span, unless the error already has a more specific one
+    pub fn with_span(mut self, new_span: Span) -> Self {
+        match &mut self {
+            Self::TypeMismatch { span, .. }
+            | Self::WrongType { span, .. }
+            | Self::WrongArgumentType { span, .. }
+            | Self::DimensionMismatch { span, .. }
+            | Self::ArgumentDimensionMismatch { span, .. }
+            | Self::ShapeMismatch { span, .. }
+            | Self::UnitMismatch { span, .. }
+            | Self::FractionalUnitPower { span, .. }
+            | Self::UnsupportedDimensions { span, .. }
+            | Self::DivisionByZero { span }
+            | Self::UnknownIdentifier { span, .. }
+            | Self::IndexOutOfRange { span, .. }
+            | Self::UnknownFunction { span, .. }
+            | Self::InvalidArguments { span, .. }
+            | Self::SingularBasis { span }
+            | Self::UnknownResult { span, .. }
+            | Self::Environment { span, .. }
+            | Self::NonFinite { span, .. }
+            | Self::NonFiniteSample { span, .. }
+            | Self::NoRoot { span, .. }
+            | Self::DidNotConverge { span, .. }
+            | Self::AssertionFailed { span, .. }
+            | Self::InvalidRange { span, .. }
+            | Self::LimitExceeded { span, .. }
+            | Self::NotInHistory { span, .. } => {
+                span.get_or_insert(new_span);
+            }
+        }
+        self
+    }
+}
+
+/// Changing a variable through [`CalculatorState::change_var`](crate::helper::CalculatorState::change_var) failed
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum StateError {
+    #[error("Variable '{name}' doesn't exist")]
+    NotFound { name: String },
+    /// Changing a variable keeps its type unless forced
+    #[error("Can't change '{name}' from a {expected} to a {got}")]
+    TypeMismatch {
+        name: String,
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// A variable declared with a type, like `let v: vec3 = ...`, only takes values of that type
+    #[error("'{name}' was declared as {declared}, so it can't be set to a {got}")]
+    DeclaredTypeMismatch {
+        name: String,
+        declared: TypeConstraint,
+        got: String,
+    },
+    /// `_` is the latest result, not a variable
+    #[error("'{name}' can't be assigned to")]
+    Reserved { name: String },
+    #[error("Workspace '{name}' doesn't exist")]
+    NoWorkspace { name: String, suggestions: Vec<String> },
+    /// Copying never overwrites a workspace
+    #[error("Workspace '{name}' already exists")]
+    WorkspaceExists { name: String },
+    #[error("There's no limit called '{name}'")]
+    UnknownLimit { name: String, suggestions: Vec<String> },
+    /// Limits can only be lowered when `CalculatorState::raise_limits` is off
+    #[error("Limit '{name}' can't be raised above {max}")]
+    CantRaiseLimit { name: String, max: usize },
+    #[error("Limit '{name}' can't be lowered below {min}")]
+    LimitTooLow { name: String, min: usize },
+}
+
+impl StateError {
+    /// The variant's name, which unlike `kind` is different for every variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "NotFound",
+            Self::TypeMismatch { .. } => "TypeMismatch",
+            Self::DeclaredTypeMismatch { .. } => "DeclaredTypeMismatch",
+            Self::Reserved { .. } => "Reserved",
+            Self::NoWorkspace { .. } => "NoWorkspace",
+            Self::WorkspaceExists { .. } => "WorkspaceExists",
+            Self::UnknownLimit { .. } => "UnknownLimit",
+            Self::CantRaiseLimit { .. } => "CantRaiseLimit",
+            Self::LimitTooLow { .. } => "LimitTooLow",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "Unknown identifier",
+            Self::TypeMismatch { .. } | Self::DeclaredTypeMismatch { .. } => "Type error",
+            Self::Reserved { .. } => "Reserved name",
+            Self::NoWorkspace { .. } | Self::WorkspaceExists { .. } => "Workspace error",
+            Self::UnknownLimit { .. } | Self::CantRaiseLimit { .. } | Self::LimitTooLow { .. } => "Limit error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::NotFound { name } => format!("'{}' is not defined", name),
+            Self::TypeMismatch { name, expected, got } => format!(
+                "'{}' is a {}, so it can't be changed to a {}. Use `.modify! {}` to change its type",
+                name, expected, got, name
+            ),
+            Self::DeclaredTypeMismatch { name, declared, got } => format!(
+                "'{}' was declared as {}, so it can't be set to a {}. Use `let {} = ...` to declare it again",
+                name, declared, got, name
+            ),
+            Self::Reserved { name } => format!("'{}' always holds the latest result, so it can't be assigned to", name),
+            Self::NoWorkspace { name, suggestions } => {
+                with_suggestions(format!("there's no workspace called '{}'", name), suggestions, "")
+            }
+            Self::WorkspaceExists { name } => format!("'{}' already exists, copying won't replace it", name),
+            Self::UnknownLimit { name, suggestions } => {
+                with_suggestions(format!("there's no limit called '{}'", name), suggestions, "")
+            }
+            Self::CantRaiseLimit { name, max } => {
+                format!("{} can only be lowered from {} here, not raised", name, max)
+            }
+            Self::LimitTooLow { name, min } => format!("{} can't be lower than {}", name, min),
+        }
+    }
+}
+
+/// Any error from handling a line of input
+#[derive(Error, Debug)]
+pub enum CalcError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error("File error: {0}")]
+    FileError(#[from] std::io::Error),
+    #[error("Clipboard error: {0}")]
+    ClipboardError(String),
+    #[error("JSON error: {0}")]
+    JsonError(String),
+    /// A command that uses files, with `CalculatorState::filesystem` off
+    #[error("'.{0}' is disabled in server mode")]
+    FilesystemDisabled(String),
+    /// Evaluating the line panicked, with the panic's message. Caught so the session survives it
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<pest::error::Error<Rule>> for CalcError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        Self::Parse(err.into())
+    }
+}
+
+impl From<ParseFloatError> for CalcError {
+    fn from(err: ParseFloatError) -> Self {
+        Self::Parse(err.into())
+    }
+}
+
+impl CalcError {
+    /// Whether the input failed to tokenize, as opposed to failing during evaluation
+    pub fn is_syntax_error(&self) -> bool {
+        matches!(
+            self,
+            Self::Parse(ParseError::PestError(_) | ParseError::UnknownCommand { .. } | ParseError::UnterminatedVector { .. })
+        )
+    }
+
+    /// The name of the variant that went wrong, like `TypeMismatch`, for programs to match on
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Parse(err) => err.name(),
+            Self::Eval(err) => err.name(),
+            Self::State(err) => err.name(),
+            Self::FileError(_) => "FileError",
+            Self::ClipboardError(_) => "ClipboardError",
+            Self::JsonError(_) => "JsonError",
+            Self::FilesystemDisabled(_) => "FilesystemDisabled",
+            Self::Internal(_) => "Internal",
+        }
+    }
+
+    /// Short name for the category of error, used as the heading when rendering it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Parse(err) => err.kind(),
+            Self::Eval(err) => err.kind(),
+            Self::State(err) => err.kind(),
+            Self::FileError(_) => "File error",
+            Self::ClipboardError(_) => "Clipboard error",
+            Self::JsonError(_) => "JSON error",
+            Self::FilesystemDisabled(_) => "Disabled",
+            Self::Internal(_) => "Internal error",
+        }
+    }
+
+    /// The error description without the kind prefix or position info
+    pub fn message(&self) -> String {
+        match self {
+            Self::Parse(err) => err.message(),
+            Self::Eval(err) => err.message(),
+            Self::State(err) => err.message(),
+            Self::FileError(err) => err.to_string(),
+            Self::ClipboardError(msg) | Self::JsonError(msg) => msg.clone(),
+            Self::FilesystemDisabled(command) => format!(
+                "'.{}' is disabled in server mode, since it uses the server's files. Start the server with --serve-allow-fs to allow it",
+                command
+            ),
+            Self::Internal(msg) => format!(
+                "something went wrong inside the calculator ({}). The line was abandoned, but the session is still \
+                 running. This is a bug, please report it with the line that caused it",
+                msg
+            ),
+        }
+    }
+
+    /// Byte range of the input that the error refers to, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Parse(err) => err.span(),
+            Self::Eval(err) => err.span(),
+            Self::State(_)
+            | Self::FileError(_)
+            | Self::ClipboardError(_)
+            | Self::JsonError(_)
+            | Self::FilesystemDisabled(_)
+            | Self::Internal(_) => None,
+        }
+    }
+
+    /// Like the REPL's error display, without the colors: the kind and message, then `input`
+    /// with the part the error refers to underlined
+    pub fn render(&self, input: &str) -> String {
+        let mut text = format!("{}: {}", self.kind(), self.message());
+        if let Some((start, end)) = self.span() {
+            let start = start.min(input.len());
+            let end = end.clamp(start, input.len());
+            let offset = input[..start].chars().count();
+            let width = input[start..end].chars().count().max(1);
+            text += &format!("\n  | {}\n  | {}{}", input, " ".repeat(offset), "^".repeat(width));
+        }
+        text
+    }
+}
+
+/// The span of a pair, for attaching to errors
+pub(crate) fn pair_span(pair: &Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    (span.start(), span.end())
+}
+
+/// The error for a pair the grammar shouldn't have put where it is, instead of panicking
+pub(crate) fn unexpected_rule(pair: &Pair<Rule>, expected: &'static str) -> ParseError {
+    ParseError::UnexpectedRule {
+        rule: format!("{:?}", pair.as_rule()),
+        expected,
+        span: pair_span(pair),
+    }
+}
+
+/// The error for a part the grammar should have put in the pair spanning `span`, but didn't
+pub(crate) fn missing_rule(span: Span, expected: &'static str) -> ParseError {
+    ParseError::UnexpectedRule {
+        rule: "nothing".to_owned(),
+        expected,
+        span,
+    }
+}
+
+/// The next of `pairs`, which are the parts of a pair spanning `span`, or an error instead of
+/// panicking if the grammar didn't give one
+pub(crate) fn next_pair<'i>(
+    pairs: &mut impl Iterator<Item = Pair<'i, Rule>>,
+    span: Span,
+    expected: &'static str,
+) -> Result<Pair<'i, Rule>, ParseError> {
+    pairs.next().ok_or_else(|| missing_rule(span, expected))
+}
+
+fn pest_message(err: &pest::error::Error<Rule>) -> String {
+    match &err.variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => {
+            if !positives.is_empty() {
+                format!("expected {}", rule_list(positives))
+            } else if !negatives.is_empty() {
+                format!("unexpected {}", rule_list(negatives))
+            } else {
+                "unexpected input".to_owned()
+            }
+        }
+        ErrorVariant::CustomError { message } => message.clone(),
+    }
+}
+
+/// Adds "did you mean 'a' or 'b'?" to a message, if there's anything to suggest
+fn with_suggestions(msg: String, suggestions: &[String], prefix: &str) -> String {
+    if suggestions.is_empty() {
+        return msg;
+    }
+    let quoted: Vec<String> = suggestions.iter().map(|name| format!("'{}{}'", prefix, name)).collect();
+    format!("{}, did you mean {}?", msg, or_list(&quoted))
+}
+
+/// Joins rule names as "a, b or c"
+fn rule_list(rules: &[Rule]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for name in rules.iter().map(rule_name) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    or_list(&names)
+}
+
+/// Joins as "a, b or c"
+fn or_list(names: &[String]) -> String {
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// Human readable name for a grammar rule
+fn rule_name(rule: &Rule) -> String {
+    match rule {
+        Rule::EOI => "end of input".to_owned(),
+        Rule::bare_number | Rule::radix_number | Rule::angle | Rule::percent | Rule::imaginary => "number".to_owned(),
+        Rule::ident => "identifier".to_owned(),
+        Rule::parser_command | Rule::unknown_name => "command".to_owned(),
+        Rule::parser_bool => "on/off".to_owned(),
+        Rule::keyword_for => "'for'".to_owned(),
+        Rule::keyword_in => "'in'".to_owned(),
+        Rule::keyword_if => "'if'".to_owned(),
+        Rule::keyword_let => "'let'".to_owned(),
+        Rule::add
+        | Rule::subtract
+        | Rule::unary_operator
+        | Rule::multiply
+        | Rule::divide
+        | Rule::modulo
+        | Rule::power
+        | Rule::dot
+        | Rule::cross
+        | Rule::equal
+        | Rule::not_equal
+        | Rule::approx_equal
+        | Rule::add_assign
+        | Rule::subtract_assign
+        | Rule::multiply_assign
+        | Rule::divide_assign => "operator".to_owned(),
+        _ => {
+            let name = format!("{:?}", rule);
+            if name.starts_with("parser_") {
+                "command".to_owned()
+            } else {
+                name.replace('_', " ")
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! A C API for embedding the calculator, built with `--features ffi`.
+//!
+//! The header is `examples/ffi/vector_calc.h`, generated with
+//! `cbindgen --config cbindgen.toml --output examples/ffi/vector_calc.h`.
+//!
+//! Strings going in are borrowed for the length of the call and must be NUL terminated UTF-8.
+//! Strings coming out are owned by the state: they stay valid until the next `vecalc_eval` or
+//! `vecalc_state_free` on it, and must not be freed by the caller. No function panics across
+//! the boundary, a panic inside becomes `VECALC_PANIC`.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::helper::{CalculatorState, Value};
+use crate::parser;
+
+/// The line evaluated, `vecalc_last_result_string` has its result if it had one
+pub const VECALC_OK: c_int = 0;
+/// The line didn't parse or evaluate, see `vecalc_last_error_string`
+pub const VECALC_ERROR: c_int = 1;
+/// A pointer was null, or the input wasn't UTF-8
+pub const VECALC_INVALID_ARGUMENT: c_int = 2;
+/// Something went wrong inside the calculator. The state can still be freed, but its variables
+/// may not be what they were
+pub const VECALC_PANIC: c_int = 3;
+
+/// A calculator session, only used through pointers from `vecalc_state_new`
+pub struct VecalcState {
+    state: CalculatorState,
+    last_result: Option<CString>,
+    last_error: Option<CString>,
+}
+
+/// A new session with no variables, to be freed with `vecalc_state_free`. Null if creating it failed.
+#[no_mangle]
+pub extern "C" fn vecalc_state_new() -> *mut VecalcState {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(VecalcState {
+            state: CalculatorState::new(),
+            last_result: None,
+            last_error: None,
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Frees a session from `vecalc_state_new`, and the strings it handed out. Null is ignored.
+///
+/// # Safety
+///
+/// `state` must be null or from `vecalc_state_new`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn vecalc_state_free(state: *mut VecalcState) {
+    if !state.is_null() {
+        // Dropping the state can't panic, but a C caller can't unwind either way
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(state))));
+    }
+}
+
+/// Evaluates one line, like typing it into the REPL, and gives one of the `VECALC_` codes.
+///
+/// Clears the last result and error first, so after `VECALC_OK` the result string is null for
+/// lines without a result, like assignments.
+///
+/// # Safety
+///
+/// `state` must be from `vecalc_state_new`, and `input` a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vecalc_eval(state: *mut VecalcState, input: *const c_char) -> c_int {
+    let Some(state) = state.as_mut() else {
+        return VECALC_INVALID_ARGUMENT;
+    };
+    state.last_result = None;
+    state.last_error = None;
+    if input.is_null() {
+        return VECALC_INVALID_ARGUMENT;
+    }
+    let Ok(input) = CStr::from_ptr(input).to_str() else {
+        return VECALC_INVALID_ARGUMENT;
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match parser::eval(input, &mut state.state) {
+        Ok(value) => {
+            state.last_result = value.map(|value| c_string(state.state.format_value(&value)));
+            VECALC_OK
+        }
+        Err(err) => {
+            state.last_error = Some(c_string(format!("{}: {}", err.kind(), err.message())));
+            VECALC_ERROR
+        }
+    }));
+    result.unwrap_or(VECALC_PANIC)
+}
+
+/// The result of the last `vecalc_eval`, formatted like the REPL shows it, or null if it didn't have one
+///
+/// # Safety
+///
+/// `state` must be from `vecalc_state_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vecalc_last_result_string(state: *const VecalcState) -> *const c_char {
+    state
+        .as_ref()
+        .and_then(|state| state.last_result.as_deref())
+        .map_or(ptr::null(), CStr::as_ptr)
+}
+
+/// The error from the last `vecalc_eval`, or null if it didn't fail
+///
+/// # Safety
+///
+/// `state` must be from `vecalc_state_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vecalc_last_error_string(state: *const VecalcState) -> *const c_char {
+    state
+        .as_ref()
+        .and_then(|state| state.last_error.as_deref())
+        .map_or(ptr::null(), CStr::as_ptr)
+}
+
+/// Copies up to `out_len` components of the vector variable `name` into `out_ptr`, and gives
+/// how many components it has. Call it with a null `out_ptr` to just get the length.
+///
+/// Gives -1 if there's no vector with that name, or an argument is invalid.
+///
+/// # Safety
+///
+/// `state` must be from `vecalc_state_new`, `name` a NUL terminated string, and `out_ptr`
+/// null or valid for writing `out_len` doubles.
+#[no_mangle]
+pub unsafe extern "C" fn vecalc_get_vector(
+    state: *const VecalcState,
+    name: *const c_char,
+    out_ptr: *mut f64,
+    out_len: usize,
+) -> isize {
+    let (Some(state), false) = (state.as_ref(), name.is_null()) else {
+        return -1;
+    };
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return -1;
+    };
+    let Some(Value::Vector(vec)) = state.state.get_var(name) else {
+        return -1;
+    };
+    if !out_ptr.is_null() {
+        let len = vec.dims().min(out_len);
+        ptr::copy_nonoverlapping(vec.as_ptr(), out_ptr, len);
+    }
+    vec.dims() as isize
+}
+
+/// Results and errors never contain NUL, but an empty string beats panicking if one does
+fn c_string(text: String) -> CString {
+    CString::new(text).unwrap_or_default()
+}
@@ -1,58 +1,180 @@
-use std::collections::HashMap;
-use std::ops::{self, Add, Sub, Mul, Div};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
+// std's clock panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::SystemTime;
+use std::ops::{self, Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, Sub, SubAssign};
 
-use rustyline::{Helper, validate::Validator, highlight::Highlighter, hint::Hinter, completion::Completer};
-
-use crate::parser::ParseError;
+use crate::output::{NullOutput, Output, Transcript};
+use crate::error::{CalcError, EvalError, StateError};
+use crate::kernels;
 
+/// A list of components.
+///
+/// Reading goes through `Deref` to the inner `Vec<f64>`, but there's deliberately no `DerefMut`:
+/// mutation only happens through `IndexMut` and [`Vector::set`], so a vector can't change its
+/// number of dimensions behind the calculator's back.
+///
+/// Clones share their components, so copying a vector around, like assigning it to another
+/// variable, costs the same however long it is. Changing one copies the components first if they're
+/// shared, and operators taking an owned vector only reuse its buffer when nothing else shares it.
+///
+/// With the `serde` feature it serializes as a plain list of numbers.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Vector(Vec<f32>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Vector(Arc<Vec<f64>>);
 
 impl ops::Deref for Vector {
-    type Target = Vec<f32>;
+    type Target = Vec<f64>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-impl From<Vec<f32>> for Vector {
-    fn from(source: Vec<f32>) -> Self {
+impl ops::Index<usize> for Vector {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl ops::IndexMut<usize> for Vector {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.components_mut()[index]
+    }
+}
+
+impl From<Vec<f64>> for Vector {
+    fn from(source: Vec<f64>) -> Self {
+        Self(Arc::new(source))
+    }
+}
+
+/// Shares the components without copying them
+impl From<Arc<Vec<f64>>> for Vector {
+    fn from(source: Arc<Vec<f64>>) -> Self {
         Self(source)
     }
 }
 
-impl FromIterator<f32> for Vector {
-    fn from_iter<T: IntoIterator<Item = f32>>(iter: T) -> Self {
-        iter.into_iter().collect::<Vec<f32>>().into()
+impl FromIterator<f64> for Vector {
+    fn from_iter<T: IntoIterator<Item = f64>>(iter: T) -> Self {
+        iter.into_iter().collect::<Vec<f64>>().into()
+    }
+}
+
+impl IntoIterator for Vector {
+    type Item = f64;
+    type IntoIter = std::vec::IntoIter<f64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Vector {
+    type Item = &'a f64;
+    type IntoIter = std::slice::Iter<'a, f64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+// Owned left hand sides reuse their buffer for the result, borrowed ones allocate a new one
+
+impl Add<&Vector> for Vector {
+    type Output = Result<Vector, EvalError>;
+
+    fn add(self, rhs: &Vector) -> Self::Output {
+        self.zip_map(rhs, "+", |x, y| x + y)
+    }
+}
+
+impl Add<&Vector> for &Vector {
+    type Output = Result<Vector, EvalError>;
+
+    fn add(self, rhs: &Vector) -> Self::Output {
+        self.zip_with(rhs, "+", |x, y| x + y)
     }
 }
 
 impl Add<Vector> for Vector {
-    type Output = Vector;
+    type Output = Result<Vector, EvalError>;
+
+    fn add(self, rhs: Vector) -> Self::Output {
+        self + &rhs
+    }
+}
+
+impl Add<Vector> for &Vector {
+    type Output = Result<Vector, EvalError>;
 
+    /// Reuses the right hand side's buffer, checking the dimensions first so errors still read left to right
     fn add(self, rhs: Vector) -> Self::Output {
-        self.iter().zip(rhs.iter()).map(|(x, y)| x + y).collect()
+        self.check_dims(&rhs, "+")?;
+        rhs.zip_map(self, "+", |y, x| x + y)
+    }
+}
+
+impl Sub<&Vector> for Vector {
+    type Output = Result<Vector, EvalError>;
+
+    fn sub(self, rhs: &Vector) -> Self::Output {
+        self.zip_map(rhs, "-", |x, y| x - y)
+    }
+}
+
+impl Sub<&Vector> for &Vector {
+    type Output = Result<Vector, EvalError>;
+
+    fn sub(self, rhs: &Vector) -> Self::Output {
+        self.zip_with(rhs, "-", |x, y| x - y)
     }
 }
 
 impl Sub<Vector> for Vector {
-    type Output = Vector;
+    type Output = Result<Vector, EvalError>;
+
+    fn sub(self, rhs: Vector) -> Self::Output {
+        self - &rhs
+    }
+}
+
+impl Sub<Vector> for &Vector {
+    type Output = Result<Vector, EvalError>;
 
+    /// Reuses the right hand side's buffer, like `&Vector + Vector`
     fn sub(self, rhs: Vector) -> Self::Output {
-        self.iter().zip(rhs.iter()).map(|(x, y)| x - y).collect()
+        self.check_dims(&rhs, "-")?;
+        rhs.zip_map(self, "-", |y, x| x - y)
     }
 }
 
-impl Mul<f32> for Vector {
+impl Mul<f64> for Vector {
     type Output = Vector;
 
-    fn mul(self, rhs: f32) -> Self::Output {
-        self.iter().map(|x| x * rhs).collect()
+    fn mul(self, rhs: f64) -> Self::Output {
+        self.map(|x| x * rhs)
     }
 }
 
-impl Mul<Vector> for f32 {
+impl Mul<f64> for &Vector {
+    type Output = Vector;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        self.map_ref(|x| x * rhs)
+    }
+}
+
+impl Mul<Vector> for f64 {
     type Output = Vector;
 
     fn mul(self, rhs: Vector) -> Self::Output {
@@ -60,37 +182,245 @@ impl Mul<Vector> for f32 {
     }
 }
 
-impl Div<f32> for Vector {
+impl Mul<&Vector> for f64 {
+    type Output = Vector;
+
+    fn mul(self, rhs: &Vector) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl Div<f64> for Vector {
+    type Output = Vector;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        self.map(|x| x / rhs)
+    }
+}
+
+impl Div<f64> for &Vector {
     type Output = Vector;
 
-    fn div(self, rhs: f32) -> Self::Output {
-        self.iter().map(|x| x / rhs).collect()
+    fn div(self, rhs: f64) -> Self::Output {
+        self.map_ref(|x| x / rhs)
+    }
+}
+
+// The compound assignment impls work in place. They panic if the dimensions don't match, use
+// `try_add_assign` and `try_sub_assign` to get an error instead
+
+impl AddAssign<&Vector> for Vector {
+    fn add_assign(&mut self, rhs: &Vector) {
+        if let Err(err) = self.try_add_assign(rhs) {
+            panic!("{}", err);
+        }
+    }
+}
+
+impl SubAssign<&Vector> for Vector {
+    fn sub_assign(&mut self, rhs: &Vector) {
+        if let Err(err) = self.try_sub_assign(rhs) {
+            panic!("{}", err);
+        }
+    }
+}
+
+impl MulAssign<f64> for Vector {
+    fn mul_assign(&mut self, rhs: f64) {
+        kernels::map_in_place(self.components_mut(), |x| x * rhs);
+    }
+}
+
+impl DivAssign<f64> for Vector {
+    fn div_assign(&mut self, rhs: f64) {
+        kernels::map_in_place(self.components_mut(), |x| x / rhs);
+    }
+}
+
+impl Neg for Vector {
+    type Output = Vector;
+
+    fn neg(self) -> Self::Output {
+        self.map(|x| -x)
+    }
+}
+
+impl Neg for &Vector {
+    type Output = Vector;
+
+    fn neg(self) -> Self::Output {
+        self.map_ref(|x| -x)
     }
 }
 
 impl Vector {
-    pub fn length(&self) -> f32 {
+    /// Applies `f` to every component, reusing the buffer unless it's shared.
+    ///
+    /// With the `parallel` feature, `f` can be called from several threads at once on long vectors.
+    pub fn map<F: Fn(f64) -> f64 + Sync + Send>(mut self, f: F) -> Vector {
+        match Arc::get_mut(&mut self.0) {
+            Some(components) => {
+                kernels::map_in_place(components, f);
+                self
+            }
+            None => self.map_ref(f),
+        }
+    }
+
+    fn map_ref<F: Fn(f64) -> f64 + Sync + Send>(&self, f: F) -> Vector {
+        kernels::map(self, f).into()
+    }
+
+    /// Combines matching components of two vectors with `f`, reusing `self`'s buffer unless it's shared.
+    ///
+    /// Along with `zip_with` and `zip_assign`, this is where element-wise operations check that the
+    /// dimensions match, naming `op` if they don't. Like `map`, `f` can be called from several
+    /// threads at once.
+    pub fn zip_map<F: Fn(f64, f64) -> f64 + Sync + Send>(
+        mut self,
+        other: &Vector,
+        op: &'static str,
+        f: F,
+    ) -> Result<Vector, EvalError> {
+        self.check_dims(other, op)?;
+        match Arc::get_mut(&mut self.0) {
+            Some(components) => {
+                kernels::zip_in_place(components, other, f);
+                Ok(self)
+            }
+            None => self.zip_with(other, op, f),
+        }
+    }
+
+    /// Like `zip_map`, but leaves `self` alone and allocates a new vector for the result
+    pub fn zip_with<F: Fn(f64, f64) -> f64 + Sync + Send>(
+        &self,
+        other: &Vector,
+        op: &'static str,
+        f: F,
+    ) -> Result<Vector, EvalError> {
+        self.check_dims(other, op)?;
+        Ok(kernels::zip(self, other, f).into())
+    }
+
+    /// In place `self += other`, erroring instead of panicking if the dimensions don't match
+    pub fn try_add_assign(&mut self, other: &Vector) -> Result<(), EvalError> {
+        self.zip_assign(other, "+=", |x, y| x + y)
+    }
+
+    /// In place `self -= other`, erroring instead of panicking if the dimensions don't match
+    pub fn try_sub_assign(&mut self, other: &Vector) -> Result<(), EvalError> {
+        self.zip_assign(other, "-=", |x, y| x - y)
+    }
+
+    fn zip_assign<F: Fn(f64, f64) -> f64 + Sync + Send>(
+        &mut self,
+        other: &Vector,
+        op: &'static str,
+        f: F,
+    ) -> Result<(), EvalError> {
+        self.check_dims(other, op)?;
+        kernels::zip_in_place(self.components_mut(), other, f);
+        Ok(())
+    }
+
+    /// The components for changing in place, copied first if they're shared
+    fn components_mut(&mut self) -> &mut [f64] {
+        Arc::make_mut(&mut self.0).as_mut_slice()
+    }
+
+    /// Takes the components, only copying them if they're shared
+    fn into_vec(self) -> Vec<f64> {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    /// Errors naming `op` unless the vectors have as many components as each other
+    fn check_dims(&self, other: &Vector, op: &'static str) -> Result<(), EvalError> {
+        if self.dims() != other.dims() {
+            return Err(EvalError::DimensionMismatch {
+                op,
+                left: self.dims(),
+                right: other.dims(),
+                span: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether every component is neither infinite nor NaN
+    pub fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+
+    pub fn length(&self) -> f64 {
         self.mag()
     }
 
-    pub fn mag(&self) -> f32 {
-        self.iter().map(|&x| x.powi(2)).sum::<f32>().sqrt()
+    pub fn mag(&self) -> f64 {
+        kernels::dot(self, self).sqrt()
     }
     
     pub fn dims(&self) -> usize {
         self.0.len()
     }
 
-    pub fn dot(&self, rhs: &Vector) -> f32 {
-        self.iter().zip(rhs.iter()).map(|(&x, &y)| x * y).sum()
+    /// The index and value of the smallest component, the first if there's a tie. `None` for
+    /// the empty vector, and NaN components are skipped
+    pub fn min_component(&self) -> Option<(usize, f64)> {
+        self.iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, x)| !x.is_nan())
+            .fold(None, |min, (i, x)| match min {
+                Some((_, min_x)) if min_x <= x => min,
+                _ => Some((i, x)),
+            })
+    }
+
+    /// Like `min_component`, for the largest
+    pub fn max_component(&self) -> Option<(usize, f64)> {
+        self.iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, x)| !x.is_nan())
+            .fold(None, |max, (i, x)| match max {
+                Some((_, max_x)) if max_x >= x => max,
+                _ => Some((i, x)),
+            })
+    }
+
+    /// Gets a component, or `None` if the index is past the end
+    pub fn get(&self, index: usize) -> Option<f64> {
+        self.0.get(index).copied()
     }
 
-    pub fn cross(&self, rhs: &Vector) -> Result<Vector, ParseError> {
-        if self.dims() != 3 && rhs.dims() != 3 {
-            return Err(ParseError::InvalidExpression("Cross product is only between two vectors, both in 3 dimensions"))
+    /// Sets a component, erroring instead of panicking if the index is past the end
+    pub fn set(&mut self, index: usize, value: f64) -> Result<(), EvalError> {
+        let len = self.dims();
+        let component = self
+            .components_mut()
+            .get_mut(index)
+            .ok_or(EvalError::IndexOutOfRange { index, len, span: None })?;
+        *component = value;
+        Ok(())
+    }
+
+    pub fn dot(&self, rhs: &Vector) -> Result<f64, EvalError> {
+        self.check_dims(rhs, "dot")?;
+        Ok(kernels::dot(self, rhs))
+    }
+
+    pub fn cross(&self, rhs: &Vector) -> Result<Vector, EvalError> {
+        if let Some(found) = [self.dims(), rhs.dims()].into_iter().find(|&dims| dims != 3) {
+            return Err(EvalError::UnsupportedDimensions {
+                op: "cross",
+                expected: 3,
+                found,
+                span: None,
+            });
         }
 
-        Ok(Vector(vec![
+        Ok(Vector::from(vec![
             // c_x = a_y * b_z − a_z * b_y
             (self[1] * rhs[2]) - (self[2] * rhs[1]),
             // c_y = a_z * b_x − a_x * b_z
@@ -100,284 +430,2904 @@ impl Vector {
         ]))
     }
 
-    pub fn angle_between(&self, other: &Vector) -> f32 {
-        (self.dot(other) / (self.mag() * other.mag())).acos()
+    pub fn angle_between(&self, other: &Vector) -> Result<f64, EvalError> {
+        self.check_dims(other, "angle")?;
+        Ok((self.dot(other)? / (self.mag() * other.mag())).acos())
+    }
+
+    /// `a·b / (|a||b|)`, clamped to [-1, 1] against rounding.
+    ///
+    /// Takes a single square root of the product of the squared magnitudes, so a nonzero vector's
+    /// similarity with itself is exactly 1. Errors if either vector is zero.
+    pub fn cosine_similarity(&self, other: &Vector) -> Result<f64, EvalError> {
+        self.check_dims(other, "cossim")?;
+        let zero = |msg| EvalError::InvalidArguments { name: "cossim".to_owned(), msg, span: None };
+        // Scaling each to a largest component of 1 doesn't change the result, but keeps the
+        // squares from overflowing
+        let (lhs, rhs) = match (self.max_abs(), other.max_abs()) {
+            (0.0, _) => return Err(zero("the first vector is zero")),
+            (_, 0.0) => return Err(zero("the second vector is zero")),
+            (lhs_scale, rhs_scale) => (self / lhs_scale, other / rhs_scale),
+        };
+        let denominator = (lhs.dot(&lhs)? * rhs.dot(&rhs)?).sqrt();
+        Ok((lhs.dot(&rhs)? / denominator).clamp(-1.0, 1.0))
+    }
+
+    /// The unit vector in the same direction, `v / |v|`. Errors if the vector is zero
+    pub fn normalized(&self) -> Result<Vector, EvalError> {
+        let scale = self.max_abs();
+        if scale == 0.0 {
+            return Err(EvalError::InvalidArguments {
+                name: "normalize".to_owned(),
+                msg: "the vector is zero",
+                span: None,
+            });
+        }
+        // Scaled like `cosine_similarity` so squaring the components can't overflow
+        let scaled = self / scale;
+        Ok(&scaled / scaled.mag())
+    }
+
+    /// The vector with `n` components, cut down or padded at the end with `fill`
+    pub fn resized(&self, n: usize, fill: f64) -> Vector {
+        if n == self.dims() {
+            return self.clone();
+        }
+        self.iter().copied().chain(std::iter::repeat(fill)).take(n).collect()
+    }
+
+    /// The part of the vector along `direction`, `(v·d̂)d̂` where `d̂` is `direction` normalized.
+    /// Errors if the direction is zero
+    pub fn component_along(&self, direction: &Vector) -> Result<Vector, EvalError> {
+        self.check_dims(direction, "along")?;
+        let scale = direction.max_abs();
+        if scale == 0.0 {
+            return Err(EvalError::InvalidArguments {
+                name: "along".to_owned(),
+                msg: "the direction is zero",
+                span: None,
+            });
+        }
+        // Scaled like `cosine_similarity` so squaring the direction can't overflow
+        let direction = direction / scale;
+        Ok(&direction * (self.dot(&direction)? / direction.dot(&direction)?))
+    }
+
+    /// The smaller of each pair of components. A NaN component gives the other one
+    pub fn component_min(&self, other: &Vector) -> Result<Vector, EvalError> {
+        self.zip_with(other, "vmin", f64::min)
+    }
+
+    /// The larger of each pair of components. A NaN component gives the other one
+    pub fn component_max(&self, other: &Vector) -> Result<Vector, EvalError> {
+        self.zip_with(other, "vmax", f64::max)
+    }
+
+    /// Moves each component into the range from `lo` to `hi`, so the point ends up inside their
+    /// bounding box. Where `lo` is above `hi`, `hi` wins
+    pub fn component_clamp(&self, lo: &Vector, hi: &Vector) -> Result<Vector, EvalError> {
+        self.check_dims(lo, "vclamp")?;
+        self.check_dims(hi, "vclamp")?;
+        self.component_max(lo)?.component_min(hi)
+    }
+
+    /// Linear interpolation, `self + (other - self) * t`
+    pub fn lerp(&self, other: &Vector, t: f64) -> Result<Vector, EvalError> {
+        self.zip_with(other, "lerp", |a, b| a + (b - a) * t)
+    }
+
+    /// Interpolates along the arc from `self` to `other`, with the magnitude interpolated linearly
+    /// between theirs. `t` outside [0, 1] carries on around the arc.
+    ///
+    /// Nearly parallel vectors fall back to [`Vector::lerp`], where the arc formula loses precision.
+    /// Errors if either vector is zero, or if they point in opposite directions since there's no
+    /// one arc between them then.
+    pub fn slerp(&self, other: &Vector, t: f64) -> Result<Vector, EvalError> {
+        let invalid = |msg| EvalError::InvalidArguments { name: "slerp".to_owned(), msg, span: None };
+        self.check_dims(other, "slerp")?;
+        if self.dims() < 2 {
+            return Err(invalid("only works for vectors with at least 2 components"));
+        }
+        let (self_mag, other_mag) = (self.mag(), other.mag());
+        if self_mag == 0.0 {
+            return Err(invalid("the first vector is zero"));
+        } else if other_mag == 0.0 {
+            return Err(invalid("the second vector is zero"));
+        }
+
+        let theta = self.cosine_similarity(other)?.acos();
+        if theta < SLERP_LERP_THRESHOLD {
+            return self.lerp(other, t);
+        } else if std::f64::consts::PI - theta < SLERP_LERP_THRESHOLD {
+            return Err(invalid("the vectors point in opposite directions"));
+        }
+        let self_weight = ((1.0 - t) * theta).sin() / theta.sin() / self_mag;
+        let other_weight = (t * theta).sin() / theta.sin() / other_mag;
+        let mag = self_mag + (other_mag - self_mag) * t;
+        self.zip_with(other, "slerp", |a, b| (a * self_weight + b * other_weight) * mag)
+    }
+
+    /// Finds the coefficients `c` with `self = c[0] * basis[0] + c[1] * basis[1] + ...`.
+    ///
+    /// The basis doesn't have to be orthogonal, but has to be 2 or 3 vectors with the same number
+    /// of components as `self`. It's solved with Cramer's rule, and a basis is treated as singular
+    /// if its determinant is within `epsilon` of zero, relative to the basis vectors' magnitudes.
+    pub fn coords_in(&self, basis: &[&Vector], epsilon: f64) -> Result<Vector, EvalError> {
+        let invalid = |msg| EvalError::InvalidArguments { name: "coords".to_owned(), msg, span: None };
+        if !(2..=3).contains(&self.dims()) {
+            return Err(invalid("only works for 2 and 3 dimensional vectors"));
+        } else if basis.len() != self.dims() {
+            return Err(invalid("expected as many basis vectors as the vector has components"));
+        }
+        for vec in basis {
+            self.check_dims(vec, "coords")?;
+        }
+
+        let det = determinant(basis);
+        // The determinant is at most the product of the magnitudes, and that only when they're orthogonal
+        let scale: f64 = basis.iter().map(|vec| vec.mag()).product();
+        if det.is_nan() || det.abs() <= epsilon * scale {
+            return Err(EvalError::SingularBasis { span: None });
+        }
+
+        Ok((0..basis.len())
+            .map(|i| {
+                let mut columns = basis.to_vec();
+                columns[i] = self;
+                determinant(&columns) / det
+            })
+            .collect())
+    }
+
+    fn max_abs(&self) -> f64 {
+        self.iter().fold(0.0, |max: f64, x| max.max(x.abs()))
     }
 }
 
+/// A grid of numbers, stored row by row.
+///
+/// Vectors used with matrices are treated as columns, so `m * v` needs `v` to have as many
+/// components as `m` has columns. Shapes are written rows by columns, like `2x3`.
+///
+/// Clones share their numbers like `Vector`'s do, so reading a big matrix from a variable doesn't
+/// copy it.
+///
+/// With the `serde` feature it serializes as its shape and the numbers row by row.
 #[derive(Debug, PartialEq, Clone)]
-pub enum Value {
-    Number(f32),
-    Vector(Vector),
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Arc<Vec<f64>>,
 }
 
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            // Print using normal rust formatting for floats
-            Value::Number(val) => f.write_fmt(format_args!("{}", val)),
-            // print as <1.0, 2.0>
-            // or as <Empty Vector> if empty
-            Value::Vector(vec) => {
-                let mut iter = vec.iter();
-                let mut next = iter.next();
-                match next {
-                    Some(val) => {
-                        f.write_str("<")?;
-                        f.write_fmt(format_args!("{}", val))?;
-                    },
-                    None => {
-                        return f.write_str("<Empty Vector>");
-                    }
-                }
-                loop {
-                    next = iter.next();
-                    match next {
-                        Some(val) => {
-                            f.write_fmt(format_args!(", {}", val))?;
-                        },
-                        None => {
-                            return f.write_str(">");
-                        }
-                    }
-                }
-            }
+impl Matrix {
+    /// Builds a matrix from its rows, which need at least one and all the same length,
+    /// otherwise gives back the index of the first row with a different length
+    pub fn from_rows(rows: Vec<Vector>) -> Result<Matrix, usize> {
+        let cols = match rows.first() {
+            Some(row) => row.dims(),
+            None => return Err(0),
+        };
+        if let Some(bad_row) = rows.iter().position(|row| row.dims() != cols) {
+            return Err(bad_row);
         }
+        Ok(Matrix {
+            rows: rows.len(),
+            cols,
+            data: Arc::new(rows.into_iter().flat_map(Vector::into_vec).collect()),
+        })
     }
-}
 
-impl From<f32> for Value {
-    fn from(source: f32) -> Self {
-        Self::Number(source)
+    /// The `n` by `n` identity matrix
+    pub fn identity(n: usize) -> Matrix {
+        let mut data = vec![0.0; n * n];
+        for i in 0..n {
+            data[i * n + i] = 1.0;
+        }
+        Matrix { rows: n, cols: n, data: Arc::new(data) }
     }
-}
 
-impl From<Vector> for Value {
-    fn from(source: Vector) -> Self {
-        Self::Vector(source)
+    /// `(rows, columns)`
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<f64> {
+        if row < self.rows && col < self.cols {
+            Some(self.data[row * self.cols + col])
+        } else {
+            None
+        }
     }
-}
 
-impl From<Vec<f32>> for Value {
-    fn from(source: Vec<f32>) -> Self {
-        source.into()
+    pub fn row(&self, row: usize) -> &[f64] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
     }
-}
 
-impl Value {
-    pub fn is_number(&self) -> bool {
-        matches!(self, Value::Number(_))
+    /// Iterates over the numbers row by row
+    pub fn iter(&self) -> std::slice::Iter<'_, f64> {
+        self.data.iter()
     }
 
-    pub fn is_vector(&self) -> bool {
-        matches!(self, Value::Vector(_))
+    pub fn is_finite(&self) -> bool {
+        self.data.iter().all(|x| x.is_finite())
     }
 
-    pub fn compare_types(&self, other: &Value) -> bool {
-        (self.is_number() && other.is_number()) ||
-        (self.is_vector() && other.is_vector())
+    pub fn transpose(&self) -> Matrix {
+        let data = (0..self.cols)
+            .flat_map(|col| (0..self.rows).map(move |row| (row, col)))
+            .map(|(row, col)| self.data[row * self.cols + col])
+            .collect();
+        Matrix { rows: self.cols, cols: self.rows, data: Arc::new(data) }
     }
 
-    /// Panics if the value is not a number
-    pub fn as_number(&self) -> f32 {
-        match self {
-            Self::Number(val) => *val,
-            Self::Vector(_) => panic!("Tried to get a number from a vector value")
+    /// Changes each number with `f`, in place unless the numbers are shared
+    pub fn map<F: FnMut(f64) -> f64>(mut self, mut f: F) -> Matrix {
+        Arc::make_mut(&mut self.data).iter_mut().for_each(|x| *x = f(*x));
+        self
+    }
+
+    /// `self * vec`, with `vec` as a column
+    pub fn mul_vector(&self, vec: &Vector) -> Result<Vector, EvalError> {
+        if vec.dims() != self.cols {
+            return Err(self.shape_mismatch("*", (vec.dims(), 1)));
         }
+        Ok((0..self.rows)
+            .map(|row| self.row(row).iter().zip(vec.iter()).fold(0.0, |sum, (a, b)| sum + a * b))
+            .collect())
     }
 
-    pub fn as_vector(&self) -> Vector {
-        match self {
-            Self::Vector(val) => val.clone(),
-            Self::Number(_) => panic!("Tried to get a vector from a number value")
+    /// The matrix product `self * rhs`
+    pub fn mul_matrix(&self, rhs: &Matrix) -> Result<Matrix, EvalError> {
+        if self.cols != rhs.rows {
+            return Err(self.shape_mismatch("*", rhs.shape()));
         }
+        let data = (0..self.rows)
+            .flat_map(|row| (0..rhs.cols).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                let products = self.row(row).iter().enumerate().map(|(i, x)| x * rhs.data[i * rhs.cols + col]);
+                products.fold(0.0, |sum, x| sum + x)
+            })
+            .collect();
+        Ok(Matrix { rows: self.rows, cols: rhs.cols, data: Arc::new(data) })
     }
-}
 
-impl Add<Value> for Value {
-    type Output = Result<Value, ParseError>;
+    fn zip_with<F: FnMut(f64, f64) -> f64>(&self, rhs: &Matrix, op: &'static str, mut f: F) -> Result<Matrix, EvalError> {
+        if self.shape() != rhs.shape() {
+            return Err(self.shape_mismatch(op, rhs.shape()));
+        }
+        let data = self.data.iter().zip(rhs.data.iter()).map(|(&x, &y)| f(x, y)).collect();
+        Ok(Matrix { data: Arc::new(data), ..*self })
+    }
 
-    fn add(self, rhs: Value) -> Self::Output {
-        if self.compare_types(&rhs) {
-            if self.is_number() {
-                Ok(Value::Number(self.as_number() + rhs.as_number()))
-            } else if self.is_vector() {
-                Ok(Value::Vector(self.as_vector() + rhs.as_vector()))
-            } else {
-                unreachable!("No other types");
-            }
-        } else {
-            Err(ParseError::InvalidExpression("Can't add a scalar and a vector together"))
+    fn shape_mismatch(&self, op: &'static str, right: (usize, usize)) -> EvalError {
+        EvalError::ShapeMismatch {
+            op,
+            left: self.shape(),
+            right,
+            span: None,
         }
     }
 }
 
-impl Sub for Value {
-    type Output = Result<Value, ParseError>;
+impl Add<&Matrix> for &Matrix {
+    type Output = Result<Matrix, EvalError>;
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        if self.compare_types(&rhs) {
-            if self.is_number() {
-                Ok(Value::Number(self.as_number() - rhs.as_number()))
-            } else if self.is_vector() {
-                Ok(Value::Vector(self.as_vector() - rhs.as_vector()))
-            } else {
-                unreachable!("No other types");
-            }
-        } else {
-            Err(ParseError::InvalidExpression("Can't subtract a scalar and a vector"))
-        }
+    fn add(self, rhs: &Matrix) -> Self::Output {
+        self.zip_with(rhs, "+", |x, y| x + y)
     }
 }
 
-impl Mul for Value {
-    type Output = Result<Value, ParseError>;
+impl Sub<&Matrix> for &Matrix {
+    type Output = Result<Matrix, EvalError>;
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        if self.is_vector() && rhs.is_number() {
-            Ok(Value::Vector(self.as_vector() * rhs.as_number()))
-        } else if self.is_number() && rhs.is_vector() {
-            Ok(Value::Vector(self.as_number() * rhs.as_vector()))
-        } else if self.is_number() && rhs.is_number() {
-            Ok(Value::Number(self.as_number() * rhs.as_number()))
-        } else if self.is_vector() && rhs.is_vector() {
-            Err(ParseError::InvalidExpression("Can't multiply two vectors"))
-        } else {
-            unreachable!("Compared all possible types")
-        }
+    fn sub(self, rhs: &Matrix) -> Self::Output {
+        self.zip_with(rhs, "-", |x, y| x - y)
     }
 }
 
-impl Div for Value {
-    type Output = Result<Value, ParseError>;
+impl Mul<f64> for Matrix {
+    type Output = Matrix;
 
-    fn div(self, rhs: Self) -> Self::Output {
-        if self.is_number() && rhs.is_number() {
-            Ok(Value::Number(self.as_number() / rhs.as_number()))
-        } else if self.is_vector() && rhs.is_number() {
-            Ok(Value::Vector(self.as_vector() / rhs.as_number()))
-        } else if self.is_number() && rhs.is_vector() {
-            Err(ParseError::InvalidExpression("Can't divide a scalar by a vector"))
-        } else if self.is_vector() && rhs.is_vector() {
-            Err(ParseError::InvalidExpression("Can't divide a vector by a vector"))
-        } else {
-            unreachable!("Compared all possible types")
-        }
+    fn mul(self, rhs: f64) -> Self::Output {
+        self.map(|x| x * rhs)
     }
 }
 
-pub struct CalculatorState {
-    pub(crate) variables: HashMap<String, Value>,
-    pub debug_level: u32,
+impl Div<f64> for Matrix {
+    type Output = Matrix;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        self.map(|x| x / rhs)
+    }
 }
 
-const DEFAULT_DEBUG_LEVEL: u32 = 1;
+impl Neg for Matrix {
+    type Output = Matrix;
 
-impl Default for CalculatorState {
-    fn default() -> Self {
-        Self {
-            variables: Default::default(),
-            debug_level: DEFAULT_DEBUG_LEVEL,
-        }
+    fn neg(self) -> Self::Output {
+        self.map(|x| -x)
     }
 }
 
-impl CalculatorState {
-    pub fn new() -> Self { 
-        Self {
-            variables: HashMap::new(),
-            debug_level: DEFAULT_DEBUG_LEVEL
-        }
-     }
+/// Prints one row per line like `[1, 2]`, with the columns lined up.
+///
+/// Formatter precision applies to each number, like for vectors.
+impl std::fmt::Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_number = |x: &f64| match f.precision() {
+            Some(precision) => format!("{:.*}", precision, x),
+            None => x.to_string(),
+        };
+        f.write_str(&align_rows(self, format_number))
+    }
+}
 
-    pub fn new_with_variables(variables: HashMap<String, Value>) -> Self {
-        Self {
-            variables,
-            debug_level: DEFAULT_DEBUG_LEVEL
-        }
+/// Lays out a matrix with each number formatted by `format_number`, one row per line with the
+/// columns right aligned
+fn align_rows<F: FnMut(&f64) -> String>(matrix: &Matrix, format_number: F) -> String {
+    // Rows without any columns wouldn't make any chunks below
+    if matrix.cols == 0 {
+        return vec!["[]"; matrix.rows].join("\n");
     }
+    let numbers: Vec<String> = matrix.iter().map(format_number).collect();
+    let mut widths = vec![0; matrix.cols];
+    for (i, number) in numbers.iter().enumerate() {
+        widths[i % matrix.cols] = widths[i % matrix.cols].max(number.chars().count());
+    }
+    let lines: Vec<String> = numbers
+        .chunks(matrix.cols)
+        .map(|row| {
+            let padded: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(number, &width)| format!("{:>width$}", number, width = width))
+                .collect();
+            format!("[{}]", padded.join(", "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// A quaternion `w + xi + yj + zk`, mostly for composing 3D rotations.
+///
+/// As rotations `a * b` is `b` followed by `a`, the same order as matrices applied to vectors.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
 
-    pub fn set_var(&mut self, key: String, value: Value) -> Option<Value> {
-        self.variables.insert(key, value)
-            //.map_or(false, |old_val| old_val != value)
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion { w, x, y, z }
     }
 
-    pub fn change_var(&mut self, key: String, value: Value) -> bool {
-        let old_val = self.get_var(&key);
-        if old_val.is_none() {
-            return false;
+    /// The rotation by `angle` radians around `axis`, which doesn't have to be a unit vector
+    pub fn from_axis_angle(axis: &Vector, angle: f64) -> Result<Quaternion, EvalError> {
+        if axis.dims() != 3 {
+            return Err(EvalError::UnsupportedDimensions {
+                op: "quat",
+                expected: 3,
+                found: axis.dims(),
+                span: None,
+            });
         }
-        let old_val = old_val.unwrap();
-
-        if value.compare_types(old_val) {
-            self.set_var(key, value);
-            true
-        }  else {
-            false
+        let mag = axis.mag();
+        if mag == 0.0 {
+            return Err(EvalError::InvalidArguments {
+                name: "quat".to_owned(),
+                msg: "the axis is zero",
+                span: None,
+            });
         }
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let scale = sin / mag;
+        Ok(Quaternion::new(cos, axis[0] * scale, axis[1] * scale, axis[2] * scale))
     }
 
-    pub fn get_var(&self, key: &str) -> Option<&Value> {
-        self.variables.get(key)
+    pub fn norm(&self) -> f64 {
+        (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
-    pub fn contains_key(&self, key: &str) -> bool {
-        self.variables.contains_key(key)
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
     }
 
-    pub fn print_debug(&self, min_debug_level: u32, string: String) {
-        if self.debug_level >= min_debug_level {
-            CalculatorState::debug_print(min_debug_level, string);
+    pub fn is_finite(&self) -> bool {
+        [self.w, self.x, self.y, self.z].iter().all(|x| x.is_finite())
+    }
+
+    /// Applies the rotation to a 3D vector, normalizing the quaternion first
+    pub fn rotate(&self, vec: &Vector) -> Result<Vector, EvalError> {
+        if vec.dims() != 3 {
+            return Err(EvalError::UnsupportedDimensions {
+                op: "qrotate",
+                expected: 3,
+                found: vec.dims(),
+                span: None,
+            });
         }
+        let norm = self.norm();
+        if norm == 0.0 {
+            return Err(EvalError::InvalidArguments {
+                name: "qrotate".to_owned(),
+                msg: "the quaternion is zero",
+                span: None,
+            });
+        }
+
+        // v + w t + u × t with t = 2 u × v, which is q v q* without the multiplications by zero
+        let q = *self / norm;
+        let axis = Vector::from(vec![q.x, q.y, q.z]);
+        let twice_cross = axis.cross(vec)? * 2.0;
+        (vec + &(&twice_cross * q.w))? + &axis.cross(&twice_cross)?
     }
+}
+
+/// The Hamilton product
+impl Mul for Quaternion {
+    type Output = Quaternion;
 
-    fn debug_print(debug_level: u32, string: String) {
-        // TODO: Print different format strings for different debug levels.
-        println!("Debug {}: {}", debug_level, string);
+    fn mul(self, rhs: Quaternion) -> Self::Output {
+        Quaternion::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        )
     }
 }
 
-impl Helper for CalculatorState {}
+impl Mul<f64> for Quaternion {
+    type Output = Quaternion;
 
-impl Validator for CalculatorState {
-    fn validate(&self, ctx: &mut rustyline::validate::ValidationContext) -> rustyline::Result<rustyline::validate::ValidationResult> {
-        let _ = ctx;
-        Ok(rustyline::validate::ValidationResult::Valid(None))
+    fn mul(self, rhs: f64) -> Self::Output {
+        Quaternion::new(self.w * rhs, self.x * rhs, self.y * rhs, self.z * rhs)
     }
+}
+
+impl Div<f64> for Quaternion {
+    type Output = Quaternion;
 
-    fn validate_while_typing(&self) -> bool {
-        false
+    fn div(self, rhs: f64) -> Self::Output {
+        Quaternion::new(self.w / rhs, self.x / rhs, self.y / rhs, self.z / rhs)
     }
 }
 
-impl Highlighter for CalculatorState {
+impl Add for Quaternion {
+    type Output = Quaternion;
 
+    fn add(self, rhs: Quaternion) -> Self::Output {
+        Quaternion::new(self.w + rhs.w, self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
 }
 
-impl Hinter for CalculatorState {
-    type Hint = String;
+impl Sub for Quaternion {
+    type Output = Quaternion;
 
-    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<Self::Hint> {
-        let _ = (line, pos, ctx);
-        None
+    fn sub(self, rhs: Quaternion) -> Self::Output {
+        Quaternion::new(self.w - rhs.w, self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
     }
 }
 
-impl Completer for CalculatorState {
-    type Candidate = String;
+impl Neg for Quaternion {
+    type Output = Quaternion;
 
-    fn complete(
-        &self, // FIXME should be `&mut self`
-        line: &str,
-        pos: usize,
-        ctx: &rustyline::Context<'_>,
-    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
-        let _ = (line, pos, ctx);
-        Ok((0, Vec::with_capacity(0)))
+    fn neg(self) -> Self::Output {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
     }
+}
 
-    fn update(&self, line: &mut rustyline::line_buffer::LineBuffer, start: usize, elected: &str) {
-        let end = line.pos();
-        line.replace(start..end, elected)
+/// Prints as `1 + 2i - 3j + 4k`, always with all four terms.
+///
+/// Formatter precision applies to each number, like for vectors.
+impl std::fmt::Display for Quaternion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_number = |x: f64| match f.precision() {
+            Some(precision) => format!("{:.*}", precision, x),
+            None => x.to_string(),
+        };
+        f.write_str(&quaternion_terms(self, format_number))
     }
-}
\ No newline at end of file
+}
+
+/// Writes `w + xi + yj + zk` with each number formatted by `format_number`, and the signs of
+/// the imaginary parts as the operators
+fn quaternion_terms<F: FnMut(f64) -> String>(q: &Quaternion, mut format_number: F) -> String {
+    let mut terms = format_number(q.w);
+    for (part, unit) in [(q.x, 'i'), (q.y, 'j'), (q.z, 'k')] {
+        let sign = if part < 0.0 { '-' } else { '+' };
+        terms.push_str(&format!(" {} {}{}", sign, format_number(part.abs()), unit));
+    }
+    terms
+}
+
+/// A complex number `re + im·i`, written `3 + 4i` in the calculator.
+///
+/// Only used as a scalar on its own, vectors and matrices are still real.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn conjugate(&self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// The magnitude, `|z|`
+    pub fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The angle from the positive real axis in radians, in (-π, π]
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Self::Output {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Self::Output {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Self::Output {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+/// Follows float division, so a zero divisor gives infinity or NaN parts
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Self::Output {
+        let denominator = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Self::Output {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+/// Prints as `3+4i` or `3-4i`, always with both parts.
+///
+/// Formatter precision applies to each part, like for vectors.
+impl std::fmt::Display for Complex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_number = |x: f64| match f.precision() {
+            Some(precision) => format!("{:.*}", precision, x),
+            None => x.to_string(),
+        };
+        f.write_str(&complex_terms(self, format_number))
+    }
+}
+
+/// Writes `re+imi` with each part formatted by `format_number`, and the sign of the imaginary
+/// part as the operator
+fn complex_terms<F: FnMut(f64) -> String>(z: &Complex, mut format_number: F) -> String {
+    let sign = if z.im < 0.0 { '-' } else { '+' };
+    format!("{}{}{}i", format_number(z.re), sign, format_number(z.im.abs()))
+}
+
+/// Determinant of the 2x2 or 3x3 matrix with `columns` as its columns
+fn determinant(columns: &[&Vector]) -> f64 {
+    match columns {
+        [a, b] => a[0] * b[1] - a[1] * b[0],
+        [a, b, c] => a.dot(&b.cross(c).expect("Checked to be 3D")).expect("Checked to be 3D"),
+        _ => unreachable!("Only 2x2 and 3x3 determinants are supported"),
+    }
+}
+
+/// The units a number can have, in the order `Unit` keeps their exponents and writes them
+pub const UNIT_NAMES: [&str; 6] = ["N", "J", "kg", "m", "s", "rad"];
+
+/// Each of `UNIT_NAMES` in metres, seconds, kilograms and radians, which is what decides whether
+/// two units can be added. They're all SI, so going between them never changes the number
+const UNIT_DIMENSIONS: [[i32; 4]; 6] = [
+    [1, -2, 1, 0],
+    [2, -2, 1, 0],
+    [0, 0, 1, 0],
+    [1, 0, 0, 0],
+    [0, 1, 0, 0],
+    [0, 0, 0, 1],
+];
+
+/// Biggest power a unit can be raised to, so exponents can't overflow
+const MAX_UNIT_POWER: f64 = 1000.0;
+
+/// A product of powers of the units in `UNIT_NAMES`, like `N·m` or `m/s^2`.
+///
+/// Kept the way it was written rather than reduced, so `N·m` stays `N·m` instead of becoming `J`.
+/// Units that are the same in metres, seconds, kilograms and radians can be added together.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unit {
+    exponents: [i32; UNIT_NAMES.len()],
+}
+
+impl Unit {
+    /// One of `UNIT_NAMES` on its own
+    pub fn named(name: &str) -> Option<Unit> {
+        let index = UNIT_NAMES.iter().position(|unit| *unit == name)?;
+        let mut unit = Unit::default();
+        unit.exponents[index] = 1;
+        Some(unit)
+    }
+
+    /// The powers of metres, seconds, kilograms and radians it comes to
+    pub fn dimensions(&self) -> [i32; 4] {
+        let mut dimensions = [0; 4];
+        for (exponent, unit) in self.exponents.iter().zip(UNIT_DIMENSIONS) {
+            for (dimension, base) in dimensions.iter_mut().zip(unit) {
+                *dimension += exponent * base;
+            }
+        }
+        dimensions
+    }
+
+    /// Whether it cancels out, like `N·m/J`
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimensions() == [0; 4]
+    }
+
+    /// The unit to the power `exponent`, or `None` if that would leave a unit with a fractional
+    /// exponent, like `m^0.5`. `(m^2)^0.5` is fine
+    pub fn powf(&self, exponent: f64) -> Option<Unit> {
+        if exponent.abs() > MAX_UNIT_POWER {
+            return None;
+        }
+        let mut unit = *self;
+        for power in &mut unit.exponents {
+            let scaled = *power as f64 * exponent;
+            if scaled.fract() != 0.0 {
+                return None;
+            }
+            *power = scaled as i32;
+        }
+        Some(unit)
+    }
+
+    /// Each unit with its exponent, in the order of `UNIT_NAMES`, leaving out the ones it doesn't have
+    fn factors(&self) -> impl Iterator<Item = (&'static str, i32)> + '_ {
+        UNIT_NAMES
+            .iter()
+            .zip(self.exponents)
+            .filter(|(_, exponent)| *exponent != 0)
+            .map(|(name, exponent)| (*name, exponent))
+    }
+
+    /// Written as LaTeX, like `\mathrm{m}\cdot\mathrm{s}^{-2}`
+    pub fn to_latex(&self) -> String {
+        let factors: Vec<String> = self
+            .factors()
+            .map(|(name, exponent)| match exponent {
+                1 => format!("\\mathrm{{{}}}", name),
+                _ => format!("\\mathrm{{{}}}^{{{}}}", name, exponent),
+            })
+            .collect();
+        factors.join("\\cdot ")
+    }
+}
+
+impl Mul for Unit {
+    type Output = Unit;
+
+    fn mul(mut self, rhs: Unit) -> Self::Output {
+        for (lhs, rhs) in self.exponents.iter_mut().zip(rhs.exponents) {
+            *lhs = lhs.saturating_add(rhs);
+        }
+        self
+    }
+}
+
+impl Div for Unit {
+    type Output = Unit;
+
+    fn div(mut self, rhs: Unit) -> Self::Output {
+        for (lhs, rhs) in self.exponents.iter_mut().zip(rhs.exponents) {
+            *lhs = lhs.saturating_sub(rhs);
+        }
+        self
+    }
+}
+
+/// Prints as `N·m` or `kg·m/s^2`, which is also how it's written in the calculator. Without any
+/// positive exponents it's `s^-1`, since the grammar has no `1/s`
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let power = |name: &str, exponent: i32| match exponent {
+            1 => name.to_owned(),
+            _ => format!("{}^{}", name, exponent),
+        };
+        let numerator: Vec<String> = self
+            .factors()
+            .filter(|(_, exponent)| *exponent > 0)
+            .map(|(name, exponent)| power(name, exponent))
+            .collect();
+        if numerator.is_empty() {
+            let factors: Vec<String> = self.factors().map(|(name, exponent)| power(name, exponent)).collect();
+            return f.write_str(&factors.join("·"));
+        }
+        f.write_str(&numerator.join("·"))?;
+        for (name, exponent) in self.factors().filter(|(_, exponent)| *exponent < 0) {
+            write!(f, "/{}", power(name, -exponent))?;
+        }
+        Ok(())
+    }
+}
+
+/// A real number with a unit, like `5 N`. Numbers whose unit cancels out are plain numbers
+/// instead, see [`Quantity::value_of`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    /// `value` in `unit`, or just the number if the unit cancels out
+    pub fn value_of(value: f64, unit: Unit) -> Value {
+        if unit.is_dimensionless() {
+            Value::Number(value)
+        } else {
+            Value::Quantity(Quantity { value, unit })
+        }
+    }
+
+    /// `self ^ exponent`, which needs a whole power unless the unit's exponents divide evenly
+    pub fn powf(&self, exponent: f64) -> Result<Value, EvalError> {
+        let unit = self.unit.powf(exponent).ok_or_else(|| EvalError::FractionalUnitPower {
+            unit: self.unit.to_string(),
+            exponent,
+            span: None,
+        })?;
+        Ok(Quantity::value_of(self.value.powf(exponent), unit))
+    }
+}
+
+/// Prints as `10 N·m`, with formatter precision applying to the number
+impl std::fmt::Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.value, f)?;
+        write!(f, " {}", self.unit)
+    }
+}
+
+/// `+`, `-`, `*` or `/` where either side has a unit, or `None` if neither does.
+///
+/// Units only go with plain numbers, so on anything else the number is used without its unit.
+/// Adding or subtracting needs units with the same dimensions, and gives the left side's unit
+fn quantity_op(op: &'static str, lhs: &Value, rhs: &Value) -> Option<Result<Value, EvalError>> {
+    if lhs.unit().is_none() && rhs.unit().is_none() {
+        return None;
+    }
+    let (Some((a, a_unit)), Some((b, b_unit))) = (lhs.real_with_unit(), rhs.real_with_unit()) else {
+        let (lhs, rhs) = (&lhs.without_unit(), &rhs.without_unit());
+        return Some(match op {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            _ => lhs.div_ieee(rhs),
+        });
+    };
+    let mismatch = || EvalError::UnitMismatch {
+        op,
+        left: unit_description(a_unit),
+        right: unit_description(b_unit),
+        span: None,
+    };
+    Some(match op {
+        "+" | "-" if a_unit.dimensions() != b_unit.dimensions() => Err(mismatch()),
+        "+" => Ok(Quantity::value_of(a + b, a_unit)),
+        "-" => Ok(Quantity::value_of(a - b, a_unit)),
+        "*" => Ok(Quantity::value_of(a * b, a_unit * b_unit)),
+        _ => Ok(Quantity::value_of(a / b, a_unit / b_unit)),
+    })
+}
+
+/// A unit for an error message, where a plain number has none
+fn unit_description(unit: Unit) -> String {
+    if unit.is_dimensionless() {
+        "no unit".to_owned()
+    } else {
+        unit.to_string()
+    }
+}
+
+/// With the `serde` feature this serializes as an externally tagged enum, `{"Number": 3.0}` or
+/// `{"Vector": [1.0, 2.0]}`. Persistence formats build on that, so it shouldn't change.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Number(f64),
+    Vector(Vector),
+    Matrix(Matrix),
+    Quaternion(Quaternion),
+    Complex(Complex),
+    /// A number with a unit, which is never one that cancels out
+    Quantity(Quantity),
+}
+
+/// Prints as `<1, 2>`, or `<>` if empty, the same as the literals.
+///
+/// Formatter options like precision and width apply to each component, so `{:.2}` gives `<0.33, 1.00>`.
+impl std::fmt::Display for Vector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut iter = self.iter();
+        match iter.next() {
+            Some(val) => {
+                f.write_str("<")?;
+                std::fmt::Display::fmt(val, f)?;
+            }
+            None => return f.write_str("<>"),
+        }
+        for val in iter {
+            f.write_str(", ")?;
+            std::fmt::Display::fmt(val, f)?;
+        }
+        f.write_str(">")
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Forward to the inner value so formatter options are kept
+        match self {
+            Value::Number(val) => std::fmt::Display::fmt(val, f),
+            Value::Vector(vec) => std::fmt::Display::fmt(vec, f),
+            Value::Matrix(matrix) => std::fmt::Display::fmt(matrix, f),
+            Value::Quaternion(q) => std::fmt::Display::fmt(q, f),
+            Value::Complex(z) => std::fmt::Display::fmt(z, f),
+            Value::Quantity(q) => std::fmt::Display::fmt(q, f),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(source: f64) -> Self {
+        Self::Number(source)
+    }
+}
+
+impl From<Vector> for Value {
+    fn from(source: Vector) -> Self {
+        Self::Vector(source)
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(source: Matrix) -> Self {
+        Self::Matrix(source)
+    }
+}
+
+impl From<Quaternion> for Value {
+    fn from(source: Quaternion) -> Self {
+        Self::Quaternion(source)
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(source: Complex) -> Self {
+        Self::Complex(source)
+    }
+}
+
+impl From<Quantity> for Value {
+    fn from(source: Quantity) -> Self {
+        Quantity::value_of(source.value, source.unit)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(source: Vec<f64>) -> Self {
+        Self::Vector(source.into())
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        f64::try_from(&value)
+    }
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = EvalError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            // Whatever takes a plain number ignores the unit
+            Value::Number(val) | Value::Quantity(Quantity { value: val, .. }) => Ok(*val),
+            other => Err(EvalError::WrongType {
+                expected: "number",
+                found: other.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vector {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Vector(vec) => Ok(vec),
+            other => Err(EvalError::WrongType {
+                expected: "vector",
+                found: other.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a Vector {
+    type Error = EvalError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Vector(vec) => Ok(vec),
+            other => Err(EvalError::WrongType {
+                expected: "vector",
+                found: other.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a Matrix {
+    type Error = EvalError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Matrix(matrix) => Ok(matrix),
+            other => Err(EvalError::WrongType {
+                expected: "matrix",
+                found: other.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl Value {
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Number(_))
+    }
+
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Value::Vector(_))
+    }
+
+    pub fn is_matrix(&self) -> bool {
+        matches!(self, Value::Matrix(_))
+    }
+
+    pub fn is_quaternion(&self) -> bool {
+        matches!(self, Value::Quaternion(_))
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    /// The unit, if it's a number with one
+    pub fn unit(&self) -> Option<Unit> {
+        match self {
+            Value::Quantity(q) => Some(q.unit),
+            _ => None,
+        }
+    }
+
+    /// The value with any unit taken off, for things that don't work with units
+    pub fn without_unit(&self) -> Value {
+        match self {
+            Value::Quantity(q) => Value::Number(q.value),
+            other => other.clone(),
+        }
+    }
+
+    /// A real number and its unit, which is dimensionless for plain numbers
+    fn real_with_unit(&self) -> Option<(f64, Unit)> {
+        match self {
+            Value::Number(val) => Some((*val, Unit::default())),
+            Value::Quantity(q) => Some((q.value, q.unit)),
+            _ => None,
+        }
+    }
+
+    /// Writes the value as a literal that parses back to exactly the same value.
+    ///
+    /// Returns `None` if the value isn't finite, since there's no literal for infinity or NaN.
+    pub fn to_literal(&self) -> Option<String> {
+        if !self.is_finite() {
+            return None;
+        }
+        // Float `Display` is the shortest form that reads back as the same bits, and never uses
+        // an exponent, which the grammar doesn't have. Vectors are written as `vec(...)`, since a
+        // `<` can be mistaken for something else
+        let vector_literal = |components: &[f64]| {
+            let components: Vec<String> = components.iter().map(f64::to_string).collect();
+            format!("vec({})", components.join(", "))
+        };
+        Some(match self {
+            Value::Number(val) => val.to_string(),
+            Value::Vector(vec) => vector_literal(vec),
+            Value::Matrix(matrix) => {
+                let rows: Vec<String> = (0..matrix.shape().0).map(|row| vector_literal(matrix.row(row))).collect();
+                format!("[{}]", rows.join(", "))
+            }
+            // There's no quaternion literal, but loading a save evaluates each line anyway
+            Value::Quaternion(q) => format!("quaternion({}, {}, {}, {})", q.w, q.x, q.y, q.z),
+            // Like quaternions this is an expression rather than a literal
+            Value::Complex(z) => z.to_string(),
+            Value::Quantity(q) => format!("{} {}", q.value, q.unit),
+        })
+    }
+
+    /// Writes the value as LaTeX, with vectors as a column `pmatrix`.
+    ///
+    /// `precision` is the number of decimal places, like the `.precision` setting. Very large and
+    /// very small numbers are written as `3\times10^{-5}` rather than with lots of zeros.
+    pub fn to_latex(&self, precision: Option<usize>) -> String {
+        match self {
+            Value::Number(val) => latex_number(*val, precision),
+            Value::Vector(vec) => {
+                let components: Vec<String> = vec.iter().map(|&x| latex_number(x, precision)).collect();
+                format!("\\begin{{pmatrix}}{}\\end{{pmatrix}}", components.join("\\\\"))
+            }
+            Value::Matrix(matrix) => {
+                let rows: Vec<String> = (0..matrix.shape().0)
+                    .map(|row| {
+                        let numbers: Vec<String> =
+                            matrix.row(row).iter().map(|&x| latex_number(x, precision)).collect();
+                        numbers.join("&")
+                    })
+                    .collect();
+                format!("\\begin{{pmatrix}}{}\\end{{pmatrix}}", rows.join("\\\\"))
+            }
+            Value::Quaternion(q) => quaternion_terms(q, |x| latex_number(x, precision)),
+            Value::Complex(z) => complex_terms(z, |x| latex_number(x, precision)),
+            Value::Quantity(q) => format!("{}\\,{}", latex_number(q.value, precision), q.unit.to_latex()),
+        }
+    }
+
+    /// Whether the number, or every component of the vector, is neither infinite nor NaN
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Value::Number(val) => val.is_finite(),
+            Value::Vector(vec) => vec.is_finite(),
+            Value::Matrix(matrix) => matrix.is_finite(),
+            Value::Quaternion(q) => q.is_finite(),
+            Value::Complex(z) => z.is_finite(),
+            Value::Quantity(q) => q.value.is_finite(),
+        }
+    }
+
+    /// Name of the variant, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Vector(_) => "vector",
+            Value::Matrix(_) => "matrix",
+            Value::Quaternion(_) => "quaternion",
+            Value::Complex(_) => "complex number",
+            Value::Quantity(_) => "quantity",
+        }
+    }
+
+    /// Name of the variant along with its size, like `vector(3)` or `matrix(2x3)`
+    pub fn shape_name(&self) -> String {
+        match self {
+            Value::Vector(vec) => format!("vector({})", vec.dims()),
+            Value::Matrix(matrix) => {
+                let (rows, cols) = matrix.shape();
+                format!("matrix({}x{})", rows, cols)
+            }
+            other => other.type_name().to_owned(),
+        }
+    }
+
+    /// Whether they're the same type. Numbers count as the same whatever their unit
+    pub fn compare_types(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(_) | Value::Quantity(_), Value::Number(_) | Value::Quantity(_)) => true,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+
+    /// Panics if the value is not a number
+    #[deprecated(note = "use `f64::try_from` or match on the value instead")]
+    pub fn as_number(&self) -> f64 {
+        match self {
+            Self::Number(val) => *val,
+            other => panic!("Tried to get a number from a {} value", other.type_name())
+        }
+    }
+
+    /// Panics if the value is not a vector
+    #[deprecated(note = "use `Vector::try_from` or match on the value instead")]
+    pub fn as_vector(&self) -> Vector {
+        match self {
+            Self::Vector(val) => val.clone(),
+            other => panic!("Tried to get a vector from a {} value", other.type_name())
+        }
+    }
+
+    /// In place `self += rhs`. Gives the same result or error as `self + rhs`, but a vector
+    /// reuses its buffer, and `self` is left alone on error.
+    pub fn try_add_assign(&mut self, rhs: &Value) -> Result<(), EvalError> {
+        match (&mut *self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => lhs.try_add_assign(rhs),
+            (lhs, rhs) => {
+                *lhs = (&*lhs + rhs)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// In place `self -= rhs`, see [`Value::try_add_assign`]
+    pub fn try_sub_assign(&mut self, rhs: &Value) -> Result<(), EvalError> {
+        match (&mut *self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => lhs.try_sub_assign(rhs),
+            (lhs, rhs) => {
+                *lhs = (&*lhs - rhs)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// In place `self *= rhs`, see [`Value::try_add_assign`]
+    pub fn try_mul_assign(&mut self, rhs: &Value) -> Result<(), EvalError> {
+        match (&mut *self, rhs) {
+            (Value::Vector(lhs), Value::Number(rhs)) => {
+                *lhs *= *rhs;
+                Ok(())
+            }
+            (lhs, rhs) => {
+                *lhs = (&*lhs * rhs)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Like `%`, but a zero divisor gives NaN as usual for floats instead of an error
+    pub fn rem_ieee(&self, rhs: &Value) -> Result<Value, EvalError> {
+        match (self, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs % rhs)),
+            (lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: "%",
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        }
+    }
+
+    /// Like `/`, but a zero divisor gives infinity or NaN as usual for floats instead of an error
+    pub fn div_ieee(&self, rhs: &Value) -> Result<Value, EvalError> {
+        if let Some(result) = quantity_op("/", self, rhs) {
+            return result;
+        }
+        match (self, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs / rhs)),
+            (Value::Vector(lhs), Value::Number(rhs)) => Ok(Value::Vector(lhs / *rhs)),
+            (Value::Matrix(lhs), Value::Number(rhs)) => Ok(Value::Matrix(lhs.clone() / *rhs)),
+            (Value::Quaternion(lhs), Value::Number(rhs)) => Ok(Value::Quaternion(*lhs / *rhs)),
+            (Value::Complex(lhs), Value::Complex(rhs)) => Ok(Value::Complex(*lhs / *rhs)),
+            (Value::Complex(lhs), Value::Number(rhs)) => Ok(Value::Complex(*lhs / Complex::from(*rhs))),
+            (Value::Number(lhs), Value::Complex(rhs)) => Ok(Value::Complex(Complex::from(*lhs) / *rhs)),
+            (lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: "/",
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        }
+    }
+
+    /// In place `self /= rhs`, see [`Value::try_add_assign`]
+    pub fn try_div_assign(&mut self, rhs: &Value) -> Result<(), EvalError> {
+        match (&mut *self, rhs) {
+            (Value::Vector(lhs), Value::Number(rhs)) => {
+                check_divisor(*rhs)?;
+                *lhs /= *rhs;
+                Ok(())
+            }
+            (lhs, rhs) => {
+                *lhs = (&*lhs / rhs)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A number in LaTeX, see [`Value::to_latex`]
+fn latex_number(x: f64, precision: Option<usize>) -> String {
+    if x.is_nan() {
+        return "\\text{NaN}".to_owned();
+    } else if x.is_infinite() {
+        return if x > 0.0 { "\\infty" } else { "-\\infty" }.to_owned();
+    }
+
+    let magnitude = x.abs();
+    if magnitude == 0.0 || (1e-4..1e15).contains(&magnitude) {
+        return match precision {
+            Some(precision) => format!("{:.*}", precision, x),
+            None => x.to_string(),
+        };
+    }
+    let formatted = match precision {
+        Some(precision) => format!("{:.*e}", precision, x),
+        None => format!("{:e}", x),
+    };
+    let (mantissa, exponent) = formatted.split_once('e').expect("`{:e}` always has an exponent");
+    format!("{}\\times10^{{{}}}", mantissa, exponent)
+}
+
+/// Division on `Value` errors for a zero divisor, see [`Value::div_ieee`] for the float behavior
+fn check_divisor(divisor: f64) -> Result<(), EvalError> {
+    if divisor == 0.0 {
+        return Err(EvalError::DivisionByZero { span: None });
+    }
+    Ok(())
+}
+
+impl Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::Number(val) => Value::Number(-val),
+            Value::Vector(vec) => Value::Vector(-vec),
+            Value::Matrix(matrix) => Value::Matrix(-matrix),
+            Value::Quaternion(q) => Value::Quaternion(-q),
+            Value::Complex(z) => Value::Complex(-z),
+            Value::Quantity(q) => Value::Quantity(Quantity { value: -q.value, ..q }),
+        }
+    }
+}
+
+impl Neg for &Value {
+    type Output = Value;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::Number(val) => Value::Number(-val),
+            Value::Vector(vec) => Value::Vector(-vec),
+            Value::Matrix(matrix) => Value::Matrix(-matrix.clone()),
+            Value::Quaternion(q) => Value::Quaternion(-*q),
+            Value::Complex(z) => Value::Complex(-*z),
+            Value::Quantity(q) => Value::Quantity(Quantity { value: -q.value, ..*q }),
+        }
+    }
+}
+
+// The owned `Value` impls only handle the cases where they can reuse the left hand side's
+// buffer, everything else goes through the borrowed impls
+
+impl Add<&Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn add(self, rhs: &Value) -> Self::Output {
+        if let Some(result) = quantity_op("+", self, rhs) {
+            return result;
+        }
+        match (self, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs + rhs)),
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs + rhs).map(Value::Vector),
+            (Value::Matrix(lhs), Value::Matrix(rhs)) => (lhs + rhs).map(Value::Matrix),
+            (Value::Quaternion(lhs), Value::Quaternion(rhs)) => Ok(Value::Quaternion(*lhs + *rhs)),
+            (Value::Complex(lhs), Value::Complex(rhs)) => Ok(Value::Complex(*lhs + *rhs)),
+            (Value::Complex(lhs), Value::Number(rhs)) => Ok(Value::Complex(*lhs + Complex::from(*rhs))),
+            (Value::Number(lhs), Value::Complex(rhs)) => Ok(Value::Complex(Complex::from(*lhs) + *rhs)),
+            (lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: "+",
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl Add<&Value> for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn add(self, rhs: &Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs + rhs).map(Value::Vector),
+            (lhs, rhs) => &lhs + rhs,
+        }
+    }
+}
+
+impl Add<Value> for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn add(self, rhs: Value) -> Self::Output {
+        self + &rhs
+    }
+}
+
+impl Add<Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn add(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs + rhs).map(Value::Vector),
+            (lhs, rhs) => lhs + &rhs,
+        }
+    }
+}
+
+impl Sub<&Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn sub(self, rhs: &Value) -> Self::Output {
+        if let Some(result) = quantity_op("-", self, rhs) {
+            return result;
+        }
+        match (self, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs - rhs)),
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs - rhs).map(Value::Vector),
+            (Value::Matrix(lhs), Value::Matrix(rhs)) => (lhs - rhs).map(Value::Matrix),
+            (Value::Quaternion(lhs), Value::Quaternion(rhs)) => Ok(Value::Quaternion(*lhs - *rhs)),
+            (Value::Complex(lhs), Value::Complex(rhs)) => Ok(Value::Complex(*lhs - *rhs)),
+            (Value::Complex(lhs), Value::Number(rhs)) => Ok(Value::Complex(*lhs - Complex::from(*rhs))),
+            (Value::Number(lhs), Value::Complex(rhs)) => Ok(Value::Complex(Complex::from(*lhs) - *rhs)),
+            (lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: "-",
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl Sub<&Value> for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn sub(self, rhs: &Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs - rhs).map(Value::Vector),
+            (lhs, rhs) => &lhs - rhs,
+        }
+    }
+}
+
+impl Sub for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self - &rhs
+    }
+}
+
+impl Sub<Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn sub(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Vector(rhs)) => (lhs - rhs).map(Value::Vector),
+            (lhs, rhs) => lhs - &rhs,
+        }
+    }
+}
+
+impl Mul<&Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn mul(self, rhs: &Value) -> Self::Output {
+        if let Some(result) = quantity_op("*", self, rhs) {
+            return result;
+        }
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Number(rhs)) => Ok(Value::Vector(lhs * *rhs)),
+            (Value::Number(lhs), Value::Vector(rhs)) => Ok(Value::Vector(*lhs * rhs)),
+            (Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(lhs * rhs)),
+            (Value::Matrix(lhs), Value::Number(rhs)) => Ok(Value::Matrix(lhs.clone() * *rhs)),
+            (Value::Number(lhs), Value::Matrix(rhs)) => Ok(Value::Matrix(rhs.clone() * *lhs)),
+            (Value::Matrix(lhs), Value::Vector(rhs)) => lhs.mul_vector(rhs).map(Value::Vector),
+            (Value::Matrix(lhs), Value::Matrix(rhs)) => lhs.mul_matrix(rhs).map(Value::Matrix),
+            (Value::Quaternion(lhs), Value::Quaternion(rhs)) => Ok(Value::Quaternion(*lhs * *rhs)),
+            (Value::Quaternion(lhs), Value::Number(rhs)) => Ok(Value::Quaternion(*lhs * *rhs)),
+            (Value::Number(lhs), Value::Quaternion(rhs)) => Ok(Value::Quaternion(*rhs * *lhs)),
+            (Value::Complex(lhs), Value::Complex(rhs)) => Ok(Value::Complex(*lhs * *rhs)),
+            (Value::Complex(lhs), Value::Number(rhs)) => Ok(Value::Complex(*lhs * Complex::from(*rhs))),
+            (Value::Number(lhs), Value::Complex(rhs)) => Ok(Value::Complex(Complex::from(*lhs) * *rhs)),
+            (lhs, rhs) => Err(EvalError::TypeMismatch {
+                op: "*",
+                left: lhs.type_name(),
+                right: rhs.type_name(),
+                span: None,
+            }),
+        }
+    }
+}
+
+impl Mul<&Value> for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn mul(self, rhs: &Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Number(rhs)) => Ok(Value::Vector(lhs * *rhs)),
+            (Value::Matrix(lhs), Value::Number(rhs)) => Ok(Value::Matrix(lhs * *rhs)),
+            (lhs, rhs) => &lhs * rhs,
+        }
+    }
+}
+
+impl Mul for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            // The vector is on the right, but it's still owned
+            (Value::Number(lhs), Value::Vector(rhs)) => Ok(Value::Vector(lhs * rhs)),
+            (lhs, rhs) => lhs * &rhs,
+        }
+    }
+}
+
+impl Mul<Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn mul(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(lhs), Value::Vector(rhs)) => Ok(Value::Vector(*lhs * rhs)),
+            (Value::Number(lhs), Value::Matrix(rhs)) => Ok(Value::Matrix(rhs * *lhs)),
+            (lhs, rhs) => lhs * &rhs,
+        }
+    }
+}
+
+impl Div<&Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn div(self, rhs: &Value) -> Self::Output {
+        match rhs {
+            Value::Number(divisor) | Value::Quantity(Quantity { value: divisor, .. }) => check_divisor(*divisor)?,
+            Value::Complex(divisor) if divisor.re == 0.0 && divisor.im == 0.0 => {
+                return Err(EvalError::DivisionByZero { span: None })
+            }
+            _ => {}
+        }
+        self.div_ieee(rhs)
+    }
+}
+
+impl Div<&Value> for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn div(self, rhs: &Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Vector(lhs), Value::Number(rhs)) => {
+                check_divisor(*rhs)?;
+                Ok(Value::Vector(lhs / *rhs))
+            }
+            (Value::Matrix(lhs), Value::Number(rhs)) => {
+                check_divisor(*rhs)?;
+                Ok(Value::Matrix(lhs / *rhs))
+            }
+            (lhs, rhs) => &lhs / rhs,
+        }
+    }
+}
+
+impl Div for Value {
+    type Output = Result<Value, EvalError>;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self / &rhs
+    }
+}
+
+/// The remainder of numbers, which has the sign of the left side like Rust's `%`
+impl Rem<&Value> for &Value {
+    type Output = Result<Value, EvalError>;
+
+    fn rem(self, rhs: &Value) -> Self::Output {
+        if let Value::Number(divisor) = rhs {
+            check_divisor(*divisor)?;
+        }
+        self.rem_ieee(rhs)
+    }
+}
+
+/// A type a variable was declared with, like `let v: vec3 = <1, 2, 3>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeConstraint {
+    Number,
+    /// A vector, with that many dimensions if given
+    Vector(Option<usize>),
+    /// A matrix, with that many rows and columns if given
+    Matrix(Option<(usize, usize)>),
+    Quaternion,
+    Complex,
+}
+
+impl TypeConstraint {
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::Number, Value::Number(_) | Value::Quantity(_)) => true,
+            (Self::Vector(dims), Value::Vector(vec)) => dims.is_none_or(|dims| dims == vec.dims()),
+            (Self::Matrix(shape), Value::Matrix(matrix)) => shape.is_none_or(|shape| shape == matrix.shape()),
+            (Self::Quaternion, Value::Quaternion(_)) => true,
+            (Self::Complex, Value::Complex(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Written the way it's declared, so `vec3` rather than `vector(3)`
+impl std::fmt::Display for TypeConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number => write!(f, "number"),
+            Self::Vector(None) => write!(f, "vector"),
+            Self::Vector(Some(dims)) => write!(f, "vec{}", dims),
+            Self::Matrix(None) => write!(f, "matrix"),
+            Self::Matrix(Some((rows, cols))) => write!(f, "mat{}x{}", rows, cols),
+            Self::Quaternion => write!(f, "quaternion"),
+            Self::Complex => write!(f, "complex"),
+        }
+    }
+}
+
+/// What's known about a variable besides its value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VariableInfo {
+    /// Set by declaring the variable with a type, which every later assignment has to match
+    pub declared: Option<TypeConstraint>,
+    /// When the current value was assigned
+    pub assigned: Option<SystemTime>,
+    /// The values it had before, newest first, with when each was assigned. Never saved
+    pub history: VecDeque<(SystemTime, Value)>,
+    /// Set by `--set` on the command line, until the variable is assigned again
+    pub from_command_line: bool,
+}
+
+/// How many earlier values each variable keeps for `v@n` and `.history`
+pub const MAX_VARIABLE_HISTORY: usize = 10;
+
+/// Always refers to the latest result, so it can't be assigned to
+pub const LAST_RESULT_NAME: &str = "_";
+
+/// Names that mean a number when there's no variable called that
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("π", std::f64::consts::PI),
+    ("τ", std::f64::consts::TAU),
+    // For keyboards without Greek letters
+    ("pi", std::f64::consts::PI),
+    ("tau", std::f64::consts::TAU),
+];
+
+pub fn constant(name: &str) -> Option<f64> {
+    CONSTANTS.iter().find(|(constant, _)| *constant == name).map(|(_, value)| *value)
+}
+
+/// A set of variables, one of a state's workspaces
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    pub(crate) variables: HashMap<String, Value>,
+    /// Only variables that have been declared or assigned have an entry
+    variable_info: HashMap<String, VariableInfo>,
+}
+
+impl Workspace {
+    /// Number of variables
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    /// The variable names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.variables.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.variables.get(key)
+    }
+
+    /// The type the variable was declared with, if it was
+    pub fn declared_type(&self, key: &str) -> Option<TypeConstraint> {
+        self.variable_info.get(key).and_then(|info| info.declared)
+    }
+
+    /// When the variable's current value was assigned, if it's been assigned since the program started
+    pub fn assigned_at(&self, key: &str) -> Option<SystemTime> {
+        self.variable_info.get(key).and_then(|info| info.assigned)
+    }
+
+    /// Whether the variable's value came from `--set`, for `.vars` to mark
+    pub fn from_command_line(&self, key: &str) -> bool {
+        self.variable_info.get(key).is_some_and(|info| info.from_command_line)
+    }
+
+    /// The values the variable had before, newest first, with when each was assigned
+    pub fn history(&self, key: &str) -> impl Iterator<Item = (SystemTime, &Value)> {
+        self.variable_info
+            .get(key)
+            .into_iter()
+            .flat_map(|info| info.history.iter().map(|(time, value)| (*time, value)))
+    }
+
+    /// The value the variable had `back` assignments ago, 0 being the current one
+    pub fn previous(&self, key: &str, back: usize) -> Option<&Value> {
+        match back {
+            0 => self.get(key),
+            _ => self.variable_info.get(key)?.history.get(back - 1).map(|(_, value)| value),
+        }
+    }
+
+    /// Sets a variable, keeping the value it replaces in its history
+    fn set(&mut self, key: String, value: Value) {
+        let now = SystemTime::now();
+        let old = self.variables.insert(key.clone(), value);
+        let info = self.variable_info.entry(key).or_default();
+        if let Some(old) = old {
+            info.history.push_front((info.assigned.unwrap_or(now), old));
+            info.history.truncate(MAX_VARIABLE_HISTORY);
+        }
+        info.assigned = Some(now);
+        info.from_command_line = false;
+    }
+}
+
+/// Bounds on how much one line can make the calculator do, so pathological input gets an error
+/// instead of hanging, running out of memory or overflowing the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Most components in a vector or matrix made by a comprehension, `identity()` or `.import_vec`,
+    /// and most intervals `nintegrate()` can be asked to use
+    pub max_vector_len: usize,
+    /// Most operators plus levels of brackets in a line, which is about how deeply parsing and
+    /// evaluating it nests
+    pub max_expr_depth: usize,
+    /// Longest line, in bytes
+    pub max_input_len: usize,
+    /// Most steps `solve()` takes looking for a root
+    pub max_iterations: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_vector_len: 10_000_000,
+            max_expr_depth: 256,
+            max_input_len: 1 << 20,
+            max_iterations: 200,
+        }
+    }
+}
+
+impl Limits {
+    /// The limits' names, as `.limits` and `--limit` take them
+    pub const NAMES: &'static [&'static str] = &["max_vector_len", "max_expr_depth", "max_input_len", "max_iterations"];
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        match name {
+            "max_vector_len" => Some(self.max_vector_len),
+            "max_expr_depth" => Some(self.max_expr_depth),
+            "max_input_len" => Some(self.max_input_len),
+            "max_iterations" => Some(self.max_iterations),
+            _ => None,
+        }
+    }
+
+    /// Changes the limit called `name`. Each has a floor, so a session can't lower a limit so far
+    /// that the `.limits` line to undo it no longer fits
+    pub fn set(&mut self, name: &str, value: usize) -> Result<(), StateError> {
+        let (limit, min) = match name {
+            "max_vector_len" => (&mut self.max_vector_len, 1),
+            "max_expr_depth" => (&mut self.max_expr_depth, 16),
+            "max_input_len" => (&mut self.max_input_len, 256),
+            "max_iterations" => (&mut self.max_iterations, 1),
+            _ => {
+                return Err(StateError::UnknownLimit {
+                    name: name.to_owned(),
+                    suggestions: closest_matches(name, Self::NAMES.iter().copied())
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect(),
+                })
+            }
+        };
+        if value < min {
+            return Err(StateError::LimitTooLow { name: name.to_owned(), min });
+        }
+        *limit = value;
+        Ok(())
+    }
+
+    pub fn check_vector_len(&self, len: usize) -> Result<(), EvalError> {
+        exceeded("max_vector_len", self.max_vector_len, len)
+    }
+
+    pub fn check_expr_depth(&self, depth: usize) -> Result<(), EvalError> {
+        exceeded("max_expr_depth", self.max_expr_depth, depth)
+    }
+
+    pub fn check_input_len(&self, len: usize) -> Result<(), EvalError> {
+        exceeded("max_input_len", self.max_input_len, len)
+    }
+}
+
+fn exceeded(limit: &'static str, max: usize, attempted: usize) -> Result<(), EvalError> {
+    if attempted > max {
+        Err(EvalError::LimitExceeded { limit, max, attempted, span: None })
+    } else {
+        Ok(())
+    }
+}
+
+/// One `name = value` line for each limit
+impl std::fmt::Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines: Vec<String> = Self::NAMES
+            .iter()
+            .map(|name| format!("{} = {}", name, self.get(name).expect("Every name has a limit")))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// The workspace a state starts in
+pub const DEFAULT_WORKSPACE: &str = "main";
+
+pub struct CalculatorState {
+    /// Named sets of variables, switched between with `.workspace`. Always has the active one
+    workspaces: BTreeMap<String, Workspace>,
+    /// The workspace variables are read from and assigned to
+    active_workspace: String,
+    pub debug_level: u32,
+    /// Print the stored value after an assignment
+    pub echo_assignments: bool,
+    /// Only print results and errors, no informational or debug output
+    pub quiet: bool,
+    /// Variables changed since the last successful save or load
+    pub dirty: bool,
+    /// Last file name used by `.save` or `.load`, without the extension
+    pub session_file: Option<String>,
+    /// Set by `.exit`, the REPL stops once the current line is done
+    pub exit_requested: bool,
+    /// Set by `.modify`, for an interactive program to prompt for the variable's new value
+    pub pending_modify: Option<ModifyRequest>,
+    /// Set by `.time` with how long the expression took
+    pub last_timing: Option<Timing>,
+    /// Counters for `.stats`. In a cell since variables are counted as they're read, which
+    /// only borrows the state
+    stats: RefCell<Stats>,
+    output: RefCell<Box<dyn Output>>,
+    /// Set by `.transcript`, gets a copy of everything sent to the output except debug messages
+    transcript: RefCell<Option<Transcript>>,
+    /// How numbers are displayed, see `format_f64`
+    pub number_format: NumberFormat,
+    /// Digits after the decimal point when displaying numbers, or `None` for as many as needed
+    pub precision: Option<usize>,
+    /// Whether displayed numbers are cleaned up into fractions, see `DisplayMode`
+    pub display_mode: DisplayMode,
+    /// How `.import_vec` reads numbers, see `NumberLocale`
+    pub locale: NumberLocale,
+    /// Longer vectors are shortened when displayed, `None` shows everything. Never affects saving
+    pub max_display_components: Option<usize>,
+    /// Set by `.showall`, asking for the result to be displayed with `format_value_full`
+    pub full_display_requested: bool,
+    /// Size of the charts from `.plot`, in characters. The REPL keeps the width matched to the terminal
+    pub plot_width: usize,
+    pub plot_height: usize,
+    /// How much one line can make the calculator do, see `Limits`
+    pub limits: Limits,
+    /// Let `.limits` raise limits as well as lower them. Off for server connections
+    pub raise_limits: bool,
+    /// Error on division by zero and on infinite or NaN results. With this off division follows
+    /// IEEE semantics, and non-finite results are only a warning
+    pub strict: bool,
+    /// Error on undefined variables. With this off they count as 0, with a warning
+    pub strict_vars: bool,
+    /// Keep units on numbers like `5 N`. With this off they're read as plain numbers, and
+    /// variables that already have units are treated as if they didn't. Set with `.units`
+    pub units: bool,
+    /// Print warnings, like an assignment changing a variable's type. Set with `.warn`
+    pub warnings: bool,
+    /// Allow the commands that read or write files, like `.save`. Off for server connections
+    /// unless the server allows it
+    pub filesystem: bool,
+    /// Report each line as one JSON object through `Output::json` instead of printing its result,
+    /// see `json`. Set with `--json` or `.json`
+    pub json: bool,
+    /// The line editor's key bindings, like `vi`, set by the REPL for `.keys` to show
+    pub key_bindings: Option<&'static str>,
+    /// How close to zero counts as zero, for things like telling if a basis is singular
+    pub epsilon: f64,
+    /// The most recent expression results, oldest first, for `out(n)`
+    results: VecDeque<Value>,
+    /// How many results have been recorded in total, so numbering survives old ones being dropped
+    result_count: usize,
+}
+
+/// Writes `label = value` for an already formatted value, with any lines after the first
+/// indented to line up under it, so matrices stay in columns
+pub fn labeled(label: &str, formatted: &str) -> String {
+    let indent = format!("\n{}", " ".repeat(label.chars().count() + 3));
+    format!("{} = {}", label, formatted.replace('\n', &indent))
+}
+
+/// The candidates closest to `name`, for suggesting what a typo was meant to be.
+///
+/// A candidate is close if it's within an edit distance of 2 (less for names under 4 characters,
+/// where that would match almost anything), or if one of the two is a prefix of the other and at
+/// least 2 characters long. Only the closest are returned, so ties give several matches, sorted
+/// alphabetically. Prefix matches count as a distance of 1.
+pub fn closest_matches<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> Vec<&'a str> {
+    let max_distance = (name.chars().count() / 2).min(2);
+
+    let mut best: Vec<&str> = Vec::new();
+    let mut best_distance = max_distance + 1;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let (shorter, longer) = if name.len() < candidate.len() { (name, candidate) } else { (candidate, name) };
+        let distance = if shorter.chars().count() >= 2 && longer.starts_with(shorter) {
+            1
+        } else {
+            edit_distance(name, candidate)
+        };
+
+        if distance > max_distance {
+            continue;
+        }
+        if distance < best_distance {
+            best_distance = distance;
+            best.clear();
+        }
+        if distance == best_distance {
+            best.push(candidate);
+        }
+    }
+    best.sort_unstable();
+    best.dedup();
+    best
+}
+
+/// Levenshtein distance, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the part of `a` seen so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Combines vectors component by component, `f(f(a, b), c)` and so on, for `bbox_min` and the
+/// like. No vectors gives the empty vector.
+///
+/// Errors with the index of the first vector without as many components as the first one, and a
+/// mismatch naming `op`.
+pub fn fold_components<F: Fn(f64, f64) -> f64 + Sync + Send + Copy>(
+    vectors: &[&Vector],
+    op: &'static str,
+    f: F,
+) -> Result<Vector, (usize, EvalError)> {
+    let Some((first, rest)) = vectors.split_first() else {
+        return Ok(Vec::new().into());
+    };
+    rest.iter()
+        .enumerate()
+        .try_fold((*first).clone(), |acc, (i, vec)| acc.zip_with(vec, op, f).map_err(|err| (i + 1, err)))
+}
+
+/// How many times adaptive Simpson's rule halves an interval before taking the estimate it has
+const MAX_SIMPSON_DEPTH: u32 = 20;
+/// How close adaptive Simpson's rule aims to get to the integral, relative to its size
+const SIMPSON_TOLERANCE: f64 = 1e-10;
+
+/// The integral of `f` from `a` to `b` by Simpson's rule, stopping at the first error from `f`.
+///
+/// With `subdivisions` it uses that many equal intervals, rounded up to an even number.
+/// Without, it keeps halving intervals until their estimates agree to within a tolerance
+/// relative to the whole integral.
+pub fn simpson<E, F: FnMut(f64) -> Result<f64, E>>(mut f: F, a: f64, b: f64, subdivisions: Option<usize>) -> Result<f64, E> {
+    if let Some(n) = subdivisions {
+        let n = (n + n % 2).max(2);
+        let h = (b - a) / n as f64;
+        let mut sum = f(a)? + f(b)?;
+        for i in 1..n {
+            let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+            sum += weight * f(a + i as f64 * h)?;
+        }
+        return Ok(sum * h / 3.0);
+    }
+    let m = (a + b) / 2.0;
+    let (fa, fm, fb) = (f(a)?, f(m)?, f(b)?);
+    let whole = simpson_rule(a, b, fa, fm, fb);
+    let tolerance = SIMPSON_TOLERANCE * whole.abs().max(1.0);
+    adaptive_simpson(&mut f, (a, fa), (m, fm), (b, fb), whole, tolerance, MAX_SIMPSON_DEPTH)
+}
+
+/// Refines `whole`, the estimate over `a` to `b` with `m` halfway, by splitting it in two.
+/// Each point comes with `f` of it, so none are sampled twice
+fn adaptive_simpson<E, F: FnMut(f64) -> Result<f64, E>>(
+    f: &mut F,
+    (a, fa): (f64, f64),
+    (m, fm): (f64, f64),
+    (b, fb): (f64, f64),
+    whole: f64,
+    tolerance: f64,
+    depth: u32,
+) -> Result<f64, E> {
+    let (left_m, right_m) = ((a + m) / 2.0, (m + b) / 2.0);
+    let (f_left_m, f_right_m) = (f(left_m)?, f(right_m)?);
+    let left = simpson_rule(a, m, fa, f_left_m, fm);
+    let right = simpson_rule(m, b, fm, f_right_m, fb);
+    let error = left + right - whole;
+    if depth == 0 || error.abs() <= 15.0 * tolerance {
+        // The halves' error is about a fifteenth of the difference, so that's taken off too
+        return Ok(left + right + error / 15.0);
+    }
+    Ok(adaptive_simpson(f, (a, fa), (left_m, f_left_m), (m, fm), left, tolerance / 2.0, depth - 1)?
+        + adaptive_simpson(f, (m, fm), (right_m, f_right_m), (b, fb), right, tolerance / 2.0, depth - 1)?)
+}
+
+fn simpson_rule(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+/// The derivative of `f` at `at`, by central differences with a step of about `scale` times the
+/// cube root of epsilon.
+///
+/// The step is rounded so it's exactly the distance between `at` and `at + h`. The cube root of
+/// epsilon rather than the square root balances rounding against the error of a central
+/// difference, which shrinks with the square of the step.
+pub fn central_difference<E, F: FnMut(f64) -> Result<f64, E>>(mut f: F, at: f64, scale: f64) -> Result<f64, E> {
+    let step = f64::EPSILON.cbrt() * scale;
+    let step = (at + step) - at;
+    Ok((f(at + step)? - f(at - step)?) / (2.0 * step))
+}
+
+/// How far either side of the guess `find_root` first looks for a sign change, relative to the guess
+const BRACKET_START: f64 = 1e-3;
+/// How many times `find_root` doubles how far it looks for a sign change before giving up
+const BRACKET_EXPANSIONS: u32 = 40;
+
+/// Why `find_root` didn't find a root
+#[derive(Debug, Clone, PartialEq)]
+pub enum RootError<E> {
+    /// From the function
+    Function(E),
+    /// Newton's method stopped getting closer, usually because the derivative was zero, and there
+    /// was no sign change near the guess to bisect. `last` is the closest it got
+    NoBracket { last: f64 },
+    /// It ran out of iterations, and `last` is the closest it got
+    OutOfIterations { iterations: usize, last: f64 },
+}
+
+/// A root of `f` near `guess`, by Newton's method with the derivative from `central_difference`.
+///
+/// Steps that overshoot are halved until they take `f` closer to zero. If none do, it falls back
+/// to bisecting, if there's a sign change either side of the guess. It's found once a step moves less than `tolerance` relative
+/// to the root, or `f` is exactly zero. Newton steps and bisections both count towards
+/// `max_iterations`.
+pub fn find_root<E, F: FnMut(f64) -> Result<f64, E>>(
+    mut f: F,
+    guess: f64,
+    tolerance: f64,
+    max_iterations: usize,
+) -> Result<f64, RootError<E>> {
+    let mut f = |x: f64| f(x).map_err(RootError::Function);
+    let mut iterations = 0;
+    let (mut x, mut fx) = (guess, f(guess)?);
+    while iterations < max_iterations {
+        if fx == 0.0 {
+            return Ok(x);
+        }
+        iterations += 1;
+        // Scaled to `x` even when it's small, so roots at zero are found to the tolerance
+        let slope = central_difference(&mut f, x, x.abs().max(tolerance.sqrt()))?;
+        let step = fx / slope;
+        // Checked first, since rounding near the root can make the last step look like it diverged
+        if step.is_finite() && step.abs() <= tolerance * (x - step).abs().max(1.0) {
+            return Ok(x - step);
+        }
+        match damped_step(&mut f, (x, fx), step, tolerance)? {
+            Some(better) => (x, fx) = better,
+            None => {
+                return match bracket(&mut f, guess)? {
+                    Some(bracket) => bisect(&mut f, bracket, tolerance, (iterations, max_iterations)),
+                    None => Err(RootError::NoBracket { last: x }),
+                }
+            }
+        }
+    }
+    Err(RootError::OutOfIterations { iterations, last: x })
+}
+
+/// The first of `x - step`, `x - step / 2` and so on where `f` is closer to zero than `fx`, with
+/// `f` there. `None` if the step gets within `tolerance` of `x` first, which is Newton's method
+/// diverging
+fn damped_step<E, F: FnMut(f64) -> Result<f64, E>>(
+    f: &mut F,
+    (x, fx): (f64, f64),
+    mut step: f64,
+    tolerance: f64,
+) -> Result<Option<(f64, f64)>, E> {
+    while step.is_finite() && step.abs() > tolerance * x.abs().max(1.0) {
+        let next = x - step;
+        let f_next = f(next)?;
+        if f_next.abs() < fx.abs() {
+            return Ok(Some((next, f_next)));
+        }
+        step /= 2.0;
+    }
+    Ok(None)
+}
+
+/// Two points around `guess` that `f` has opposite signs at, with `f` at the first, looking
+/// further out until there are some or it's looked far enough
+fn bracket<E, F: FnMut(f64) -> Result<f64, E>>(f: &mut F, guess: f64) -> Result<Option<(f64, f64, f64)>, E> {
+    let f_guess = f(guess)?;
+    let mut width = BRACKET_START * guess.abs().max(1.0);
+    for _ in 0..BRACKET_EXPANSIONS {
+        let (low, high) = (guess - width, guess + width);
+        let (f_low, f_high) = (f(low)?, f(high)?);
+        if changes_sign(f_low, f_guess) {
+            return Ok(Some((low, guess, f_low)));
+        }
+        if changes_sign(f_guess, f_high) {
+            return Ok(Some((guess, high, f_guess)));
+        }
+        if changes_sign(f_low, f_high) {
+            return Ok(Some((low, high, f_low)));
+        }
+        width *= 2.0;
+    }
+    Ok(None)
+}
+
+/// Halves the bracket from `bracket` until it's within `tolerance` of the root, counting from
+/// `iterations` so far up to at most `max_iterations`
+fn bisect<E, F: FnMut(f64) -> Result<f64, RootError<E>>>(
+    f: &mut F,
+    (mut low, mut high, mut f_low): (f64, f64, f64),
+    tolerance: f64,
+    (mut iterations, max_iterations): (usize, usize),
+) -> Result<f64, RootError<E>> {
+    let mut mid = low + (high - low) / 2.0;
+    while iterations < max_iterations {
+        iterations += 1;
+        mid = low + (high - low) / 2.0;
+        let f_mid = f(mid)?;
+        if f_mid == 0.0 || (high - low) / 2.0 <= tolerance * mid.abs().max(1.0) {
+            return Ok(mid);
+        }
+        if changes_sign(f_low, f_mid) {
+            high = mid;
+        } else {
+            (low, f_low) = (mid, f_mid);
+        }
+    }
+    Err(RootError::OutOfIterations { iterations, last: mid })
+}
+
+/// Whether there's a root between points `f` is `a` and `b` at, which counts either being zero
+fn changes_sign(a: f64, b: f64) -> bool {
+    a.is_finite() && b.is_finite() && (a == 0.0 || b == 0.0 || (a < 0.0) != (b < 0.0))
+}
+
+/// A variable `.modify` asked to change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifyRequest {
+    pub name: String,
+    /// Set by `.modify!`, which allows changing the variable's type
+    pub force: bool,
+}
+
+/// Formats like `{:e}`, but with the exponent rounded down to a multiple of 3.
+///
+/// This shifts the decimal point in the scientific form rather than dividing, so the digits
+/// stay exactly the shortest representation of `x`.
+fn format_engineering(x: f64, precision: Option<usize>) -> String {
+    let split_exponent = |scientific: &str| -> (String, i32) {
+        let (mantissa, exponent) = scientific
+            .split_once('e')
+            .expect("Scientific formatting always has an exponent");
+        (mantissa.to_owned(), exponent.parse().expect("Exponent is an integer"))
+    };
+
+    let (mut mantissa, mut exponent) = split_exponent(&format!("{:e}", x));
+    if let Some(precision) = precision {
+        // Digits moved in front of the point don't count towards the precision
+        let shift = |exponent: i32| exponent.rem_euclid(3) as usize;
+        let (rounded, rounded_exponent) = split_exponent(&format!("{:.*e}", precision + shift(exponent), x));
+        (mantissa, exponent) = if rounded_exponent == exponent {
+            (rounded, exponent)
+        } else {
+            // Rounding carried up to exactly a power of ten, which can move a different number
+            // of digits in front of the point
+            let sign = if x < 0.0 { "-" } else { "" };
+            let zeros = "0".repeat(precision + shift(rounded_exponent));
+            let point = if zeros.is_empty() { "" } else { "." };
+            (format!("{}1{}{}", sign, point, zeros), rounded_exponent)
+        };
+    }
+    let eng_exponent = exponent.div_euclid(3) * 3;
+
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa.as_str()),
+    };
+    // The mantissa has one digit before the point, and we need to move the point right by up to 2
+    let int_len = 1 + (exponent - eng_exponent) as usize;
+    let digits = format!("{:0<width$}", mantissa.replace('.', ""), width = int_len);
+    let (int_part, frac_part) = digits.split_at(int_len);
+
+    if frac_part.is_empty() {
+        format!("{}{}e{}", sign, int_part, eng_exponent)
+    } else {
+        format!("{}{}.{}e{}", sign, int_part, frac_part, eng_exponent)
+    }
+}
+
+/// The largest denominator `.display nice` tries
+const MAX_NICE_DENOMINATOR: i64 = 64;
+/// Numbers bigger than this are always shown as they are, since a fraction wouldn't be any nicer
+const MAX_NICE_MAGNITUDE: f64 = 1e6;
+/// The most `.display nice` rounds by, relative to the number, however big the epsilon is.
+/// Fractions with denominators up to 64 are at least 1/4096 apart, so this can't pick the wrong one
+const MAX_NICE_TOLERANCE: f64 = 1e-6;
+
+/// `x` as a small fraction or fraction of π, like `1/2` or `3π/4`, if it's within `epsilon` of
+/// one. Integers come out as integers, and exact ones give `None` since they're already nice.
+///
+/// Only meant for display, the value itself is never rounded.
+fn nice_number(x: f64, epsilon: f64) -> Option<String> {
+    if !x.is_finite() || x.fract() == 0.0 || x.abs() > MAX_NICE_MAGNITUDE {
+        return None;
+    }
+    let tolerance = epsilon.min(MAX_NICE_TOLERANCE) * x.abs().max(1.0);
+    // The smallest denominator that's close enough, which is already in lowest terms
+    let fraction = |x: f64| {
+        (1..=MAX_NICE_DENOMINATOR).find_map(|denominator| {
+            let numerator = (x * denominator as f64).round();
+            ((x - numerator / denominator as f64).abs() <= tolerance).then_some((numerator as i64, denominator))
+        })
+    };
+
+    if let Some((numerator, denominator)) = fraction(x) {
+        return Some(match denominator {
+            1 => numerator.to_string(),
+            _ => format!("{}/{}", numerator, denominator),
+        });
+    }
+    let (numerator, denominator) = fraction(x / std::f64::consts::PI)?;
+    let multiple = match numerator {
+        1 => "π".to_owned(),
+        -1 => "-π".to_owned(),
+        _ => format!("{}π", numerator),
+    };
+    Some(match denominator {
+        1 => multiple,
+        _ => format!("{}/{}", multiple, denominator),
+    })
+}
+
+/// How long the parts of evaluating an expression took
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub parse: Duration,
+    pub eval: Duration,
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse: {}, eval: {}", format_duration(self.parse), format_duration(self.eval))
+    }
+}
+
+/// How many variables `.stats` lists
+const TOP_VARIABLES: usize = 5;
+
+/// Counters for `.stats`, kept from the start of the session or the last `.stats reset`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Expressions evaluated, including the ones in assignments and commands
+    pub expressions: usize,
+    /// How many errors of each kind, by `CalcError::kind`
+    pub errors: BTreeMap<&'static str, usize>,
+    /// Time spent evaluating expressions, not counting parsing them
+    pub eval_time: Duration,
+    /// The most components a vector stored or given as a result has had
+    pub largest_vector: usize,
+    /// How many times each variable has been read while evaluating
+    pub references: HashMap<String, usize>,
+}
+
+impl Stats {
+    pub fn record_eval(&mut self, time: Duration) {
+        self.expressions += 1;
+        self.eval_time += time;
+    }
+
+    pub fn record_value(&mut self, value: &Value) {
+        if let Value::Vector(vec) = value {
+            self.largest_vector = self.largest_vector.max(vec.dims());
+        }
+    }
+
+    pub fn record_reference(&mut self, name: &str) {
+        // Only the first reference to a name allocates
+        match self.references.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.references.insert(name.to_owned(), 1);
+            }
+        }
+    }
+
+    /// The `n` most read variables and how often, most first and ties alphabetically
+    pub fn top_variables(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = self.references.iter().map(|(name, &count)| (name.as_str(), count)).collect();
+        counts.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        counts.truncate(n);
+        counts
+    }
+}
+
+/// One counter per line, then the errors and variables indented under their headings
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Expressions evaluated: {}", self.expressions)?;
+        writeln!(f, "Evaluation time: {}", format_duration(self.eval_time))?;
+        writeln!(f, "Largest vector: {} components", self.largest_vector)?;
+        write!(f, "Errors: {}", self.errors.values().sum::<usize>())?;
+        for (kind, count) in &self.errors {
+            write!(f, "\n  {}: {}", kind, count)?;
+        }
+        let top = self.top_variables(TOP_VARIABLES);
+        if top.is_empty() {
+            write!(f, "\nMost used variables: none")
+        } else {
+            write!(f, "\nMost used variables:")?;
+            for (name, count) in top {
+                write!(f, "\n  {}: {}", name, count)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Formats a duration with a unit that keeps the number readable, like `12.3µs`
+fn format_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    if nanos < 1_000 {
+        format!("{}ns", nanos)
+    } else if nanos < 1_000_000 {
+        format!("{:.1}µs", nanos as f64 / 1e3)
+    } else if nanos < 1_000_000_000 {
+        format!("{:.2}ms", nanos as f64 / 1e6)
+    } else {
+        format!("{:.3}s", nanos as f64 / 1e9)
+    }
+}
+
+/// Whether results are shown exactly, or cleaned up for reading. Set with `.display`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayMode {
+    /// Every number as it is
+    #[default]
+    Raw,
+    /// Numbers within the epsilon of a small fraction or fraction of π are shown as one, like
+    /// `1/2` for `0.49999999999`, see `CalculatorState::format_number`
+    Nice,
+}
+
+/// How `.import_vec` expects numbers to be written. Set with `.locale`, and never changes how
+/// numbers are displayed or how typed input is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberLocale {
+    /// `3.14`, with numbers separated by commas or whitespace
+    #[default]
+    Point,
+    /// `3,14`, with numbers separated by semicolons or whitespace. Decimal points are still
+    /// read, so files from `.export_vec` load the same either way
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NumberFormat {
+    /// Normal rust float formatting
+    #[default]
+    Auto,
+    /// `1.5e3`
+    Scientific,
+    /// Like scientific, but the exponent is always a multiple of 3: `15e3`
+    Engineering,
+}
+
+/// The persistent part of a `CalculatorState`: variables and settings, but nothing about the
+/// current session like results, the output sink or pending commands.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateSnapshot {
+    /// Sorted so serialized output is stable
+    pub variables: BTreeMap<String, Value>,
+    pub debug_level: u32,
+    pub echo_assignments: bool,
+    pub number_format: NumberFormat,
+}
+
+const DEFAULT_DEBUG_LEVEL: u32 = 1;
+const DEFAULT_MAX_DISPLAY_COMPONENTS: usize = 11;
+const DEFAULT_EPSILON: f64 = 1e-10;
+/// Angle in radians below which `slerp` is just `lerp`
+const SLERP_LERP_THRESHOLD: f64 = 1e-6;
+const MAX_STORED_RESULTS: usize = 200;
+
+impl Default for CalculatorState {
+    fn default() -> Self {
+        Self {
+            workspaces: BTreeMap::from([(DEFAULT_WORKSPACE.to_owned(), Workspace::default())]),
+            active_workspace: DEFAULT_WORKSPACE.to_owned(),
+            debug_level: DEFAULT_DEBUG_LEVEL,
+            echo_assignments: true,
+            quiet: false,
+            dirty: false,
+            session_file: None,
+            exit_requested: false,
+            pending_modify: None,
+            last_timing: None,
+            stats: RefCell::new(Stats::default()),
+            output: RefCell::new(Box::new(NullOutput)),
+            transcript: RefCell::new(None),
+            number_format: NumberFormat::Auto,
+            precision: None,
+            display_mode: DisplayMode::Raw,
+            locale: NumberLocale::Point,
+            max_display_components: Some(DEFAULT_MAX_DISPLAY_COMPONENTS),
+            full_display_requested: false,
+            plot_width: 60,
+            plot_height: 12,
+            limits: Limits::default(),
+            raise_limits: true,
+            strict: true,
+            strict_vars: true,
+            units: true,
+            warnings: true,
+            filesystem: true,
+            json: false,
+            key_bindings: None,
+            epsilon: DEFAULT_EPSILON,
+            results: VecDeque::new(),
+            result_count: 0,
+        }
+    }
+}
+
+impl CalculatorState {
+    pub fn new() -> Self { 
+        Self::default()
+     }
+
+    pub fn new_with_variables(variables: HashMap<String, Value>) -> Self {
+        let mut state = Self::default();
+        state.workspace_mut().variables = variables;
+        state
+    }
+
+    /// The active workspace
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspaces[&self.active_workspace]
+    }
+
+    pub(crate) fn workspace_mut(&mut self) -> &mut Workspace {
+        self.workspaces
+            .get_mut(&self.active_workspace)
+            .expect("The active workspace always exists")
+    }
+
+    pub fn active_workspace(&self) -> &str {
+        &self.active_workspace
+    }
+
+    /// Every workspace, sorted by name
+    pub fn workspaces(&self) -> impl Iterator<Item = (&str, &Workspace)> {
+        self.workspaces.iter().map(|(name, workspace)| (name.as_str(), workspace))
+    }
+
+    /// Makes `name` the active workspace, creating it empty if it's new. Gives whether it was created
+    pub fn switch_workspace(&mut self, name: &str) -> bool {
+        let created = !self.workspaces.contains_key(name);
+        if created {
+            self.workspaces.insert(name.to_owned(), Workspace::default());
+        }
+        self.active_workspace = name.to_owned();
+        created
+    }
+
+    /// Creates the workspace `to` with a copy of the variables in `from`, which stays active if it was
+    pub fn copy_workspace(&mut self, from: &str, to: &str) -> Result<(), StateError> {
+        let workspace = self
+            .workspaces
+            .get(from)
+            .ok_or_else(|| StateError::NoWorkspace {
+                name: from.to_owned(),
+                suggestions: closest_matches(from, self.workspaces.keys().map(String::as_str))
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect(),
+            })?
+            .clone();
+        if self.workspaces.contains_key(to) {
+            return Err(StateError::WorkspaceExists { name: to.to_owned() });
+        }
+        self.workspaces.insert(to.to_owned(), workspace);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Sets a variable without any checks, keeping the value it replaces in its history
+    pub fn set_var(&mut self, key: String, value: Value) {
+        self.dirty = true;
+        self.stats.get_mut().record_value(&value);
+        self.workspace_mut().set(key, value);
+    }
+
+    /// Changes an existing variable, as long as the new value has the same type
+    pub fn change_var(&mut self, key: String, value: Value) -> Result<(), StateError> {
+        let old_val = self
+            .get_var(&key)
+            .ok_or_else(|| StateError::NotFound { name: key.clone() })?;
+
+        if !value.compare_types(old_val) {
+            return Err(StateError::TypeMismatch {
+                expected: old_val.type_name(),
+                got: value.type_name(),
+                name: key,
+            });
+        }
+        self.assign_var(key, value)
+    }
+
+    /// Like `change_var`, but allows changing the type, e.g. a number into a vector. A type the
+    /// variable was declared with still has to match
+    pub fn force_change_var(&mut self, key: String, value: Value) -> Result<(), StateError> {
+        if !self.contains_key(&key) {
+            return Err(StateError::NotFound { name: key });
+        }
+        self.assign_var(key, value)
+    }
+
+    /// Sets a variable given with `--set`, which is marked as coming from the command line until
+    /// it's assigned again
+    pub fn set_command_line_var(&mut self, key: String, value: Value) -> Result<(), StateError> {
+        self.assign_var(key.clone(), value)?;
+        self.workspace_mut().variable_info.entry(key).or_default().from_command_line = true;
+        Ok(())
+    }
+
+    /// Sets one component of a vector variable, keeping the old vector in its history like any
+    /// other assignment. Nothing changes if the variable isn't a vector or the index is past its end
+    pub fn set_component(&mut self, key: &str, index: usize, value: f64) -> Result<(), CalcError> {
+        let vec = match self.get_var(key) {
+            Some(Value::Vector(vec)) => vec,
+            Some(other) => {
+                return Err(EvalError::WrongType {
+                    expected: "vector",
+                    found: other.type_name(),
+                    span: None,
+                }
+                .into())
+            }
+            None => return Err(StateError::NotFound { name: key.to_owned() }.into()),
+        };
+        // Changing the copy leaves the stored vector alone if the index is out of range
+        let mut vec = vec.clone();
+        vec.set(index, value)?;
+        Ok(self.assign_var(key.to_owned(), Value::Vector(vec))?)
+    }
+
+    /// Sets a variable, as long as the value matches the type it was declared with, if any
+    pub fn assign_var(&mut self, key: String, value: Value) -> Result<(), StateError> {
+        if key == LAST_RESULT_NAME {
+            return Err(StateError::Reserved { name: key });
+        }
+        if let Some(declared) = self.declared_type(&key) {
+            if !declared.accepts(&value) {
+                return Err(StateError::DeclaredTypeMismatch {
+                    name: key,
+                    declared,
+                    got: value.shape_name(),
+                });
+            }
+        }
+        self.set_var(key, value);
+        Ok(())
+    }
+
+    /// Sets a variable and replaces its declared type, `None` making it take any value again
+    pub fn declare_var(&mut self, key: String, declared: Option<TypeConstraint>, value: Value) -> Result<(), StateError> {
+        if key == LAST_RESULT_NAME {
+            return Err(StateError::Reserved { name: key });
+        }
+        match declared {
+            Some(declared) if !declared.accepts(&value) => Err(StateError::DeclaredTypeMismatch {
+                name: key,
+                declared,
+                got: value.shape_name(),
+            }),
+            Some(declared) => {
+                self.workspace_mut().variable_info.entry(key.clone()).or_default().declared = Some(declared);
+                self.set_var(key, value);
+                Ok(())
+            }
+            None => {
+                if let Some(info) = self.workspace_mut().variable_info.get_mut(&key) {
+                    info.declared = None;
+                }
+                self.set_var(key, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// The type the variable was declared with, if it was
+    pub fn declared_type(&self, key: &str) -> Option<TypeConstraint> {
+        self.workspace().declared_type(key)
+    }
+
+    /// The value the variable had `back` assignments ago in the active workspace, see `Workspace::previous`
+    pub fn previous_var(&self, key: &str, back: usize) -> Option<&Value> {
+        self.workspace().previous(key, back)
+    }
+
+    pub fn get_var(&self, key: &str) -> Option<&Value> {
+        self.workspace().get(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.workspace().variables.contains_key(key)
+    }
+
+    /// Defined variables with names close to `name`, for "did you mean" hints
+    pub fn suggest_variables(&self, name: &str) -> Vec<String> {
+        closest_matches(name, self.workspace().variables.keys().map(String::as_str))
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The settings and the active workspace's variables
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            variables: self
+                .workspace()
+                .variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            debug_level: self.debug_level,
+            echo_assignments: self.echo_assignments,
+            number_format: self.number_format,
+        }
+    }
+
+    /// Replaces the active workspace's variables and the settings with the ones from `snapshot`
+    pub fn restore(&mut self, snapshot: StateSnapshot) {
+        let workspace = self.workspace_mut();
+        workspace.variables = snapshot.variables.into_iter().collect();
+        // Snapshots don't have declared types, and the old ones may not fit the new values
+        workspace.variable_info.clear();
+        self.debug_level = snapshot.debug_level;
+        self.echo_assignments = snapshot.echo_assignments;
+        self.number_format = snapshot.number_format;
+        self.dirty = true;
+    }
+
+    /// Number of the most recent result, 0 if there hasn't been one
+    pub fn result_count(&self) -> usize {
+        self.result_count
+    }
+
+    /// Records an expression result, returning its 1-based number
+    pub fn push_result(&mut self, value: Value) -> usize {
+        self.stats.get_mut().record_value(&value);
+        if self.results.len() == MAX_STORED_RESULTS {
+            self.results.pop_front();
+        }
+        self.results.push_back(value);
+        self.result_count += 1;
+        self.result_count
+    }
+
+    /// Gets result number `n`, or counting back from the latest if `n` is negative (`-1` is the latest)
+    pub fn get_result(&self, n: i64) -> Option<&Value> {
+        let oldest_stored = self.result_count - self.results.len() + 1;
+        let number = if n < 0 {
+            self.result_count as i64 + 1 + n
+        } else {
+            n
+        };
+        if number < oldest_stored as i64 {
+            return None;
+        }
+        self.results.get((number as usize) - oldest_stored)
+    }
+
+    /// Formats a number for display according to `number_format`.
+    ///
+    /// This is only for showing values to the user, save files use `Value::to_literal` so they stay
+    /// reparseable.
+    pub fn format_f64(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return format!("{}", x);
+        }
+        match (self.number_format, self.precision) {
+            (NumberFormat::Auto, None) => format!("{}", x),
+            (NumberFormat::Auto, Some(precision)) => format!("{:.*}", precision, x),
+            (NumberFormat::Scientific, None) => format!("{:e}", x),
+            (NumberFormat::Scientific, Some(precision)) => format!("{:.*e}", precision, x),
+            (NumberFormat::Engineering, precision) => format_engineering(x, precision),
+        }
+    }
+
+    /// Formats a real number for display, as a fraction if `display_mode` is `Nice` and it's
+    /// close enough to one. Setting a precision shows the digits instead.
+    ///
+    /// Complex and quaternion parts don't use this, since `1/2i` would be ambiguous.
+    pub fn format_number(&self, x: f64) -> String {
+        let nice = match (self.display_mode, self.precision) {
+            (DisplayMode::Nice, None) => nice_number(x, self.epsilon),
+            _ => None,
+        };
+        nice.unwrap_or_else(|| self.format_f64(x))
+    }
+
+    /// Same layout as `Value`'s `Display`, but with real numbers going through `format_number`, and
+    /// vectors longer than `max_display_components` cut short in the middle
+    pub fn format_value(&self, value: &Value) -> String {
+        self.format_value_limited(value, self.max_display_components)
+    }
+
+    /// Like `format_value`, but always shows every component
+    pub fn format_value_full(&self, value: &Value) -> String {
+        self.format_value_limited(value, None)
+    }
+
+    /// Like `format_value_full`, but always on one line, with matrices written like their literal
+    pub fn format_value_line(&self, value: &Value) -> String {
+        match value {
+            Value::Matrix(matrix) => {
+                let rows: Vec<String> = (0..matrix.shape().0)
+                    .map(|row| {
+                        let components: Vec<String> = matrix.row(row).iter().map(|&x| self.format_number(x)).collect();
+                        format!("<{}>", components.join(", "))
+                    })
+                    .collect();
+                format!("[{}]", rows.join(", "))
+            }
+            _ => self.format_value_full(value),
+        }
+    }
+
+    fn format_value_limited(&self, value: &Value, max_components: Option<usize>) -> String {
+        let vec = match value {
+            Value::Number(val) => return self.format_number(*val),
+            Value::Vector(vec) => vec,
+            Value::Matrix(matrix) => return align_rows(matrix, |&x| self.format_number(x)),
+            Value::Quaternion(q) => return quaternion_terms(q, |x| self.format_f64(x)),
+            Value::Complex(z) => return complex_terms(z, |x| self.format_f64(x)),
+            Value::Quantity(q) if self.units => return format!("{} {}", self.format_number(q.value), q.unit),
+            Value::Quantity(q) => return self.format_number(q.value),
+        };
+        let format_all = |components: &[f64]| -> Vec<String> {
+            components.iter().map(|&x| self.format_number(x)).collect()
+        };
+
+        let components = match max_components {
+            Some(max) if vec.dims() > max => {
+                // Mostly the start, with a few from the end so it's clear where it stops
+                let tail = (max / 3).min(3);
+                let head = max - tail;
+                let mut components = format_all(&vec.as_slice()[..head]);
+                components.push(format!("… ({} components)", vec.dims()));
+                components.extend(format_all(&vec.as_slice()[vec.dims() - tail..]));
+                components
+            }
+            _ => format_all(vec),
+        };
+        format!("<{}>", components.join(", "))
+    }
+
+    /// Replaces where messages get sent, they are discarded by default
+    pub fn set_output<O: Output + 'static>(&mut self, output: O) {
+        self.output = RefCell::new(Box::new(output));
+    }
+
+    /// Sends a debug message if the debug level is at least `min_debug_level`.
+    ///
+    /// `module` is a module path like `vector_calc::parser`, of which only the last part is shown.
+    /// Inside the crate the `print_debug!` macro fills it in.
+    pub fn print_debug(&self, min_debug_level: u32, module: &str, args: std::fmt::Arguments) {
+        if !self.quiet && self.debug_level >= min_debug_level {
+            let module = module.rsplit("::").next().unwrap_or(module);
+            self.output.borrow_mut().debug(min_debug_level, module, args);
+        }
+    }
+
+    /// Sends a status message, unless running quietly
+    pub fn print_info(&self, string: &str) {
+        if !self.quiet {
+            self.output.borrow_mut().info(string);
+            self.record(string);
+        }
+    }
+
+    /// Sends the answer to something the user explicitly asked for, even when running quietly
+    pub fn print_reply(&self, string: &str) {
+        self.output.borrow_mut().info(string);
+        self.record(string);
+    }
+
+    /// Sends a warning, unless running quietly or warnings are off
+    pub fn print_warning(&self, string: &str) {
+        if self.warnings && !self.quiet {
+            self.output.borrow_mut().warn(string);
+            self.record(&format!("Warning: {}", string));
+        }
+    }
+
+    /// Sends the JSON object reporting a line
+    pub fn print_json(&self, line: &str) {
+        self.output.borrow_mut().json(line);
+    }
+
+    /// Sends the result of a line, labeled with its number unless running quietly.
+    ///
+    /// Uses `format_value_full` once if `.showall` asked for it, `format_value` otherwise.
+    pub fn print_result(&mut self, value: &Value) {
+        let formatted = if std::mem::take(&mut self.full_display_requested) {
+            self.format_value_full(value)
+        } else {
+            self.format_value(value)
+        };
+        let formatted = if self.quiet {
+            formatted
+        } else {
+            labeled(&format!("[{}]", self.result_count()), &formatted)
+        };
+        self.output.borrow_mut().result(value, &formatted);
+        self.record(&formatted);
+    }
+
+    /// Starts appending the session to a transcript file, replacing any transcript already being written
+    pub fn start_transcript(&mut self, path: &str) -> std::io::Result<()> {
+        let transcript = Transcript::append_to(path)?;
+        if let Some(mut old) = self.transcript.replace(Some(transcript)) {
+            old.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Stops writing the transcript, leaving out the line that stopped it, and gives where it
+    /// was written if there was one
+    pub fn stop_transcript(&mut self) -> std::io::Result<Option<std::path::PathBuf>> {
+        self.transcript.take().map(Transcript::finish).transpose()
+    }
+
+    /// The counters `.stats` shows
+    pub fn stats(&self) -> std::cell::Ref<'_, Stats> {
+        self.stats.borrow()
+    }
+
+    pub fn reset_stats(&mut self) {
+        *self.stats.get_mut() = Stats::default();
+    }
+
+    /// Updates the counters, which works while evaluating since it only needs to borrow the state
+    pub fn update_stats<F: FnOnce(&mut Stats)>(&self, update: F) {
+        update(&mut self.stats.borrow_mut());
+    }
+
+    /// Adds an input line to the transcript, if one is being written
+    pub fn record_input(&self, line: &str) {
+        self.write_transcript(|transcript| transcript.input(line));
+    }
+
+    /// Adds an error to the transcript the way `CalcError::render` shows it, since errors
+    /// don't go through the output
+    pub fn record_error(&self, err: &CalcError, input: &str) {
+        self.record(&err.render(input));
+    }
+
+    /// Writes out what the transcript has buffered, so it isn't lost if the program stops
+    pub fn flush_transcript(&self) {
+        self.write_transcript(Transcript::flush);
+    }
+
+    fn record(&self, text: &str) {
+        self.write_transcript(|transcript| transcript.output(text));
+    }
+
+    /// Stops the transcript if writing to it fails, rather than failing every line after
+    fn write_transcript<F: FnOnce(&mut Transcript) -> std::io::Result<()>>(&self, write: F) {
+        let mut slot = self.transcript.borrow_mut();
+        let Some(transcript) = slot.as_mut() else {
+            return;
+        };
+        if let Err(err) = write(transcript) {
+            let msg = format!("Couldn't write to {}, stopped the transcript: {}", transcript.path().display(), err);
+            *slot = None;
+            drop(slot);
+            self.output.borrow_mut().warn(&msg);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Conversions between [`Vector`] and other libraries' vector types, with the `nalgebra` and
+//! `glam` features.
+//!
+//! `f32` components are widened going into a `Vector` and rounded coming out of one. Converting to
+//! a fixed size type is a `TryFrom` that errors unless the number of components matches.
+
+use crate::error::EvalError;
+use crate::helper::Vector;
+
+fn check_dims(vec: &Vector, type_name: &'static str, dims: usize) -> Result<(), EvalError> {
+    if vec.dims() != dims {
+        return Err(EvalError::UnsupportedDimensions {
+            op: type_name,
+            expected: dims,
+            found: vec.dims(),
+            span: None,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "nalgebra")]
+macro_rules! nalgebra_conversions {
+    ($scalar:ty) => {
+        impl<const N: usize> From<nalgebra::SVector<$scalar, N>> for Vector {
+            fn from(source: nalgebra::SVector<$scalar, N>) -> Self {
+                source.iter().map(|&x| x as f64).collect()
+            }
+        }
+
+        impl<const N: usize> TryFrom<&Vector> for nalgebra::SVector<$scalar, N> {
+            type Error = EvalError;
+
+            fn try_from(vec: &Vector) -> Result<Self, Self::Error> {
+                check_dims(vec, "nalgebra::SVector", N)?;
+                Ok(Self::from_iterator(vec.iter().map(|&x| x as $scalar)))
+            }
+        }
+
+        impl From<nalgebra::DVector<$scalar>> for Vector {
+            fn from(source: nalgebra::DVector<$scalar>) -> Self {
+                source.iter().map(|&x| x as f64).collect()
+            }
+        }
+
+        impl From<&Vector> for nalgebra::DVector<$scalar> {
+            fn from(vec: &Vector) -> Self {
+                Self::from_iterator(vec.dims(), vec.iter().map(|&x| x as $scalar))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "nalgebra")]
+nalgebra_conversions!(f32);
+#[cfg(feature = "nalgebra")]
+nalgebra_conversions!(f64);
+
+#[cfg(feature = "glam")]
+macro_rules! glam_conversions {
+    ($glam:ident, $scalar:ty, $dims:literal) => {
+        impl From<glam::$glam> for Vector {
+            fn from(source: glam::$glam) -> Self {
+                source.to_array().iter().map(|&x| x as f64).collect()
+            }
+        }
+
+        impl TryFrom<&Vector> for glam::$glam {
+            type Error = EvalError;
+
+            fn try_from(vec: &Vector) -> Result<Self, Self::Error> {
+                check_dims(vec, concat!("glam::", stringify!($glam)), $dims)?;
+                let mut components = [0.0; $dims];
+                for (component, &x) in components.iter_mut().zip(vec.iter()) {
+                    *component = x as $scalar;
+                }
+                Ok(Self::from_array(components))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "glam")]
+glam_conversions!(Vec2, f32, 2);
+#[cfg(feature = "glam")]
+glam_conversions!(Vec3, f32, 3);
+#[cfg(feature = "glam")]
+glam_conversions!(Vec4, f32, 4);
+#[cfg(feature = "glam")]
+glam_conversions!(DVec2, f64, 2);
+#[cfg(feature = "glam")]
+glam_conversions!(DVec3, f64, 3);
+#[cfg(feature = "glam")]
+glam_conversions!(DVec4, f64, 4);
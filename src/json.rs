@@ -0,0 +1,94 @@
+//! Reporting each line as one JSON object, for `--json` and `.json`, which needs the `json` feature.
+//!
+//! A line that worked is `{"ok":true,"type":"vector","value":[1.0,2.0]}`, or just `{"ok":true}`
+//! without a result. One that failed is
+//! `{"ok":false,"error_kind":"TypeMismatch","message":"...","span":[3,10]}`, with a `null` span
+//! when the error doesn't point at part of the line.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+#[cfg(feature = "json")]
+use crate::error::{CalcError, Span};
+#[cfg(feature = "json")]
+use crate::helper::{Complex, Matrix, Quaternion, Value, Vector};
+
+/// Structs rather than `json!`, which would sort the keys and put `ok` in the middle
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct Success<'a> {
+    ok: bool,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    type_name: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Inner<'a>>,
+}
+
+/// A value without the enum around it
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Inner<'a> {
+    Number(f64),
+    Vector(&'a Vector),
+    Matrix(&'a Matrix),
+    Quaternion(&'a Quaternion),
+    Complex(&'a Complex),
+    Quantity { value: f64, unit: String },
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct Failure {
+    ok: bool,
+    error_kind: &'static str,
+    message: String,
+    span: Option<Span>,
+}
+
+/// Whether this build can report lines as JSON, with a message for the user if it can't
+#[cfg(feature = "json")]
+pub fn check_available() -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(not(feature = "json"))]
+pub fn check_available() -> Result<(), String> {
+    Err("this build doesn't support JSON output, rebuild with `--features json`".to_owned())
+}
+
+/// The object for a line that worked, with its result if it had one. Values are in the same form
+/// as the `serde` feature gives them, without the enum around them. Infinity and NaN are `null`
+#[cfg(feature = "json")]
+pub fn success(value: Option<&Value>) -> String {
+    let inner = value.map(|value| match value {
+        Value::Number(x) => Inner::Number(*x),
+        Value::Vector(vec) => Inner::Vector(vec),
+        Value::Matrix(matrix) => Inner::Matrix(matrix),
+        Value::Quaternion(q) => Inner::Quaternion(q),
+        Value::Complex(z) => Inner::Complex(z),
+        // The unit as it's written rather than its exponents, so it can be shown as is
+        Value::Quantity(q) => Inner::Quantity {
+            value: q.value,
+            unit: q.unit.to_string(),
+        },
+    });
+    let success = Success {
+        ok: true,
+        type_name: value.map(Value::type_name),
+        value: inner,
+    };
+    serde_json::to_string(&success).expect("Results always serialize")
+}
+
+/// The object for a line that failed
+#[cfg(feature = "json")]
+pub fn failure(err: &CalcError) -> String {
+    let failure = Failure {
+        ok: false,
+        error_kind: err.name(),
+        message: err.message(),
+        span: err.span(),
+    };
+    serde_json::to_string(&failure).expect("Errors always serialize")
+}
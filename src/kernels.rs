@@ -0,0 +1,139 @@
+//! The loops over vector components behind the arithmetic.
+//!
+//! Short vectors always take the plain loops in [`scalar`]. From [`LARGE_THRESHOLD`] components,
+//! the `simd` feature sums with explicit SIMD lanes and the `parallel` feature splits the work
+//! across threads. Both add the components up in a different order than the plain loops, so sums
+//! like `dot` and `mag` can differ from them in the last few bits. Element-wise results are the same.
+//!
+//! The [`scalar`] versions are the reference the others are checked against.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Vectors this long or longer use the faster versions, shorter ones aren't worth the setup
+pub const LARGE_THRESHOLD: usize = 10_000;
+
+/// How many components each thread works on at a time
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK: usize = 4_096;
+
+/// Splitting the work up only pays off with more than one thread to give it to
+#[cfg(feature = "parallel")]
+fn use_threads(len: usize) -> bool {
+    len >= LARGE_THRESHOLD && rayon::current_num_threads() > 1
+}
+
+/// The sum of the products of matching components, which has to be the same length
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < LARGE_THRESHOLD {
+        return scalar::dot(a, b);
+    }
+    #[cfg(feature = "parallel")]
+    if use_threads(a.len()) {
+        return a
+            .par_chunks(PARALLEL_CHUNK)
+            .zip(b.par_chunks(PARALLEL_CHUNK))
+            .map(|(a, b)| dot_chunk(a, b))
+            .reduce(|| 0.0, |x, y| x + y);
+    }
+    dot_chunk(a, b)
+}
+
+fn dot_chunk(a: &[f64], b: &[f64]) -> f64 {
+    #[cfg(feature = "simd")]
+    return simd::dot(a, b);
+    #[cfg(not(feature = "simd"))]
+    scalar::dot(a, b)
+}
+
+/// Sets each component of `a` to `f` of it and the matching one in `b`
+pub fn zip_in_place<F: Fn(f64, f64) -> f64 + Sync + Send>(a: &mut [f64], b: &[f64], f: F) {
+    #[cfg(feature = "parallel")]
+    if use_threads(a.len()) {
+        a.par_iter_mut()
+            .zip(b.par_iter())
+            .with_min_len(PARALLEL_CHUNK)
+            .for_each(|(x, &y)| *x = f(*x, y));
+        return;
+    }
+    scalar::zip_in_place(a, b, f)
+}
+
+/// Like `zip_in_place`, but collects the results into a new buffer
+pub fn zip(a: &[f64], b: &[f64], f: impl Fn(f64, f64) -> f64 + Sync + Send) -> Vec<f64> {
+    #[cfg(feature = "parallel")]
+    if use_threads(a.len()) {
+        return a
+            .par_iter()
+            .zip(b.par_iter())
+            .with_min_len(PARALLEL_CHUNK)
+            .map(|(&x, &y)| f(x, y))
+            .collect();
+    }
+    scalar::zip(a, b, f)
+}
+
+/// Sets each component of `a` to `f` of it
+pub fn map_in_place<F: Fn(f64) -> f64 + Sync + Send>(a: &mut [f64], f: F) {
+    #[cfg(feature = "parallel")]
+    if use_threads(a.len()) {
+        a.par_iter_mut().with_min_len(PARALLEL_CHUNK).for_each(|x| *x = f(*x));
+        return;
+    }
+    scalar::map_in_place(a, f)
+}
+
+/// Like `map_in_place`, but collects the results into a new buffer
+pub fn map(a: &[f64], f: impl Fn(f64) -> f64 + Sync + Send) -> Vec<f64> {
+    #[cfg(feature = "parallel")]
+    if use_threads(a.len()) {
+        return a.par_iter().with_min_len(PARALLEL_CHUNK).map(|&x| f(x)).collect();
+    }
+    scalar::map(a, f)
+}
+
+/// One component at a time, in order.
+///
+/// The compiler already vectorizes the element-wise loops, only the sums are held back by having
+/// to add in order.
+pub mod scalar {
+    pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+        // `sum` starts from -0.0, which would make the dot product of empty vectors -0
+        a.iter().zip(b).fold(0.0, |sum, (&x, &y)| sum + x * y)
+    }
+
+    pub fn zip_in_place(a: &mut [f64], b: &[f64], f: impl Fn(f64, f64) -> f64) {
+        a.iter_mut().zip(b).for_each(|(x, &y)| *x = f(*x, y));
+    }
+
+    pub fn zip(a: &[f64], b: &[f64], f: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+        a.iter().zip(b).map(|(&x, &y)| f(x, y)).collect()
+    }
+
+    pub fn map_in_place(a: &mut [f64], f: impl Fn(f64) -> f64) {
+        a.iter_mut().for_each(|x| *x = f(*x));
+    }
+
+    pub fn map(a: &[f64], f: impl Fn(f64) -> f64) -> Vec<f64> {
+        a.iter().map(|&x| f(x)).collect()
+    }
+}
+
+#[cfg(feature = "simd")]
+mod simd {
+    use wide::f64x4;
+
+    /// Keeps four running sums, one per lane, and adds them together at the end
+    pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+        let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+        let rest = super::scalar::dot(a_chunks.remainder(), b_chunks.remainder());
+        let sums = a_chunks.zip(b_chunks).fold(f64x4::ZERO, |sums, (x, y)| {
+            sums + lanes(x) * lanes(y)
+        });
+        sums.reduce_add() + rest
+    }
+
+    fn lanes(chunk: &[f64]) -> f64x4 {
+        f64x4::new(chunk.try_into().expect("chunks_exact(4)"))
+    }
+}
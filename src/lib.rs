@@ -0,0 +1,18 @@
+pub mod ast;
+pub mod builtins;
+pub mod clipboard;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod helper;
+pub mod json;
+#[cfg(any(feature = "nalgebra", feature = "glam"))]
+mod interop;
+pub mod kernels;
+pub mod output;
+pub mod parser;
+pub mod plot;
+pub mod repl;
+pub mod version;
+#[cfg(feature = "wasm")]
+pub mod wasm;
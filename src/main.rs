@@ -2,48 +2,397 @@
 //#[macro_use]
 //extern crate pest_derive;
 
+use std::io::{BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use colored::Colorize;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{Cmd, ConditionalEventHandler, Config, EditMode, Editor, Event, EventContext, EventHandler, KeyEvent};
+use rustyline::RepeatCount;
+
+mod completion;
+mod config;
+#[cfg(feature = "json")]
+mod server;
+
+use completion::ReplHelper;
+use config::Options;
+use vector_calc::helper::{CalculatorState, DEFAULT_WORKSPACE};
+use vector_calc::output::{JsonOutput, StdoutOutput};
+use vector_calc::error::CalcError;
+use vector_calc::parser;
+use vector_calc::repl::{run_line, Input, LineEditor, LineOutcome, Prompt};
+use vector_calc::version;
+
+const USAGE: &str = "\
+Usage: vector_calc [OPTIONS] [SCRIPT]
+
+Runs SCRIPT, or the lines piped to stdin, or starts the REPL.
+
+Options:
+      --history PATH       Keep the REPL history in PATH
+      --no-history         Don't keep the REPL history
+      --history-size N     Keep at most N history entries
+  -q, --quiet              Only print results and errors
+  -v, --verbose            Start at debug level 3
+      --fail-fast          Stop a script at the first line that fails
+      --json               Print one JSON object for each line, and everything else to stderr
+      --serve ADDR         Answer lines sent to ADDR, like 127.0.0.1:7878 or a Unix socket path
+      --serve-allow-fs     Let server connections use commands that read and write files
+      --limit NAME=VALUE   Change a limit, like max_vector_len=1000. See .limits
+      --set NAME=VALUE     Set a variable before the script runs, like mass=3.5
+      --vi                 Use vi key bindings
+      --emacs              Use emacs key bindings, the default
+  -h, --help               Print this and exit
+  -V, --version            Print the version and exit";
+
+/// Exit code for a script or piped input where a line failed
+const EXIT_LINE_FAILED: i32 = 1;
+/// Exit code for bad arguments, or a script that couldn't be read
+const EXIT_BAD_INPUT: i32 = 2;
+
+/// The terminal side of the REPL, saving each history entry to the history file right away
+struct Terminal {
+    rl: Editor<ReplHelper>,
+    history_path: Option<PathBuf>,
+    /// Whether the line was empty when Ctrl-C was last pressed, since rustyline doesn't say
+    interrupted_empty: Arc<AtomicBool>,
+}
+
+/// Notes whether anything was typed when Ctrl-C is pressed, then lets it interrupt as usual
+struct CtrlC(Arc<AtomicBool>);
+
+impl ConditionalEventHandler for CtrlC {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        self.0.store(ctx.line().is_empty(), Ordering::Relaxed);
+        None
+    }
+}
+
+impl LineEditor for Terminal {
+    fn readline_with_initial(&mut self, prompt: &str, initial: &str) -> Option<String> {
+        match self.rl.readline_with_initial(prompt, (initial, "")) {
+            Ok(input) => Some(input),
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => None,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                None
+            }
+        }
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        if self.rl.add_history_entry(line) {
+            // Append right away so `.exit` or a crash doesn't lose it
+            if let Some(path) = &self.history_path {
+                if let Err(err) = self.rl.append_history(path) {
+                    eprintln!("Couldn't save history to {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
 
-pub mod helper;
-pub mod parser;
+    fn show_error(&mut self, err: &CalcError, input: &str) {
+        render_error(err, input, None);
+    }
+}
+
+/// Lines from a script or piped stdin, with errors labeled by where they came from
+struct Script<R> {
+    /// The file name, or `<stdin>`
+    name: String,
+    lines: std::io::Lines<R>,
+    line_num: usize,
+    /// Errors shown so far, including ones from answering `.modify`
+    errors: usize,
+    /// Set if reading stopped early because of an error
+    read_error: Option<std::io::Error>,
+}
 
-use helper::CalculatorState;
+impl<R: BufRead> Script<R> {
+    fn new(name: String, reader: R) -> Self {
+        Self {
+            name,
+            lines: reader.lines(),
+            line_num: 0,
+            errors: 0,
+            read_error: None,
+        }
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        match self.lines.next()? {
+            Ok(line) => {
+                self.line_num += 1;
+                Some(line)
+            }
+            Err(err) => {
+                self.read_error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+impl<R: BufRead> LineEditor for Script<R> {
+    /// The answer to `.modify` is the next line
+    fn readline_with_initial(&mut self, _prompt: &str, _initial: &str) -> Option<String> {
+        self.next_line()
+    }
+
+    /// Scripts don't go in the history
+    fn add_history_entry(&mut self, _line: &str) {}
+
+    fn show_error(&mut self, err: &CalcError, input: &str) {
+        self.errors += 1;
+        render_error(err, input, Some(&format!("{}:{}", self.name, self.line_num)));
+    }
+}
+
+/// Runs every line of a script, skipping blank ones, and gives the exit code: 0 if every line
+/// worked, `EXIT_LINE_FAILED` if one didn't, and `EXIT_BAD_INPUT` if reading it failed.
+///
+/// With `fail_fast` it stops at the first line that fails, otherwise it carries on.
+fn run_script<R: BufRead>(state: &mut CalculatorState, mut script: Script<R>, fail_fast: bool) -> i32 {
+    while let Some(line) = script.next_line() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let outcome = run_line_switching_output(state, &mut script, &line);
+        if outcome == LineOutcome::Exit || (fail_fast && script.errors > 0) {
+            break;
+        }
+    }
+
+    if let Some(err) = script.read_error {
+        eprintln!("Couldn't read {}: {}", script.name, err);
+        EXIT_BAD_INPUT
+    } else if script.errors > 0 {
+        EXIT_LINE_FAILED
+    } else {
+        0
+    }
+}
+
+/// Sends messages to stdout, or in JSON mode only the JSON, with everything else on stderr
+fn set_output(state: &mut CalculatorState) {
+    if state.json {
+        state.set_output(JsonOutput);
+    } else {
+        state.set_output(StdoutOutput);
+    }
+}
+
+/// Runs a line, switching the output if it was `.json` turning JSON mode on or off
+fn run_line_switching_output<E: LineEditor>(state: &mut CalculatorState, editor: &mut E, line: &str) -> LineOutcome {
+    let json = state.json;
+    let outcome = run_line(state, editor, line);
+    if state.json != json {
+        set_output(state);
+    }
+    outcome
+}
+
+/// Prints an error to stderr as a colored heading, followed by the input with
+/// the offending part underlined when the error knows where it happened.
+///
+/// `location` is where the input came from, like `script.txt:3`, when it's not being typed in.
+fn render_error(err: &CalcError, input: &str, location: Option<&str>) {
+    let heading = format!("{}:", err.kind()).red().bold();
+    match location {
+        Some(location) => eprintln!("{}: {} {}", location, heading, err.message()),
+        None => eprintln!("{} {}", heading, err.message()),
+    }
+
+    if let Some((start, end)) = err.span() {
+        let start = start.min(input.len());
+        let end = end.clamp(start, input.len());
+        // Spans are byte offsets, but the underline has to line up with characters
+        let offset = input[..start].chars().count();
+        let width = input[start..end].chars().count().max(1);
+
+        eprintln!("  {} {}", "|".blue().bold(), input);
+        eprintln!(
+            "  {} {}{}",
+            "|".blue().bold(),
+            " ".repeat(offset),
+            "^".repeat(width).red().bold()
+        );
+    }
+}
+
+/// Asks whether to save unsaved changes before quitting.
+///
+/// Only used interactively, scripts just exit.
+fn offer_save(rl: &mut Editor<ReplHelper>, state: &mut CalculatorState) {
+    let default_file = state
+        .session_file
+        .clone()
+        .unwrap_or_else(|| "session".to_owned());
+
+    let answer = match rl.readline("Save session before exiting? [y/N/filename] ") {
+        Ok(answer) => answer,
+        Err(_) => return,
+    };
+    match answer.trim() {
+        "" | "n" | "N" | "no" => {}
+        "y" | "Y" | "yes" => save_or_report(&default_file, state),
+        filename => save_or_report(filename, state),
+    }
+}
+
+/// Saves every workspace if there's more than one, so quitting doesn't lose any
+fn save_or_report(filename: &str, state: &mut CalculatorState) {
+    let all = state.workspaces().count() > 1;
+    if let Err(err) = parser::save_state(filename, all, state) {
+        render_error(&err, "", None);
+    }
+}
 
 fn main() {
-    // <()> means no completer
-    let mut rl = Editor::<()>::new();
-    if rl.load_history("history.txt").is_err() {
-        println!("No previous history");
+    let options = match Options::load(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(EXIT_BAD_INPUT);
+        }
+    };
+
+    if options.show_help {
+        println!("{}", USAGE);
+        return;
+    }
+    if options.show_version {
+        println!("{}", version::build_info());
+        return;
+    }
+
+    // colored already honors NO_COLOR, but only checks stdout for a terminal
+    if !std::io::stderr().is_terminal() {
+        colored::control::set_override(false);
+    }
+
+    if options.json || options.serve.is_some() {
+        if let Err(err) = vector_calc::json::check_available() {
+            eprintln!("Error: --json and --serve can't be used, {}", err);
+            std::process::exit(EXIT_BAD_INPUT);
+        }
+    }
+    #[cfg(feature = "json")]
+    if let Some(address) = &options.serve {
+        std::process::exit(server::serve(address, options.serve_allow_fs, options.limits));
     }
 
     let mut state = CalculatorState::new();
-    
+    state.json = options.json;
+    state.limits = options.limits;
+    state.locale = options.locale;
+    set_output(&mut state);
+    state.quiet = options.quiet;
+    if let Some(level) = options.debug_level {
+        state.debug_level = level;
+    }
+    if options.verbose {
+        state.debug_level = 3;
+    }
+    for setting in &options.settings {
+        if let Err(err) = parser::set_from_command_line(setting, &mut state) {
+            render_error(&err, setting, Some("--set"));
+            std::process::exit(EXIT_BAD_INPUT);
+        }
+    }
+
+    // Mid-session errors in the REPL are just shown, so it always exits with 0
+    let code = if let Some(path) = &options.script {
+        match std::fs::File::open(path) {
+            Ok(file) => {
+                let script = Script::new(path.display().to_string(), std::io::BufReader::new(file));
+                run_script(&mut state, script, options.fail_fast)
+            }
+            Err(err) => {
+                eprintln!("Couldn't open {}: {}", path.display(), err);
+                EXIT_BAD_INPUT
+            }
+        }
+    } else if !std::io::stdin().is_terminal() {
+        run_script(&mut state, Script::new("<stdin>".to_owned(), std::io::stdin().lock()), options.fail_fast)
+    } else {
+        run_interactive(&options, &mut state);
+        0
+    };
+    std::process::exit(code);
+}
+
+/// `>> `, with a `*` in front when there are unsaved changes, and the workspace if it isn't the default
+fn prompt(state: &CalculatorState) -> String {
+    let dirty = if state.dirty { "*" } else { "" };
+    match state.active_workspace() {
+        DEFAULT_WORKSPACE => format!("{}>> ", dirty),
+        workspace => format!("{}{}>> ", dirty, workspace),
+    }
+}
+
+fn run_interactive(options: &Options, state: &mut CalculatorState) {
+    let config = Config::builder()
+        .history_ignore_dups(true)
+        .max_history_size(options.history_size)
+        .edit_mode(options.edit_mode)
+        .completion_type(options.completion_type)
+        .bracketed_paste(options.bracketed_paste)
+        .build();
+    state.key_bindings = Some(match options.edit_mode {
+        EditMode::Vi => "vi",
+        _ => "emacs",
+    });
+
+    let mut terminal = Terminal {
+        rl: Editor::with_config(config),
+        history_path: options.history_path(),
+        interrupted_empty: Arc::new(AtomicBool::new(true)),
+    };
+    terminal.rl.set_helper(Some(ReplHelper::new()));
+    let ctrl_c = CtrlC(Arc::clone(&terminal.interrupted_empty));
+    terminal.rl.bind_sequence(KeyEvent::ctrl('C'), EventHandler::Conditional(Box::new(ctrl_c)));
+    if let Some(path) = &terminal.history_path {
+        match terminal.rl.load_history(path) {
+            // A missing file just means this is the first run
+            Err(ReadlineError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Couldn't load history from {}: {}", path.display(), err),
+            Ok(()) => {}
+        }
+    }
+
+    let mut session = Prompt::new();
     loop {
-        let readline = rl.readline(">> ");
-        match readline {
-            Ok(line) => {
-                let result = parser::parse(line.as_str(), &mut state);
-                if let Err(err) = result {
-                    eprintln!("ERR: {}", err);
-                } else {
-                    rl.add_history_entry(line.as_str());
-                }
-            },
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break
-            },
-            Err(ReadlineError::Eof) => {
-                println!("CTRL-D");
-                break
+        if let Some((width, _)) = terminal.rl.dimensions() {
+            state.plot_width = width;
+        }
+        if let Some(helper) = terminal.rl.helper_mut() {
+            helper.variables = state.workspace().names().into_iter().map(str::to_owned).collect();
+        }
+        let input = match terminal.rl.readline(&prompt(state)) {
+            Ok(line) => Input::Line(line),
+            Err(ReadlineError::Interrupted) => Input::Interrupted {
+                empty: terminal.interrupted_empty.load(Ordering::Relaxed),
             },
+            Err(ReadlineError::Eof) => Input::Eof,
             Err(err) => {
-                println!("Error: {:?}", err);
+                eprintln!("Error: {}", err);
                 break
             }
-            
+        };
+        if session.handle(state, input, |state, line| run_line_switching_output(state, &mut terminal, line)).is_some() {
+            if state.dirty {
+                offer_save(&mut terminal.rl, state);
+            }
+            break;
+        }
+    }
+    if let Some(path) = &terminal.history_path {
+        if let Err(err) = terminal.rl.append_history(path) {
+            eprintln!("Couldn't save history to {}: {}", path.display(), err);
         }
     }
-    rl.save_history("history.txt").unwrap();
-}
\ No newline at end of file
+}
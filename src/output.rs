@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::helper::Value;
+
+/// Where the calculator sends anything it wants to tell the user that isn't an error.
+///
+/// Results and errors are returned from [`crate::parser::eval`], so a library user that
+/// doesn't set an output gets no surprise writes to stdout. Results only come through here
+/// when something passes them to [`CalculatorState::print_result`](crate::helper::CalculatorState::print_result),
+/// like the REPL does.
+pub trait Output {
+    /// Status messages and replies to commands
+    fn info(&mut self, msg: &str);
+    /// Debug messages, only sent when the debug level allows them. `module` is the part of
+    /// the calculator that sent it, like `parser`.
+    fn debug(&mut self, level: u32, module: &str, msg: fmt::Arguments);
+    /// A result, along with how it should be displayed. Shown like `info` unless overridden
+    fn result(&mut self, _value: &Value, formatted: &str) {
+        self.info(formatted);
+    }
+    /// Something that worked, but probably not how the user wanted. Shown like `info` unless overridden
+    fn warn(&mut self, msg: &str) {
+        self.info(&format!("Warning: {}", msg));
+    }
+    /// The JSON object reporting a line, in JSON mode. Shown like `info` unless overridden
+    fn json(&mut self, line: &str) {
+        self.info(line);
+    }
+}
+
+/// Discards everything, the default for a new `CalculatorState`
+pub struct NullOutput;
+
+impl Output for NullOutput {
+    fn info(&mut self, _msg: &str) {}
+
+    fn debug(&mut self, _level: u32, _module: &str, _msg: fmt::Arguments) {}
+}
+
+/// Prints to stdout, except for warnings and debug messages which go to stderr so they don't
+/// mix with results. Used by the REPL.
+pub struct StdoutOutput;
+
+impl Output for StdoutOutput {
+    fn info(&mut self, msg: &str) {
+        println!("{}", msg);
+    }
+
+    fn debug(&mut self, level: u32, module: &str, msg: fmt::Arguments) {
+        eprintln!("[dbg{} {}] {}", level, module, msg);
+    }
+
+    fn warn(&mut self, msg: &str) {
+        eprintln!("Warning: {}", msg);
+    }
+}
+
+/// Prints the JSON for each line to stdout and everything else to stderr, so stdout only ever
+/// has one JSON object per line of input. Used by the REPL in JSON mode.
+pub struct JsonOutput;
+
+impl Output for JsonOutput {
+    fn info(&mut self, msg: &str) {
+        eprintln!("{}", msg);
+    }
+
+    fn debug(&mut self, level: u32, module: &str, msg: fmt::Arguments) {
+        eprintln!("[dbg{} {}] {}", level, module, msg);
+    }
+
+    fn warn(&mut self, msg: &str) {
+        eprintln!("Warning: {}", msg);
+    }
+
+    fn json(&mut self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+/// Which `Output` method a message a [`BufferedOutput`] kept came through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Result,
+    Info,
+    Warning,
+    Debug,
+    Json,
+}
+
+/// Keeps everything in memory instead of showing it, to check what a session printed.
+///
+/// Clones share the same messages, so give one to the state with `set_output` and read them
+/// from another.
+#[derive(Clone, Default)]
+pub struct BufferedOutput {
+    messages: Rc<RefCell<Vec<(MessageKind, String)>>>,
+}
+
+impl BufferedOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything sent so far, oldest first
+    pub fn messages(&self) -> Vec<(MessageKind, String)> {
+        self.messages.borrow().clone()
+    }
+
+    /// Like `messages`, but empties the buffer
+    pub fn take(&self) -> Vec<(MessageKind, String)> {
+        std::mem::take(&mut *self.messages.borrow_mut())
+    }
+
+    fn push(&self, kind: MessageKind, msg: String) {
+        self.messages.borrow_mut().push((kind, msg));
+    }
+}
+
+impl Output for BufferedOutput {
+    fn info(&mut self, msg: &str) {
+        self.push(MessageKind::Info, msg.to_owned());
+    }
+
+    fn debug(&mut self, level: u32, module: &str, msg: fmt::Arguments) {
+        self.push(MessageKind::Debug, format!("[dbg{} {}] {}", level, module, msg));
+    }
+
+    fn result(&mut self, _value: &Value, formatted: &str) {
+        self.push(MessageKind::Result, formatted.to_owned());
+    }
+
+    fn warn(&mut self, msg: &str) {
+        self.push(MessageKind::Warning, msg.to_owned());
+    }
+
+    fn json(&mut self, line: &str) {
+        self.push(MessageKind::Json, line.to_owned());
+    }
+}
+
+/// A record of a session appended to a file, each input line as `>> input` followed by what it
+/// printed: results, errors, warnings and status messages, but not debug messages.
+///
+/// Lines are buffered until [`Transcript::flush`], which the REPL calls after every input line,
+/// so a crash loses at most the line it happened on.
+///
+/// Replaying the inputs from a transcript prints what's in it, so the line that stops it is left out.
+pub struct Transcript {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Only written once the line turns out not to be the one stopping the transcript
+    pending_input: Option<String>,
+}
+
+impl Transcript {
+    /// Starts appending to `path`, creating it if needed
+    pub fn append_to<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = File::options().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            pending_input: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn input(&mut self, line: &str) -> io::Result<()> {
+        self.write_pending_input()?;
+        self.pending_input = Some(line.to_owned());
+        Ok(())
+    }
+
+    /// Something the line printed, which may be several lines long
+    pub fn output(&mut self, text: &str) -> io::Result<()> {
+        self.write_pending_input()?;
+        writeln!(self.writer, "{}", text)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_pending_input()?;
+        self.writer.flush()
+    }
+
+    /// Flushes everything but the latest input line, which is the one stopping the transcript
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.pending_input = None;
+        self.writer.flush()?;
+        Ok(self.path)
+    }
+
+    fn write_pending_input(&mut self) -> io::Result<()> {
+        match self.pending_input.take() {
+            Some(line) => writeln!(self.writer, ">> {}", line),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sends a debug message through [`CalculatorState::print_debug`](crate::helper::CalculatorState::print_debug),
+/// tagged with the module it came from.
+///
+/// Takes format arguments like `format!`, which are only formatted if the debug level lets
+/// the message through.
+macro_rules! print_debug {
+    ($state:expr, $level:expr, $($arg:tt)+) => {
+        $state.print_debug($level, module_path!(), format_args!($($arg)+))
+    };
+}
+pub(crate) use print_debug;
@@ -1,300 +1,2041 @@
-use std::num::ParseFloatError;
+use std::str::FromStr;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Instant, SystemTime};
+// std's clock panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::{Instant, SystemTime};
 
-use crate::helper::{CalculatorState, Value, Vector};
-use once_cell::sync::Lazy;
-use pest::prec_climber::{Assoc, Operator, PrecClimber};
+use crate::ast::{self, Expr};
+use crate::builtins::{self, Builtin, Category, BUILTINS};
+use crate::clipboard;
+use crate::json;
+use crate::error::{missing_rule, next_pair, pair_span, unexpected_rule, CalcError, EvalError, ParseError, Span, StateError};
+use crate::helper::{
+    closest_matches, constant, fold_components, labeled, CalculatorState, Complex, DisplayMode, Limits, Matrix, ModifyRequest,
+    NumberFormat, NumberLocale, Quantity, Quaternion, Timing, TypeConstraint, Value, Vector, Workspace, CONSTANTS,
+    LAST_RESULT_NAME,
+};
+use crate::output::print_debug;
+use crate::version;
+use crate::plot::{draw_vectors, plot_vector};
 use pest::{
+    error::InputLocation,
     iterators::{Pair, Pairs},
     Parser,
 };
-use thiserror::Error;
 
 #[derive(pest_derive::Parser)]
 #[grammar = "calc.pest"]
 struct CalcParser;
 
-static PREC_CLIMBER: Lazy<PrecClimber<Rule>> = Lazy::new(|| {
-    use Assoc::*;
-    use Rule::*;
+/// Parses and evaluates one line of input.
+///
+/// Returns the value of an expression (which is also recorded for `out(n)`), or `None` for
+/// assignments and parser commands that don't produce one.
+pub fn eval(input: &str, state: &mut CalculatorState) -> Result<Option<Value>, CalcError> {
+    count_error(eval_line(input, state), state)
+}
 
-    PrecClimber::new(vec![
-        Operator::new(add, Left) | Operator::new(subtract, Left),
-        Operator::new(dot, Left) | Operator::new(cross, Left),
-        Operator::new(multiply, Left) | Operator::new(divide, Left),
-        Operator::new(power, Right)
-    ])
-});
+fn eval_line(input: &str, state: &mut CalculatorState) -> Result<Option<Value>, CalcError> {
+    check_limits(input, &state.limits)?;
+    let pairs = parse_command(input)?;
+    let mut result = None;
 
-pub fn parse(input: &str, state: &mut CalculatorState) -> Result<(), ParseError> {
-    let pairs = CalcParser::parse(Rule::command, input)?;
+    for pair in pairs {
+        print_debug!(state, 3, "{:?} : {}", pair.as_rule(), pair.as_str());
+        match pair.as_rule() {
+            Rule::variable_assignment => variable_assignment(pair, state)?,
+            Rule::compound_assignment => compound_assignment(pair, state)?,
+            Rule::component_assignment => component_assignment(pair, state)?,
+            // Checks only say something when they fail, and aren't results to refer back to
+            Rule::expression if is_check(&pair) => {
+                parse_expression(pair, state)?;
+            }
+            Rule::expression | Rule::continuation => result = Some(eval_result(pair, state)?),
+            Rule::parser_command => result = parse_parser_command(pair, state)?,
+            Rule::EOI => (),
+            _ => return Err(unexpected_rule(&pair, "a statement").into()),
+        }
+    }
+    Ok(result)
+}
+
+/// Checks that a line is well formed, without evaluating it.
+///
+/// Lines past the default `Limits` aren't parsed, in case they're deep enough to overflow the
+/// stack, and are reported as a syntax error at the start of the line.
+///
+/// # Examples
+///
+/// ```
+/// use vector_calc::parser::check;
+///
+/// // Variables don't have to exist, since nothing is evaluated
+/// assert!(check("x = <1, 2> + y").is_ok());
+/// assert!(check(".load session").is_ok());
+///
+/// let err = check("x = <1, 2 +").unwrap_err();
+/// assert_eq!(err.name(), "UnterminatedVector");
+/// assert_eq!(err.to_string(), "Unterminated vector literal starting at column 5");
+/// ```
+pub fn check(input: &str) -> Result<(), ParseError> {
+    check_default_limits(input)?;
+    lower_expressions(parse_command(input)?)
+}
 
+/// Lowers the outermost expressions in `pairs` and throws the trees away, for the errors
+/// lowering finds that parsing doesn't
+fn lower_expressions(pairs: Pairs<Rule>) -> Result<(), ParseError> {
     for pair in pairs {
-        state.print_debug(3, format!("{:?} : {}", pair.as_rule(), pair.as_str()));
         match pair.as_rule() {
-            Rule::variable_assignment => variable_assignment(pair.into_inner(), state)?,
-            Rule::ident => {
-                let key = pair.as_str();
-                match state.get_var(key) {
-                    Some(value) => println!("{} = {}", key, value),
-                    None => println!("Variable '{}' not found", key),
+            Rule::expression | Rule::continuation => drop(ast::lower(pair.into_inner())?),
+            _ => lower_expressions(pair.into_inner())?,
+        }
+    }
+    Ok(())
+}
+
+/// Names that could finish the word before `pos` in `line`, sorted. After a `.` at the start of
+/// the line they're the commands, `.` included, and otherwise the functions, constants, keywords
+/// and the active workspace's variables.
+///
+/// Each is the whole name, to replace the word typed so far. There are none before a word has
+/// been started, or if `pos` isn't a character boundary in `line`.
+///
+/// # Examples
+///
+/// ```
+/// use vector_calc::helper::CalculatorState;
+/// use vector_calc::parser::{complete_prefixes, eval};
+///
+/// let mut state = CalculatorState::new();
+/// eval("velocity = <1, 2>", &mut state).unwrap();
+/// assert_eq!(complete_prefixes(&state, "ve", 2), ["vec", "velocity"]);
+/// assert_eq!(complete_prefixes(&state, "2 * cro", 7), ["cross"]);
+/// assert_eq!(complete_prefixes(&state, ".lo", 3), [".load", ".locale"]);
+/// assert!(complete_prefixes(&state, "2 * ", 4).is_empty());
+/// ```
+pub fn complete_prefixes(state: &CalculatorState, line: &str, pos: usize) -> Vec<String> {
+    complete_with_variables(line, pos, state.workspace().names()).1
+}
+
+/// Like `complete_prefixes` with the variable names given instead of a state, for a completer
+/// that can't borrow one. Also gives where the word being replaced starts
+pub fn complete_with_variables<'a, I: IntoIterator<Item = &'a str>>(
+    line: &str,
+    pos: usize,
+    variables: I,
+) -> (usize, Vec<String>) {
+    let Some(before) = line.get(..pos) else {
+        return (pos, Vec::new());
+    };
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_name_char(c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &before[start..];
+
+    let (start, mut names): (usize, Vec<String>) = if before[..start].trim_start() == "." {
+        // The `.` is part of what's replaced
+        let commands = COMMANDS.iter().filter(|command| command.starts_with(word));
+        (start - 1, commands.map(|command| format!(".{}", command)).collect())
+    } else if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+        (pos, Vec::new())
+    } else {
+        let constants = CONSTANTS.iter().map(|(name, _)| *name);
+        let functions = BUILTINS.iter().map(|builtin| builtin.name);
+        let names = functions.chain(KEYWORDS.iter().copied()).chain(constants).chain(variables);
+        (start, names.filter(|name| name.starts_with(word)).map(str::to_owned).collect())
+    };
+    names.sort();
+    names.dedup();
+    (start, names)
+}
+
+/// Parses a single expression into its tree, without evaluating it. Limited like `check`
+pub(crate) fn parse_expression_tree(input: &str) -> Result<Expr, ParseError> {
+    check_default_limits(input)?;
+    let mut pairs = CalcParser::parse(Rule::expression_input, input).map_err(|err| syntax_error(input, err))?;
+    ast::lower(next_pair(&mut pairs, (0, input.len()), "an expression")?.into_inner())
+}
+
+/// The default `Limits` as a syntax error, for parsing without a state
+fn check_default_limits(input: &str) -> Result<(), ParseError> {
+    if check_limits(input, &Limits::default()).is_err() {
+        let err = pest::error::Error::new_from_pos(
+            pest::error::ErrorVariant::CustomError {
+                message: "the line is too long or nested too deeply to check".to_owned(),
+            },
+            pest::Position::from_start(input),
+        );
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Checks the line against the length and depth limits, before parsing it
+fn check_limits(input: &str, limits: &Limits) -> Result<(), EvalError> {
+    limits.check_input_len(input.len())?;
+    limits.check_expr_depth(expression_depth(input))
+}
+
+/// More than how deeply parsing and evaluating `input` can nest: its operators, plus the most
+/// brackets it has open at once. Cheap enough to count before parsing, so a huge expression
+/// can't overflow the stack
+fn expression_depth(input: &str) -> usize {
+    let (mut open, mut deepest, mut operators) = (0usize, 0, 0);
+    for c in input.chars() {
+        match c {
+            '(' | '[' | '<' => {
+                open += 1;
+                deepest = deepest.max(open);
+            }
+            ')' | ']' | '>' => open = open.saturating_sub(1),
+            // Every comparison has an `=`
+            '+' | '-' | '*' | '/' | '%' | '^' | '=' => operators += 1,
+            _ => {}
+        }
+    }
+    let keywords = input
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| KEYWORDS.contains(word))
+        .count();
+    operators + keywords + deepest
+}
+
+/// Parses a line, failing on unknown commands as well as malformed input
+fn parse_command(input: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    let pairs = CalcParser::parse(Rule::command, input).map_err(|err| syntax_error(input, err))?;
+    for pair in pairs.clone() {
+        match pair.as_rule() {
+            Rule::parser_command => {
+                let span = pair_span(&pair);
+                let command = next_pair(&mut pair.into_inner(), span, "a command")?;
+                if command.as_rule() == Rule::unknown_command {
+                    return Err(unknown_command(command));
                 }
             }
-            Rule::bare_number => println!("{}", parse_value(pair, state)?),
-            Rule::expression => println!("{}", parse_expression(pair, state)?),
-            Rule::parser_command => parse_parser_command(pair.into_inner(), state),
-            _ => unreachable!("Not recognized"),
+            Rule::invalid_assignment => {
+                let span = pair_span(&pair);
+                let target_pair = next_pair(&mut pair.into_inner(), span, "an expression")?;
+                // The expression takes the spaces before the `=` with it
+                let target = target_pair.as_str().trim_end();
+                let start = target_pair.as_span().start();
+                return Err(ParseError::InvalidAssignmentTarget {
+                    target: target.to_owned(),
+                    span: (start, start + target.len()),
+                });
+            }
+            _ => {}
         }
     }
+    Ok(pairs)
+}
+
+/// Makes a pest error into a `ParseError`, blaming a `<` that's never closed if there is one
+fn syntax_error(input: &str, err: pest::error::Error<Rule>) -> ParseError {
+    let failed_at = match err.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    };
+    // A `>` later on means the `<` was closed, and something else inside it is wrong
+    if input[failed_at..].contains('>') {
+        return err.into();
+    }
+    let mut open = Vec::new();
+    for (i, c) in input[..failed_at].char_indices() {
+        match c {
+            '<' => open.push(i),
+            '>' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    match open.last() {
+        Some(&start) => ParseError::UnterminatedVector {
+            column: input[..start].chars().count() + 1,
+            span: (start, start + 1),
+        },
+        None => err.into(),
+    }
+}
+
+/// Names of the parser commands, without the `.`
+pub(crate) const COMMANDS: &[&str] = &[
+    "debug", "modify", "exit", "save", "load", "import_vec", "export_vec", "echo", "quiet", "format",
+    "time", "showall", "plot", "precision", "strict", "latex", "copy", "epsilon", "explain",
+    "strict_vars", "type", "warn", "transcript", "keys", "version",
+    "info", "workspace", "out", "json", "limits", "display", "history", "units",
+    "locale", "builtins", "help", "stats", "vars",
+];
+
+/// Commands that read or write files, which `CalculatorState::filesystem` can turn off
+const USES_FILES: &[Rule] = &[
+    Rule::parser_save,
+    Rule::parser_load,
+    Rule::parser_import_vec,
+    Rule::parser_export_vec,
+    Rule::parser_transcript,
+    Rule::parser_out,
+];
+
+/// Words the grammar gives a meaning to, which still work as variable names
+const KEYWORDS: &[&str] = &["dot", "cross", "let"];
+
+const DEFAULT_DRAW_SIZE: usize = 15;
+const MIN_DRAW_SIZE: usize = 2;
+const MAX_DRAW_SIZE: usize = 99;
+
+/// The most decimal places `.precision` takes, which is as many as the smallest `f64` needs
+const MAX_PRECISION: usize = 1074;
+
+/// The error for a `.` followed by a name that isn't a command
+fn unknown_command(pair: Pair<Rule>) -> ParseError {
+    let span = pair_span(&pair);
+    let Some(name_pair) = pair.into_inner().next() else {
+        return missing_rule(span, "a command name");
+    };
+    let name = name_pair.as_str();
+    let span = name_pair.as_span();
+    ParseError::UnknownCommand {
+        name: name.to_owned(),
+        suggestions: closest_matches(name, COMMANDS.iter().copied())
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        span: (span.start(), span.end()),
+    }
+}
+
+/// The pair inside `pair`, if there's just one
+fn only_inner(pair: Pair<Rule>) -> Option<Pair<Rule>> {
+    let mut inner = pair.into_inner();
+    inner.next().filter(|_| inner.next().is_none())
+}
+
+/// Whether an expression is just a call to `assert()` or `expect()`
+fn is_check(pair: &Pair<Rule>) -> bool {
+    // The expression's only operand, and that operand's only value
+    match only_inner(pair.clone()).and_then(only_inner) {
+        Some(call) if call.as_rule() == Rule::function_call => {
+            let name = call.into_inner().next();
+            name.is_some_and(|name| matches!(name.as_str(), "assert" | "expect"))
+        }
+        _ => false,
+    }
+}
+
+/// Evaluates an expression on its own line, recording the result for `out(n)`
+fn eval_result(pair: Pair<Rule>, state: &mut CalculatorState) -> Result<Value, CalcError> {
+    let (expr, span) = (pair.as_str(), pair_span(&pair));
+    let value = parse_expression(pair, state)?;
+    check_finite(&value, expr, None, span, state)?;
+    state.push_result(value.clone());
+    Ok(value)
+}
+
+/// Evaluates a single expression, without allowing assignments or commands
+pub fn eval_expression(input: &str, state: &CalculatorState) -> Result<Value, CalcError> {
+    count_error(eval_expression_input(input, state), state)
+}
+
+fn eval_expression_input(input: &str, state: &CalculatorState) -> Result<Value, CalcError> {
+    check_limits(input, &state.limits)?;
+    let mut pairs = CalcParser::parse(Rule::expression_input, input).map_err(|err| syntax_error(input, err))?;
+    parse_expression(next_pair(&mut pairs, (0, input.len()), "an expression")?, state)
+}
+
+/// Sets a variable from a `NAME=VALUE` setting, like `--set v0=<0, 10, 0>` on the command line.
+///
+/// The value can be any expression, including ones using earlier settings. It gets the same
+/// warnings as an assignment, and `.vars` marks the variable as coming from the command line.
+pub fn set_from_command_line(setting: &str, state: &mut CalculatorState) -> Result<(), CalcError> {
+    let mut pairs = CalcParser::parse(Rule::setting_input, setting).map_err(|err| syntax_error(setting, err))?;
+    let span = (0, setting.len());
+    let name = next_pair(&mut pairs, span, "a name")?.as_str();
+    let expr_pair = next_pair(&mut pairs, span, "an expression")?;
+    let (expr, span) = (expr_pair.as_str(), pair_span(&expr_pair));
+    let value = parse_expression(expr_pair, state)?;
+    check_finite(&value, expr, Some(name), span, state)?;
+    let warnings = assignment_warnings(name, &value, false, state);
+    state.set_command_line_var(name.to_owned(), value)?;
+    for warning in warnings {
+        state.print_warning(&warning);
+    }
     Ok(())
 }
 
-fn variable_assignment(pairs: Pairs<Rule>, state: &mut CalculatorState) -> Result<(), ParseError> {
+/// Adds a failed result to the errors `.stats` counts
+fn count_error<T>(result: Result<T, CalcError>, state: &CalculatorState) -> Result<T, CalcError> {
+    if let Err(err) = &result {
+        state.update_stats(|stats| *stats.errors.entry(err.kind()).or_default() += 1);
+    }
+    result
+}
+
+fn variable_assignment(pair: Pair<Rule>, state: &mut CalculatorState) -> Result<(), CalcError> {
+    let span = pair_span(&pair);
     let mut key: Option<String> = None;
     let mut value: Option<Value> = None;
+    let mut expr = ("", (0, 0));
+    let mut silenced = false;
+    let mut declaration = false;
+    let mut annotation: Option<TypeConstraint> = None;
 
-    for pair in pairs {
+    for pair in pair.into_inner() {
         match pair.as_rule() {
+            Rule::declaration => declaration = true,
             Rule::ident => key = Some(pair.as_str().to_owned()),
-            Rule::value => value = Some(parse_value(pair, state)?),
-            Rule::expression => value = Some(parse_expression(pair, state)?),
-            _ => state.print_debug(
-                2,
-                format!("Var Assignment unknown rule: {:?}", pair.as_rule()),
-            ),
+            Rule::type_annotation => annotation = Some(parse_type_annotation(&pair)?),
+            Rule::expression => {
+                expr = (pair.as_str(), pair_span(&pair));
+                let tree = ast::lower(pair.into_inner())?;
+                if state.debug_level >= 2 {
+                    let name = key.as_deref().unwrap_or_default();
+                    print_debug!(state, 2, "{} = {}", name, ast::fold_constants_for(&tree, state));
+                }
+                value = Some(eval_tree(&tree, state)?)
+            }
+            Rule::silence => silenced = true,
+            _ => print_debug!(state, 2, "Var Assignment unknown rule: {:?}", pair.as_rule()),
         }
     }
 
-    state.set_var(
-        key.expect("Parsing error in variable name"),
-        value.expect("Parsing error in value"),
-    );
+    let key = key.ok_or_else(|| missing_rule(span, "a variable name"))?;
+    let value = value.ok_or_else(|| missing_rule(span, "an expression"))?;
+    check_finite(&value, expr.0, Some(&key), expr.1, state)?;
+    let echo = (state.echo_assignments && !silenced).then(|| labeled(&key, &state.format_value(&value)));
+    let warnings = assignment_warnings(&key, &value, declaration, state);
+    if declaration {
+        state.declare_var(key, annotation, value)?;
+    } else {
+        state.assign_var(key, value)?;
+    }
+    if let Some(echo) = echo {
+        state.print_info(&echo);
+    }
+    for warning in warnings {
+        state.print_warning(&warning);
+    }
 
     Ok(())
 }
 
-fn parse_expression(outer_pair: Pair<Rule>, state: &CalculatorState) -> Result<Value, ParseError> {
-    let pairs = outer_pair.into_inner();
-
-    PREC_CLIMBER.climb(
-        pairs,
-        |pair: Pair<Rule>| parse_value(pair, state),
-        |lhs: Result<Value, ParseError>, op: Pair<Rule>, rhs: Result<Value, ParseError>| {
-            let lhs = lhs?;
-            let rhs = rhs?;
-            match op.as_rule() {
-                Rule::add => lhs + rhs,
-                Rule::subtract => lhs - rhs,
-                Rule::multiply => lhs * rhs,
-                Rule::divide => lhs / rhs,
-                Rule::dot => {
-                    if lhs.is_vector() && rhs.is_vector() {
-                        Ok(lhs.as_vector().dot(&rhs.as_vector()).into())
-                    } else {
-                        // Err(ParseError::from_pair("Can only do a dot product on two vectors", outer_pair))
-                        Err(ParseError::InvalidExpression(
-                            "Can only do a dot product on two vectors",
-                        ))
-                    }
-                }
-                Rule::cross => {
-                    if lhs.is_vector() && rhs.is_vector() {
-                        lhs.as_vector()
-                            .cross(&rhs.as_vector())
-                            .map(Value::Vector)
-                    } else {
-                        Err(ParseError::InvalidExpression(
-                            "Can only do a cross product on two vectors",
-                        ))
-                    }
-                }
-                _ => unreachable!("parse_expression unknown operator rule"),
+/// Things about assigning `value` to `key` that are probably mistakes, though still allowed:
+/// changing the type or size of an existing variable, and names that mean something else.
+///
+/// Declaring a variable again with `let` is clearly on purpose, so it doesn't warn about the type.
+fn assignment_warnings(key: &str, value: &Value, declaration: bool, state: &CalculatorState) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(old) = state.get_var(key).filter(|_| !declaration) {
+        let (old_shape, new_shape) = (old.shape_name(), value.shape_name());
+        if old_shape != new_shape {
+            warnings.push(format!("'{}' was a {}, and is now a {}", key, old_shape, new_shape));
+        }
+    }
+    if state.workspace().from_command_line(key) {
+        warnings.push(format!("'{}' was set with --set, this replaces it", key));
+    }
+    if KEYWORDS.contains(&key) {
+        warnings.push(format!("'{}' is also a keyword, so it may be read as that instead of the variable", key));
+    } else if constant(key).is_some() {
+        warnings.push(format!("'{}' is also a constant, the variable will be used instead", key));
+    } else if builtins::find(key).is_some() {
+        warnings.push(format!("'{}' is also a function, calling it won't use the variable", key));
+    }
+    warnings
+}
+
+/// Reads a `type_annotation`, which the grammar has already checked apart from how big the sizes are
+fn parse_type_annotation(pair: &Pair<Rule>) -> Result<TypeConstraint, ParseError> {
+    let text = pair.as_str();
+    let number = |digits: &str| {
+        digits.parse().map_err(|_| ParseError::InvalidInteger {
+            literal: text.to_owned(),
+            reason: "has a size that's too big".to_owned(),
+            span: pair_span(pair),
+        })
+    };
+    Ok(match text {
+        "number" => TypeConstraint::Number,
+        "vector" => TypeConstraint::Vector(None),
+        "matrix" => TypeConstraint::Matrix(None),
+        "quaternion" => TypeConstraint::Quaternion,
+        "complex" => TypeConstraint::Complex,
+        _ => {
+            if let Some(dims) = text.strip_prefix("vec") {
+                TypeConstraint::Vector(Some(number(dims)?))
+            } else {
+                let unexpected = || unexpected_rule(pair, "a type annotation");
+                let shape = text.strip_prefix("mat").ok_or_else(unexpected)?;
+                let (rows, cols) = shape.split_once('x').ok_or_else(unexpected)?;
+                TypeConstraint::Matrix(Some((number(rows)?, number(cols)?)))
             }
+        }
+    })
+}
+
+fn compound_assignment(pair: Pair<Rule>, state: &mut CalculatorState) -> Result<(), CalcError> {
+    let line_span = pair_span(&pair);
+    let mut pairs = pair.into_inner();
+    let key_pair = next_pair(&mut pairs, line_span, "an identifier")?;
+    let key = key_pair.as_str();
+    let op_pair = next_pair(&mut pairs, line_span, "an operator")?;
+    let rhs_pair = next_pair(&mut pairs, line_span, "an expression")?;
+    let expr = format!("{} {} {}", key, op_pair.as_str(), rhs_pair.as_str());
+    let span = (key_pair.as_span().start(), rhs_pair.as_span().end());
+    let rhs = parse_expression(rhs_pair, state)?;
+    let silenced = pairs.next().is_some();
+
+    if key == LAST_RESULT_NAME {
+        return Err(StateError::Reserved { name: key.to_owned() }.into());
+    }
+    if !state.contains_key(key) {
+        return Err(unknown_identifier(&key_pair, state).into());
+    }
+    // The old value goes into the variable's history, and a failure has to leave the variable
+    // as it was, so update a copy
+    let mut value = state.get_var(key).expect("Checked above").clone();
+    apply_compound(&op_pair, &mut value, &rhs, state.strict)?;
+    check_finite(&value, &expr, Some(key), span, state)?;
+
+    let echo = (state.echo_assignments && !silenced).then(|| labeled(key, &state.format_value(&value)));
+    state.assign_var(key.to_owned(), value)?;
+    if let Some(echo) = echo {
+        state.print_info(&echo);
+    }
+
+    Ok(())
+}
+
+fn component_assignment(pair: Pair<Rule>, state: &mut CalculatorState) -> Result<(), CalcError> {
+    let line_span = pair_span(&pair);
+    let mut pairs = pair.into_inner();
+    let key_pair = next_pair(&mut pairs, line_span, "an identifier")?;
+    let key = key_pair.as_str();
+    let component_pair = next_pair(&mut pairs, line_span, "a component")?;
+    let index = match component_pair.as_rule() {
+        Rule::component_index => parse_count(&component_pair)?,
+        _ => match component_pair.as_str() {
+            ".x" => 0,
+            ".y" => 1,
+            ".z" => 2,
+            _ => 3,
         },
-    )
+    };
+    let target_span = (key_pair.as_span().start(), component_pair.as_span().end());
+    let rhs_pair = next_pair(&mut pairs, line_span, "an expression")?;
+    let rhs_span = pair_span(&rhs_pair);
+    let rhs = parse_expression(rhs_pair, state)?;
+    let silenced = pairs.next().is_some();
+
+    if key == LAST_RESULT_NAME {
+        return Err(StateError::Reserved { name: key.to_owned() }.into());
+    }
+    if !state.contains_key(key) {
+        return Err(unknown_identifier(&key_pair, state).into());
+    }
+    let value = f64::try_from(&rhs).map_err(|err| err.with_span(rhs_span))?;
+    state.set_component(key, index, value).map_err(|err| match err {
+        CalcError::Eval(err) => err.with_span(target_span).into(),
+        err => err,
+    })?;
+
+    if state.echo_assignments && !silenced {
+        let vec = state.get_var(key).expect("Just assigned");
+        state.print_info(&labeled(key, &state.format_value(vec)));
+    }
+    Ok(())
+}
+
+fn apply_compound(op_pair: &Pair<Rule>, value: &mut Value, rhs: &Value, strict: bool) -> Result<(), CalcError> {
+    let result = match op_pair.as_rule() {
+        Rule::add_assign => value.try_add_assign(rhs),
+        Rule::subtract_assign => value.try_sub_assign(rhs),
+        Rule::multiply_assign => value.try_mul_assign(rhs),
+        Rule::divide_assign if !strict => value.div_ieee(rhs).map(|result| *value = result),
+        Rule::divide_assign => value.try_div_assign(rhs),
+        _ => return Err(unexpected_rule(op_pair, "a compound assignment operator").into()),
+    };
+    Ok(result.map_err(|err| err.with_span(pair_span(op_pair)))?)
+}
+
+/// Reads a count the grammar has checked is digits, which can still be too big for `T`
+fn parse_count<T: FromStr>(pair: &Pair<Rule>) -> Result<T, ParseError> {
+    pair.as_str().replace('_', "").parse().map_err(|_| ParseError::InvalidInteger {
+        literal: pair.as_str().to_owned(),
+        reason: "is too big".to_owned(),
+        span: pair_span(pair),
+    })
+}
+
+/// Lowers an `expression` pair to an [`Expr`] and evaluates it
+fn parse_expression(pair: Pair<Rule>, state: &CalculatorState) -> Result<Value, CalcError> {
+    let expr = ast::lower(pair.into_inner())?;
+    Ok(eval_tree(&expr, state)?)
+}
+
+/// Evaluates a tree, adding it and how long it took to `.stats`
+fn eval_tree(expr: &Expr, state: &CalculatorState) -> Result<Value, EvalError> {
+    let start = Instant::now();
+    let result = ast::eval(expr, state);
+    let time = start.elapsed();
+    state.update_stats(|stats| stats.record_eval(time));
+    result
+}
+
+/// Infinity or NaN is an error when `state.strict` is on, and just a warning otherwise.
+///
+/// `variable` is where the value is about to be stored, if anywhere.
+fn check_finite(
+    value: &Value,
+    expr: &str,
+    variable: Option<&str>,
+    span: Span,
+    state: &CalculatorState,
+) -> Result<(), EvalError> {
+    if value.is_finite() {
+        return Ok(());
+    }
+    if state.strict {
+        return Err(EvalError::NonFinite {
+            expr: expr.to_owned(),
+            variable: variable.map(str::to_owned),
+            span: Some(span),
+        });
+    }
+    match variable {
+        Some(variable) => state.print_warning(&format!("'{}' isn't finite, storing it in '{}' anyway", expr, variable)),
+        None => state.print_warning(&format!("'{}' isn't finite", expr)),
+    }
+    Ok(())
 }
 
-fn parse_parser_command(mut pairs: Pairs<Rule>, state: &mut CalculatorState) {
-    let command_type = pairs.next().unwrap();
+fn parse_parser_command(pair: Pair<Rule>, state: &mut CalculatorState) -> Result<Option<Value>, CalcError> {
+    let command_span = pair_span(&pair);
+    let mut pairs = pair.into_inner();
+    let command_type = next_pair(&mut pairs, command_span, "a command")?;
+    if !state.filesystem && USES_FILES.contains(&command_type.as_rule()) {
+        return Err(CalcError::FilesystemDisabled(command_type.as_str().to_owned()));
+    }
 
     match command_type.as_rule() {
         Rule::parser_debug => {
             let data = pairs.next();
             if let Some(debug_level_pair) = data {
-                let debug_level: u32 = debug_level_pair
-                    .as_str()
-                    .parse()
-                    .expect("Grammar only allows a single number, so this should never happen");
+                let debug_level: u32 = parse_count(&debug_level_pair)?;
                 state.debug_level = debug_level;
-                state.print_debug(1, format!("Changed debug level to {}", debug_level));
+                print_debug!(state, 1, "Changed debug level to {}", debug_level);
+            } else {
+                state.print_reply(&format!("Debug level: {}", state.debug_level));
+            }
+        }
+        Rule::parser_modify => {
+            let force = pairs.peek().map(|pair| pair.as_rule()) == Some(Rule::modify_force);
+            if force {
+                pairs.next();
+            }
+            let var_pair = next_pair(&mut pairs, command_span, "an identifier")?;
+            let var_name = var_pair.as_str();
+            if !state.contains_key(var_name) {
+                return Err(unknown_identifier(&var_pair, state).into());
+            }
+            state.pending_modify = Some(ModifyRequest {
+                name: var_name.to_owned(),
+                force,
+            });
+        }
+        Rule::parser_exit => state.exit_requested = true,
+        Rule::parser_save => {
+            let mut next = next_pair(&mut pairs, command_span, "a path")?;
+            let all = next.as_rule() == Rule::save_all;
+            if all {
+                next = next_pair(&mut pairs, command_span, "a path")?;
+            }
+            save_state(next.as_str(), all, state)?
+        }
+        Rule::parser_load => load_state(next_pair(&mut pairs, command_span, "a path")?.as_str(), state)?,
+        Rule::parser_import_vec => {
+            let name = next_pair(&mut pairs, command_span, "an identifier")?.as_str();
+            let path = file_path(next_pair(&mut pairs, command_span, "a path")?);
+            let column = pairs.next().map(|pair| parse_count(&pair)).transpose()?;
+            import_vec(name, path, column, state)?
+        }
+        Rule::parser_export_vec => {
+            let name_pair = next_pair(&mut pairs, command_span, "an identifier")?;
+            let path = file_path(next_pair(&mut pairs, command_span, "a path")?);
+            let vec: &Vector = state
+                .get_var(name_pair.as_str())
+                .ok_or_else(|| unknown_identifier(&name_pair, state))?
+                .try_into()
+                .map_err(|err: EvalError| err.with_span(pair_span(&name_pair)))?;
+            export_vec(vec, path)?;
+        }
+        Rule::parser_echo => {
+            if let Some(bool_pair) = pairs.next() {
+                state.echo_assignments = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Echo: {}", if state.echo_assignments { "on" } else { "off" }));
+            }
+        }
+        Rule::parser_quiet => {
+            if let Some(bool_pair) = pairs.next() {
+                state.quiet = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Quiet: {}", if state.quiet { "on" } else { "off" }));
+            }
+        }
+        Rule::parser_format => {
+            if let Some(mode_pair) = pairs.next() {
+                state.number_format = match mode_pair.as_str() {
+                    "sci" => NumberFormat::Scientific,
+                    "eng" => NumberFormat::Engineering,
+                    _ => NumberFormat::Auto,
+                };
+            } else {
+                let mode = match state.number_format {
+                    NumberFormat::Auto => "auto",
+                    NumberFormat::Scientific => "sci",
+                    NumberFormat::Engineering => "eng",
+                };
+                state.print_reply(&format!("Format: {}", mode));
+            }
+        }
+        Rule::parser_display => match pairs.next() {
+            Some(mode_pair) => {
+                state.display_mode = match mode_pair.as_str() {
+                    "nice" => DisplayMode::Nice,
+                    _ => DisplayMode::Raw,
+                }
+            }
+            None => {
+                let mode = match state.display_mode {
+                    DisplayMode::Raw => "raw",
+                    DisplayMode::Nice => "nice",
+                };
+                state.print_reply(&format!("Display: {}", mode));
+            }
+        },
+        Rule::parser_locale => match pairs.next() {
+            Some(mode_pair) => {
+                state.locale = match mode_pair.as_str() {
+                    "comma" => NumberLocale::Comma,
+                    _ => NumberLocale::Point,
+                }
+            }
+            None => {
+                let mode = match state.locale {
+                    NumberLocale::Point => "point",
+                    NumberLocale::Comma => "comma",
+                };
+                state.print_reply(&format!("Locale: {}", mode));
+            }
+        },
+        Rule::parser_precision => {
+            if let Some(setting_pair) = pairs.next() {
+                state.precision = match setting_pair.as_str() {
+                    "auto" => None,
+                    _ => match parse_count(&setting_pair)? {
+                        precision if precision > MAX_PRECISION => {
+                            return Err(ParseError::InvalidInteger {
+                                literal: setting_pair.as_str().to_owned(),
+                                reason: format!("is more decimal places than a number has, the most is {}", MAX_PRECISION),
+                                span: pair_span(&setting_pair),
+                            }
+                            .into())
+                        }
+                        precision => Some(precision),
+                    },
+                };
+            } else {
+                match state.precision {
+                    Some(precision) => state.print_reply(&format!("Precision: {}", precision)),
+                    None => state.print_reply("Precision: auto"),
+                }
+            }
+        }
+        Rule::parser_strict => {
+            if let Some(bool_pair) = pairs.next() {
+                state.strict = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Strict: {}", if state.strict { "on" } else { "off" }));
+            }
+        }
+        Rule::parser_units => {
+            if let Some(bool_pair) = pairs.next() {
+                state.units = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Units: {}", if state.units { "on" } else { "off" }));
+            }
+        }
+        Rule::parser_strict_vars => {
+            if let Some(bool_pair) = pairs.next() {
+                state.strict_vars = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Strict vars: {}", if state.strict_vars { "on" } else { "off" }));
+            }
+        }
+        Rule::parser_epsilon => {
+            if let Some(setting_pair) = pairs.next() {
+                state.epsilon = setting_pair.as_str().parse()?;
+            } else {
+                state.print_reply(&format!("Epsilon: {}", state.epsilon));
+            }
+        }
+        Rule::parser_plot => {
+            let expr_pair = next_pair(&mut pairs, command_span, "an expression")?;
+            let span = pair_span(&expr_pair);
+            let value = parse_expression(expr_pair, state)?;
+            let vec: &Vector = (&value).try_into().map_err(|err: EvalError| err.with_span(span))?;
+            state.print_reply(&plot_vector(vec, state.plot_width, state.plot_height));
+        }
+        Rule::parser_latex => {
+            let value = command_value(pairs.next(), state)?;
+            state.print_reply(&value.to_latex(state.precision));
+        }
+        Rule::parser_copy => {
+            let latex = pairs.peek().map(|pair| pair.as_rule()) == Some(Rule::copy_latex);
+            if latex {
+                pairs.next();
+            }
+            let value = command_value(pairs.next(), state)?;
+            let text = if latex {
+                value.to_latex(state.precision)
+            } else {
+                value.to_string()
+            };
+            clipboard::copy(&text).map_err(CalcError::ClipboardError)?;
+            state.print_info(&format!("Copied {}", text));
+        }
+        // Only lowers the expression, so it works with variables that aren't defined yet
+        Rule::parser_explain => {
+            let expr = ast::lower(next_pair(&mut pairs, command_span, "an expression")?.into_inner())?;
+            state.print_reply(&expr.parenthesized());
+            if state.debug_level >= 2 {
+                state.print_reply(&expr.tree());
+            }
+        }
+        Rule::parser_warn => {
+            if let Some(bool_pair) = pairs.next() {
+                state.warnings = parse_bool(bool_pair);
+            } else {
+                state.print_reply(&format!("Warnings: {}", if state.warnings { "on" } else { "off" }));
+            }
+        }
+        // `.transcript off` stops it, a file actually named `off` can be given in quotes
+        Rule::parser_transcript => {
+            let path_pair = next_pair(&mut pairs, command_span, "a path")?;
+            if path_pair.as_str() == "off" {
+                match state.stop_transcript()? {
+                    Some(path) => state.print_info(&format!("Stopped the transcript in {}", path.display())),
+                    None => state.print_info("There's no transcript to stop"),
+                }
+            } else {
+                let path = file_path(path_pair);
+                state.print_info(&format!("Writing a transcript to {}", path));
+                state.start_transcript(path).map_err(|err| with_path(err, path))?;
+            }
+        }
+        Rule::parser_version => state.print_reply(&version::build_info()),
+        Rule::parser_builtins => state.print_reply(&builtin_table()),
+        Rule::parser_vars => {
+            let workspace = state.workspace();
+            let lines: Vec<String> = workspace
+                .names()
+                .into_iter()
+                .map(|name| {
+                    let line = labeled(name, &state.format_value_line(workspace.get(name).expect("Listed by names")));
+                    if workspace.from_command_line(name) {
+                        format!("{} (from --set)", line)
+                    } else {
+                        line
+                    }
+                })
+                .collect();
+            if lines.is_empty() {
+                state.print_reply("No variables");
             } else {
-                println!("Debug level: {}", state.debug_level);
-            }
-        }
-        Rule::parser_modify => modify_variable(
-            pairs
-                .next()
-                .expect("Grammar expects an identifier here")
-                .as_str(),
-            state,
-        ),
-        Rule::parser_exit => {
-            std::process::exit(0);
+                state.print_reply(&lines.join("\n"));
+            }
+        }
+        Rule::parser_stats => match pairs.next() {
+            Some(_) => {
+                state.reset_stats();
+                state.print_info("Stats reset");
+            }
+            None => state.print_reply(&state.stats().to_string()),
+        },
+        Rule::parser_help => match pairs.next() {
+            Some(name_pair) => match builtins::find(name_pair.as_str()) {
+                Some(builtin) => state.print_reply(&builtin_help(builtin)),
+                None => return Err(unknown_function(name_pair.as_str(), pair_span(&name_pair)).into()),
+            },
+            None => state.print_reply("`.builtins` lists the functions, and `.help NAME` shows one with an example"),
+        },
+        Rule::parser_limits => match (pairs.next(), pairs.next()) {
+            (Some(name_pair), Some(value_pair)) => {
+                let name = name_pair.as_str();
+                let value = parse_count(&value_pair)?;
+                match state.limits.get(name) {
+                    Some(max) if value > max && !state.raise_limits => {
+                        return Err(StateError::CantRaiseLimit { name: name.to_owned(), max }.into())
+                    }
+                    _ => state.limits.set(name, value)?,
+                }
+                state.print_info(&format!("{} = {}", name, value));
+            }
+            _ => state.print_reply(&state.limits.to_string()),
+        },
+        Rule::parser_json => match pairs.next() {
+            Some(bool_pair) => {
+                let on = parse_bool(bool_pair);
+                if on {
+                    json::check_available().map_err(CalcError::JsonError)?;
+                }
+                state.json = on;
+            }
+            None => state.print_reply(&format!("JSON: {}", if state.json { "on" } else { "off" })),
+        },
+        Rule::parser_out => {
+            let path = file_path(next_pair(&mut pairs, command_span, "a path")?);
+            let value = eval_result(next_pair(&mut pairs, command_span, "an expression")?, state)?;
+            let line = state.format_value_line(&value);
+            append_line(path, &line)?;
+            state.print_info(&format!("Wrote {} to {}", line, path));
+        }
+        Rule::parser_workspace => match pairs.next() {
+            Some(copy) if copy.as_rule() == Rule::workspace_copy => {
+                let from = next_pair(&mut pairs, command_span, "an identifier")?.as_str();
+                let to = next_pair(&mut pairs, command_span, "an identifier")?.as_str();
+                state.copy_workspace(from, to)?;
+                state.print_info(&format!("Copied workspace '{}' to '{}'", from, to));
+            }
+            Some(name_pair) => {
+                let name = name_pair.as_str();
+                if state.switch_workspace(name) {
+                    state.print_info(&format!("Created workspace '{}'", name));
+                } else {
+                    state.print_info(&format!("Switched to workspace '{}'", name));
+                }
+            }
+            None => {
+                let lines: Vec<String> = state
+                    .workspaces()
+                    .map(|(name, workspace)| {
+                        let marker = if name == state.active_workspace() { '*' } else { ' ' };
+                        let plural = if workspace.len() == 1 { "" } else { "s" };
+                        format!("{} {} ({} variable{})", marker, name, workspace.len(), plural)
+                    })
+                    .collect();
+                state.print_reply(&lines.join("\n"));
+            }
         },
-        Rule::parser_save => save_state(pairs.next().expect("Grammar expects something here").as_str(), state),
-        Rule::parser_load => load_state(pairs.next().expect("Grammar expects something here").as_str(), state),
-        _ => unreachable!("Unknown parser command"),
+        Rule::parser_keys => match state.key_bindings {
+            Some(bindings) => state.print_reply(&format!("Keys: {}", bindings)),
+            None => state.print_reply("Keys: there's no line editor"),
+        },
+        Rule::parser_history => {
+            let var_pair = next_pair(&mut pairs, command_span, "an identifier")?;
+            let name = var_pair.as_str();
+            let Some(value) = state.get_var(name) else {
+                return Err(unknown_identifier(&var_pair, state).into());
+            };
+            let workspace = state.workspace();
+            let line = |label: String, time: Option<SystemTime>, value: &Value| match time {
+                Some(time) => format!("{} = {} (set {})", label, state.format_value_line(value), format_age(time)),
+                None => format!("{} = {}", label, state.format_value_line(value)),
+            };
+            let mut lines = vec![line(name.to_owned(), workspace.assigned_at(name), value)];
+            for (back, (time, value)) in workspace.history(name).enumerate() {
+                lines.push(line(format!("{}@{}", name, back + 1), Some(time), value));
+            }
+            if lines.len() == 1 {
+                lines.push("No earlier values".to_owned());
+            }
+            state.print_reply(&lines.join("\n"));
+        }
+        Rule::parser_info => {
+            let var_pair = next_pair(&mut pairs, command_span, "an identifier")?;
+            let name = var_pair.as_str();
+            let report = match (state.get_var(name), constant(name)) {
+                (Some(value), _) => variable_report(name, value, state),
+                (None, Some(value)) => format!(
+                    "{}\nType: number\nConstant: yes, built in",
+                    labeled(name, &state.format_value(&Value::Number(value)))
+                ),
+                (None, None) => return Err(unknown_identifier(&var_pair, state).into()),
+            };
+            state.print_reply(&report);
+        }
+        Rule::parser_type => {
+            let var_pair = next_pair(&mut pairs, command_span, "an identifier")?;
+            let value = state
+                .get_var(var_pair.as_str())
+                .ok_or_else(|| unknown_identifier(&var_pair, state))?;
+            match state.declared_type(var_pair.as_str()) {
+                Some(declared) => state.print_reply(&format!("{}, declared as {}", value.shape_name(), declared)),
+                None => state.print_reply(&value.shape_name()),
+            }
+        }
+        Rule::parser_showall => {
+            let value = eval_result(next_pair(&mut pairs, command_span, "an expression")?, state)?;
+            state.full_display_requested = true;
+            return Ok(Some(value));
+        }
+        Rule::parser_time => {
+            return time_expression(next_pair(&mut pairs, command_span, "an expression")?, state)
+                .map(Some)
+        }
+        _ => return Err(unexpected_rule(&command_type, "a command").into()),
+    }
+    Ok(None)
+}
+
+/// What `.info` shows about a variable: its value, type, and for vectors their magnitude and
+/// extreme components, counting from 1
+fn variable_report(name: &str, value: &Value, state: &CalculatorState) -> String {
+    let mut lines = vec![labeled(name, &state.format_value(value))];
+    match state.declared_type(name) {
+        Some(declared) => lines.push(format!("Type: {}, declared as {}", value.shape_name(), declared)),
+        None => lines.push(format!("Type: {}", value.shape_name())),
     }
+    if let Value::Vector(vec) = value {
+        let number = |x: f64| state.format_value(&Value::Number(x));
+        lines.push(format!("Magnitude: {}", number(vec.mag())));
+        if let (Some((min_index, min)), Some((max_index, max))) = (vec.min_component(), vec.max_component()) {
+            lines.push(format!("Min: {} (component {})", number(min), min_index + 1));
+            lines.push(format!("Max: {} (component {})", number(max), max_index + 1));
+        }
+    }
+    if constant(name).is_some() {
+        lines.push("Constant: no, this variable hides the built in one".to_owned());
+    } else {
+        lines.push("Constant: no".to_owned());
+    }
+    lines.join("\n")
+}
+
+/// How long ago `time` was, roughly, like `5m ago`
+fn format_age(time: SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |age| age.as_secs());
+    match secs {
+        0 => "just now".to_owned(),
+        1..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Evaluates the expression a command was given, or gets the latest result if it wasn't given one
+fn command_value(expr_pair: Option<Pair<Rule>>, state: &mut CalculatorState) -> Result<Value, CalcError> {
+    match expr_pair {
+        Some(expr_pair) => parse_expression(expr_pair, state),
+        None => state
+            .get_result(-1)
+            .cloned()
+            .ok_or_else(|| EvalError::UnknownResult { index: -1, span: None }.into()),
+    }
+}
+
+/// Evaluates an expression once, recording how long parsing and evaluation took separately in `last_timing`
+fn time_expression(expr_pair: Pair<Rule>, state: &mut CalculatorState) -> Result<Value, CalcError> {
+    // The line has already been parsed as a whole, so parse just the expression again to time it.
+    // The tree is lowered from the original pair so its spans still point into the whole line
+    let (text, span) = (expr_pair.as_str(), pair_span(&expr_pair));
+    let start = Instant::now();
+    CalcParser::parse(Rule::expression, text)?;
+    let expr = ast::lower(expr_pair.into_inner())?;
+    let parse_time = start.elapsed();
+
+    let start = Instant::now();
+    let result = ast::eval(&expr, state);
+    let eval_time = start.elapsed();
+    state.update_stats(|stats| stats.record_eval(eval_time));
+    let value = result?;
+    check_finite(&value, text, None, span, state)?;
+
+    state.push_result(value.clone());
+    state.last_timing = Some(Timing {
+        parse: parse_time,
+        eval: eval_time,
+    });
+    Ok(value)
+}
+
+fn parse_bool(pair: Pair<Rule>) -> bool {
+    matches!(pair.as_str(), "true" | "on")
 }
 
 const STATE_FILE_EXT: &str = "vecalc";
 
-pub fn save_state(filename: &str, state: &CalculatorState) {
+/// Writes the variables and debug level to `filename.vecalc`, as lines `load_state` can replay.
+///
+/// Values are written with [`Value::to_literal`], so they load back exactly.
+/// Saves the active workspace's variables and the settings, or with `all` every workspace.
+///
+/// Every workspace is saved as a `.workspace` line followed by its variables, ending by
+/// switching back to the active one, so loading the file recreates them all.
+pub fn save_state(filename: &str, all: bool, state: &mut CalculatorState) -> Result<(), CalcError> {
     let mut data = String::new();
 
-    for (name, val) in state.variables.iter() {
-        data.push_str(&format!("{} = {}\n", name, val))
+    if all {
+        for (name, workspace) in state.workspaces() {
+            data.push_str(&format!(".workspace {}\n", name));
+            push_variables(&mut data, workspace)?;
+        }
+        data.push_str(&format!(".workspace {}\n", state.active_workspace()));
+    } else {
+        push_variables(&mut data, state.workspace())?;
     }
 
     data.push_str(&format!(".debug {}", state.debug_level));
 
-    let err = std::fs::write(&format!("{}.{}", filename, STATE_FILE_EXT), data);
-    if let Err(err) = err {
-        eprintln!("Error write state file: {}", err);
+    std::fs::write(format!("{}.{}", filename, STATE_FILE_EXT), data)?;
+    state.dirty = false;
+    state.session_file = Some(filename.to_owned());
+    Ok(())
+}
+
+/// Adds a line assigning each of the workspace's variables
+fn push_variables(data: &mut String, workspace: &Workspace) -> Result<(), CalcError> {
+    for name in workspace.names() {
+        let literal = workspace.get(name).expect("Named by the workspace").to_literal().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("'{}' isn't finite, so it can't be saved", name),
+            )
+        })?;
+        match workspace.declared_type(name) {
+            Some(declared) => data.push_str(&format!("let {}: {} = {}\n", name, declared, literal)),
+            None => data.push_str(&format!("{} = {}\n", name, literal)),
+        }
     }
+    Ok(())
 }
 
-pub fn load_state(filename: &str, state: &mut CalculatorState) {
-    use std::io::prelude::*;
+pub fn load_state(filename: &str, state: &mut CalculatorState) -> Result<(), CalcError> {
+    let file = std::fs::File::open(format!("{}.{}", filename, STATE_FILE_EXT))?;
 
     state.debug_level = 0;
-    match std::fs::File::open(&format!("{}.{}", filename, STATE_FILE_EXT)) {
-        Ok(file) => { 
-            let mut reader = std::io::BufReader::new(file);
-            let mut line: String = String::new();
-
-            let mut num_lines: usize = 0;
-    
-            loop {
-                let len = reader.read_line(&mut line).expect("reading from the cursor won't fail");
-                if len == 0 {
-                    break;
-                }
-                parse(&line, state).unwrap();
-                num_lines += 1;
+    // Replaying the file shouldn't echo every stored variable
+    let echo_assignments = std::mem::replace(&mut state.echo_assignments, false);
+    let result = replay_lines(std::io::BufReader::new(file), state);
+    state.echo_assignments = echo_assignments;
+
+    let num_lines = result?;
+    state.print_info(&format!("Processed {} lines", num_lines));
+    state.dirty = false;
+    state.session_file = Some(filename.to_owned());
+
+    state.print_info("Finished loading state file.");
+    Ok(())
+}
+
+/// Strips the quotes from a quoted `file_path`
+fn file_path(pair: Pair<'_, Rule>) -> &str {
+    let path = pair.as_str();
+    path.strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+        .unwrap_or(path)
+}
+
+/// Reads numbers separated by commas or whitespace from a file into the vector variable `name`.
+///
+/// With a `column` (counting from 1) only that field of each line is used, otherwise every
+/// number is, in order. Blank lines are skipped. The file is read a line at a time, and reading
+/// stops with an error past the `max_vector_len` limit.
+pub fn import_vec(name: &str, path: &str, column: Option<usize>, state: &mut CalculatorState) -> Result<(), CalcError> {
+    use std::io::BufRead;
+
+    let invalid = |line_num: usize, msg: String| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} line {}: {}", path, line_num, msg))
+    };
+
+    let file = std::fs::File::open(path).map_err(|err| with_path(err, path))?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut values: Vec<f64> = Vec::new();
+    let mut line = String::new();
+    let mut line_num = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_num += 1;
+
+        let separator = match state.locale {
+            NumberLocale::Point => ',',
+            NumberLocale::Comma => ';',
+        };
+        let mut fields = line
+            .split(|c: char| c == separator || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .peekable();
+        if fields.peek().is_none() {
+            continue;
+        }
+        let fields: Box<dyn Iterator<Item = &str>> = match column {
+            Some(column) => {
+                let field = fields
+                    .nth(column.saturating_sub(1))
+                    .ok_or_else(|| invalid(line_num, format!("there's no column {}", column)))?;
+                Box::new(std::iter::once(field))
             }
+            None => Box::new(fields),
+        };
 
-            println!("Processed {} lines", num_lines);
-        },
-        Err(err) => eprintln!("Error opening state file: {}", err),
+        let mut decimal = None;
+        for field in fields {
+            let value = match state.locale {
+                NumberLocale::Point => field.parse().ok(),
+                NumberLocale::Comma => parse_comma_decimal(field, &mut decimal).map_err(|msg| invalid(line_num, msg))?,
+            };
+            let value: f64 = value
+                .filter(|value: &f64| value.is_finite())
+                .ok_or_else(|| invalid(line_num, format!("'{}' isn't a finite number", field)))?;
+            // The whole file isn't read just to say how long it is
+            state.limits.check_vector_len(values.len() + 1)?;
+            values.push(value);
+        }
     }
 
-    println!("Finished loading state file.")
+    let count = values.len();
+    state.assign_var(name.to_owned(), Value::Vector(values.into()))?;
+    state.print_info(&format!("Read {} values into {}", count, name));
+    Ok(())
+}
+
+/// Reads a number written with a decimal comma, or with a decimal point, for `.locale comma`.
+///
+/// `decimal` is the separator the line's earlier numbers used, and a number using the other one
+/// is an error rather than guessing which is a thousands separator.
+fn parse_comma_decimal(field: &str, decimal: &mut Option<char>) -> Result<Option<f64>, String> {
+    let separator = match (field.contains(','), field.contains('.')) {
+        (true, true) => return Err(format!("'{}' has both a decimal comma and a decimal point", field)),
+        (true, false) => Some(','),
+        (false, true) => Some('.'),
+        (false, false) => None,
+    };
+    let name = |separator| if separator == ',' { "comma" } else { "point" };
+    match (separator, *decimal) {
+        (Some(used), Some(earlier)) if used != earlier => {
+            return Err(format!(
+                "'{}' has a decimal {}, but the numbers before it on the line have a decimal {}",
+                field,
+                name(used),
+                name(earlier)
+            ))
+        }
+        (Some(used), None) => *decimal = Some(used),
+        _ => {}
+    }
+    Ok(field.replace(',', ".").parse().ok())
 }
 
-fn modify_variable(var_name: &str, state: &mut CalculatorState) {
-    if !state.contains_key(var_name) {
-        println!("Unknown variable {}", var_name);
-        return;
+/// Writes the components of a vector to a file, one per line, exactly as `import_vec` reads them
+pub fn export_vec(vec: &Vector, path: &str) -> Result<(), CalcError> {
+    use std::io::Write;
+
+    let file = std::fs::File::create(path).map_err(|err| with_path(err, path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    for x in vec {
+        writeln!(writer, "{}", x)?;
     }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Adds a line to the end of a file, creating it if needed
+fn append_line(path: &str, line: &str) -> Result<(), CalcError> {
+    use std::io::Write;
 
-    let data_enum = state
-        .get_var(var_name)
-        .expect("Already checked existence")
-        .clone();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|err| with_path(err, path))?;
+    writeln!(file, "{}", line).map_err(|err| with_path(err, path))?;
+    Ok(())
+}
 
-    let mut rl = rustyline::Editor::<()>::new();
+fn with_path(err: std::io::Error, path: &str) -> std::io::Error {
+    std::io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
 
-    let prompt = format!("Change {var_name} from {data_enum} to: ");
-    let result = rl.readline_with_initial(&prompt, data_enum.to_string().split_at(1));
+/// Evaluates every line from `reader`, returning how many there were
+fn replay_lines<R: std::io::BufRead>(mut reader: R, state: &mut CalculatorState) -> Result<usize, CalcError> {
+    let mut line: String = String::new();
+    let mut num_lines: usize = 0;
 
-    if let Ok(str_result) = result {
-        let parser_result = CalcParser::parse(Rule::value, &str_result);
-        if let Ok(mut value_pairs) = parser_result {
-            let value = parse_value(
-                value_pairs.next().expect("Grammar specifies existence"),
-                state,
-            )
-            .unwrap();
-            let change_result = state.change_var(var_name.to_owned(), value);
-            if change_result {
-                println!("Changed {var_name}")
-            } else {
-                println!("Failed to change {var_name} because of differing value")
-            }
-        } else {
-            println!("Failed to parse value");
+    loop {
+        line.clear();
+        let len = reader.read_line(&mut line)?;
+        if len == 0 {
+            break;
         }
-    } else {
-        println!("Rustyline error");
-    }
-}
-
-#[derive(Error, Debug)]
-pub enum ParseError {
-    #[error("Tokenization error: {0}")]
-    PestError(#[from] pest::error::Error<Rule>),
-    #[error("Float parsing error: {0}")]
-    ValueParseError(#[from] ParseFloatError),
-    #[error("Invalid identifier '{token}'")]
-    InvalidIdentifier {
-        token: String,
-        // position: u32
-    },
-    #[error("Invalid operator '{token}'")]
-    InvalidOperator {
-        token: String,
-        // position: u32
-    },
-    // TODO: Add separate type for TypeError for use in problems in operators / functions
-    // TODO: Add slot for position of expression, since we have that information
-    #[error("Invalid expression: {0}")]
-    InvalidExpression(&'static str),
-
-    #[error("Invalid expression: {msg} in the expression from {start} to {end}")]
-    InvalidExpr {
-        msg: &'static str,
-        start: usize,
-        end: usize,
-    },
-}
-
-impl ParseError {
-    pub fn from_pair(msg: &'static str, pair: Pair<Rule>) -> Self {
-        let span = pair.as_span();
-        Self::InvalidExpr {
-            msg,
-            start: span.start(),
-            end: span.end(),
-        }
-    }
-}
-
-fn parse_value(pair: Pair<Rule>, state: &CalculatorState) -> Result<Value, ParseError> {
-    state.print_debug(3, format!("(parse_value) rule: {:?}", pair.as_rule()));
-    state.print_debug(3, format!("(parse_value) data: '{}'", pair.as_str()));
+        eval(line.trim_end(), state)?;
+        num_lines += 1;
+    }
+    Ok(num_lines)
+}
+
+/// Every builtin grouped by category, with the descriptions lined up
+fn builtin_table() -> String {
+    let width = BUILTINS.iter().map(|builtin| builtin.signature.chars().count()).max().unwrap_or(0);
+    let mut table = String::new();
+    for category in Category::ALL {
+        if !table.is_empty() {
+            table.push('\n');
+        }
+        table.push_str(category.heading());
+        for builtin in BUILTINS.iter().filter(|builtin| builtin.category == category) {
+            table.push_str(&format!("\n  {:width$}  {}", builtin.signature, builtin.description, width = width));
+        }
+    }
+    table
+}
+
+/// A builtin's signature and description, then its example with what it gives in a new session
+fn builtin_help(builtin: &Builtin) -> String {
+    let mut help = format!("{}\n{}", builtin.signature, builtin.description);
+    let mut session = CalculatorState::new();
+    for line in builtin.example {
+        help.push_str(&format!("\n>> {}", line));
+        match eval(line, &mut session) {
+            Ok(Some(value)) => help.push_str(&format!("\n{}", session.format_value(&value))),
+            Ok(None) => {}
+            Err(err) => help.push_str(&format!("\n{}: {}", err.kind(), err.message())),
+        }
+    }
+    help
+}
+
+fn unknown_function(name: &str, span: Span) -> EvalError {
+    EvalError::UnknownFunction {
+        name: name.to_owned(),
+        suggestions: closest_matches(name, builtins::names()).into_iter().map(str::to_owned).collect(),
+        span: Some(span),
+    }
+}
+
+fn unknown_identifier(pair: &Pair<Rule>, state: &CalculatorState) -> EvalError {
+    EvalError::UnknownIdentifier {
+        name: pair.as_str().to_owned(),
+        suggestions: state.suggest_variables(pair.as_str()),
+        span: Some(pair_span(pair)),
+    }
+}
+
+/// Parses a number, vector or matrix literal, which doesn't need any state
+fn parse_literal(pair: Pair<Rule>) -> Result<Value, ParseError> {
     match pair.as_rule() {
-        Rule::bare_number => Ok(Value::Number(pair.as_str().parse::<f32>()?)),
+        Rule::bare_number => Ok(Value::Number(ast::parse_decimal(pair.as_str())?)),
+        Rule::radix_number => Ok(Value::Number(ast::parse_radix_number(&pair)?)),
+        Rule::angle => Ok(Value::Number(ast::parse_angle(pair.as_str())?)),
+        Rule::percent => Ok(Value::Number(ast::parse_percent(pair.as_str())?)),
+        Rule::imaginary => {
+            let im = ast::parse_decimal(pair.as_str().trim_end_matches('i'))?;
+            Ok(Value::Complex(Complex::new(0.0, im)))
+        }
         Rule::vector => Ok(Value::Vector(parse_vector(pair.into_inner())?)),
-        Rule::ident => {
-            if let Some(value) = state.get_var(pair.as_str()) {
-                Ok(value.to_owned())
-            } else {
-                return Err(ParseError::InvalidIdentifier {
-                    token: pair.as_str().to_string(),
-                });
+        Rule::matrix => {
+            let row_pairs: Vec<Pair<Rule>> = pair.into_inner().collect();
+            let rows = row_pairs
+                .iter()
+                .map(|row_pair| parse_vector(row_pair.clone().into_inner()))
+                .collect::<Result<Vec<Vector>, ParseError>>()?;
+            let lengths: Vec<usize> = rows.iter().map(|row| row.dims()).collect();
+            Matrix::from_rows(rows).map(Value::Matrix).map_err(|row| ParseError::RaggedMatrix {
+                row: row + 1,
+                expected: lengths[0],
+                found: lengths[row],
+                span: pair_span(&row_pairs[row]),
+            })
+        }
+        _ => Err(unexpected_rule(&pair, "a literal")),
+    }
+}
+
+/// Parses a number, vector or matrix literal like `3.5`, `<1, 2, 3>` or `[<1, 0>, <0, 1>]`.
+///
+/// Variables and expressions aren't allowed, since there's no state to evaluate them against.
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = CalcParser::parse(Rule::literal_input, s)?;
+        parse_literal(next_pair(&mut pairs, (0, s.len()), "a literal")?)
+    }
+}
+
+/// Parses a vector literal like `<1, 2, 3>`
+impl FromStr for Vector {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = CalcParser::parse(Rule::vector_input, s)?;
+        let vector_pair = next_pair(&mut pairs, (0, s.len()), "a vector")?;
+        parse_vector(vector_pair.into_inner())
+    }
+}
+
+/// `sin`, `cos` or `tan` of `x` radians
+fn trig(name: &str, x: f64) -> f64 {
+    match name {
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        _ => x.tan(),
+    }
+}
+
+/// `value` as a vector, or an error saying `role` has to be one
+fn vector_argument<'v>(name: &str, role: &'static str, value: &'v Value, name_span: Span) -> Result<&'v Vector, EvalError> {
+    match value {
+        Value::Vector(vec) => Ok(vec),
+        other => Err(EvalError::WrongArgumentType {
+            name: name.to_owned(),
+            role,
+            expected: "vector",
+            found: other.type_name(),
+            span: Some(name_span),
+        }),
+    }
+}
+
+/// `prev(v, n)`, the value `v` had `n` assignments ago, or one assignment ago without `n`
+fn previous_call(arg_exprs: &[Expr], name_span: Span, state: &CalculatorState) -> Result<Value, EvalError> {
+    let invalid = || EvalError::InvalidArguments {
+        name: "prev".to_owned(),
+        msg: "expected a variable and a whole number of assignments back",
+        span: Some(name_span),
+    };
+    let (var, back) = match arg_exprs {
+        [var] => (var, 1),
+        [var, back] => match ast::eval(back, state)? {
+            Value::Number(n) if n.fract() == 0.0 && n >= 0.0 => (var, n as usize),
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    let Expr::Ident { name, span } = var else {
+        return Err(invalid());
+    };
+    ast::previous_value(name, back, *span, state).cloned()
+}
+
+/// `env(NAME, default)`, the environment variable `NAME` read as a literal like `.import_vec` reads
+/// files, or `default` if it isn't set and there is one
+fn env_call(arg_exprs: &[Expr], name_span: Span, state: &CalculatorState) -> Result<Value, EvalError> {
+    let ([Expr::Ident { name, span }] | [Expr::Ident { name, span }, _]) = arg_exprs else {
+        return Err(EvalError::InvalidArguments {
+            name: "env".to_owned(),
+            msg: "expected the name of an environment variable, and optionally a default",
+            span: Some(name_span),
+        });
+    };
+    let error = |msg: String| EvalError::Environment {
+        name: name.clone(),
+        msg,
+        span: Some(*span),
+    };
+    // Whoever can't read the server's files shouldn't see its environment either
+    if !state.filesystem {
+        return Err(error("can't be read in server mode".to_owned()));
+    }
+    let text = match (std::env::var(name), arg_exprs.get(1)) {
+        (Ok(text), _) => text,
+        (Err(std::env::VarError::NotPresent), Some(default)) => return ast::eval(default, state),
+        (Err(std::env::VarError::NotPresent), None) => return Err(error("isn't set".to_owned())),
+        (Err(std::env::VarError::NotUnicode(_)), _) => return Err(error("isn't valid UTF-8".to_owned())),
+    };
+    text.trim()
+        .parse()
+        .map_err(|_| error(format!("is '{}', which isn't a number, vector or matrix", text)))
+}
+
+/// `nderiv(f, x, at)`, the derivative of `f` with respect to the variable `x` where `x` is `at`
+fn derivative_call(arg_exprs: &[Expr], name_span: Span, state: &CalculatorState) -> Result<Value, EvalError> {
+    let invalid = |msg| EvalError::InvalidArguments {
+        name: "nderiv".to_owned(),
+        msg,
+        span: Some(name_span),
+    };
+    let [body, Expr::Ident { name: var, .. }, at] = arg_exprs else {
+        return Err(invalid("expected an expression, the variable to differentiate by, and where"));
+    };
+    let at_span = at.span();
+    let at = f64::try_from(&ast::eval(at, state)?).map_err(|err| err.with_span(at_span))?;
+    if !at.is_finite() {
+        return Err(invalid("can only differentiate at a finite number"));
+    }
+    ast::derivative(body, var, at, state).map(Value::Number)
+}
+
+/// `nintegrate(f, x, a, b)`, the integral of `f` over the variable `x` from `a` to `b`, with an
+/// optional fifth argument for how many intervals to use instead of choosing adaptively
+fn integral_call(arg_exprs: &[Expr], name_span: Span, state: &CalculatorState) -> Result<Value, EvalError> {
+    let invalid = |msg| EvalError::InvalidArguments {
+        name: "nintegrate".to_owned(),
+        msg,
+        span: Some(name_span),
+    };
+    let (body, var, bounds, subdivisions) = match arg_exprs {
+        [body, Expr::Ident { name, .. }, a, b] => (body, name, (a, b), None),
+        [body, Expr::Ident { name, .. }, a, b, n] => (body, name, (a, b), Some(n)),
+        _ => return Err(invalid("expected an expression, the variable to integrate over, and where to start and end")),
+    };
+    let number = |expr: &Expr| -> Result<f64, EvalError> {
+        f64::try_from(&ast::eval(expr, state)?).map_err(|err| err.with_span(expr.span()))
+    };
+    let (a, b) = (number(bounds.0)?, number(bounds.1)?);
+    if !a.is_finite() || !b.is_finite() {
+        return Err(invalid("can only integrate between finite numbers"));
+    }
+    let subdivisions = match subdivisions {
+        Some(n) => match number(n)? {
+            n if n.fract() == 0.0 && n >= 1.0 => {
+                // Saturates, so a huge count still fails the check
+                let n = n as usize;
+                state.limits.check_vector_len(n).map_err(|err| err.with_span(name_span))?;
+                Some(n)
+            }
+            _ => return Err(invalid("the number of intervals has to be a positive whole number")),
+        },
+        None => None,
+    };
+    ast::integral(body, var, (a, b), subdivisions, state).map(Value::Number)
+}
+
+/// `solve(f, x, guess)`, where `f` is zero for the variable `x`, looking near `guess`
+fn solve_call(arg_exprs: &[Expr], name_span: Span, state: &CalculatorState) -> Result<Value, EvalError> {
+    let invalid = |msg| EvalError::InvalidArguments {
+        name: "solve".to_owned(),
+        msg,
+        span: Some(name_span),
+    };
+    let [body, Expr::Ident { name: var, .. }, guess] = arg_exprs else {
+        return Err(invalid("expected an expression, the variable to solve for, and a guess"));
+    };
+    let guess_span = guess.span();
+    let guess = f64::try_from(&ast::eval(guess, state)?).map_err(|err| err.with_span(guess_span))?;
+    if !guess.is_finite() {
+        return Err(invalid("the guess has to be a finite number"));
+    }
+    ast::root(body, var, guess, state)
+        .map(Value::Number)
+        .map_err(|err| err.with_span(name_span))
+}
+
+/// Calls a built in function, evaluating its arguments first, apart from `prev` which takes a
+/// variable, and `nderiv`, `nintegrate` and `solve` which evaluate their expression themselves
+pub(crate) fn call_function(
+    name: &str,
+    arg_exprs: &[Expr],
+    name_span: Span,
+    state: &CalculatorState,
+) -> Result<Value, EvalError> {
+    let span = Some(name_span);
+    if builtins::find(name).is_none() {
+        return Err(unknown_function(name, name_span));
+    }
+    // Takes the variable itself rather than its value
+    if name == "prev" {
+        return previous_call(arg_exprs, name_span, state);
+    }
+    if name == "env" {
+        return env_call(arg_exprs, name_span, state);
+    }
+    if name == "nderiv" {
+        return derivative_call(arg_exprs, name_span, state);
+    }
+    if name == "nintegrate" {
+        return integral_call(arg_exprs, name_span, state);
+    }
+    if name == "solve" {
+        return solve_call(arg_exprs, name_span, state);
+    }
+    // None of the functions use units, so they get plain numbers
+    let args = arg_exprs
+        .iter()
+        .map(|arg| match ast::eval(arg, state)? {
+            Value::Quantity(q) => Ok(Value::Number(q.value)),
+            value => Ok(value),
+        })
+        .collect::<Result<Vec<Value>, EvalError>>()?;
+
+    match name {
+        "out" => {
+            let n = match args.as_slice() {
+                [Value::Number(n)] if n.fract() == 0.0 => *n as i64,
+                _ => {
+                    return Err(EvalError::InvalidArguments {
+                        name: name.to_owned(),
+                        msg: "expected a single whole number",
+                        span,
+                    })
+                }
+            };
+            state
+                .get_result(n)
+                .cloned()
+                .ok_or(EvalError::UnknownResult { index: n, span })
+        }
+        // Shows the chart and passes the vector through, so it can be used mid-expression
+        "plot" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Vector(vec)]) => {
+                state.print_reply(&plot_vector(&vec, state.plot_width, state.plot_height));
+                Ok(Value::Vector(vec))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single vector",
+                span,
+            }),
+        },
+        "mag" | "normalize" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Vector(vec)]) if name == "mag" => Ok(Value::Number(vec.mag())),
+            Ok([Value::Vector(vec)]) => vec
+                .normalized()
+                .map(Value::Vector)
+                .map_err(|err| err.with_span(name_span)),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single vector",
+                span,
+            }),
+        },
+        // The same as the operators, for writing them like the other functions
+        "dot" | "cross" => match <[Value; 2]>::try_from(args) {
+            Ok([Value::Vector(a), Value::Vector(b)]) => {
+                let result = if name == "dot" { a.dot(&b).map(Value::Number) } else { a.cross(&b).map(Value::Vector) };
+                result.map_err(|err| err.with_span(name_span))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected two vectors",
+                span,
+            }),
+        },
+        "cossim" => match <[Value; 2]>::try_from(args) {
+            Ok([Value::Vector(a), Value::Vector(b)]) => a
+                .cosine_similarity(&b)
+                .map(Value::Number)
+                .map_err(|err| err.with_span(name_span)),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected two vectors",
+                span,
+            }),
+        },
+        // The dot and cross products and projection under the names mechanics uses, with errors
+        // saying what each argument is for: work = F·d, torque = r×F, along = (v·d̂)d̂
+        "work" | "torque" | "along" => {
+            let (roles, msg) = match name {
+                "work" => (["force", "displacement"], "expected a force and a displacement"),
+                "torque" => (["lever arm", "force"], "expected a lever arm and a force"),
+                _ => (["vector", "direction"], "expected a vector and a direction"),
+            };
+            let [a, b] = <[Value; 2]>::try_from(args).map_err(|_| EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg,
+                span,
+            })?;
+            let (a, b) = (vector_argument(name, roles[0], &a, name_span)?, vector_argument(name, roles[1], &b, name_span)?);
+            let result = match name {
+                "work" => a.dot(b).map(Value::Number),
+                "torque" => a.cross(b).map(Value::Vector),
+                _ => a.component_along(b).map(Value::Vector),
+            };
+            result.map_err(|err| err.with_span(name_span))
+        }
+        "vmin" | "vmax" => match <[Value; 2]>::try_from(args) {
+            Ok([Value::Vector(a), Value::Vector(b)]) => {
+                let result = if name == "vmin" { a.component_min(&b) } else { a.component_max(&b) };
+                result.map(Value::Vector).map_err(|err| err.with_span(name_span))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected two vectors",
+                span,
+            }),
+        },
+        // Any number of vectors, like `bbox_min(a, b, c)`
+        "bbox_min" | "bbox_max" | "extent" => {
+            let vectors = args
+                .iter()
+                .map(|arg| match arg {
+                    Value::Vector(vec) => Some(vec),
+                    _ => None,
+                })
+                .collect::<Option<Vec<&Vector>>>()
+                .filter(|vectors| !vectors.is_empty())
+                .ok_or_else(|| EvalError::InvalidArguments {
+                    name: name.to_owned(),
+                    msg: "expected one or more vectors",
+                    span,
+                })?;
+            let mismatch = |(index, _): (usize, EvalError)| EvalError::ArgumentDimensionMismatch {
+                name: name.to_owned(),
+                argument: index + 1,
+                expected: vectors[0].dims(),
+                found: vectors[index].dims(),
+                span: Some(arg_exprs[index].span()),
+            };
+            let min = || fold_components(&vectors, "bbox_min", f64::min).map_err(mismatch);
+            let max = || fold_components(&vectors, "bbox_max", f64::max).map_err(mismatch);
+            match name {
+                "bbox_min" => min().map(Value::Vector),
+                "bbox_max" => max().map(Value::Vector),
+                _ => (max()? - min()?).map(Value::Vector),
+            }
+        }
+        "vclamp" => match <[Value; 3]>::try_from(args) {
+            Ok([Value::Vector(p), Value::Vector(lo), Value::Vector(hi)]) => p
+                .component_clamp(&lo, &hi)
+                .map(Value::Vector)
+                .map_err(|err| err.with_span(name_span)),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a point and the two corners to keep it between",
+                span,
+            }),
+        },
+        // These also take real numbers, as complex numbers with no imaginary part
+        "conj" | "abs" | "arg" => {
+            let z = match <[Value; 1]>::try_from(args) {
+                Ok([Value::Complex(z)]) => z,
+                Ok([Value::Number(x)]) => Complex::from(x),
+                _ => {
+                    return Err(EvalError::InvalidArguments {
+                        name: name.to_owned(),
+                        msg: "expected a single complex or real number",
+                        span,
+                    })
+                }
+            };
+            Ok(match name {
+                "conj" => Value::Complex(z.conjugate()),
+                "abs" => Value::Number(z.abs()),
+                _ => Value::Number(z.arg()),
+            })
+        }
+        "sin" | "cos" | "tan" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Number(x)]) => Ok(Value::Number(trig(name, x))),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected an angle in radians",
+                span,
+            }),
+        },
+        "quat" => match <[Value; 2]>::try_from(args) {
+            Ok([Value::Vector(axis), Value::Number(angle)]) => {
+                let mag = axis.mag();
+                if (mag - 1.0).abs() > state.epsilon {
+                    print_debug!(state, 2, "Normalizing the axis for quat(), its magnitude is {}", mag);
+                }
+                Quaternion::from_axis_angle(&axis, angle)
+                    .map(Value::Quaternion)
+                    .map_err(|err| err.with_span(name_span))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected an axis vector and an angle in radians",
+                span,
+            }),
+        },
+        "quaternion" => match <[Value; 4]>::try_from(args) {
+            Ok([Value::Number(w), Value::Number(x), Value::Number(y), Value::Number(z)]) => {
+                Ok(Value::Quaternion(Quaternion::new(w, x, y, z)))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected the four numbers w, x, y and z",
+                span,
+            }),
+        },
+        "qrotate" => match <[Value; 2]>::try_from(args) {
+            Ok([Value::Quaternion(q), Value::Vector(vec)]) => {
+                let norm = q.norm();
+                if (norm - 1.0).abs() > state.epsilon {
+                    print_debug!(state, 2, "Normalizing the quaternion for qrotate(), its norm is {}", norm);
+                }
+                q.rotate(&vec)
+                    .map(Value::Vector)
+                    .map_err(|err| err.with_span(name_span))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a quaternion and a vector",
+                span,
+            }),
+        },
+        "transpose" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Matrix(matrix)]) => Ok(Value::Matrix(matrix.transpose())),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single matrix",
+                span,
+            }),
+        },
+        "identity" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Number(n)]) if n.fract() == 0.0 && n >= 1.0 => {
+                // Converting saturates, so huge sizes still fail the check
+                let size = n as usize;
+                state
+                    .limits
+                    .check_vector_len(size.saturating_mul(size))
+                    .map_err(|err| err.with_span(name_span))?;
+                Ok(Value::Matrix(Matrix::identity(size)))
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a whole number size, at least 1",
+                span,
+            }),
+        },
+        // Lifts 2D vectors into 3D and back, so they can be mixed with a cross product
+        "extend" | "truncate" => {
+            let invalid = |msg| EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg,
+                span,
+            };
+            let (vec, n, fill) = match args.as_slice() {
+                [Value::Vector(vec), Value::Number(n)] => (vec, *n, 0.0),
+                [Value::Vector(vec), Value::Number(n), Value::Number(fill)] if name == "extend" => (vec, *n, *fill),
+                _ if name == "extend" => return Err(invalid("expected a vector, a dimension, and optionally a fill value")),
+                _ => return Err(invalid("expected a vector and a dimension")),
+            };
+            if n.fract() != 0.0 || n < 0.0 {
+                return Err(invalid("the dimension has to be a non-negative whole number"));
+            }
+            // Converting saturates, so huge dimensions still fail the check
+            let n = n as usize;
+            state.limits.check_vector_len(n).map_err(|err| err.with_span(name_span))?;
+            if name == "extend" && n < vec.dims() {
+                return Err(invalid("the vector already has more components, use truncate() to remove them"));
+            }
+            if name == "truncate" && n > vec.dims() {
+                return Err(invalid("the vector has fewer components, use extend() to add them"));
+            }
+            Ok(Value::Vector(vec.resized(n, fill)))
+        }
+        "lerp" | "slerp" => match <[Value; 3]>::try_from(args) {
+            Ok([Value::Vector(a), Value::Vector(b), Value::Number(t)]) => {
+                let result = if name == "lerp" { a.lerp(&b, t) } else { a.slerp(&b, t) };
+                result
+                    .map(Value::Vector)
+                    .map_err(|err| err.with_span(name_span))
             }
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected two vectors and a number",
+                span,
+            }),
+        },
+        // The coordinates of the first vector in the basis made by the rest
+        "coords" => {
+            let vectors = args
+                .iter()
+                .map(|arg| match arg {
+                    Value::Vector(vec) => Some(vec),
+                    _ => None,
+                })
+                .collect::<Option<Vec<&Vector>>>();
+            match vectors.as_deref() {
+                Some([vec, basis @ ..]) if !basis.is_empty() => vec
+                    .coords_in(basis, state.epsilon)
+                    .map(Value::Vector)
+                    .map_err(|err| err.with_span(name_span)),
+                _ => Err(EvalError::InvalidArguments {
+                    name: name.to_owned(),
+                    msg: "expected a vector followed by the basis vectors",
+                    span,
+                }),
+            }
+        }
+        // Draws up to 9 2D vectors, with an optional size at the end, and gives how many were drawn
+        "draw" => {
+            let (vectors, size) = match args.split_last() {
+                Some((Value::Number(size), vectors)) => (vectors, *size),
+                _ => (args.as_slice(), DEFAULT_DRAW_SIZE as f64),
+            };
+            // Labeled with how they were written, which for a variable is just its name
+            let arg_texts: Vec<String> = arg_exprs.iter().map(Expr::to_string).collect();
+            let labeled = vectors
+                .iter()
+                .zip(&arg_texts)
+                .map(|(value, text)| match value {
+                    Value::Vector(vec) => Some((text.as_str(), vec)),
+                    _ => None,
+                })
+                .collect::<Option<Vec<(&str, &Vector)>>>();
+            // A grid any smaller has no room for an arrow
+            let size_ok = size.fract() == 0.0 && size >= MIN_DRAW_SIZE as f64;
+            let labeled = match labeled {
+                Some(labeled) if (1..=9).contains(&labeled.len()) && size_ok => labeled,
+                _ => {
+                    return Err(EvalError::InvalidArguments {
+                        name: name.to_owned(),
+                        msg: "expected 1 to 9 vectors, and optionally a whole number size of at least 2",
+                        span,
+                    })
+                }
+            };
+            let diagram = draw_vectors(&labeled, (size as usize).min(MAX_DRAW_SIZE))
+                .map_err(|err| err.with_span(name_span))?;
+            state.print_reply(&diagram);
+            Ok(Value::Number(labeled.len() as f64))
+        }
+        // A number counts as having no components, like the empty vector. Use `isvec` to tell them apart
+        "dims" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Vector(vec)]) => Ok(Value::Number(vec.dims() as f64)),
+            Ok([Value::Number(_)]) => Ok(Value::Number(0.0)),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single vector or number",
+                span,
+            }),
+        },
+        // Only called when the arguments aren't all number literals, which the grammar takes as a vector literal
+        "vec" => args
+            .into_iter()
+            .map(|arg| match arg {
+                Value::Number(x) => Ok(x),
+                _ => Err(EvalError::InvalidArguments {
+                    name: name.to_owned(),
+                    msg: "expected numbers",
+                    span,
+                }),
+            })
+            .collect::<Result<Vector, EvalError>>()
+            .map(Value::Vector),
+        // 1 if the value is that type and 0 otherwise, for any type
+        "isvec" | "isnum" => match <[Value; 1]>::try_from(args) {
+            Ok([value]) => {
+                let matches = match name {
+                    "isvec" => matches!(value, Value::Vector(_)),
+                    _ => matches!(value, Value::Number(_)),
+                };
+                Ok(Value::Number(if matches { 1.0 } else { 0.0 }))
+            }
+            Err(_) => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single value",
+                span,
+            }),
+        },
+        // Both pass the value they checked through. On a line of their own they don't print anything
+        "assert" => match <[Value; 1]>::try_from(args) {
+            Ok([Value::Number(cond)]) if cond != 0.0 && !cond.is_nan() => Ok(Value::Number(cond)),
+            Ok([Value::Number(cond)]) => Err(EvalError::AssertionFailed {
+                msg: assertion_message(&arg_exprs[0], cond, state),
+                span,
+            }),
+            _ => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected a single number, which fails if it's 0",
+                span,
+            }),
+        },
+        "expect" => match <[Value; 2]>::try_from(args) {
+            Ok([actual, expected]) => match expect_close(&actual, &expected, state) {
+                Ok(()) => Ok(actual),
+                Err(msg) => Err(EvalError::AssertionFailed { msg, span }),
+            },
+            Err(_) => Err(EvalError::InvalidArguments {
+                name: name.to_owned(),
+                msg: "expected the actual value and the expected one",
+                span,
+            }),
+        },
+        // Registered but not handled above
+        _ => Err(unknown_function(name, name_span)),
+    }
+}
+
+/// Checks two values have the same shape and every component within `state.epsilon`, or
+/// describes how they differ
+fn expect_close(actual: &Value, expected: &Value, state: &CalculatorState) -> Result<(), String> {
+    let (actual_shape, expected_shape) = (actual.shape_name(), expected.shape_name());
+    if actual_shape != expected_shape {
+        return Err(format!("expected a {}, got a {}", expected_shape, actual_shape));
+    }
+    let delta = components(actual)
+        .iter()
+        .zip(components(expected))
+        // Equal infinities are no distance apart, rather than NaN
+        .map(|(a, b)| if *a == b { 0.0 } else { (a - b).abs() })
+        // NaN anywhere should fail, which `f64::max` would skip over
+        .fold(0.0, |max: f64, delta| if delta.is_nan() { f64::NAN } else { max.max(delta) });
+    if delta <= state.epsilon {
+        return Ok(());
+    }
+    let delta_name = if matches!(actual, Value::Number(_)) { "off by" } else { "max component delta" };
+    Err(format!(
+        "expected {}, got {}, {} {}",
+        state.format_value(expected),
+        state.format_value(actual),
+        delta_name,
+        state.format_value(&Value::Number(delta))
+    ))
+}
+
+/// Whether two values are the same for `==`: the same shape and unit, and every component equal
+pub(crate) fn values_equal(lhs: &Value, rhs: &Value) -> bool {
+    lhs.shape_name() == rhs.shape_name() && lhs.unit() == rhs.unit() && components(lhs) == components(rhs)
+}
+
+/// Whether two values are the same for `~=`, like `==` but with every component within the
+/// `.epsilon`, the way `expect()` checks them
+pub(crate) fn values_close(lhs: &Value, rhs: &Value, state: &CalculatorState) -> bool {
+    lhs.unit() == rhs.unit() && expect_close(lhs, rhs, state).is_ok()
+}
+
+/// Why `assert(expr)` failed, `cond` being what `expr` gave. For a comparison that includes both
+/// sides, which are evaluated again for it
+fn assertion_message(expr: &Expr, cond: f64, state: &CalculatorState) -> String {
+    let mut msg = format!("'{}' is {}", expr, state.format_value(&Value::Number(cond)));
+    if let Expr::BinaryOp { op, lhs, rhs, .. } = expr {
+        if let (true, Ok(lhs_value), Ok(rhs_value)) = (op.is_comparison(), ast::eval(lhs, state), ast::eval(rhs, state)) {
+            msg += &format!(
+                ", {} is {} and {} is {}",
+                lhs,
+                state.format_value(&lhs_value),
+                rhs,
+                state.format_value(&rhs_value)
+            );
         }
-        _ => unreachable!("non-value being parsed as value"),
+    }
+    msg
+}
+
+/// Every number making up a value, in a fixed order
+fn components(value: &Value) -> Vec<f64> {
+    match value {
+        Value::Number(x) | Value::Quantity(Quantity { value: x, .. }) => vec![*x],
+        Value::Vector(vec) => vec.to_vec(),
+        Value::Matrix(matrix) => matrix.iter().copied().collect(),
+        Value::Quaternion(q) => vec![q.w, q.x, q.y, q.z],
+        Value::Complex(z) => vec![z.re, z.im],
     }
 }
 
-fn parse_vector(pairs: Pairs<Rule>) -> Result<Vector, ParseFloatError> {
-    let mut values: Vec<f32> = Vec::new();
+fn parse_vector(pairs: Pairs<Rule>) -> Result<Vector, ParseError> {
+    let mut values: Vec<f64> = Vec::new();
 
     for pair in pairs {
         match pair.as_rule() {
-            Rule::bare_number => values.push(pair.as_str().parse()?),
-            _ => unreachable!("Non-number inside of vec"),
+            Rule::bare_number => values.push(ast::parse_decimal(pair.as_str())?),
+            Rule::radix_number => values.push(ast::parse_radix_number(&pair)?),
+            Rule::angle => values.push(ast::parse_angle(pair.as_str())?),
+            Rule::percent => values.push(ast::parse_percent(pair.as_str())?),
+            _ => return Err(unexpected_rule(&pair, "a number")),
         }
     }
 
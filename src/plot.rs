@@ -0,0 +1,175 @@
+//! Text charts, for a quick look at values without leaving the terminal
+
+use crate::error::EvalError;
+use crate::helper::Vector;
+
+/// Renders the components of `vec` as a chart of `*` marks, in `width` by `height` characters
+/// including the axes and labels.
+///
+/// Component indices run along the bottom and values up the side. When there are more
+/// components than columns, each column covers the range of the components that fall in it.
+/// Infinite and NaN components are left out, with a note under the chart.
+pub fn plot_vector(vec: &Vector, width: usize, height: usize) -> String {
+    let finite: Vec<(usize, f64)> = vec
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, x)| x.is_finite())
+        .collect();
+    let skipped = vec.dims() - finite.len();
+    let note = match skipped {
+        0 => String::new(),
+        1 => "\n1 component isn't finite and isn't shown".to_owned(),
+        n => format!("\n{} components aren't finite and aren't shown", n),
+    };
+    if finite.is_empty() {
+        return format!("Nothing to plot{}", note);
+    }
+
+    let min = finite.iter().map(|&(_, x)| x).fold(f64::INFINITY, f64::min);
+    let max = finite.iter().map(|&(_, x)| x).fold(f64::NEG_INFINITY, f64::max);
+    let (max_label, min_label) = (axis_label(max), axis_label(min));
+    let label_width = max_label.len().max(min_label.len());
+
+    // Leave room for the labels and ` |` on the left, and the axis and indices underneath
+    let cols = width.saturating_sub(label_width + 2).max(1);
+    let rows = height.saturating_sub(2).max(1);
+
+    // The highest and lowest row each column has a mark in
+    let mut spans: Vec<Option<(usize, usize)>> = vec![None; cols];
+    for &(index, x) in &finite {
+        let col = if vec.dims() == 1 { 0 } else { index * (cols - 1) / (vec.dims() - 1) };
+        let row = if max == min {
+            rows / 2
+        } else {
+            ((max - x) / (max - min) * (rows - 1) as f64).round() as usize
+        };
+        spans[col] = Some(match spans[col] {
+            Some((top, bottom)) => (top.min(row), bottom.max(row)),
+            None => (row, row),
+        });
+    }
+
+    let mut lines = Vec::with_capacity(rows + 2);
+    for row in 0..rows {
+        let label = match row {
+            // Everything is on the middle row, so that's the only one with a value
+            _ if max == min && row == rows / 2 => max_label.as_str(),
+            _ if max == min => "",
+            0 => max_label.as_str(),
+            _ if row == rows - 1 => min_label.as_str(),
+            _ => "",
+        };
+        let marks: String = spans
+            .iter()
+            .map(|span| match span {
+                Some((top, bottom)) if (*top..=*bottom).contains(&row) => '*',
+                _ => ' ',
+            })
+            .collect();
+        lines.push(format!("{:>width$} |{}", label, marks.trim_end(), width = label_width));
+    }
+    lines.push(format!("{:>width$} +{}", "", "-".repeat(cols), width = label_width));
+
+    let last_index = (vec.dims() - 1).to_string();
+    let gap = cols.saturating_sub(1 + last_index.len());
+    let indices = if vec.dims() == 1 {
+        "0".to_owned()
+    } else {
+        format!("0{}{}", " ".repeat(gap), last_index)
+    };
+    lines.push(format!("{:>width$}  {}", "", indices, width = label_width));
+
+    lines.join("\n") + &note
+}
+
+/// A short label for the value axis, since the full shortest form can be very long
+fn axis_label(x: f64) -> String {
+    let magnitude = x.abs();
+    if magnitude != 0.0 && !(1e-3..1e5).contains(&magnitude) {
+        return format!("{:.2e}", x);
+    }
+    let label = format!("{:.3}", x);
+    let label = label.trim_end_matches('0').trim_end_matches('.');
+    match label {
+        "-0" => "0".to_owned(),
+        label => label.to_owned(),
+    }
+}
+
+/// Draws 2D vectors as arrows from the origin, `size` rows tall and twice as many columns wide
+/// since characters are about twice as tall as they are wide.
+///
+/// Each vector is drawn with a line in roughly its direction, ending in its number in the
+/// legend underneath, which labels it with its name. Everything is scaled to fit the longest
+/// component, and if every vector is zero only the axes are drawn.
+pub fn draw_vectors(vectors: &[(&str, &Vector)], size: usize) -> Result<String, EvalError> {
+    if let Some(&(_, vec)) = vectors.iter().find(|(_, vec)| vec.dims() != 2) {
+        return Err(EvalError::UnsupportedDimensions {
+            op: "draw",
+            expected: 2,
+            found: vec.dims(),
+            span: None,
+        });
+    }
+
+    // An odd size puts the origin on a character
+    let rows = size.max(MIN_DRAW_SIZE) | 1;
+    let cols = rows * 2 + 1;
+    let (center_row, center_col) = (rows / 2, cols / 2);
+    let mut grid = vec![vec![' '; cols]; rows];
+    for (row, line) in grid.iter_mut().enumerate() {
+        line[center_col] = if row == center_row { '+' } else { '|' };
+    }
+    for (col, cell) in grid[center_row].iter_mut().enumerate() {
+        if col != center_col {
+            *cell = '-';
+        }
+    }
+
+    let scale = vectors
+        .iter()
+        .flat_map(|(_, vec)| vec.iter())
+        .fold(0.0, |max: f64, x| max.max(x.abs()));
+    let mut legend = Vec::with_capacity(vectors.len());
+    for (num, &(name, vec)) in vectors.iter().enumerate() {
+        let marker = char::from_digit(num as u32 + 1, 36).unwrap_or('#');
+        legend.push(format!("{}: {} = {}", marker, name, vec));
+        if scale == 0.0 || !vec.is_finite() || (vec[0] == 0.0 && vec[1] == 0.0) {
+            continue;
+        }
+
+        let end_col = (vec[0] / scale * center_col as f64).round() as isize;
+        let end_row = -(vec[1] / scale * center_row as f64).round() as isize;
+        let shaft = shaft_char(vec[0], vec[1]);
+        let steps = end_col.abs().max(end_row.abs());
+        for step in 1..=steps {
+            let col = center_col as isize + (end_col * step) / steps;
+            let row = center_row as isize + (end_row * step) / steps;
+            grid[row as usize][col as usize] = if step == steps { marker } else { shaft };
+        }
+    }
+
+    let mut lines: Vec<String> = grid
+        .into_iter()
+        .map(|line| line.into_iter().collect::<String>().trim_end().to_owned())
+        .collect();
+    lines.extend(legend);
+    Ok(lines.join("\n"))
+}
+
+const MIN_DRAW_SIZE: usize = 5;
+
+/// The character closest to the direction of `(x, y)`
+fn shaft_char(x: f64, y: f64) -> char {
+    let slope = (y / x).abs();
+    if slope < 0.4 {
+        '-'
+    } else if slope > 2.5 {
+        '|'
+    } else if (x > 0.0) == (y > 0.0) {
+        '/'
+    } else {
+        '\\'
+    }
+}
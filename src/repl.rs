@@ -0,0 +1,279 @@
+//! What the REPL does with each line, apart from the terminal.
+//!
+//! The binary drives [`run_line`] with rustyline, and [`ScriptedEditor`] drives it from a list
+//! of lines, so a whole session can be replayed and checked without a terminal.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::error::CalcError;
+use crate::helper::{labeled, CalculatorState, ModifyRequest};
+use crate::parser;
+
+/// The parts of a line editor `run_line` needs, besides reading the line itself
+pub trait LineEditor {
+    /// Asks for another line with `initial` already typed in, or `None` if the user backed out
+    fn readline_with_initial(&mut self, prompt: &str, initial: &str) -> Option<String>;
+    /// Keeps a line for recalling later
+    fn add_history_entry(&mut self, line: &str);
+    /// Shows an error from evaluating `input`
+    fn show_error(&mut self, err: &CalcError, input: &str);
+}
+
+/// How a line went, and whether to keep going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOutcome {
+    Ok,
+    /// The line failed, and the error has been shown
+    Failed,
+    /// The line was `.exit`
+    Exit,
+}
+
+/// Evaluates a line and prints what it produced, prompting for the new value first if it was
+/// `.modify`.
+///
+/// Lines that parsed are added to the history whether or not they evaluated, since they're
+/// still worth recalling.
+/// Everything goes into the transcript if one is being written, which is flushed once the line is done.
+///
+/// In JSON mode the result isn't printed, and once the line is done exactly one JSON object
+/// reporting it is sent instead. Errors are still shown by the editor as well.
+///
+/// A panic while evaluating is shown as an internal error rather than ending the session.
+pub fn run_line<E: LineEditor>(state: &mut CalculatorState, editor: &mut E, line: &str) -> LineOutcome {
+    state.record_input(line);
+    let result = catch_panic(|| parser::eval(line, &mut *state));
+    let keep = match &result {
+        Ok(_) => true,
+        Err(err) => !err.is_syntax_error(),
+    };
+    #[cfg(feature = "json")]
+    let report = state.json.then(|| match &result {
+        Ok(value) => crate::json::success(value.as_ref()),
+        Err(err) => crate::json::failure(err),
+    });
+    let outcome = match result {
+        Ok(value) => {
+            match value {
+                Some(_) if state.json => state.full_display_requested = false,
+                Some(value) => state.print_result(&value),
+                None => {}
+            }
+            LineOutcome::Ok
+        }
+        Err(err) => {
+            show_error(state, editor, &err, line);
+            LineOutcome::Failed
+        }
+    };
+    if let Some(timing) = state.last_timing.take() {
+        state.print_reply(&timing.to_string());
+    }
+    if let Some(request) = state.pending_modify.take() {
+        modify_variable(request, editor, state);
+    }
+    #[cfg(feature = "json")]
+    if let Some(report) = report {
+        state.print_json(&report);
+    }
+    if keep {
+        editor.add_history_entry(line);
+    }
+    state.flush_transcript();
+
+    if state.exit_requested {
+        LineOutcome::Exit
+    } else {
+        outcome
+    }
+}
+
+/// What asking for a line at the prompt gave
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// Ctrl-C, and whether nothing had been typed on the line yet
+    Interrupted { empty: bool },
+    /// Ctrl-D
+    Eof,
+}
+
+/// Why a session at the prompt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// A line was `.exit`
+    Exit,
+    /// Ctrl-C twice in a row on an empty line
+    Interrupted,
+    /// Ctrl-D
+    Eof,
+}
+
+/// The prompt's memory between lines, which is only whether Ctrl-C was just pressed.
+///
+/// Ctrl-C cancels the line being typed. On an empty line it says how to quit instead, and
+/// pressing it on an empty line again straight after quits. Typing anything in between starts over.
+#[derive(Debug, Default)]
+pub struct Prompt {
+    interrupted: bool,
+}
+
+impl Prompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deals with one input, running it with `run` if it's a line, and gives why the session
+    /// ended if it did
+    pub fn handle(
+        &mut self,
+        state: &mut CalculatorState,
+        input: Input,
+        run: impl FnOnce(&mut CalculatorState, &str) -> LineOutcome,
+    ) -> Option<SessionEnd> {
+        match input {
+            Input::Line(line) => {
+                self.interrupted = false;
+                (run(state, &line) == LineOutcome::Exit).then_some(SessionEnd::Exit)
+            }
+            Input::Interrupted { empty: false } => {
+                self.interrupted = false;
+                None
+            }
+            Input::Interrupted { empty: true } if !self.interrupted => {
+                self.interrupted = true;
+                state.print_info("(Ctrl-D or .exit quits, or press Ctrl-C again)");
+                None
+            }
+            Input::Interrupted { empty: true } => {
+                state.print_info("CTRL-C");
+                Some(SessionEnd::Interrupted)
+            }
+            Input::Eof => {
+                state.print_info("CTRL-D");
+                Some(SessionEnd::Eof)
+            }
+        }
+    }
+}
+
+/// Prompts for a new value for the variable, starting from the current one
+fn modify_variable<E: LineEditor>(request: ModifyRequest, editor: &mut E, state: &mut CalculatorState) {
+    let var_name = request.name.as_str();
+    let current = state
+        .get_var(var_name)
+        .expect("Checked by the parser")
+        .clone();
+
+    // Matrices display over several lines, but the literal fits on the one being edited
+    let initial = current.to_literal().unwrap_or_else(|| current.to_string());
+    let prompt = format!("Change {var_name} from {initial} to: ");
+    // Backing out of the prompt leaves the variable alone
+    let Some(input) = editor.readline_with_initial(&prompt, &initial) else {
+        return;
+    };
+    // The answer is read as the next line when replaying, so it's recorded like one
+    state.record_input(&input);
+
+    let value = match catch_panic(|| parser::eval_expression(&input, state)) {
+        Ok(value) => value,
+        Err(err) => {
+            show_error(state, editor, &err, &input);
+            return;
+        }
+    };
+    let formatted = state.format_value(&value);
+    let result = if request.force {
+        state.force_change_var(var_name.to_owned(), value)
+    } else {
+        state.change_var(var_name.to_owned(), value)
+    };
+    match result {
+        Ok(()) if state.echo_assignments => state.print_info(&labeled(var_name, &formatted)),
+        Ok(()) => state.print_info(&format!("Changed {var_name}")),
+        Err(err) => show_error(state, editor, &err.into(), &input),
+    }
+}
+
+/// Runs `f`, turning a panic into [`CalcError::Internal`].
+///
+/// The state may be partway through a change when that happens, but losing a variable beats
+/// losing the whole session.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, CalcError>) -> Result<T, CalcError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(CalcError::Internal(panic_message(&*payload))))
+}
+
+/// What a panic said, which is a `&str` or a `String` unless it was raised with something else
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(msg) => (*msg).to_owned(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_owned()),
+    }
+}
+
+/// Shows an error and adds it to the transcript
+fn show_error<E: LineEditor>(state: &CalculatorState, editor: &mut E, err: &CalcError, input: &str) {
+    editor.show_error(err, input);
+    state.record_error(err, input);
+}
+
+/// A `LineEditor` that answers prompts from a list instead of a terminal, and keeps the
+/// history and errors for checking afterwards
+#[derive(Debug, Default)]
+pub struct ScriptedEditor {
+    /// Answers for `.modify` prompts, in order. Running out is like backing out of the prompt
+    pub responses: VecDeque<String>,
+    pub history: Vec<String>,
+    /// Each error's heading and message, like `Unknown identifier: 'x' is not defined`
+    pub errors: Vec<String>,
+}
+
+impl ScriptedEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs each line in turn until one exits, giving how each went
+    pub fn run<'a, I: IntoIterator<Item = &'a str>>(&mut self, state: &mut CalculatorState, lines: I) -> Vec<LineOutcome> {
+        let mut outcomes = Vec::new();
+        for line in lines {
+            let outcome = run_line(state, self, line);
+            outcomes.push(outcome);
+            if outcome == LineOutcome::Exit {
+                break;
+            }
+        }
+        outcomes
+    }
+
+    /// Feeds each input to a [`Prompt`] in turn, like keys pressed at the terminal, until the
+    /// session ends. Gives why it ended, or `None` if the inputs ran out first
+    pub fn run_inputs<I>(&mut self, state: &mut CalculatorState, inputs: I) -> Option<SessionEnd>
+    where
+        I: IntoIterator<Item = Input>,
+    {
+        let mut prompt = Prompt::new();
+        inputs
+            .into_iter()
+            .find_map(|input| prompt.handle(state, input, |state, line| run_line(state, self, line)))
+    }
+}
+
+impl LineEditor for ScriptedEditor {
+    fn readline_with_initial(&mut self, _prompt: &str, _initial: &str) -> Option<String> {
+        self.responses.pop_front()
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        self.history.push(line.to_owned());
+    }
+
+    fn show_error(&mut self, err: &CalcError, _input: &str) {
+        self.errors.push(format!("{}: {}", err.kind(), err.message()));
+    }
+}
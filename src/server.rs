@@ -0,0 +1,295 @@
+//! `--serve`: evaluating lines sent over TCP or a Unix socket, for editor plugins and the like.
+//!
+//! Each connection gets its own `CalculatorState` in its own thread. Every line sent is one
+//! request, and gets exactly one line back in the `--json` format. Commands that use files are
+//! disabled unless the server was started with `--serve-allow-fs`, since they'd be using the
+//! server's files on behalf of whoever connected.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use vector_calc::error::CalcError;
+use vector_calc::helper::{CalculatorState, Limits};
+use vector_calc::json;
+use vector_calc::output::{BufferedOutput, MessageKind};
+use vector_calc::repl::{run_line, LineEditor, LineOutcome};
+
+/// Connections past this many are told so and closed straight away
+const MAX_CONNECTIONS: usize = 16;
+/// Connections are closed after this long without a request
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Longest request line, so a client can't make the server buffer forever
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+/// How often waiting threads check whether the server is shutting down
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The stack for each connection's thread. Evaluating recurses once per level of nesting, and
+/// the default is too small for expressions as deep as `max_expr_depth` allows
+const CONNECTION_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Set by SIGINT, after which no more connections are accepted and open ones are closed
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// A TCP address like `127.0.0.1:7878`, or otherwise the path of a Unix socket to create
+enum Address {
+    Tcp(SocketAddr),
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Unix(PathBuf),
+}
+
+impl Address {
+    fn parse(address: &str) -> Self {
+        match address.parse() {
+            Ok(addr) => Self::Tcp(addr),
+            Err(_) => Self::Unix(PathBuf::from(address)),
+        }
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    fn bind(address: &Address) -> io::Result<Self> {
+        let listener = match address {
+            Address::Tcp(addr) => Self::Tcp(TcpListener::bind(addr)?),
+            #[cfg(unix)]
+            Address::Unix(path) => Self::Unix(UnixListener::bind(path)?, path.clone()),
+            #[cfg(not(unix))]
+            Address::Unix(path) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("'{}' isn't an address, and Unix sockets aren't supported here", path.display()),
+                ))
+            }
+        };
+        // Accepting without blocking, so the loop can notice SIGINT
+        match &listener {
+            Self::Tcp(listener) => listener.set_nonblocking(true)?,
+            #[cfg(unix)]
+            Self::Unix(listener, _) => listener.set_nonblocking(true)?,
+        }
+        Ok(listener)
+    }
+
+    fn accept(&self) -> io::Result<Connection> {
+        let connection = match self {
+            Self::Tcp(listener) => Connection::Tcp(listener.accept()?.0),
+            #[cfg(unix)]
+            Self::Unix(listener, _) => Connection::Unix(listener.accept()?.0),
+        };
+        // Accepted sockets can inherit non-blocking mode, but reads rely on timeouts instead
+        match &connection {
+            Connection::Tcp(stream) => stream.set_nonblocking(false)?,
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.set_nonblocking(false)?,
+        }
+        Ok(connection)
+    }
+}
+
+impl Drop for Listener {
+    /// A Unix socket's file outlives the listener, and would stop the next bind to the same path
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Self::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(Some(timeout)),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(Some(timeout)),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Errors go back in the reply, and there's no one to prompt for `.modify`
+struct ConnectionEditor;
+
+impl LineEditor for ConnectionEditor {
+    fn readline_with_initial(&mut self, _prompt: &str, _initial: &str) -> Option<String> {
+        None
+    }
+
+    fn add_history_entry(&mut self, _line: &str) {}
+
+    fn show_error(&mut self, _err: &CalcError, _input: &str) {}
+}
+
+/// Serves connections on `address` until SIGINT, and gives the exit code.
+///
+/// Every connection starts with `limits`, and can lower them but not raise them.
+pub fn serve(address: &str, allow_fs: bool, limits: Limits) -> i32 {
+    let listener = match Listener::bind(&Address::parse(address)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Couldn't listen on {}: {}", address, err);
+            return 2;
+        }
+    };
+    catch_sigint();
+    eprintln!("Listening on {}", address);
+
+    let open = Arc::new(AtomicUsize::new(0));
+    let mut threads = Vec::new();
+    while !SHUTDOWN.load(Ordering::Relaxed) {
+        let mut connection = match listener.accept() {
+            Ok(connection) => connection,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Couldn't accept a connection: {}", err);
+                continue;
+            }
+        };
+        if open.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            let reply = format!(
+                r#"{{"ok":false,"error_kind":"TooManyConnections","message":"the server already has {} connections","span":null}}"#,
+                MAX_CONNECTIONS
+            );
+            let _ = writeln!(connection, "{}", reply);
+            continue;
+        }
+
+        open.fetch_add(1, Ordering::SeqCst);
+        let open = Arc::clone(&open);
+        let spawned = thread::Builder::new().stack_size(CONNECTION_STACK_SIZE).spawn(move || {
+            if let Err(err) = handle_connection(connection, allow_fs, limits) {
+                eprintln!("Connection failed: {}", err);
+            }
+            open.fetch_sub(1, Ordering::SeqCst);
+        });
+        match spawned {
+            Ok(thread) => threads.push(thread),
+            Err(err) => eprintln!("Couldn't start a thread for a connection: {}", err),
+        }
+        threads.retain(|thread| !thread.is_finished());
+    }
+
+    eprintln!("Shutting down");
+    for thread in threads {
+        let _ = thread.join();
+    }
+    0
+}
+
+/// Answers each line sent until the client disconnects, sends `.exit`, goes quiet for
+/// `IDLE_TIMEOUT`, or the server shuts down
+fn handle_connection(connection: Connection, allow_fs: bool, limits: Limits) -> io::Result<()> {
+    connection.set_read_timeout(POLL_INTERVAL)?;
+    let mut reader = BufReader::new(connection);
+    let output = BufferedOutput::new();
+    let mut state = CalculatorState::new();
+    state.set_output(output.clone());
+    state.json = true;
+    state.filesystem = allow_fs;
+    state.limits = limits;
+    state.raise_limits = false;
+
+    let mut request = Vec::new();
+    let mut last_request = Instant::now();
+    loop {
+        if SHUTDOWN.load(Ordering::Relaxed) || last_request.elapsed() >= IDLE_TIMEOUT {
+            return Ok(());
+        }
+        // Partial lines stay in `request` when the read times out, and the next read carries on
+        match reader.by_ref().take((MAX_REQUEST_LEN + 1 - request.len()) as u64).read_until(b'\n', &mut request) {
+            Ok(0) => return Ok(()),
+            Ok(_) if request.ends_with(b"\n") => {}
+            Ok(_) if request.len() > MAX_REQUEST_LEN => {
+                let reply = format!(
+                    r#"{{"ok":false,"error_kind":"RequestTooLong","message":"requests can be at most {} bytes","span":null}}"#,
+                    MAX_REQUEST_LEN
+                );
+                return writeln!(reader.get_mut(), "{}", reply);
+            }
+            // The client closed the connection partway through a line
+            Ok(_) => return Ok(()),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+        last_request = Instant::now();
+
+        let line = String::from_utf8_lossy(&request).trim_end_matches(['\r', '\n']).to_owned();
+        request.clear();
+        let outcome = run_line(&mut state, &mut ConnectionEditor, &line);
+
+        // `.json off` would leave the client without a reply, so it's always back on
+        state.json = true;
+        let reply = output
+            .take()
+            .into_iter()
+            .find(|(kind, _)| *kind == MessageKind::Json)
+            .map_or_else(|| json::success(None), |(_, reply)| reply);
+        writeln!(reader.get_mut(), "{}", reply)?;
+        if outcome == LineOutcome::Exit {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn catch_sigint() {
+    extern "C" fn on_sigint(_signal: libc::c_int) {
+        SHUTDOWN.store(true, Ordering::Relaxed);
+    }
+    // Only stores to an atomic, which is fine in a signal handler
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Without signals to catch, Ctrl-C just stops the process
+#[cfg(not(unix))]
+fn catch_sigint() {}
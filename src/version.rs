@@ -0,0 +1,39 @@
+//! What build this is, for `--version` and `.version`
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The commit it was built from, if it was built from a git checkout
+pub const GIT_HASH: Option<&str> = option_env!("VECALC_GIT_HASH");
+
+/// The cargo features it was built with
+pub fn features() -> Vec<&'static str> {
+    [
+        ("serde", cfg!(feature = "serde")),
+        ("json", cfg!(feature = "json")),
+        ("clipboard", cfg!(feature = "clipboard")),
+        ("nalgebra", cfg!(feature = "nalgebra")),
+        ("glam", cfg!(feature = "glam")),
+        ("simd", cfg!(feature = "simd")),
+        ("parallel", cfg!(feature = "parallel")),
+        ("wasm", cfg!(feature = "wasm")),
+        ("ffi", cfg!(feature = "ffi")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+/// `vecalc 0.1.0 (abc1234)`, or just `vecalc 0.1.0` without a commit. Scripts may parse this,
+/// so it stays on one line in this form
+pub fn version_line() -> String {
+    match GIT_HASH {
+        Some(hash) => format!("vecalc {} ({})", VERSION, hash),
+        None => format!("vecalc {}", VERSION),
+    }
+}
+
+/// The version line, then the features on a line of their own
+pub fn build_info() -> String {
+    let features = features();
+    let features = if features.is_empty() { "none".to_owned() } else { features.join(", ") };
+    format!("{}\nFeatures: {}", version_line(), features)
+}
@@ -0,0 +1,78 @@
+//! Bindings for using the calculator from JavaScript, built with `--features wasm`.
+//!
+//! There are no files in the browser, so `.save`, `.load`, `.import_vec` and `.export_vec`
+//! and `.transcript` give an error there.
+
+use wasm_bindgen::prelude::*;
+
+use crate::helper::{CalculatorState, Value};
+use crate::output::BufferedOutput;
+use crate::parser;
+
+/// A calculator session, keeping its variables and results between calls
+#[wasm_bindgen]
+pub struct WasmCalculator {
+    state: CalculatorState,
+    output: BufferedOutput,
+}
+
+#[wasm_bindgen]
+impl WasmCalculator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmCalculator {
+        let output = BufferedOutput::new();
+        let mut state = CalculatorState::new();
+        state.set_output(output.clone());
+        WasmCalculator { state, output }
+    }
+
+    /// Evaluates a line, giving everything it printed as the REPL would show it, one message per
+    /// line. Errors are the text the REPL would show, with the offending part underlined.
+    pub fn eval(&mut self, input: &str) -> Result<String, String> {
+        let result = parser::eval(input, &mut self.state);
+        if let Ok(Some(value)) = &result {
+            self.state.print_result(value);
+        }
+        if let Some(timing) = self.state.last_timing.take() {
+            self.state.print_reply(&timing.to_string());
+        }
+        let printed: Vec<String> = self.output.take().into_iter().map(|(_, msg)| msg).collect();
+        match result {
+            Ok(_) => Ok(printed.join("\n")),
+            Err(err) => Err(err.render(input)),
+        }
+    }
+
+    /// The variable as JSON, in the same form as the `serde` feature, or `undefined` if it isn't set
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        self.state
+            .get_var(name)
+            .map(|value| serde_json::to_string(value).expect("Values always serialize"))
+    }
+
+    /// Sets a variable from JSON like `get_var` gives, like `{"Vector": [1.0, 2.0]}`
+    pub fn set_var(&mut self, name: &str, json: &str) -> Result<(), String> {
+        if !is_identifier(name) {
+            return Err(format!("'{}' isn't a valid variable name", name));
+        }
+        let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        self.state
+            .assign_var(name.to_owned(), value)
+            .map_err(|err| format!("{}: {}", err.kind(), err.message()))
+    }
+}
+
+impl Default for WasmCalculator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `name` could be typed as a variable, so one set from JavaScript can be used. Close
+/// to the grammar's `ident`, which uses the Unicode identifier classes
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_ok = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+    let digit_after_underscore = name.starts_with('_') && chars.clone().next().is_some_and(|c| c.is_ascii_digit());
+    starts_ok && !digit_after_underscore && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
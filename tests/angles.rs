@@ -0,0 +1,63 @@
+mod common;
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use common::{error, eval, run, session, show};
+use vector_calc::helper::Value;
+
+fn assert_close(value: Value, expected: f64) {
+    match value {
+        Value::Number(x) => assert!((x - expected).abs() < 1e-12, "{} isn't {}", x, expected),
+        other => panic!("{:?} isn't a number", other),
+    }
+}
+
+#[test]
+fn suffixes_convert_to_radians() {
+    let (mut state, _) = session();
+    assert_close(eval(&mut state, "180deg"), PI);
+    assert_close(eval(&mut state, "45deg"), FRAC_PI_4);
+    assert_close(eval(&mut state, "-90deg"), -FRAC_PI_2);
+    assert_close(eval(&mut state, "1.5rad"), 1.5);
+    assert_close(eval(&mut state, "0.5deg * 2"), PI / 180.0);
+}
+
+#[test]
+fn suffixes_work_inside_vector_literals() {
+    let (mut state, _) = session();
+    let Value::Vector(vec) = eval(&mut state, "<90deg, 2rad, 3>") else {
+        panic!("should be a vector");
+    };
+    assert!((vec[0] - FRAC_PI_2).abs() < 1e-12);
+    assert_eq!(vec[1], 2.0);
+    assert_eq!(vec[2], 3.0);
+}
+
+#[test]
+fn suffixes_work_as_function_arguments() {
+    let (mut state, _) = session();
+    assert_close(eval(&mut state, "sin(30deg)"), 0.5);
+    assert_close(eval(&mut state, "cos(0rad)"), 1.0);
+    let Value::Vector(rotated) = eval(&mut state, "qrotate(quat(<0, 0, 1>, 90deg), <1, 0, 0>)") else {
+        panic!("should be a vector");
+    };
+    assert!(rotated[0].abs() < 1e-12 && (rotated[1] - 1.0).abs() < 1e-12, "{}", rotated);
+}
+
+#[test]
+fn a_space_makes_it_something_else() {
+    let (mut state, _) = session();
+    // `deg` on its own is just a name
+    assert_eq!(error(&mut state, "45 deg").0, "Syntax error");
+    run(&mut state, &["deg = 2"]);
+    assert_eq!(eval(&mut state, "45 * deg"), Value::Number(90.0));
+    // `1.5 rad` is a quantity, which is still radians
+    assert_eq!(show(&mut state, "1.5 rad"), "1.5 rad");
+}
+
+#[test]
+fn longer_words_arent_suffixes() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "45degrees").0, "Syntax error");
+    assert_eq!(error(&mut state, "2radius").0, "Syntax error");
+}
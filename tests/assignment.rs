@@ -0,0 +1,61 @@
+//! Reading a variable back gives exactly what was assigned, and assigning one never changes another
+
+mod common;
+
+use common::{eval, run, session, Rng};
+use vector_calc::helper::{Value, Vector};
+
+#[test]
+fn reads_give_what_was_assigned() {
+    let (mut state, _) = session();
+    for (line, name) in [
+        ("n = 3.5", "n"),
+        ("v = <1, -2, 3.25>", "v"),
+        ("e = <>", "e"),
+        ("m = [<1, 2>, <3, 4>]", "m"),
+        ("q = 3 m", "q"),
+    ] {
+        let expected = eval(&mut state, line.split_once(" = ").unwrap().1);
+        run(&mut state, &[line]);
+        assert_eq!(state.get_var(name), Some(&expected), "{}", line);
+        assert_eq!(eval(&mut state, name), expected, "{}", line);
+    }
+}
+
+#[test]
+fn random_vectors_read_back_exactly() {
+    let (mut state, _) = session();
+    let mut rng = Rng::new(200);
+    for i in 0..200 {
+        let len = rng.below(40);
+        let vector = Vector::from(rng.numbers(len));
+        state.set_var(format!("v{}", i), Value::Vector(vector.clone()));
+        assert_eq!(eval(&mut state, &format!("v{}", i)), Value::Vector(vector));
+    }
+}
+
+#[test]
+fn copies_are_independent() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 2, 3>", "b = a", "a[0] = 10", "a += <1, 1, 1>"]);
+    assert_eq!(eval(&mut state, "a"), eval(&mut state, "<11, 3, 4>"));
+    assert_eq!(eval(&mut state, "b"), eval(&mut state, "<1, 2, 3>"));
+    run(&mut state, &["b.y = 20"]);
+    assert_eq!(eval(&mut state, "a"), eval(&mut state, "<11, 3, 4>"));
+}
+
+#[test]
+fn reading_many_times_doesnt_change_it() {
+    let (mut state, _) = session();
+    run(&mut state, &["v = <1, 2>"]);
+    assert_eq!(eval(&mut state, "v + v * 2 - v"), eval(&mut state, "<2, 4>"));
+    assert_eq!(eval(&mut state, "v dot v + v dot v"), Value::Number(10.0));
+    assert_eq!(eval(&mut state, "v"), eval(&mut state, "<1, 2>"));
+}
+
+#[test]
+fn reassigning_from_itself() {
+    let (mut state, _) = session();
+    run(&mut state, &["v = <1, 2>", "v = v + v", "v = v * v dot v"]);
+    assert_eq!(eval(&mut state, "v"), eval(&mut state, "<40, 80>"));
+}
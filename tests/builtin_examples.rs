@@ -0,0 +1,36 @@
+//! Every builtin's example working in a new session, as `.help NAME` shows it
+
+mod common;
+
+use common::{messages, run, session};
+use vector_calc::builtins::BUILTINS;
+use vector_calc::output::MessageKind;
+use vector_calc::parser;
+
+#[test]
+fn every_example_evaluates() {
+    for builtin in BUILTINS {
+        let (mut state, _) = session();
+        for line in builtin.example {
+            if let Err(err) = parser::eval(line, &mut state) {
+                panic!("{}'s example '{}' failed: {}: {}", builtin.name, line, err.kind(), err.message());
+            }
+        }
+    }
+}
+
+#[test]
+fn every_example_ends_by_calling_its_function() {
+    for builtin in BUILTINS {
+        let last = builtin.example.last().unwrap_or_else(|| panic!("{} has no example", builtin.name));
+        assert!(last.contains(&format!("{}(", builtin.name)), "{}'s example ends with '{}'", builtin.name, last);
+    }
+}
+
+#[test]
+fn help_shows_the_example_and_its_result() {
+    let (mut state, output) = session();
+    run(&mut state, &[".help lerp"]);
+    let help = messages(&output, MessageKind::Info).join("\n");
+    assert!(help.contains(">> lerp(<0, 0>, <10, 20>, 0.25)\n<2.5, 5>"), "{}", help);
+}
@@ -0,0 +1,149 @@
+//! `nintegrate()` and `nderiv()` against closed forms, and the quadrature behind them
+
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::{central_difference, simpson, Value};
+
+fn number(value: Value) -> f64 {
+    match value {
+        Value::Number(x) => x,
+        value => panic!("{:?} isn't a number", value),
+    }
+}
+
+fn assert_close(got: f64, expected: f64, tolerance: f64) {
+    assert!((got - expected).abs() <= tolerance, "got {}, expected {}", got, expected);
+}
+
+#[test]
+fn integrals_match_their_closed_forms() {
+    let (mut state, _) = session();
+    for (line, expected) in [
+        ("nintegrate(x^2, x, 0, 3)", 9.0),
+        ("nintegrate(x^3, x, -1, 2)", 3.75),
+        ("nintegrate(sin(x), x, 0, pi)", 2.0),
+        ("nintegrate(cos(x), x, 0, pi / 2)", 1.0),
+        ("nintegrate(1 / x, x, 1, 2)", std::f64::consts::LN_2),
+        ("nintegrate(2, x, 1, 4)", 6.0),
+    ] {
+        let got = number(eval(&mut state, line));
+        assert!((got - expected).abs() <= 1e-8, "{} gave {}, expected {}", line, got, expected);
+    }
+}
+
+#[test]
+fn integrating_backwards_flips_the_sign() {
+    let (mut state, _) = session();
+    assert_close(number(eval(&mut state, "nintegrate(x^2, x, 3, 0)")), -9.0, 1e-9);
+    assert_eq!(number(eval(&mut state, "nintegrate(x^2, x, 2, 2)")), 0.0);
+}
+
+#[test]
+fn a_fixed_number_of_intervals_is_exact_for_cubics() {
+    let (mut state, _) = session();
+    // Simpson's rule has no error for polynomials up to degree 3
+    assert_close(number(eval(&mut state, "nintegrate(x^3 - x, x, 0, 2, 2)")), 2.0, 1e-12);
+    assert_close(number(eval(&mut state, "nintegrate(sin(x), x, 0, pi, 100)")), 2.0, 1e-6);
+}
+
+#[test]
+fn the_integration_variable_is_bound_per_sample() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 100", "k = 3"]);
+    assert_close(number(eval(&mut state, "nintegrate(k * x, x, 0, 2)")), 6.0, 1e-9);
+    assert_eq!(eval(&mut state, "x"), Value::Number(100.0));
+}
+
+#[test]
+fn non_finite_samples_name_where() {
+    let (mut state, _) = session();
+    run(&mut state, &[".strict off"]);
+    let (kind, message) = error(&mut state, "nintegrate(1 / x, x, 0, 1)");
+    assert_eq!(kind, "Non-finite value");
+    assert!(message.contains("where x is 0"), "{}", message);
+}
+
+#[test]
+fn integrals_need_scalars_and_finite_bounds() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "nintegrate(vec(x, 1), x, 0, 1)").0, "Type error");
+    assert_eq!(error(&mut state, "nintegrate(x, x, 0)").0, "Invalid arguments");
+    assert_eq!(error(&mut state, "nintegrate(x, x, 0, 1, 0)").0, "Invalid arguments");
+    assert_eq!(error(&mut state, "nintegrate(x, x, 0, 1, 1.5)").0, "Invalid arguments");
+}
+
+#[test]
+fn derivatives_match_their_closed_forms() {
+    let (mut state, _) = session();
+    for (line, expected) in [
+        ("nderiv(x^3, x, 2)", 12.0),
+        ("nderiv(x^2 - 4 * x, x, 0)", -4.0),
+        ("nderiv(sin(x), x, 0)", 1.0),
+        ("nderiv(cos(x), x, pi / 2)", -1.0),
+        ("nderiv(1 / x, x, 2)", -0.25),
+        ("nderiv(5, x, 1)", 0.0),
+    ] {
+        let got = number(eval(&mut state, line));
+        assert!((got - expected).abs() <= 1e-7, "{} gave {}, expected {}", line, got, expected);
+    }
+}
+
+#[test]
+fn derivatives_need_a_variable() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "nderiv(x^2, 2, 1)").0, "Invalid arguments");
+}
+
+#[test]
+fn adaptive_simpson_converges() {
+    let integral = simpson(|x: f64| Ok::<_, ()>(x.exp()), 0.0, 1.0, None).unwrap();
+    assert_close(integral, std::f64::consts::E - 1.0, 1e-10);
+    let integral = simpson(|x: f64| Ok::<_, ()>(x.sqrt()), 0.0, 1.0, None).unwrap();
+    assert_close(integral, 2.0 / 3.0, 1e-8);
+}
+
+#[test]
+fn fixed_simpson_rounds_up_to_an_even_count() {
+    let mut samples = 0;
+    let integral = simpson(
+        |x: f64| {
+            samples += 1;
+            Ok::<_, ()>(x * x)
+        },
+        0.0,
+        3.0,
+        Some(3),
+    )
+    .unwrap();
+    assert_close(integral, 9.0, 1e-12);
+    assert_eq!(samples, 5);
+}
+
+#[test]
+fn simpson_stops_at_the_first_error() {
+    let mut samples = 0;
+    let result = simpson(
+        |x: f64| {
+            samples += 1;
+            if x > 0.5 {
+                Err(x)
+            } else {
+                Ok(x)
+            }
+        },
+        0.0,
+        1.0,
+        Some(10),
+    );
+    assert!(result.is_err());
+    assert!(samples <= 3, "{} samples", samples);
+}
+
+#[test]
+fn central_differences_of_polynomials() {
+    let slope = central_difference(|x: f64| Ok::<_, ()>(x * x * x), 2.0, 2.0).unwrap();
+    assert_close(slope, 12.0, 1e-8);
+    let slope = central_difference(|x: f64| Ok::<_, ()>(3.0 * x + 1.0), 5.0, 5.0).unwrap();
+    assert_close(slope, 3.0, 1e-9);
+}
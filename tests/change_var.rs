@@ -0,0 +1,74 @@
+mod common;
+
+use common::{run, session};
+use vector_calc::error::StateError;
+use vector_calc::helper::Value;
+use vector_calc::repl::ScriptedEditor;
+
+fn vector(components: &[f64]) -> Value {
+    Value::Vector(components.to_vec().into())
+}
+
+#[test]
+fn changing_to_the_same_type_works() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 1"]);
+    assert_eq!(state.change_var("x".to_owned(), Value::Number(2.0)), Ok(()));
+    assert_eq!(state.get_var("x"), Some(&Value::Number(2.0)));
+}
+
+#[test]
+fn changing_a_missing_variable_is_not_found() {
+    let (mut state, _) = session();
+    let err = state.change_var("nope".to_owned(), Value::Number(2.0)).unwrap_err();
+    assert_eq!(err, StateError::NotFound { name: "nope".to_owned() });
+    let err = state.force_change_var("nope".to_owned(), Value::Number(2.0)).unwrap_err();
+    assert_eq!(err, StateError::NotFound { name: "nope".to_owned() });
+    assert!(!state.contains_key("nope"));
+}
+
+#[test]
+fn changing_the_type_is_a_mismatch() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 1"]);
+    let err = state.change_var("x".to_owned(), vector(&[1.0, 2.0])).unwrap_err();
+    assert_eq!(
+        err,
+        StateError::TypeMismatch {
+            name: "x".to_owned(),
+            expected: "number",
+            got: "vector",
+        }
+    );
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn forcing_changes_the_type() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 1"]);
+    assert_eq!(state.force_change_var("x".to_owned(), vector(&[1.0, 2.0])), Ok(()));
+    assert_eq!(state.get_var("x"), Some(&vector(&[1.0, 2.0])));
+}
+
+#[test]
+fn modify_shows_why_it_failed() {
+    let (mut state, _) = session();
+    let mut editor = ScriptedEditor::new();
+    editor.responses.push_back("<1, 2>".to_owned());
+    editor.run(&mut state, ["x = 1", ".modify x"]);
+    assert_eq!(editor.errors.len(), 1);
+    assert!(editor.errors[0].contains("'x'"), "{}", editor.errors[0]);
+    assert!(editor.errors[0].contains("vector"), "{}", editor.errors[0]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn forced_modify_changes_the_type() {
+    let (mut state, _) = session();
+    let mut editor = ScriptedEditor::new();
+    editor.responses.push_back("<1, 2>".to_owned());
+    editor.run(&mut state, ["x = 1", ".modify! x"]);
+    assert!(editor.errors.is_empty(), "{:?}", editor.errors);
+    assert_eq!(state.get_var("x"), Some(&vector(&[1.0, 2.0])));
+}
@@ -0,0 +1,83 @@
+//! `assert()`, `expect()` and the comparisons they're written with
+
+mod common;
+
+use common::{error, eval, messages, run, session};
+use vector_calc::helper::Value;
+use vector_calc::output::MessageKind;
+use vector_calc::parser;
+
+#[test]
+fn comparisons_give_one_or_zero() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 2>"]);
+    for (line, expected) in [
+        ("2 == 2", 1.0),
+        ("2 == 3", 0.0),
+        ("2 != 3", 1.0),
+        ("a == <1, 2>", 1.0),
+        ("a == <1, 2, 0>", 0.0),
+        ("a == 1", 0.0),
+        ("0.1 + 0.2 == 0.3", 0.0),
+        ("0.1 + 0.2 ~= 0.3", 1.0),
+        ("a ~= <1, 2.1>", 0.0),
+        ("5 N == 5 kg", 0.0),
+        // Looser than everything else
+        ("1 + 1 == 2", 1.0),
+        ("-2 ^ 2 == -4", 1.0),
+    ] {
+        assert_eq!(eval(&mut state, line), Value::Number(expected), "{}", line);
+    }
+}
+
+#[test]
+fn infinities_are_equal_but_nan_never_is() {
+    let (mut state, _) = session();
+    run(&mut state, &[".strict off"]);
+    assert_eq!(eval(&mut state, "1 / 0 == 1 / 0"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "1 / 0 ~= 1 / 0"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "0 / 0 == 0 / 0"), Value::Number(0.0));
+    assert_eq!(eval(&mut state, "0 / 0 != 0 / 0"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "0 / 0 ~= 0 / 0"), Value::Number(0.0));
+}
+
+#[test]
+fn approx_equal_uses_the_session_epsilon() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "1 ~= 1.001"), Value::Number(0.0));
+    run(&mut state, &[".epsilon 0.01"]);
+    assert_eq!(eval(&mut state, "1 ~= 1.001"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "<1, 2> ~= <1.001, 1.999>"), Value::Number(1.0));
+}
+
+#[test]
+fn passing_checks_print_nothing() {
+    let (mut state, output) = session();
+    run(&mut state, &["a = <1, 0, 0>", "b = <0, 2, 0>", "area = 2"]);
+    assert_eq!(parser::eval("assert(mag(cross(a,b)) ~= area)", &mut state).unwrap(), None);
+    assert_eq!(parser::eval("expect(a cross b, <0, 0, 2>)", &mut state).unwrap(), None);
+    assert!(messages(&output, MessageKind::Result).is_empty());
+}
+
+#[test]
+fn failing_comparisons_show_both_sides() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 0, 0>", "b = <0, 2, 0>", "area = 3"]);
+    let (kind, message) = error(&mut state, "assert(mag(cross(a,b)) ~= area)");
+    assert_eq!(kind, "Assertion failed");
+    assert_eq!(message, "'mag(cross(a, b)) ~= area' is 0, mag(cross(a, b)) is 2 and area is 3");
+
+    let (_, message) = error(&mut state, "assert(dims(a) - 3)");
+    assert_eq!(message, "'dims(a) - 3' is 0");
+}
+
+#[test]
+fn expect_describes_the_difference() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "expect(<0.5, 0, 0.25>, <1, 0, 0>)");
+    assert_eq!(kind, "Assertion failed");
+    assert_eq!(message, "expected <1, 0, 0>, got <0.5, 0, 0.25>, max component delta 0.5");
+    let (_, message) = error(&mut state, "expect(<1, 0>, <1, 0, 0>)");
+    assert_eq!(message, "expected a vector(3), got a vector(2)");
+    assert_eq!(eval(&mut state, "expect(0.1 + 0.2, 0.3) * 10"), Value::Number(3.0000000000000004));
+}
@@ -0,0 +1,55 @@
+//! Unknown `.` commands reaching Rust and getting a proper error, rather than failing in the grammar
+
+mod common;
+
+use common::{error, run, session};
+use vector_calc::parser;
+
+#[test]
+fn misspellings_suggest_the_command() {
+    let (mut state, _) = session();
+    for (line, suggestion) in [
+        (".deubg 2", ".debug"),
+        (".laod session", ".load"),
+        (".svae session", ".save"),
+        (".precison 3", ".precision"),
+        (".workspac new", ".workspace"),
+    ] {
+        let (kind, message) = error(&mut state, line);
+        assert_eq!(kind, "Unknown command", "{}", line);
+        assert!(message.contains(&format!("did you mean '{}'", suggestion)), "{}: {}", line, message);
+    }
+}
+
+#[test]
+fn the_error_names_the_command_and_lists_them_all() {
+    let (mut state, _) = session();
+    let (_, message) = error(&mut state, ".deubg 2");
+    assert!(message.starts_with("'.deubg' is not a command"), "{}", message);
+    for command in [".debug", ".load", ".save", ".limits", ".workspace"] {
+        assert!(message.contains(command), "{} missing from {}", command, message);
+    }
+}
+
+#[test]
+fn commands_without_a_close_match_still_list_them() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, ".zzzzzzzz");
+    assert_eq!(kind, "Unknown command");
+    assert!(!message.contains("did you mean"), "{}", message);
+    assert!(message.contains("The commands are .") && message.contains(".debug"), "{}", message);
+}
+
+#[test]
+fn the_span_covers_the_name() {
+    let (mut state, _) = session();
+    let err = parser::eval(".deubg 2", &mut state).unwrap_err();
+    assert_eq!(err.span(), Some((1, 6)));
+}
+
+#[test]
+fn known_commands_still_work() {
+    let (mut state, _) = session();
+    run(&mut state, &[".debug 2", ".precision 3"]);
+    assert_eq!(state.debug_level, 2);
+}
@@ -0,0 +1,118 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use vector_calc::helper::{CalculatorState, Value};
+use vector_calc::output::{BufferedOutput, MessageKind};
+use vector_calc::parser;
+
+/// A fresh state whose output is kept, and the buffer to read it from
+pub fn session() -> (CalculatorState, BufferedOutput) {
+    let mut state = CalculatorState::new();
+    let output = BufferedOutput::new();
+    state.set_output(output.clone());
+    (state, output)
+}
+
+/// Evaluates a line that has to succeed and produce a value
+pub fn eval(state: &mut CalculatorState, line: &str) -> Value {
+    match parser::eval(line, state) {
+        Ok(Some(value)) => value,
+        Ok(None) => panic!("'{}' gave no value", line),
+        Err(err) => panic!("'{}' failed: {}: {}", line, err.kind(), err.message()),
+    }
+}
+
+/// Runs lines that have to succeed, like assignments and commands
+pub fn run(state: &mut CalculatorState, lines: &[&str]) {
+    for line in lines {
+        if let Err(err) = parser::eval(line, state) {
+            panic!("'{}' failed: {}: {}", line, err.kind(), err.message());
+        }
+    }
+}
+
+/// Evaluates a line and formats its value the way the REPL shows it
+pub fn show(state: &mut CalculatorState, line: &str) -> String {
+    let value = eval(state, line);
+    state.format_value(&value)
+}
+
+/// The kind and message of a line's error, like `("Unknown identifier", "'x' is not defined")`
+pub fn error(state: &mut CalculatorState, line: &str) -> (&'static str, String) {
+    match parser::eval(line, state) {
+        Ok(value) => panic!("'{}' should have failed, but gave {:?}", line, value),
+        Err(err) => (err.kind(), err.message()),
+    }
+}
+
+/// The text of the messages of one kind, oldest first
+pub fn messages(output: &BufferedOutput, kind: MessageKind) -> Vec<String> {
+    output
+        .messages()
+        .into_iter()
+        .filter(|(message_kind, _)| *message_kind == kind)
+        .map(|(_, text)| text)
+        .collect()
+}
+
+/// An empty directory under the system's temporary directory, removed when dropped
+pub struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vector_calc-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Couldn't create the temporary directory");
+        Self(path)
+    }
+
+    /// A path in the directory, as a string for commands like `.save`
+    pub fn file(&self, name: &str) -> String {
+        self.0.join(name).to_str().expect("Temporary paths are UTF-8").to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A small xorshift generator, so property tests are repeatable without a dependency
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A whole number from 0 up to but not including `n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A finite number, sometimes a short decimal and sometimes any bit pattern at all
+    pub fn number(&mut self) -> f64 {
+        loop {
+            let x = match self.below(3) {
+                0 => (self.below(2001) as f64 - 1000.0) / 8.0,
+                1 => (self.next_u64() as f64 / u64::MAX as f64 - 0.5) * 1e6,
+                _ => f64::from_bits(self.next_u64()),
+            };
+            if x.is_finite() {
+                return x;
+            }
+        }
+    }
+
+    pub fn numbers(&mut self, n: usize) -> Vec<f64> {
+        (0..n).map(|_| self.number()).collect()
+    }
+}
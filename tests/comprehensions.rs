@@ -0,0 +1,40 @@
+//! Vector comprehensions like `<x ^ 2 for x in 1..10>`
+
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::Value;
+
+fn vector(components: &[f64]) -> Value {
+    Value::from(components.to_vec())
+}
+
+#[test]
+fn ranges_include_both_ends() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "<x ^ 2 for x in 1..4>"), vector(&[1.0, 4.0, 9.0, 16.0]));
+    assert_eq!(eval(&mut state, "<x for x in -1..1>"), vector(&[-1.0, 0.0, 1.0]));
+    assert_eq!(eval(&mut state, "<x for x in 3..1>"), vector(&[]));
+}
+
+#[test]
+fn filters_keep_the_values_they_hold_for() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "<x for x in 1..10 if x % 2 == 0>"), vector(&[2.0, 4.0, 6.0, 8.0, 10.0]));
+    assert_eq!(eval(&mut state, "<x ^ 2 for x in 1..10 if x % 2 == 0>"), vector(&[4.0, 16.0, 36.0, 64.0, 100.0]));
+    assert_eq!(eval(&mut state, "<x for x in 1..6 if x % 3 != 0>"), vector(&[1.0, 2.0, 4.0, 5.0]));
+    // Any nonzero number keeps the value
+    assert_eq!(eval(&mut state, "<x for x in 1..6 if x % 3>"), vector(&[1.0, 2.0, 4.0, 5.0]));
+    assert_eq!(eval(&mut state, "<x for x in 1..10 if x == 20>"), vector(&[]));
+}
+
+#[test]
+fn the_variable_is_only_bound_inside() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 100"]);
+    assert_eq!(eval(&mut state, "<x + 1 for x in 1..3>"), vector(&[2.0, 3.0, 4.0]));
+    assert_eq!(eval(&mut state, "x"), Value::Number(100.0));
+    let (kind, _) = error(&mut state, "<x for x in 1..1 / 0>");
+    assert_eq!(kind, "Division by zero");
+    assert_eq!(eval(&mut state, "x"), Value::Number(100.0));
+}
@@ -0,0 +1,114 @@
+//! Which dimension error each operation gives and what it carries, matched on the variant
+//! rather than the message
+
+mod common;
+
+use common::{run, session};
+use vector_calc::error::{CalcError, EvalError};
+use vector_calc::helper::{CalculatorState, Vector};
+use vector_calc::parser;
+
+fn eval_error(state: &mut CalculatorState, line: &str) -> EvalError {
+    match parser::eval(line, state) {
+        Err(CalcError::Eval(err)) => err,
+        result => panic!("'{}' should have failed evaluating, but gave {:?}", line, result),
+    }
+}
+
+fn vector(dims: usize) -> Vector {
+    Vector::from(vec![1.0; dims])
+}
+
+#[test]
+fn add_and_subtract() {
+    let (mut state, _) = session();
+    for (line, op, span) in [("<1, 2> + <1, 2, 3>", "+", (7, 8)), ("<1, 2> - <1, 2, 3>", "-", (7, 8))] {
+        match eval_error(&mut state, line) {
+            EvalError::DimensionMismatch { op: got, left: 2, right: 3, span: Some(got_span) } => {
+                assert_eq!((got, got_span), (op, span), "{}", line);
+            }
+            err => panic!("{}: {:?}", line, err),
+        }
+    }
+}
+
+#[test]
+fn dot() {
+    let (mut state, _) = session();
+    match eval_error(&mut state, "<1, 2, 3> dot <1, 2>") {
+        EvalError::DimensionMismatch { op: "dot", left: 3, right: 2, span: Some((10, 13)) } => {}
+        err => panic!("{:?}", err),
+    }
+    assert!(matches!(
+        vector(4).dot(&vector(1)),
+        Err(EvalError::DimensionMismatch { op: "dot", left: 4, right: 1, span: None })
+    ));
+}
+
+#[test]
+fn cross_needs_three_dimensions() {
+    let (mut state, _) = session();
+    for (line, found) in [("<1, 2> cross <1, 2, 3>", 2), ("<1, 2, 3> cross <1, 2, 3, 4>", 4)] {
+        match eval_error(&mut state, line) {
+            EvalError::UnsupportedDimensions { op: "cross", expected: 3, found: got, span: Some(_) } => {
+                assert_eq!(got, found, "{}", line);
+            }
+            err => panic!("{}: {:?}", line, err),
+        }
+    }
+}
+
+#[test]
+fn matrix_products() {
+    let (mut state, _) = session();
+    run(&mut state, &["m = [<1, 2>, <3, 4>]", "n = [<1, 2, 3>]"]);
+    for (line, left, right) in [
+        ("m * n", (2, 2), (1, 3)),
+        ("n * m", (1, 3), (2, 2)),
+        ("m * <1, 2, 3>", (2, 2), (3, 1)),
+    ] {
+        match eval_error(&mut state, line) {
+            EvalError::ShapeMismatch { op: "*", left: got_left, right: got_right, span: Some((2, 3)) } => {
+                assert_eq!((got_left, got_right), (left, right), "{}", line);
+            }
+            err => panic!("{}: {:?}", line, err),
+        }
+    }
+}
+
+#[test]
+fn two_vector_functions() {
+    let (mut state, _) = session();
+    for (line, op) in [
+        ("lerp(<1>, <1, 2>, 0.5)", "lerp"),
+        ("slerp(<1>, <1, 2>, 0.5)", "slerp"),
+        ("along(<1>, <1, 2>)", "along"),
+        ("cossim(<1>, <1, 2>)", "cossim"),
+    ] {
+        match eval_error(&mut state, line) {
+            EvalError::DimensionMismatch { op: got, left: 1, right: 2, span: Some(_) } => assert_eq!(got, op),
+            err => panic!("{}: {:?}", line, err),
+        }
+    }
+}
+
+#[test]
+fn many_vector_functions_name_the_argument() {
+    let (mut state, _) = session();
+    match eval_error(&mut state, "bbox_min(<1, 2>, <1, 2>, <1>)") {
+        EvalError::ArgumentDimensionMismatch { name, argument: 3, expected: 2, found: 1, span: Some((25, 28)) } => {
+            assert_eq!(name, "bbox_min");
+        }
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn the_message_names_both_sides() {
+    let (mut state, _) = session();
+    let err = eval_error(&mut state, "<1, 2, 3> dot <1, 2, 3, 4>");
+    assert_eq!(err.kind(), "Dimension mismatch");
+    assert_eq!(err.name(), "DimensionMismatch");
+    let message = err.message();
+    assert!(message.contains('3') && message.contains('4'), "{}", message);
+}
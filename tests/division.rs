@@ -0,0 +1,42 @@
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::Value;
+
+#[test]
+fn dividing_by_zero_is_an_error() {
+    let (mut state, _) = session();
+    run(&mut state, &["zero = 1 - 1"]);
+    for line in ["5 / 0", "0 / 0", "-1 / 0", "<1, 2> / 0", "<1, 2> / zero"] {
+        let (kind, message) = error(&mut state, line);
+        assert_eq!(kind, "Division by zero", "{}: {}", line, message);
+    }
+}
+
+#[test]
+fn dividing_by_zero_leaves_the_variable_alone() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 4"]);
+    assert_eq!(error(&mut state, "x /= 0").0, "Division by zero");
+    assert_eq!(state.get_var("x"), Some(&Value::Number(4.0)));
+}
+
+#[test]
+fn dividing_by_something_else_still_works() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "5 / 2"), Value::Number(2.5));
+    assert_eq!(eval(&mut state, "<2, 4> / 2"), Value::Vector(vec![1.0, 2.0].into()));
+    assert_eq!(eval(&mut state, "0 / 5"), Value::Number(0.0));
+}
+
+#[test]
+fn strict_off_follows_ieee() {
+    let (mut state, _) = session();
+    run(&mut state, &[".strict off"]);
+    assert_eq!(eval(&mut state, "5 / 0"), Value::Number(f64::INFINITY));
+    assert_eq!(eval(&mut state, "-1 / 0"), Value::Number(f64::NEG_INFINITY));
+    assert!(matches!(eval(&mut state, "0 / 0"), Value::Number(x) if x.is_nan()));
+    assert_eq!(eval(&mut state, "<1, -1> / 0"), Value::Vector(vec![f64::INFINITY, f64::NEG_INFINITY].into()));
+    run(&mut state, &["x = 1", "x /= 0"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(f64::INFINITY)));
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{error, eval, messages, session};
+use vector_calc::helper::{Value, Vector};
+use vector_calc::output::MessageKind;
+use vector_calc::plot::draw_vectors;
+
+#[test]
+fn arrows_end_in_their_number() {
+    let (right, up) = (Vector::from(vec![1.0, 0.0]), Vector::from(vec![0.0, 1.0]));
+    let diagram = draw_vectors(&[("a", &right), ("b", &up)], 5).unwrap();
+    let expected = ["     2", "     |", "-----+----1", "     |", "     |", "1: a = <1, 0>", "2: b = <0, 1>"];
+    assert_eq!(diagram.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn diagonals_use_slashes() {
+    let (up_right, down_right) = (Vector::from(vec![2.0, 2.0]), Vector::from(vec![2.0, -2.0]));
+    let diagram = draw_vectors(&[("a", &up_right), ("b", &down_right)], 5).unwrap();
+    let lines: Vec<&str> = diagram.lines().collect();
+    assert_eq!(lines[0], "     |    1");
+    assert!(lines[1].contains('/'), "{}", diagram);
+    assert!(lines[3].contains('\\'), "{}", diagram);
+    assert_eq!(lines[4], "     |    2");
+}
+
+#[test]
+fn zero_vectors_only_draw_the_axes() {
+    let zero = Vector::from(vec![0.0, 0.0]);
+    let diagram = draw_vectors(&[("z", &zero)], 5).unwrap();
+    let expected = ["     |", "     |", "-----+-----", "     |", "     |", "1: z = <0, 0>"];
+    assert_eq!(diagram.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn only_2d_vectors_can_be_drawn() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "draw(<1, 2, 3>)");
+    assert_eq!(kind, "Dimension mismatch");
+    assert_eq!(message, "draw needs 2 dimensional vectors, found 3");
+}
+
+#[test]
+fn draw_prints_the_diagram_and_gives_the_count() {
+    let (mut state, output) = session();
+    assert_eq!(eval(&mut state, "draw(<1, 2>, <2, -1>, 7)"), Value::Number(2.0));
+    let replies = messages(&output, MessageKind::Info);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].lines().count(), 7 + 2);
+}
+
+#[test]
+fn sizes_below_two_are_rejected() {
+    let (mut state, _) = session();
+    for line in ["draw(<1, 2>, 0)", "draw(<1, 2>, 1)", "draw(<1, 2>, 2.5)", "draw(<1, 2>, -3)"] {
+        let (kind, message) = error(&mut state, line);
+        assert_eq!(kind, "Invalid arguments", "{}: {}", line, message);
+        assert!(message.contains("at least 2"), "{}: {}", line, message);
+    }
+    assert_eq!(eval(&mut state, "draw(<1, 2>, 2)"), Value::Number(1.0));
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{error, eval, run, session, show, TempDir};
+use vector_calc::helper::{CalculatorState, Value, Vector};
+use vector_calc::parser::{load_state, save_state};
+
+fn empty() -> Value {
+    Value::Vector(Vector::from(Vec::<f64>::new()))
+}
+
+#[test]
+fn parses_and_displays_as_angle_brackets() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "<>"), empty());
+    assert_eq!(eval(&mut state, "vec()"), empty());
+    assert_eq!(show(&mut state, "<>"), "<>");
+}
+
+#[test]
+fn arithmetic_with_another_empty_vector_works() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "<> + <>"), empty());
+    assert_eq!(eval(&mut state, "<> - <>"), empty());
+    assert_eq!(eval(&mut state, "<> dot <>"), Value::Number(0.0));
+    assert_eq!(eval(&mut state, "dims(<>)"), Value::Number(0.0));
+}
+
+#[test]
+fn scaling_gives_the_empty_vector() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "<> * 2"), empty());
+    assert_eq!(eval(&mut state, "2 * <>"), empty());
+    assert_eq!(eval(&mut state, "<> / 2"), empty());
+}
+
+#[test]
+fn anything_with_a_non_empty_vector_is_a_dimension_error() {
+    let (mut state, _) = session();
+    for line in ["<> + <1>", "<1> - <>", "<> dot <1, 2>", "<> cross <>", "lerp(<>, <1>, 0.5)"] {
+        let (kind, message) = error(&mut state, line);
+        assert_eq!(kind, "Dimension mismatch", "{}: {}", line, message);
+    }
+}
+
+#[test]
+fn can_be_extended() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "extend(<>, 2)"), Value::Vector(vec![0.0, 0.0].into()));
+}
+
+#[test]
+fn saves_and_loads_back() {
+    let dir = TempDir::new("empty-vector");
+    let file = dir.file("empty");
+    let (mut state, _) = session();
+    run(&mut state, &["e = <>"]);
+    save_state(&file, false, &mut state).unwrap();
+    let mut loaded = CalculatorState::new();
+    load_state(&file, &mut loaded).unwrap();
+    assert_eq!(loaded.get_var("e"), Some(&empty()));
+}
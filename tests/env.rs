@@ -0,0 +1,31 @@
+//! `env()` reading environment variables, and falling back to its default
+
+mod common;
+
+use common::{error, eval, session};
+use vector_calc::helper::Value;
+
+/// Nothing sets this, so the tests don't depend on where they run
+const UNSET: &str = "VECTOR_CALC_TEST_UNSET";
+
+#[test]
+fn unset_variables_give_the_default() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, &format!("env({}, 2)", UNSET)), Value::Number(2.0));
+    assert_eq!(eval(&mut state, &format!("env({}, <1, 2> * 3)", UNSET)), Value::from(vec![3.0, 6.0]));
+}
+
+#[test]
+fn unset_variables_without_a_default_fail() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, &format!("env({})", UNSET));
+    assert_eq!(kind, "Environment error");
+    assert_eq!(message, format!("environment variable '{}' isn't set", UNSET));
+}
+
+#[test]
+fn the_name_has_to_be_an_identifier() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "env(1)").0, "Invalid arguments");
+    assert_eq!(error(&mut state, &format!("env({}, 1, 2)", UNSET)).0, "Invalid arguments");
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{run, session, show};
+
+#[test]
+fn engineering_exponents_are_multiples_of_three() {
+    let (mut state, _) = session();
+    run(&mut state, &[".format eng"]);
+    assert_eq!(show(&mut state, "1500"), "1.5e3");
+    assert_eq!(show(&mut state, "15000"), "15e3");
+    assert_eq!(show(&mut state, "0.00025"), "250e-6");
+    assert_eq!(show(&mut state, "-2000000"), "-2e6");
+}
+
+#[test]
+fn engineering_rounding_carries_into_the_next_exponent() {
+    let (mut state, _) = session();
+    run(&mut state, &[".format eng", ".precision 0"]);
+    assert_eq!(show(&mut state, "999.9"), "1e3");
+    assert_eq!(show(&mut state, "9.99"), "10e0");
+    run(&mut state, &[".precision 2"]);
+    assert_eq!(show(&mut state, "999.999"), "1.00e3");
+    assert_eq!(show(&mut state, "-999.999"), "-1.00e3");
+    assert_eq!(show(&mut state, "99.999"), "100.00e0");
+    assert_eq!(show(&mut state, "1234.5"), "1.23e3");
+}
+
+#[test]
+fn scientific_uses_one_digit_before_the_point() {
+    let (mut state, _) = session();
+    run(&mut state, &[".format sci"]);
+    assert_eq!(show(&mut state, "1500"), "1.5e3");
+    run(&mut state, &[".precision 2"]);
+    assert_eq!(show(&mut state, "999.999"), "1.00e3");
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::Rng;
+use vector_calc::error::ParseError;
+use vector_calc::helper::{Matrix, Value, Vector};
+
+/// The same bits, so `-0` and `0` are told apart
+fn same_numbers(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
+}
+
+#[test]
+fn parses_literals_without_a_state() {
+    assert_eq!("<1, 2, 3>".parse::<Vector>().unwrap(), Vector::from(vec![1.0, 2.0, 3.0]));
+    assert_eq!("vec(1, 2)".parse::<Vector>().unwrap(), Vector::from(vec![1.0, 2.0]));
+    assert_eq!("-2.5".parse::<Value>().unwrap(), Value::Number(-2.5));
+    assert_eq!("0xff".parse::<Value>().unwrap(), Value::Number(255.0));
+    assert_eq!("1_000".parse::<Value>().unwrap(), Value::Number(1000.0));
+    let matrix = Matrix::from_rows(vec![vec![1.0, 2.0].into(), vec![3.0, 4.0].into()]).unwrap();
+    assert_eq!("[<1, 2>, <3, 4>]".parse::<Value>().unwrap(), Value::Matrix(matrix));
+}
+
+#[test]
+fn rejects_anything_but_a_literal() {
+    assert!(matches!("x".parse::<Value>(), Err(ParseError::PestError(_))));
+    assert!(matches!("1 + 2".parse::<Value>(), Err(ParseError::PestError(_))));
+    assert!(matches!("3".parse::<Vector>(), Err(ParseError::PestError(_))));
+    assert!("<1, 2".parse::<Vector>().is_err());
+    assert!(matches!("[<1, 2>, <3>]".parse::<Value>(), Err(ParseError::RaggedMatrix { row: 2, .. })));
+}
+
+#[test]
+fn numbers_round_trip_through_display() {
+    let mut rng = Rng::new(120);
+    for _ in 0..5000 {
+        let x = rng.number();
+        match x.to_string().parse::<Value>() {
+            Ok(Value::Number(parsed)) => assert_eq!(parsed.to_bits(), x.to_bits(), "{} came back as {}", x, parsed),
+            other => panic!("{} came back as {:?}", x, other),
+        }
+    }
+}
+
+#[test]
+fn vectors_round_trip_through_display() {
+    let mut rng = Rng::new(121);
+    for _ in 0..2000 {
+        let dims = rng.below(6);
+        let components = rng.numbers(dims);
+        let vec = Vector::from(components.clone());
+        let text = vec.to_string();
+        let parsed = text.parse::<Vector>().unwrap_or_else(|err| panic!("{} didn't parse: {}", text, err));
+        assert!(same_numbers(&parsed, &components), "{} came back as {}", text, parsed);
+        assert_eq!(text.parse::<Value>().ok(), Some(Value::Vector(parsed)));
+    }
+}
+
+#[test]
+fn empty_vector_round_trips() {
+    let empty = Vector::from(Vec::<f64>::new());
+    assert_eq!(empty.to_string(), "<>");
+    assert_eq!("<>".parse::<Vector>().unwrap(), empty);
+}
+
+/// Matrices display as aligned rows over several lines, so these are written with each row's
+/// vector `Display` instead
+#[test]
+fn matrices_round_trip_through_their_rows() {
+    let mut rng = Rng::new(122);
+    for _ in 0..500 {
+        let (rows, cols) = (1 + rng.below(4), 1 + rng.below(4));
+        let row_vectors: Vec<Vector> = (0..rows).map(|_| rng.numbers(cols).into()).collect();
+        let text = format!("[{}]", row_vectors.iter().map(Vector::to_string).collect::<Vec<_>>().join(", "));
+        let Ok(Value::Matrix(parsed)) = text.parse::<Value>() else {
+            panic!("{} didn't parse as a matrix", text);
+        };
+        assert_eq!(parsed.shape(), (rows, cols));
+        for (row, expected) in row_vectors.iter().enumerate() {
+            assert!(same_numbers(parsed.row(row), expected), "{} came back as {}", text, parsed);
+        }
+    }
+}
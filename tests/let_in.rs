@@ -0,0 +1,42 @@
+//! `let name = value in body`, which binds a name for just the body
+
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::Value;
+
+#[test]
+fn binds_a_name_for_the_body() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <0, 2>", "v = <3, 4>"]);
+    // The part of v along a
+    assert_eq!(eval(&mut state, "let n = normalize(a) in (v dot n) * n"), Value::from(vec![0.0, 4.0]));
+    assert_eq!(eval(&mut state, "let n = 2 in n * 3 + 1"), Value::Number(7.0));
+    assert!(!state.contains_key("n"));
+}
+
+#[test]
+fn a_shadowed_variable_is_restored() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <0, 2>", "v = <3, 4>", "n = 5"]);
+    assert_eq!(eval(&mut state, "let n = normalize(a) in (v dot n) * n"), Value::from(vec![0.0, 4.0]));
+    assert_eq!(eval(&mut state, "n"), Value::Number(5.0));
+
+    // Even when the body fails partway through
+    let (kind, _) = error(&mut state, "let n = normalize(a) in (v dot n) * n + <1, 2, 3>");
+    assert_eq!(kind, "Dimension mismatch");
+    assert_eq!(eval(&mut state, "n"), Value::Number(5.0));
+
+    // Or the value does
+    let (kind, message) = error(&mut state, "let n = normalize(<0, 0>) in (v dot n) * n");
+    assert_eq!(kind, "Invalid arguments");
+    assert!(message.contains("the vector is zero"), "{}", message);
+    assert_eq!(eval(&mut state, "n"), Value::Number(5.0));
+}
+
+#[test]
+fn nested_lets_see_the_closest_binding() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "let n = 2 in let n = n + 1 in n * 10"), Value::Number(30.0));
+    assert_eq!(eval(&mut state, "(let n = 2 in n) + (let n = 3 in n)"), Value::Number(5.0));
+}
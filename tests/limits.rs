@@ -0,0 +1,77 @@
+//! `.limits`, and the limits it sets stopping pathological input
+
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::Limits;
+use vector_calc::parser;
+
+#[test]
+fn shows_and_changes_limits() {
+    let (mut state, _) = session();
+    run(&mut state, &[".limits max_vector_len 1_000"]);
+    assert_eq!(state.limits.max_vector_len, 1000);
+    let (kind, message) = error(&mut state, "identity(1001)");
+    assert_eq!(kind, "Limit exceeded");
+    assert!(message.contains("max_vector_len"), "{}", message);
+    eval(&mut state, "identity(3)");
+}
+
+#[test]
+fn limits_have_a_floor() {
+    let (mut state, _) = session();
+    for name in Limits::NAMES {
+        let (kind, message) = error(&mut state, &format!(".limits {} 0", name));
+        assert_eq!(kind, "Limit error");
+        assert!(message.contains("can't be lower than"), "{}", message);
+    }
+    assert_eq!(state.limits, Limits::default());
+}
+
+#[test]
+fn the_lowest_limits_can_still_be_raised() {
+    let (mut state, _) = session();
+    run(&mut state, &[".limits max_input_len 256", ".limits max_expr_depth 16"]);
+    run(&mut state, &[".limits max_input_len 100_000", ".limits max_expr_depth 100"]);
+    assert_eq!(state.limits.max_input_len, 100_000);
+    assert_eq!(state.limits.max_expr_depth, 100);
+}
+
+#[test]
+fn overflowing_values_are_rejected() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, ".limits max_vector_len 99999999999999999999999");
+    assert_eq!(kind, "Number error");
+    assert!(message.contains("too big"), "{}", message);
+    assert_eq!(state.limits, Limits::default());
+    // A too big value used to saturate to usize::MAX, and then this aborted
+    assert_eq!(error(&mut state, "identity(100_000)").0, "Limit exceeded");
+}
+
+#[test]
+fn unknown_limits_are_suggested() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, ".limits max_vector_ln 10");
+    assert_eq!(kind, "Limit error");
+    assert!(message.contains("max_vector_len"), "{}", message);
+}
+
+#[test]
+fn raising_can_be_turned_off() {
+    let (mut state, _) = session();
+    state.raise_limits = false;
+    run(&mut state, &[".limits max_vector_len 50"]);
+    let (kind, message) = error(&mut state, ".limits max_vector_len 51");
+    assert_eq!(kind, "Limit error");
+    assert!(message.contains("only be lowered from 50"), "{}", message);
+}
+
+#[test]
+fn long_and_deep_lines_are_refused_before_parsing() {
+    let (mut state, _) = session();
+    let long = format!("x = {}", "1".repeat(Limits::default().max_input_len));
+    assert_eq!(error(&mut state, &long).0, "Limit exceeded");
+    let deep = vec!["1"; 1000].join(" + ");
+    assert_eq!(error(&mut state, &deep).0, "Limit exceeded");
+    assert!(parser::check(&deep).is_err());
+}
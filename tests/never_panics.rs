@@ -0,0 +1,198 @@
+//! Odd and malformed input getting an error rather than a panic, from a corpus and from random
+//! lines made of the grammar's own pieces
+
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+
+use common::{session, Rng};
+use vector_calc::helper::{CalculatorState, Value, Vector};
+use vector_calc::parser;
+
+const CORPUS: &[&str] = &[
+    "",
+    " ",
+    "\t",
+    "   \t  ",
+    "<",
+    "<1, 2",
+    "<1,,2>",
+    "<,>",
+    "[",
+    "[<1, 2>, <3>]",
+    "[<1, 2>,",
+    "[]",
+    "(",
+    ")",
+    "+",
+    "-",
+    "*",
+    "%",
+    "^",
+    "1 +",
+    "+ 1",
+    "* * *",
+    "1 + * 2",
+    "1 dot",
+    "cross cross",
+    "dot = 2",
+    "= 3",
+    "x =",
+    "x = = 3",
+    "x += ",
+    "x[",
+    "x[99999999999999999999] = 1",
+    "x.w = 1",
+    "<1, 2> = 3",
+    "1 = 1",
+    "x@",
+    "x@99999999999999999999",
+    "0x",
+    "0xzz",
+    "0b12",
+    "99999999999999999999999999999",
+    "1e400",
+    "1.",
+    ".5",
+    "1__000",
+    "_1",
+    "15%%",
+    "5%%2",
+    "45degrad",
+    "4ii",
+    "5 N^",
+    "5 N^9999",
+    "5 m/",
+    "5 kg*",
+    "let",
+    "let x",
+    "let x = 1 in",
+    "let = 1 in 2",
+    "<x for x in 1..>",
+    "<x for in 1..3>",
+    "<x for x in 1..3 if>",
+    "<x for x in 3..1>",
+    "f(",
+    "f(,)",
+    "sin()",
+    "sin(1, 2)",
+    "nintegrate(x, x, 0, 1, 99999999999999999999)",
+    "solve(x, x, 99999999999999999999)",
+    "identity(99999999999999999999)",
+    "extend(<1>, -0)",
+    "out(99999999999999999999)",
+    "prev(1)",
+    ".",
+    "..",
+    ". debug",
+    ".debug -1",
+    ".debug 99999999999999999999",
+    ".debug x",
+    ".precision 99999999999999999999",
+    ".precision -",
+    ".epsilon",
+    ".epsilon 1.",
+    ".limits",
+    ".limits max_vector_len",
+    ".limits max_vector_len -1",
+    ".limits nope 1",
+    ".display weird",
+    ".modify",
+    ".modify!",
+    ".workspace",
+    ".workspace copy",
+    ".help",
+    ".help nonsense",
+    ".explain",
+    ".explain <",
+    ".time",
+    ".draw",
+    ".draw <1, 2, 3>",
+    ".json maybe",
+    "\u{0}",
+    "\u{feff}",
+    "é = 1",
+    "🦀",
+    "<🦀>",
+    "x = <1, 2> cross <3, 4>",
+    "1 / 0",
+    "-0 % 0",
+];
+
+/// Pieces of the grammar, put together at random into mostly nonsense lines
+const PIECES: &[&str] = &[
+    "1", "-2.5", "0x1f", "0b101", "30deg", "50%", "2i", "3 m/s^2", "<", ">", "<1, 2>", "<>", "[", "]",
+    "[<1, 0>, <0, 1>]", "(", ")", ",", "+", "-", "*", "/", "%", "^", "dot", "cross", "=", "+=", "==", "~=", "x", "v",
+    "v[0]", "v.x", "v@1", "_", "sin(", "solve(", "nintegrate(", "identity(", "let", "in", "for", "if", "..",
+    ".", ".debug", ".vars", ".limits", ".explain", ".precision", ".display", "nice", "raw", "!", "π", " ",
+];
+
+/// Runs `f`, failing with the input if it panicked
+fn no_panic(input: &str, f: impl FnOnce()) {
+    if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+        panic!("'{}' ({:?}) panicked", input, input);
+    }
+}
+
+fn try_everything(input: &str, state: &mut CalculatorState) {
+    no_panic(input, || {
+        let _ = parser::eval(input, state);
+        let _ = parser::check(input);
+        let _ = parser::eval_expression(input, state);
+        let _ = input.parse::<Value>();
+        let _ = input.parse::<Vector>();
+        let _ = parser::complete_prefixes(state, input, input.len());
+    });
+}
+
+/// A session with some variables for the input to refer to, that can't touch files
+fn sandbox() -> CalculatorState {
+    let (mut state, _) = session();
+    state.filesystem = false;
+    for line in ["x = 2", "v = <1, 2>"] {
+        parser::eval(line, &mut state).unwrap();
+    }
+    state
+}
+
+#[test]
+fn the_corpus_never_panics() {
+    let mut state = sandbox();
+    for input in CORPUS {
+        try_everything(input, &mut state);
+        try_everything(input, &mut sandbox());
+    }
+}
+
+#[test]
+fn random_lines_never_panic() {
+    let mut rng = Rng::new(208);
+    let mut state = sandbox();
+    for _ in 0..5_000 {
+        let pieces = 1 + rng.below(8);
+        let input: String = (0..pieces).map(|_| PIECES[rng.below(PIECES.len())]).collect::<Vec<_>>().join(" ");
+        try_everything(&input, &mut state);
+    }
+}
+
+#[test]
+fn random_lines_without_spaces_never_panic() {
+    let mut rng = Rng::new(2080);
+    let mut state = sandbox();
+    for _ in 0..5_000 {
+        let pieces = 1 + rng.below(6);
+        let input: String = (0..pieces).map(|_| PIECES[rng.below(PIECES.len())]).collect();
+        try_everything(&input, &mut state);
+    }
+}
+
+#[test]
+fn truncated_lines_never_panic() {
+    let mut state = sandbox();
+    let lines = ["x = <1, 2> dot <3, 4> + let n = 2 in n ^ 2", "<k ^ 2 for k in 1..5 if k % 2>", ".limits max_vector_len 10"];
+    for line in lines {
+        for (end, _) in line.char_indices() {
+            try_everything(&line[..end], &mut state);
+        }
+    }
+}
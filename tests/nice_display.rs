@@ -0,0 +1,97 @@
+//! `.display nice` showing numbers close to small fractions and fractions of π as those
+// Numbers close to π are the point here
+#![allow(clippy::approx_constant)]
+
+mod common;
+
+use common::{eval, run, session, show};
+use vector_calc::helper::{CalculatorState, Value};
+
+fn nice() -> CalculatorState {
+    let (mut state, _) = session();
+    run(&mut state, &[".display nice"]);
+    state
+}
+
+#[test]
+fn fractions_are_recognised() {
+    let state = nice();
+    for (x, shown) in [
+        (0.5, "1/2"),
+        (-0.25, "-1/4"),
+        (2.0 / 3.0, "2/3"),
+        (1.0 / 64.0, "1/64"),
+        (7.0 / 3.0, "7/3"),
+        (0.1 + 0.2, "3/10"),
+        (3.14, "157/50"),
+    ] {
+        assert_eq!(state.format_number(x), shown, "{}", x);
+    }
+}
+
+#[test]
+fn fractions_of_pi_are_recognised() {
+    let state = nice();
+    let pi = std::f64::consts::PI;
+    for (x, shown) in [
+        (pi, "π"),
+        (-pi, "-π"),
+        (pi / 2.0, "π/2"),
+        (-3.0 * pi / 4.0, "-3π/4"),
+        (2.0 * pi, "2π"),
+    ] {
+        assert_eq!(state.format_number(x), shown, "{}", x);
+    }
+}
+
+#[test]
+fn near_misses_are_left_alone() {
+    let state = nice();
+    for x in [0.5003, 0.501, 0.3334, 3.1416, 1.0 / 65.0, 0.1234567, 0.49999997, 2e6 + 0.5] {
+        assert_eq!(state.format_number(x), state.format_f64(x), "{}", x);
+    }
+}
+
+#[test]
+fn the_epsilon_decides_whats_close_enough() {
+    let mut state = nice();
+    run(&mut state, &[".epsilon 0.0000001"]);
+    // What single precision makes of 1/2, π and 3
+    assert_eq!(state.format_number(0.49999997), "1/2");
+    assert_eq!(state.format_number(3.1415927), "π");
+    assert_eq!(state.format_number(2.9999999), "3");
+    assert_eq!(state.format_number(0.5003), state.format_f64(0.5003));
+}
+
+#[test]
+fn a_large_epsilon_doesnt_loosen_it() {
+    let mut state = nice();
+    run(&mut state, &[".epsilon 0.01"]);
+    assert_eq!(state.format_number(0.5003), state.format_f64(0.5003));
+    assert_eq!(state.format_number(0.49999997), "1/2");
+}
+
+#[test]
+fn results_are_shown_nicely() {
+    let mut state = nice();
+    assert_eq!(show(&mut state, "1 / 3"), "1/3");
+    assert_eq!(show(&mut state, "pi / 2"), "π/2");
+    assert_eq!(show(&mut state, "<1, 2, 3> * pi / 4"), "<π/4, π/2, 3π/4>");
+}
+
+#[test]
+fn stored_values_are_not_rounded() {
+    let mut state = nice();
+    run(&mut state, &[".epsilon 0.0000001", "x = 0.49999997"]);
+    assert_eq!(show(&mut state, "x"), "1/2");
+    assert_eq!(eval(&mut state, "x"), Value::Number(0.49999997));
+    run(&mut state, &[".display raw"]);
+    assert_eq!(show(&mut state, "x"), "0.49999997");
+}
+
+#[test]
+fn a_precision_shows_the_digits() {
+    let mut state = nice();
+    run(&mut state, &[".precision 4"]);
+    assert_eq!(show(&mut state, "1 / 2"), "0.5000");
+}
@@ -0,0 +1,26 @@
+//! `mag()` and `normalize()`
+
+mod common;
+
+use common::{error, eval, session, show};
+use vector_calc::helper::Value;
+
+#[test]
+fn mag_is_the_length() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "mag(<3, 4>)"), Value::Number(5.0));
+    assert_eq!(eval(&mut state, "mag(<>)"), Value::Number(0.0));
+    assert_eq!(error(&mut state, "mag(2)").0, "Invalid arguments");
+}
+
+#[test]
+fn normalize_gives_a_unit_vector() {
+    let (mut state, _) = session();
+    assert_eq!(show(&mut state, "normalize(<3, 4>)"), "<0.6, 0.8>");
+    assert_eq!(eval(&mut state, "normalize(<0, -5>)"), Value::from(vec![0.0, -1.0]));
+    // Scaled first, so squaring the components doesn't overflow
+    assert_eq!(eval(&mut state, "normalize(<1, 0> * 10 ^ 300)"), Value::from(vec![1.0, 0.0]));
+    let (kind, message) = error(&mut state, "normalize(<0, 0>)");
+    assert_eq!(kind, "Invalid arguments");
+    assert!(message.contains("the vector is zero"), "{}", message);
+}
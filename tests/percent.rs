@@ -0,0 +1,94 @@
+//! Percent literals and the modulo operator, which share `%`
+
+mod common;
+
+use common::{error, eval, messages, run, session, show};
+use vector_calc::helper::Value;
+use vector_calc::output::MessageKind;
+use vector_calc::parser;
+
+fn number(line: &str) -> f64 {
+    let (mut state, _) = session();
+    match eval(&mut state, line) {
+        Value::Number(x) => x,
+        value => panic!("'{}' gave {:?}", line, value),
+    }
+}
+
+#[test]
+fn percentages_are_fractions() {
+    assert_eq!(number("50%"), 0.5);
+    assert_eq!(number("15%"), 0.15);
+    assert_eq!(number("-25%"), -0.25);
+    assert_eq!(number("1_000%"), 10.0);
+    assert_eq!(number("5% * 2"), 0.1);
+    assert_eq!(number("5%-2"), 0.05 - 2.0);
+    let (mut state, _) = session();
+    assert_eq!(show(&mut state, "<50%, 1>"), "<0.5, 1>");
+    assert_eq!(show(&mut state, "<1, 2> * 50%"), "<0.5, 1>");
+}
+
+#[test]
+fn modulo_between_values() {
+    assert_eq!(number("5 % 2"), 1.0);
+    assert_eq!(number("5%2"), 1.0);
+    assert_eq!(number("7.5 % 2"), 1.5);
+    assert_eq!(number("-7 % 3"), -1.0);
+    assert_eq!(number("7 % -3"), 1.0);
+    let (mut state, _) = session();
+    run(&mut state, &["n = 17"]);
+    assert_eq!(eval(&mut state, "n % 5"), Value::Number(2.0));
+    assert_eq!(eval(&mut state, "20%n"), Value::Number(3.0));
+}
+
+#[test]
+fn percent_and_modulo_together() {
+    assert_eq!(number("50% % 20%"), 0.5 % 0.2);
+    assert_eq!(number("5 % 50%"), 0.0);
+}
+
+#[test]
+fn modulo_is_at_multiply_precedence() {
+    let (mut state, output) = session();
+    run(&mut state, &[".explain a + b % c * d", ".explain a % b ^ c", ".explain a * b % c"]);
+    assert_eq!(
+        messages(&output, MessageKind::Info),
+        ["(a + ((b % c) * d))", "(a % (b ^ c))", "((a * b) % c)"]
+    );
+    assert_eq!(number("1 + 7 % 4 * 2"), 7.0);
+    assert_eq!(number("2 ^ 3 % 5"), 3.0);
+}
+
+#[test]
+fn modulo_by_zero() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "5 % 0").0, "Division by zero");
+    run(&mut state, &[".strict off"]);
+    match eval(&mut state, "5 % 0") {
+        Value::Number(x) => assert!(x.is_nan()),
+        value => panic!("{:?}", value),
+    }
+}
+
+#[test]
+fn modulo_only_takes_numbers() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "<5, 6> % 2");
+    assert_eq!(kind, "Type error");
+    assert!(message.contains('%'), "{}", message);
+}
+
+#[test]
+fn a_line_can_start_with_modulo() {
+    let (mut state, _) = session();
+    eval(&mut state, "17");
+    assert_eq!(eval(&mut state, "% 5"), Value::Number(2.0));
+}
+
+#[test]
+fn a_lone_percent_sign_is_a_syntax_error() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "%").0, "Syntax error");
+    assert_eq!(error(&mut state, "5 %").0, "Syntax error");
+    assert!(parser::check("5 %% 2").is_err());
+}
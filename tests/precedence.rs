@@ -0,0 +1,141 @@
+//! How the precedence climber groups operators, checked through `.explain` and by evaluating
+
+mod common;
+
+use common::{eval, messages, run, session};
+use vector_calc::helper::Value;
+use vector_calc::output::MessageKind;
+
+/// What `.explain` brackets `line` as
+fn grouping(line: &str) -> String {
+    let (mut state, output) = session();
+    run(&mut state, &[&format!(".explain {}", line)]);
+    messages(&output, MessageKind::Info).join("\n")
+}
+
+fn groups_as(cases: &[(&str, &str)]) {
+    for (line, grouped) in cases {
+        assert_eq!(grouping(line), *grouped, "{}", line);
+    }
+}
+
+#[test]
+fn dot_and_cross_bind_tighter_than_add() {
+    groups_as(&[
+        ("a + b dot c", "(a + (b dot c))"),
+        ("a dot b + c", "((a dot b) + c)"),
+        ("a - b cross c", "(a - (b cross c))"),
+        ("a dot b + 1 * 2", "((a dot b) + (1 * 2))"),
+    ]);
+}
+
+#[test]
+fn dot_and_cross_bind_tighter_than_multiply() {
+    groups_as(&[
+        ("2 * a dot b", "(2 * (a dot b))"),
+        ("a dot b * 2", "((a dot b) * 2)"),
+        ("a cross b / 2", "((a cross b) / 2)"),
+    ]);
+}
+
+#[test]
+fn dot_and_cross_are_left_associative() {
+    groups_as(&[
+        ("a cross b dot c", "((a cross b) dot c)"),
+        ("a cross b cross c", "((a cross b) cross c)"),
+    ]);
+}
+
+#[test]
+fn power_binds_tightest_and_is_right_associative() {
+    groups_as(&[
+        ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+        ("a dot b ^ 2", "(a dot (b ^ 2))"),
+        ("2 * 3 ^ 2", "(2 * (3 ^ 2))"),
+        ("a ^ -1", "(a ^ (-1))"),
+    ]);
+}
+
+#[test]
+fn multiply_and_divide_are_left_associative() {
+    groups_as(&[
+        ("a * b / c * d", "(((a * b) / c) * d)"),
+        ("a / b / c", "((a / b) / c)"),
+        ("a / b * c", "((a / b) * c)"),
+        ("a - b - c", "((a - b) - c)"),
+        ("a + b * c - d", "((a + (b * c)) - d)"),
+    ]);
+}
+
+#[test]
+fn groupings_give_the_right_values() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 0, 0>", "b = <0, 1, 0>", "c = <0, 0, 1>"]);
+    assert_eq!(eval(&mut state, "2 ^ 3 ^ 2"), Value::Number(512.0));
+    assert_eq!(eval(&mut state, "8 / 4 / 2"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "2 * 6 / 3 * 2"), Value::Number(8.0));
+    assert_eq!(eval(&mut state, "10 - 4 - 3"), Value::Number(3.0));
+    assert_eq!(eval(&mut state, "a cross b dot c"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "2 * a dot a"), Value::Number(2.0));
+    assert_eq!(eval(&mut state, "a dot a * 3"), Value::Number(3.0));
+    assert_eq!(eval(&mut state, "1 + a dot a"), Value::Number(2.0));
+}
+
+#[test]
+fn minus_binds_looser_than_power() {
+    // `-2` is the negation of 2, not a literal, so `-2 ^ 2` is `-(2 ^ 2)`
+    assert_eq!(grouping("-2 ^ 2"), "(-(2 ^ 2))");
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "-2 ^ 2"), Value::Number(-4.0));
+    assert_eq!(eval(&mut state, "2 * -3"), Value::Number(-6.0));
+    assert_eq!(eval(&mut state, "2 ^ -1"), Value::Number(0.5));
+}
+
+#[test]
+fn minus_binds_tighter_than_everything_else() {
+    groups_as(&[
+        ("-a dot b", "((-a) dot b)"),
+        ("2 * -a", "(2 * (-a))"),
+        ("a - -b", "(a - (-b))"),
+        ("--a", "(-(-a))"),
+        ("-a cross b ^ 2", "((-a) cross (b ^ 2))"),
+    ]);
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 2>", "b = <3, 4>"]);
+    assert_eq!(eval(&mut state, "-a dot b"), Value::Number(-11.0));
+    assert_eq!(eval(&mut state, "2 * -a"), Value::from(vec![-2.0, -4.0]));
+    // On its own line `-a` is a negation, not the last result minus `a`
+    assert_eq!(eval(&mut state, "-a"), Value::from(vec![-1.0, -2.0]));
+}
+
+#[test]
+fn parentheses_group_first() {
+    groups_as(&[
+        ("(a + b) * c", "((a + b) * c)"),
+        ("a * (b + c)", "(a * (b + c))"),
+        ("(a dot b) ^ 2", "((a dot b) ^ 2)"),
+        ("(2 ^ 3) ^ 2", "((2 ^ 3) ^ 2)"),
+        ("a / (b / c)", "(a / (b / c))"),
+        ("-(a + b)", "(-(a + b))"),
+        ("((a))", "a"),
+    ]);
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 0, 0>", "b = <0, 1, 0>"]);
+    assert_eq!(eval(&mut state, "(2 + 3) * 4"), Value::Number(20.0));
+    assert_eq!(eval(&mut state, "(2 ^ 3) ^ 2"), Value::Number(64.0));
+    assert_eq!(eval(&mut state, "(-2) ^ 2"), Value::Number(4.0));
+    assert_eq!(eval(&mut state, "-(1 + 2) * 2"), Value::Number(-6.0));
+    assert_eq!(eval(&mut state, "(a + b) dot a"), Value::Number(1.0));
+    assert_eq!(eval(&mut state, "(a cross b) * 2"), Value::from(vec![0.0, 0.0, 2.0]));
+}
+
+#[test]
+fn folded_assignments_keep_the_parentheses_they_need() {
+    let (mut state, output) = session();
+    run(&mut state, &["a = 1", "b = 2", ".debug 2"]);
+    for line in ["(a + b) * a", "a + b * a", "a - (b - a)", "(a ^ b) ^ a", "-(a + b)", "(-a) ^ 2", "a * -b"] {
+        run(&mut state, &[&format!("x = {}", line)]);
+        let shown = messages(&output, MessageKind::Debug).pop().unwrap_or_default();
+        assert_eq!(shown, format!("[dbg2 parser] x = {}", line));
+    }
+}
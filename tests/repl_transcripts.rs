@@ -0,0 +1,199 @@
+//! Whole sessions replayed through `run_line`, checking what they printed, what went into the
+//! history, and the variables they left behind
+
+mod common;
+
+use common::{messages, session, TempDir};
+use vector_calc::helper::{CalculatorState, Value};
+use vector_calc::output::{BufferedOutput, MessageKind};
+use vector_calc::repl::{Input, LineOutcome, ScriptedEditor, SessionEnd};
+
+/// Runs the lines in a new session, giving the state, its output and the editor
+fn transcript(lines: &[&str]) -> (CalculatorState, BufferedOutput, ScriptedEditor, Vec<LineOutcome>) {
+    let (mut state, output) = session();
+    let mut editor = ScriptedEditor::new();
+    let outcomes = editor.run(&mut state, lines.iter().copied());
+    (state, output, editor, outcomes)
+}
+
+#[test]
+fn assignment_echoes_and_stores() {
+    let (state, output, editor, outcomes) = transcript(&["x = 3", "v = <1, 2>"]);
+    assert_eq!(outcomes, [LineOutcome::Ok, LineOutcome::Ok]);
+    assert_eq!(messages(&output, MessageKind::Info), ["x = 3", "v = <1, 2>"]);
+    assert!(editor.errors.is_empty());
+    assert_eq!(state.get_var("x"), Some(&Value::Number(3.0)));
+    assert_eq!(state.get_var("v"), Some(&Value::Vector(vec![1.0, 2.0].into())));
+}
+
+#[test]
+fn results_are_numbered() {
+    let (_, output, _, _) = transcript(&["x = 3", "x * 2", "x * <1, 1>"]);
+    assert_eq!(messages(&output, MessageKind::Result), ["[1] = 6", "[2] = <3, 3>"]);
+}
+
+#[test]
+fn underscore_is_the_last_result() {
+    let (_, output, _, _) = transcript(&["2 * 3", "_ + 1", "out(1) * 10"]);
+    assert_eq!(messages(&output, MessageKind::Result), ["[1] = 6", "[2] = 7", "[3] = 60"]);
+}
+
+#[test]
+fn compound_assignment_updates_the_variable() {
+    let (state, output, _, _) = transcript(&["x = 3", "x += 2", "x *= 10"]);
+    assert_eq!(messages(&output, MessageKind::Info), ["x = 3", "x = 5", "x = 50"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(50.0)));
+}
+
+#[test]
+fn errors_are_shown_and_the_session_goes_on() {
+    let (state, output, editor, outcomes) = transcript(&["y", "x = 1", "x + <1>"]);
+    assert_eq!(outcomes, [LineOutcome::Failed, LineOutcome::Ok, LineOutcome::Failed]);
+    assert_eq!(editor.errors.len(), 2);
+    assert_eq!(editor.errors[0], "Unknown identifier: 'y' is not defined");
+    assert!(editor.errors[1].starts_with("Type error"), "{}", editor.errors[1]);
+    assert!(messages(&output, MessageKind::Result).is_empty());
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn syntax_errors_stay_out_of_the_history() {
+    let (_, _, editor, outcomes) = transcript(&["x = 1", "1 +", "y", "x"]);
+    assert_eq!(outcomes[1], LineOutcome::Failed);
+    assert!(editor.errors[0].starts_with("Syntax error"), "{}", editor.errors[0]);
+    // `y` parsed, so it's worth recalling even though it failed
+    assert_eq!(editor.history, ["x = 1", "y", "x"]);
+}
+
+#[test]
+fn failed_assignments_leave_the_old_value() {
+    let (state, _, editor, _) = transcript(&["x = 4", "x /= 0", "x = nope"]);
+    assert_eq!(editor.errors.len(), 2);
+    assert!(editor.errors[0].starts_with("Division by zero"), "{}", editor.errors[0]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(4.0)));
+}
+
+#[test]
+fn echo_can_be_turned_off() {
+    let (state, output, _, _) = transcript(&[".echo", ".echo off", "x = 3", ".echo"]);
+    assert_eq!(messages(&output, MessageKind::Info), ["Echo: on", "Echo: off"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(3.0)));
+}
+
+#[test]
+fn precision_applies_to_later_results() {
+    let (_, output, _, _) = transcript(&["1 / 3", ".precision 2", "1 / 3", ".precision auto", "1 / 4"]);
+    assert_eq!(messages(&output, MessageKind::Result), ["[1] = 0.3333333333333333", "[2] = 0.33", "[3] = 0.25"]);
+}
+
+#[test]
+fn unknown_commands_suggest_one() {
+    let (_, _, editor, outcomes) = transcript(&[".laod session"]);
+    assert_eq!(outcomes, [LineOutcome::Failed]);
+    assert!(editor.errors[0].starts_with("Unknown command: '.laod' is not a command, did you mean '.load'?"));
+}
+
+#[test]
+fn exit_stops_the_session() {
+    let (state, _, editor, outcomes) = transcript(&["x = 1", ".exit", "x = 2"]);
+    assert_eq!(outcomes, [LineOutcome::Ok, LineOutcome::Exit]);
+    assert_eq!(editor.history, ["x = 1", ".exit"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn ctrl_c_twice_on_an_empty_line_quits() {
+    let (mut state, output) = session();
+    let mut editor = ScriptedEditor::new();
+    let end = editor.run_inputs(&mut state, [
+        Input::Line("x = 1".to_owned()),
+        Input::Interrupted { empty: true },
+        Input::Interrupted { empty: true },
+        Input::Line("x = 2".to_owned()),
+    ]);
+    assert_eq!(end, Some(SessionEnd::Interrupted));
+    assert_eq!(messages(&output, MessageKind::Info), ["x = 1", "(Ctrl-D or .exit quits, or press Ctrl-C again)", "CTRL-C"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn ctrl_c_on_a_typed_line_only_cancels_it() {
+    let (mut state, output) = session();
+    let mut editor = ScriptedEditor::new();
+    let end = editor.run_inputs(&mut state, [
+        Input::Interrupted { empty: true },
+        Input::Interrupted { empty: false },
+        Input::Interrupted { empty: true },
+        Input::Interrupted { empty: false },
+        Input::Line("x = 2".to_owned()),
+        Input::Interrupted { empty: true },
+        Input::Line("x = 3".to_owned()),
+    ]);
+    assert_eq!(end, None);
+    let hint = "(Ctrl-D or .exit quits, or press Ctrl-C again)";
+    assert_eq!(messages(&output, MessageKind::Info), [hint, hint, "x = 2", hint, "x = 3"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(3.0)));
+}
+
+#[test]
+fn ctrl_d_and_exit_end_the_session() {
+    let (mut state, _) = session();
+    let end = ScriptedEditor::new().run_inputs(&mut state, [Input::Interrupted { empty: true }, Input::Eof]);
+    assert_eq!(end, Some(SessionEnd::Eof));
+    let exit = [Input::Interrupted { empty: true }, Input::Line(".exit".to_owned())];
+    let end = ScriptedEditor::new().run_inputs(&mut state, exit);
+    assert_eq!(end, Some(SessionEnd::Exit));
+}
+
+#[test]
+fn modify_prompts_for_the_new_value() {
+    let (mut state, output) = session();
+    let mut editor = ScriptedEditor::new();
+    editor.responses.push_back("x + 4".to_owned());
+    editor.run(&mut state, ["x = 1", ".modify x"]);
+    assert_eq!(messages(&output, MessageKind::Info), ["x = 1", "x = 5"]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(5.0)));
+}
+
+#[test]
+fn backing_out_of_modify_keeps_the_value() {
+    let (state, _, editor, outcomes) = transcript(&["x = 1", ".modify x"]);
+    assert_eq!(outcomes, [LineOutcome::Ok, LineOutcome::Ok]);
+    assert!(editor.errors.is_empty());
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn save_and_load_restore_the_variables() {
+    let dir = TempDir::new("transcript-save-load");
+    let file = dir.file("session");
+    let save = format!(".save {}", file);
+    let load = format!(".load {}", file);
+    let (state, output, editor, _) = transcript(&["x = 3", "v = <1, 2>", &save, "x = 100", "v = 0", &load]);
+    assert!(editor.errors.is_empty(), "{:?}", editor.errors);
+    assert!(std::path::Path::new(&format!("{}.vecalc", file)).exists());
+    assert_eq!(state.get_var("x"), Some(&Value::Number(3.0)));
+    assert_eq!(state.get_var("v"), Some(&Value::Vector(vec![1.0, 2.0].into())));
+    assert!(!state.dirty);
+    let info = messages(&output, MessageKind::Info);
+    assert_eq!(&info[info.len() - 2..], ["Processed 3 lines", "Finished loading state file."]);
+}
+
+#[test]
+fn loading_a_missing_file_is_an_error() {
+    let dir = TempDir::new("transcript-missing");
+    let load = format!(".load {}", dir.file("nothing"));
+    let (state, _, editor, outcomes) = transcript(&["x = 1", &load]);
+    assert_eq!(outcomes[1], LineOutcome::Failed);
+    assert!(editor.errors[0].starts_with("File error"), "{}", editor.errors[0]);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn workspaces_keep_their_own_variables() {
+    let (mut state, _, editor, _) = transcript(&["x = 1", ".workspace other", "x = 2", ".workspace main"]);
+    assert!(editor.errors.is_empty(), "{:?}", editor.errors);
+    assert_eq!(state.get_var("x"), Some(&Value::Number(1.0)));
+    state.switch_workspace("other");
+    assert_eq!(state.get_var("x"), Some(&Value::Number(2.0)));
+}
@@ -0,0 +1,75 @@
+//! `extend()`, `truncate()` and `Vector::resized` behind them
+
+mod common;
+
+use common::{error, eval, run, session, show};
+use vector_calc::helper::{Value, Vector};
+
+fn vector(components: &[f64]) -> Vector {
+    Vector::from(components.to_vec())
+}
+
+#[test]
+fn resized_grows_shrinks_and_keeps() {
+    let v = vector(&[1.0, 2.0]);
+    assert_eq!(v.resized(4, 0.0), vector(&[1.0, 2.0, 0.0, 0.0]));
+    assert_eq!(v.resized(3, 7.5), vector(&[1.0, 2.0, 7.5]));
+    assert_eq!(v.resized(1, 0.0), vector(&[1.0]));
+    assert_eq!(v.resized(0, 0.0), vector(&[]));
+    assert_eq!(v.resized(2, 9.0), v);
+    assert_eq!(vector(&[]).resized(2, 1.0), vector(&[1.0, 1.0]));
+}
+
+#[test]
+fn extend_pads_with_zeros_or_a_fill() {
+    let (mut state, _) = session();
+    assert_eq!(show(&mut state, "extend(<1, 2>, 3)"), "<1, 2, 0>");
+    assert_eq!(show(&mut state, "extend(<1, 2>, 4, -1)"), "<1, 2, -1, -1>");
+    assert_eq!(show(&mut state, "extend(<1, 2>, 2)"), "<1, 2>");
+    assert_eq!(show(&mut state, "extend(<>, 2)"), "<0, 0>");
+}
+
+#[test]
+fn truncate_cuts_from_the_end() {
+    let (mut state, _) = session();
+    assert_eq!(show(&mut state, "truncate(<1, 2, 3>, 2)"), "<1, 2>");
+    assert_eq!(show(&mut state, "truncate(<1, 2, 3>, 3)"), "<1, 2, 3>");
+    assert_eq!(show(&mut state, "truncate(<1, 2, 3>, 0)"), "<>");
+}
+
+#[test]
+fn lifting_into_3d_allows_a_cross_product() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = <1, 0>", "b = <0, 1, 0>"]);
+    assert_eq!(error(&mut state, "a cross b").0, "Dimension mismatch");
+    assert_eq!(show(&mut state, "extend(a, 3) cross b"), "<0, 0, 1>");
+    assert_eq!(eval(&mut state, "truncate(b, 2) dot a"), Value::Number(0.0));
+}
+
+#[test]
+fn the_dimension_has_to_be_a_non_negative_whole_number() {
+    let (mut state, _) = session();
+    for line in ["extend(<1>, 2.5)", "extend(<1>, -1)", "truncate(<1>, 0.5)", "extend(1, 2)", "truncate(<1>, 0, 0)"] {
+        assert_eq!(error(&mut state, line).0, "Invalid arguments", "{}", line);
+    }
+}
+
+#[test]
+fn the_wrong_direction_is_refused() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "extend(<1, 2, 3>, 2)");
+    assert_eq!(kind, "Invalid arguments");
+    assert!(message.contains("truncate()"), "{}", message);
+    let (kind, message) = error(&mut state, "truncate(<1>, 2)");
+    assert_eq!(kind, "Invalid arguments");
+    assert!(message.contains("extend()"), "{}", message);
+}
+
+#[test]
+fn growing_is_limited() {
+    let (mut state, _) = session();
+    run(&mut state, &[".limits max_vector_len 10"]);
+    assert_eq!(error(&mut state, "extend(<1>, 11)").0, "Limit exceeded");
+    assert_eq!(error(&mut state, "extend(<1>, 10 ^ 30)").0, "Limit exceeded");
+    eval(&mut state, "extend(<1>, 10)");
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::{Quaternion, Value};
+
+/// Whether `value` is a vector within 1e-9 of `expected`
+fn close_to(value: &Value, expected: &[f64]) -> bool {
+    match value {
+        Value::Vector(vec) => vec.dims() == expected.len() && vec.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-9),
+        _ => false,
+    }
+}
+
+#[test]
+fn quarter_turn_about_z() {
+    let (mut state, _) = session();
+    let rotated = eval(&mut state, "qrotate(quat(<0, 0, 1>, pi/2), <1, 0, 0>)");
+    assert!(close_to(&rotated, &[0.0, 1.0, 0.0]), "{:?}", rotated);
+    let rotated = eval(&mut state, "qrotate(quat(<0, 0, 1>, 90deg), <1, 0, 0>)");
+    assert!(close_to(&rotated, &[0.0, 1.0, 0.0]), "{:?}", rotated);
+}
+
+#[test]
+fn multiplying_composes_right_to_left() {
+    let (mut state, _) = session();
+    run(&mut state, &["qz = quat(<0, 0, 1>, π / 2)", "qx = quat(<1, 0, 0>, π / 2)"]);
+    // z first, taking x to y, then x, taking y to z
+    let rotated = eval(&mut state, "qrotate(qx * qz, <1, 0, 0>)");
+    assert!(close_to(&rotated, &[0.0, 0.0, 1.0]), "{:?}", rotated);
+    let rotated = eval(&mut state, "qrotate(qz * qx, <1, 0, 0>)");
+    assert!(close_to(&rotated, &[0.0, 1.0, 0.0]), "{:?}", rotated);
+}
+
+#[test]
+fn two_half_turns_are_a_full_turn() {
+    let (mut state, _) = session();
+    run(&mut state, &["q = quat(<0, 1, 0>, π)"]);
+    let rotated = eval(&mut state, "qrotate(q * q, <1, 2, 3>)");
+    assert!(close_to(&rotated, &[1.0, 2.0, 3.0]), "{:?}", rotated);
+}
+
+#[test]
+fn axes_are_normalized() {
+    let (mut state, _) = session();
+    let Value::Quaternion(q) = eval(&mut state, "quat(<0, 0, 5>, π)") else {
+        panic!("quat() should give a quaternion");
+    };
+    assert!((q.norm() - 1.0).abs() < 1e-12);
+    let rotated = eval(&mut state, "qrotate(quat(<0, 0, 5>, π/2), <1, 0, 0>)");
+    assert!(close_to(&rotated, &[0.0, 1.0, 0.0]), "{:?}", rotated);
+}
+
+#[test]
+fn quaternions_rotate_like_the_method() {
+    let q = Quaternion::from_axis_angle(&vec![0.0, 0.0, 1.0].into(), std::f64::consts::FRAC_PI_2).unwrap();
+    let rotated = q.rotate(&vec![1.0, 0.0, 0.0].into()).unwrap();
+    assert!(close_to(&Value::Vector(rotated), &[0.0, 1.0, 0.0]));
+}
+
+#[test]
+fn only_3d_vectors_rotate() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "qrotate(quat(<0, 0, 1>, 1), <1, 0>)").0, "Dimension mismatch");
+    assert_eq!(error(&mut state, "quat(<0, 0, 0>, 1)").0, "Invalid arguments");
+}
+
+#[test]
+fn scalar_trig_takes_radians() {
+    let (mut state, _) = session();
+    let number = |value: Value| match value {
+        Value::Number(x) => x,
+        other => panic!("{:?} isn't a number", other),
+    };
+    assert!((number(eval(&mut state, "sin(pi / 6)")) - 0.5).abs() < 1e-12);
+    assert!((number(eval(&mut state, "cos(60deg)")) - 0.5).abs() < 1e-12);
+    assert!((number(eval(&mut state, "tan(π / 4)")) - 1.0).abs() < 1e-12);
+    assert!((number(eval(&mut state, "cos(1.5 rad)")) - 1.5f64.cos()).abs() < 1e-12);
+    assert_eq!(eval(&mut state, "tau"), Value::Number(std::f64::consts::TAU));
+    assert_eq!(error(&mut state, "sin(<1, 2>)").0, "Invalid arguments");
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{Rng, TempDir};
+use vector_calc::helper::{CalculatorState, Complex, Matrix, Quantity, Quaternion, Unit, Value, Vector, UNIT_NAMES};
+use vector_calc::parser::{load_state, save_state};
+
+/// Any shape of value, with components from `Rng::number`
+fn random_value(rng: &mut Rng) -> Value {
+    match rng.below(6) {
+        0 => Value::Number(rng.number()),
+        1 => {
+            let dims = rng.below(6);
+            Value::Vector(rng.numbers(dims).into())
+        }
+        2 => {
+            let (rows, cols) = (1 + rng.below(3), 1 + rng.below(3));
+            let rows: Vec<Vector> = (0..rows).map(|_| rng.numbers(cols).into()).collect();
+            Value::Matrix(Matrix::from_rows(rows).unwrap())
+        }
+        3 => Value::Quaternion(Quaternion::new(rng.number(), rng.number(), rng.number(), rng.number())),
+        4 => Value::Complex(Complex::new(rng.number(), rng.number())),
+        _ => {
+            let unit = Unit::named(UNIT_NAMES[rng.below(UNIT_NAMES.len())]).unwrap();
+            Quantity::value_of(rng.number(), unit)
+        }
+    }
+}
+
+/// `Debug` shows every float exactly, and tells `-0` from `0`
+fn exact(value: Option<&Value>) -> String {
+    format!("{:?}", value)
+}
+
+#[test]
+fn random_values_load_back_exactly() {
+    let dir = TempDir::new("save-load-random");
+    let file = dir.file("random");
+    let mut rng = Rng::new(134);
+    for _ in 0..100 {
+        let mut saved = CalculatorState::new();
+        for i in 0..40 {
+            saved.set_var(format!("v{}", i), random_value(&mut rng));
+        }
+        save_state(&file, false, &mut saved).unwrap();
+
+        let mut loaded = CalculatorState::new();
+        load_state(&file, &mut loaded).unwrap();
+        for i in 0..40 {
+            let name = format!("v{}", i);
+            assert_eq!(exact(loaded.get_var(&name)), exact(saved.get_var(&name)), "{} changed", name);
+        }
+    }
+}
+
+#[test]
+fn values_survive_repeated_cycles() {
+    let dir = TempDir::new("save-load-cycles");
+    let file = dir.file("cycles");
+    let mut rng = Rng::new(135);
+    let mut state = CalculatorState::new();
+    for i in 0..40 {
+        state.set_var(format!("v{}", i), random_value(&mut rng));
+    }
+    let original: Vec<String> = (0..40).map(|i| exact(state.get_var(&format!("v{}", i)))).collect();
+    for _ in 0..5 {
+        save_state(&file, false, &mut state).unwrap();
+        state = CalculatorState::new();
+        load_state(&file, &mut state).unwrap();
+    }
+    let cycled: Vec<String> = (0..40).map(|i| exact(state.get_var(&format!("v{}", i)))).collect();
+    assert_eq!(cycled, original);
+}
+
+#[test]
+fn non_finite_values_arent_saved() {
+    let dir = TempDir::new("save-load-non-finite");
+    let mut state = CalculatorState::new();
+    state.set_var("x".to_owned(), Value::Number(f64::NAN));
+    let err = save_state(&dir.file("nan"), false, &mut state).unwrap_err();
+    assert!(err.message().contains("'x' isn't finite"), "{}", err.message());
+}
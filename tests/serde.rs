@@ -0,0 +1,67 @@
+//! The JSON representation is what saved files and embedding programs read, so these pin it
+#![cfg(feature = "json")]
+
+mod common;
+
+use common::{eval, run, session};
+use serde_json::json;
+use vector_calc::helper::{CalculatorState, Complex, Matrix, NumberFormat, Quaternion, StateSnapshot, Value, Vector};
+
+fn round_trip(value: &Value) -> Value {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+#[test]
+fn numbers_and_vectors_are_tagged() {
+    assert_eq!(serde_json::to_value(Value::Number(3.0)).unwrap(), json!({"Number": 3.0}));
+    assert_eq!(
+        serde_json::to_value(Value::Vector(Vector::from(vec![1.0, 2.0]))).unwrap(),
+        json!({"Vector": [1.0, 2.0]})
+    );
+    assert_eq!(serde_json::to_value(Vector::from(Vec::<f64>::new())).unwrap(), json!([]));
+}
+
+#[test]
+fn other_shapes_are_tagged_structs() {
+    let matrix = Matrix::from_rows(vec![vec![1.0, 2.0].into(), vec![3.0, 4.0].into()]).unwrap();
+    assert_eq!(
+        serde_json::to_value(Value::Matrix(matrix)).unwrap(),
+        json!({"Matrix": {"rows": 2, "cols": 2, "data": [1.0, 2.0, 3.0, 4.0]}})
+    );
+    assert_eq!(
+        serde_json::to_value(Value::Quaternion(Quaternion::new(1.0, 0.0, 0.5, 0.0))).unwrap(),
+        json!({"Quaternion": {"w": 1.0, "x": 0.0, "y": 0.5, "z": 0.0}})
+    );
+    assert_eq!(
+        serde_json::to_value(Value::Complex(Complex::new(3.0, -4.0))).unwrap(),
+        json!({"Complex": {"re": 3.0, "im": -4.0}})
+    );
+}
+
+#[test]
+fn every_shape_round_trips() {
+    let (mut state, _) = session();
+    for line in ["2.5", "<1, -2, 3>", "<>", "[<1, 2>, <3, 4>]", "quaternion(1, 2, 3, 4)", "3 + 4i", "5 N", "9.8 m/s^2"] {
+        let value = eval(&mut state, line);
+        assert_eq!(round_trip(&value), value, "{} didn't round trip", line);
+    }
+}
+
+#[test]
+fn snapshots_keep_variables_and_settings() {
+    let (mut state, _) = session();
+    run(&mut state, &["a = 1", "v = <1, 2>", "m = [<1, 0>, <0, 1>]", ".format sci", ".echo off"]);
+    let json = serde_json::to_value(state.snapshot()).unwrap();
+    assert_eq!(json["variables"]["a"], json!({"Number": 1.0}));
+    assert_eq!(json["variables"]["v"], json!({"Vector": [1.0, 2.0]}));
+    assert_eq!(json["number_format"], json!("Scientific"));
+    assert_eq!(json["echo_assignments"], json!(false));
+
+    let snapshot: StateSnapshot = serde_json::from_value(json).unwrap();
+    let mut restored = CalculatorState::new();
+    restored.restore(snapshot);
+    assert_eq!(restored.get_var("v"), state.get_var("v"));
+    assert_eq!(restored.get_var("m"), state.get_var("m"));
+    assert_eq!(restored.number_format, NumberFormat::Scientific);
+    assert!(!restored.echo_assignments);
+}
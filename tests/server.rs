@@ -0,0 +1,157 @@
+//! Starts `--serve` on a Unix socket and talks to it like a client would
+#![cfg(all(unix, feature = "json"))]
+
+mod common;
+
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use common::TempDir;
+use serde_json::{json, Value};
+
+/// A running server, stopped with SIGINT when dropped if the test didn't already
+struct Server {
+    child: Child,
+    socket: String,
+}
+
+impl Server {
+    fn start(dir: &TempDir, extra_args: &[&str]) -> Self {
+        let socket = dir.file("vecalc.sock");
+        let child = Command::new(env!("CARGO_BIN_EXE_vector_calc"))
+            .arg("--serve")
+            .arg(&socket)
+            .args(extra_args)
+            // Keep the user's config file out of it
+            .env("HOME", dir.file(""))
+            .env("XDG_CONFIG_HOME", dir.file("config"))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Couldn't start the server");
+        let started = Instant::now();
+        while !Path::new(&socket).exists() {
+            assert!(started.elapsed() < Duration::from_secs(10), "The server never created its socket");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Self { child, socket }
+    }
+
+    fn connect(&self) -> Client {
+        // The socket file appears when it's bound, just before the server starts listening
+        let started = Instant::now();
+        let stream = loop {
+            match UnixStream::connect(&self.socket) {
+                Ok(stream) => break stream,
+                Err(err) if err.kind() == ErrorKind::ConnectionRefused && started.elapsed() < Duration::from_secs(10) => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(err) => panic!("Couldn't connect to the server: {}", err),
+            }
+        };
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    /// Sends SIGINT and gives the exit code
+    fn stop(&mut self) -> Option<i32> {
+        unsafe {
+            libc::kill(self.child.id() as libc::pid_t, libc::SIGINT);
+        }
+        self.child.wait().unwrap().code()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            self.stop();
+        }
+    }
+}
+
+struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Sends one request line and reads the one line of JSON that answers it
+    fn request(&mut self, line: &str) -> Value {
+        writeln!(self.writer, "{}", line).unwrap();
+        let mut reply = String::new();
+        self.reader.read_line(&mut reply).expect("No reply from the server");
+        serde_json::from_str(&reply).unwrap_or_else(|err| panic!("'{}' isn't JSON: {}", reply, err))
+    }
+}
+
+#[test]
+fn round_trips_requests() {
+    let dir = TempDir::new("server-round-trip");
+    let mut server = Server::start(&dir, &[]);
+    let mut client = server.connect();
+
+    let reply = client.request("x = <1, 2>");
+    assert_eq!(reply["ok"], json!(true));
+    let reply = client.request("x * 2");
+    assert_eq!(reply["ok"], json!(true));
+    assert_eq!(reply["value"], json!([2.0, 4.0]));
+    let reply = client.request("y");
+    assert_eq!(reply["ok"], json!(false));
+    assert_eq!(reply["error_kind"], json!("UnknownIdentifier"));
+    assert_eq!(reply["span"], json!([0, 1]));
+
+    assert_eq!(server.stop(), Some(0));
+    assert!(!Path::new(&server.socket).exists(), "The socket file should be removed on shutdown");
+}
+
+#[test]
+fn connections_have_their_own_variables() {
+    let dir = TempDir::new("server-separate");
+    let server = Server::start(&dir, &[]);
+    let mut first = server.connect();
+    let mut second = server.connect();
+    assert_eq!(first.request("x = 1")["ok"], json!(true));
+    assert_eq!(second.request("x")["error_kind"], json!("UnknownIdentifier"));
+    assert_eq!(first.request("x")["value"], json!(1.0));
+}
+
+#[test]
+fn file_commands_are_disabled_by_default() {
+    let dir = TempDir::new("server-no-fs");
+    let server = Server::start(&dir, &[]);
+    let mut client = server.connect();
+    let reply = client.request(&format!(".save {}", dir.file("session")));
+    assert_eq!(reply["ok"], json!(false));
+    assert_eq!(reply["error_kind"], json!("FilesystemDisabled"));
+    let message = reply["message"].as_str().unwrap();
+    assert!(message.contains("disabled in server mode"), "{}", message);
+    assert!(!Path::new(&dir.file("session.vecalc")).exists());
+}
+
+#[test]
+fn allow_fs_enables_file_commands() {
+    let dir = TempDir::new("server-fs");
+    let server = Server::start(&dir, &["--serve-allow-fs"]);
+    let mut client = server.connect();
+    client.request("x = 3");
+    let reply = client.request(&format!(".save {}", dir.file("session")));
+    assert_eq!(reply["ok"], json!(true), "{}", reply);
+    assert!(Path::new(&dir.file("session.vecalc")).exists());
+}
+
+#[test]
+fn exit_closes_the_connection() {
+    let dir = TempDir::new("server-exit");
+    let server = Server::start(&dir, &[]);
+    let mut client = server.connect();
+    assert_eq!(client.request(".exit")["ok"], json!(true));
+    let mut rest = String::new();
+    assert_eq!(client.reader.read_line(&mut rest).unwrap(), 0);
+}
@@ -0,0 +1,103 @@
+//! `solve()` and the root finding behind it
+
+mod common;
+
+use common::{error, eval, run, session};
+use vector_calc::helper::{find_root, RootError, Value};
+
+fn number(value: Value) -> f64 {
+    match value {
+        Value::Number(x) => x,
+        value => panic!("{:?} isn't a number", value),
+    }
+}
+
+#[test]
+fn polynomial_roots() {
+    let (mut state, _) = session();
+    for (line, expected) in [
+        ("solve(x^2 - 2, x, 1)", std::f64::consts::SQRT_2),
+        ("solve(x^2 - 2, x, -1)", -std::f64::consts::SQRT_2),
+        ("solve(x - 3, x, 0)", 3.0),
+        ("solve(x^3 - x - 2, x, 1.5)", 1.5213797068045676),
+        ("solve(x^2 - 5 * x + 6, x, 10)", 3.0),
+        ("solve(x^2 - 5 * x + 6, x, 0)", 2.0),
+        ("solve(x^3, x, 1)", 0.0),
+    ] {
+        let got = number(eval(&mut state, line));
+        assert!((got - expected).abs() <= 1e-8, "{} gave {}, expected {}", line, got, expected);
+    }
+}
+
+#[test]
+fn transcendental_roots() {
+    let (mut state, _) = session();
+    let got = number(eval(&mut state, "solve(cos(x) - x, x, 1)"));
+    assert!((got - 0.7390851332151607).abs() <= 1e-9, "{}", got);
+    let got = number(eval(&mut state, "solve(sin(x), x, 3)"));
+    assert!((got - std::f64::consts::PI).abs() <= 1e-9, "{}", got);
+}
+
+#[test]
+fn the_variable_is_left_alone() {
+    let (mut state, _) = session();
+    run(&mut state, &["x = 10", "c = 4"]);
+    assert_eq!(number(eval(&mut state, "solve(x^2 - c, x, 1)")), 2.0);
+    assert_eq!(eval(&mut state, "x"), Value::Number(10.0));
+}
+
+#[test]
+fn running_out_of_iterations_gives_the_last_estimate() {
+    let (mut state, _) = session();
+    run(&mut state, &[".limits max_iterations 2"]);
+    let (kind, message) = error(&mut state, "solve(cos(x) - x, x, 100)");
+    assert_eq!(kind, "Did not converge");
+    assert!(message.contains("didn't converge after 2 iterations"), "{}", message);
+    assert!(message.contains("the last estimate was"), "{}", message);
+    assert!(message.contains("`.limits max_iterations N`"), "{}", message);
+}
+
+#[test]
+fn no_root_and_no_bracket() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "solve(x^2 + 1, x, 0.5)");
+    assert_eq!(kind, "No root found");
+    assert!(message.contains("found no sign change nearby. Try another guess"), "{}", message);
+    // More iterations wouldn't help, so they aren't suggested
+    assert!(!message.contains("max_iterations"), "{}", message);
+}
+
+#[test]
+fn vector_expressions_are_refused() {
+    let (mut state, _) = session();
+    assert_eq!(error(&mut state, "solve(vec(x, 1), x, 0)").0, "Type error");
+    assert_eq!(error(&mut state, "solve(x, 2, 0)").0, "Invalid arguments");
+}
+
+#[test]
+fn find_root_falls_back_to_bisecting() {
+    // Flat either side of the step, so Newton's method has nothing to go on
+    let step = |x: f64| Ok::<_, ()>(if x < 0.3 { -1.0 } else { 1.0 });
+    let root = find_root(step, 0.0, 1e-12, 200).unwrap();
+    assert!((root - 0.3).abs() <= 1e-9, "{}", root);
+}
+
+#[test]
+fn find_root_without_a_sign_change() {
+    match find_root(|x: f64| Ok::<_, ()>(x * x + 1.0), 0.5, 1e-12, 200) {
+        Err(RootError::NoBracket { last }) => assert!(last.is_finite()),
+        result => panic!("{:?}", result),
+    }
+}
+
+#[test]
+fn find_root_counts_iterations() {
+    let result = find_root(|x: f64| Ok::<_, ()>(x.cos() - x), 100.0, 1e-12, 1);
+    assert!(matches!(result, Err(RootError::OutOfIterations { iterations: 1, .. })), "{:?}", result);
+}
+
+#[test]
+fn find_root_passes_on_errors() {
+    let result = find_root(|x: f64| if x > 1.0 { Err("too far") } else { Ok(x - 2.0) }, 0.0, 1e-12, 200);
+    assert_eq!(result, Err(RootError::Function("too far")));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{error, run, session};
+use vector_calc::helper::closest_matches;
+
+#[test]
+fn a_typo_matches_the_name() {
+    assert_eq!(closest_matches("gravty", ["gravity", "mass", "velocity"]), ["gravity"]);
+    assert_eq!(closest_matches("laod", ["load", "save", "list"]), ["load"]);
+    assert_eq!(closest_matches("velcoity", ["velocity", "speed"]), ["velocity"]);
+}
+
+#[test]
+fn ties_give_every_closest_match_sorted() {
+    assert_eq!(closest_matches("cat", ["hat", "bat", "cart", "dog"]), ["bat", "cart", "hat"]);
+    assert_eq!(closest_matches("pos", ["pos1", "pos2"]), ["pos1", "pos2"]);
+}
+
+#[test]
+fn only_the_closest_are_kept() {
+    assert_eq!(closest_matches("mass", ["mast", "most"]), ["mast"]);
+    assert_eq!(closest_matches("speed", ["sped", "spend", "speeds", "spade"]), ["sped", "speeds", "spend"]);
+}
+
+#[test]
+fn nothing_close_gives_nothing() {
+    assert!(closest_matches("gravity", ["mass", "velocity"]).is_empty());
+    assert!(closest_matches("x", ["y", "z"]).is_empty());
+    assert!(closest_matches("anything", std::iter::empty()).is_empty());
+}
+
+#[test]
+fn prefixes_count_as_one_edit() {
+    assert_eq!(closest_matches("velcoity", ["velocity", "vel"]), ["vel"]);
+    assert_eq!(closest_matches("pos", ["position"]), ["position"]);
+}
+
+#[test]
+fn the_name_itself_isnt_suggested() {
+    assert_eq!(closest_matches("load", ["load", "loads"]), ["loads"]);
+}
+
+#[test]
+fn short_names_need_to_be_closer() {
+    assert!(closest_matches("ab", ["xy"]).is_empty());
+    assert_eq!(closest_matches("abc", ["abd", "xyz"]), ["abd"]);
+}
+
+#[test]
+fn unknown_variables_suggest_defined_ones() {
+    let (mut state, _) = session();
+    run(&mut state, &["gravity = 9.8", "mass = 2"]);
+    assert_eq!(state.suggest_variables("gravty"), ["gravity"]);
+    assert!(state.suggest_variables("zzz").is_empty());
+    let (kind, message) = error(&mut state, "gravty * mass");
+    assert_eq!(kind, "Unknown identifier");
+    assert!(message.ends_with("did you mean 'gravity'?"), "{}", message);
+}
+
+#[test]
+fn unknown_variables_without_a_match_dont_suggest() {
+    let (mut state, _) = session();
+    let (_, message) = error(&mut state, "gravity");
+    assert!(!message.contains("did you mean"), "{}", message);
+}
+
+#[test]
+fn unknown_functions_suggest_builtins() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, "outt(1)");
+    assert_eq!(kind, "Unknown function");
+    assert!(message.contains("did you mean 'out'"), "{}", message);
+}
+
+#[test]
+fn unknown_commands_suggest_commands() {
+    let (mut state, _) = session();
+    let (kind, message) = error(&mut state, ".laod session");
+    assert_eq!(kind, "Unknown command");
+    assert!(message.contains("did you mean '.load'"), "{}", message);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::f64::consts::{PI, TAU};
+
+use common::{eval, run, session, show, TempDir};
+use vector_calc::helper::{CalculatorState, Value};
+use vector_calc::parser::{self, complete_with_variables, load_state, save_state};
+
+#[test]
+fn greek_letters_name_variables() {
+    let (mut state, _) = session();
+    run(&mut state, &["θ = 0.5", "φ = 2", "Δv = <1, 2>"]);
+    assert_eq!(eval(&mut state, "θ * φ"), Value::Number(1.0));
+    assert_eq!(show(&mut state, "Δv * φ"), "<2, 4>");
+    assert_eq!(state.get_var("Δv"), Some(&Value::Vector(vec![1.0, 2.0].into())));
+}
+
+#[test]
+fn pi_and_tau_are_constants() {
+    let (mut state, _) = session();
+    assert_eq!(eval(&mut state, "π"), Value::Number(PI));
+    assert_eq!(eval(&mut state, "τ / 2"), Value::Number(PI));
+    assert_eq!(eval(&mut state, "2 * π"), Value::Number(TAU));
+}
+
+#[test]
+fn greek_names_save_and_load() {
+    let dir = TempDir::new("unicode-save-load");
+    let file = dir.file("greek");
+    let (mut state, _) = session();
+    run(&mut state, &["θ = 0.25", "Δv = <1, -2, 3>", "ω2 = 7"]);
+    save_state(&file, false, &mut state).unwrap();
+
+    let mut loaded = CalculatorState::new();
+    load_state(&file, &mut loaded).unwrap();
+    for name in ["θ", "Δv", "ω2"] {
+        assert_eq!(loaded.get_var(name), state.get_var(name), "{} wasn't reloaded", name);
+    }
+}
+
+#[test]
+fn echo_shows_the_name() {
+    let (mut state, output) = session();
+    run(&mut state, &["Δv = <1, 2>"]);
+    assert_eq!(output.take()[0].1, "Δv = <1, 2>");
+}
+
+#[test]
+fn underlines_count_characters() {
+    let (mut state, _) = session();
+    run(&mut state, &["Δv = 1"]);
+    let input = "Δv + Δw";
+    let err = parser::eval(input, &mut state).unwrap_err();
+    let (start, end) = err.span().unwrap();
+    assert_eq!(&input[start..end], "Δw");
+    assert_eq!(
+        err.render(input),
+        "Unknown identifier: 'Δw' is not defined, did you mean 'Δv'?\n  | Δv + Δw\n  |      ^^"
+    );
+}
+
+#[test]
+fn completes_multibyte_names() {
+    let (start, names) = complete_with_variables("1 + Δ", "1 + Δ".len(), ["Δv", "Δx", "θ"]);
+    assert_eq!(start, 4);
+    assert_eq!(names, ["Δv", "Δx"]);
+    let (start, names) = complete_with_variables("θ", "θ".len(), ["θ", "θ2"]);
+    assert_eq!(start, 0);
+    assert_eq!(names, ["θ", "θ2"]);
+}